@@ -0,0 +1,5 @@
+// Headless 反代服务: antigravity-proxy serve --config proxy.json
+
+fn main() {
+    antigravity_tools_lib::headless::run()
+}
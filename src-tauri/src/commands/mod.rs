@@ -334,6 +334,17 @@ pub async fn save_config(
         instance.axum_server.update_security(&config.proxy).await;
         // 更新 z.ai 配置
         instance.axum_server.update_zai(&config.proxy).await;
+        // 更新上游服务商
+        instance.axum_server.update_providers(&config.proxy).await;
+        // 更新 v1internal 端点与熔断参数
+        instance.axum_server.update_endpoints(&config.proxy);
+        // 更新 token 后台刷新参数
+        instance.axum_server.update_token_refresh(&config.proxy);
+        // 更新调度配置 (粘性模式 / 单账号并发上限)
+        instance
+            .token_manager
+            .update_sticky_config(config.proxy.scheduling.clone())
+            .await;
         tracing::debug!("已同步热更新反代服务配置");
     }
 
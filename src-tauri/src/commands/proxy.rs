@@ -57,7 +57,7 @@ pub async fn start_proxy_service(
     {
         let mut monitor_lock = state.monitor.write().await;
         if monitor_lock.is_none() {
-            *monitor_lock = Some(Arc::new(ProxyMonitor::new(1000, Some(app_handle.clone()))));
+            *monitor_lock = Some(Arc::new(ProxyMonitor::new(1000, Some(Arc::new(app_handle.clone())))));
         }
         // Sync enabled state from config
         if let Some(monitor) = monitor_lock.as_ref() {
@@ -81,25 +81,30 @@ pub async fn start_proxy_service(
     let active_accounts = token_manager.load_accounts().await
         .map_err(|e| format!("加载账号失败: {}", e))?;
     
-    if active_accounts == 0 {
-        let zai_enabled = config.zai.enabled
-            && !matches!(config.zai.dispatch_mode, crate::proxy::ZaiDispatchMode::Off);
-        if !zai_enabled {
-            return Err("没有可用账号，请先添加账号".to_string());
-        }
+    // 仅配置了上游服务商 (z.ai / 自定义) 时允许无账号启动
+    if active_accounts == 0 && !config.has_active_provider() {
+        return Err("没有可用账号，请先添加账号".to_string());
     }
     
+    // 加载模型能力覆盖
+    crate::proxy::common::model_registry::ModelRegistry::global().reload(&config.model_capabilities);
+    // v1internal 端点顺序与熔断参数
+    monitor.endpoints.configure(&config.upstream_endpoints);
+    // access_token 后台刷新参数
+    monitor.token_refresh.configure(&config.token_refresh);
+
     // 启动 Axum 服务器
     let (axum_server, server_handle) =
         match crate::proxy::AxumServer::start(
             config.get_bind_address().to_string(),
             config.port,
             token_manager.clone(),
-            config.custom_mapping.clone(),
+            crate::proxy::common::model_router::ModelRouter::from_config(&config),
             config.request_timeout,
             config.upstream_proxy.clone(),
             crate::proxy::ProxySecurityConfig::from_proxy_config(&config),
             config.zai.clone(),
+            config.providers.clone(),
             monitor.clone(),
             config.experimental.clone(),
 
@@ -189,6 +194,42 @@ pub async fn get_proxy_stats(
     }
 }
 
+/// 获取 v1internal 端点健康状态
+#[tauri::command]
+pub async fn get_upstream_endpoint_health(
+    state: State<'_, ProxyServiceState>,
+) -> Result<Vec<crate::proxy::upstream::health::EndpointHealthSnapshot>, String> {
+    let monitor_lock = state.monitor.read().await;
+    Ok(monitor_lock
+        .as_ref()
+        .map(|monitor| monitor.endpoints.snapshot())
+        .unwrap_or_default())
+}
+
+/// 获取 access_token 后台刷新状态
+#[tauri::command]
+pub async fn get_token_refresh_health(
+    state: State<'_, ProxyServiceState>,
+) -> Result<crate::proxy::token_refresh::TokenRefreshSnapshot, String> {
+    let monitor_lock = state.monitor.read().await;
+    Ok(monitor_lock
+        .as_ref()
+        .map(|monitor| monitor.token_refresh.snapshot())
+        .unwrap_or_default())
+}
+
+/// 获取各账号进行中的请求数 (email -> count)
+#[tauri::command]
+pub async fn get_account_in_flight(
+    state: State<'_, ProxyServiceState>,
+) -> Result<std::collections::HashMap<String, usize>, String> {
+    let instance_lock = state.instance.read().await;
+    Ok(instance_lock
+        .as_ref()
+        .map(|instance| instance.token_manager.in_flight_by_account().into_iter().collect())
+        .unwrap_or_default())
+}
+
 /// 获取反代请求日志
 #[tauri::command]
 pub async fn get_proxy_logs(
@@ -279,8 +320,7 @@ pub async fn update_model_mapping(
 ) -> Result<(), String> {
     let instance_lock = state.instance.read().await;
     
-    // 1. 如果服务正在运行，立即更新内存中的映射 (这里目前只更新了 anthropic_mapping 的 RwLock, 
-    // 后续可以根据需要让 resolve_model_route 直接读取全量 config)
+    // 1. 如果服务正在运行，立即重新编译路由规则 (model_routing_rules + custom_mapping + 回退链 + 模型能力)
     if let Some(instance) = instance_lock.as_ref() {
         instance.axum_server.update_mapping(&config).await;
         tracing::debug!("后端服务已接收全量模型映射配置");
@@ -289,6 +329,9 @@ pub async fn update_model_mapping(
     // 2. 无论是否运行，都保存到全局配置持久化
     let mut app_config = crate::modules::config::load_app_config().map_err(|e| e)?;
     app_config.proxy.custom_mapping = config.custom_mapping;
+    app_config.proxy.model_routing_rules = config.model_routing_rules;
+    app_config.proxy.model_fallback_chains = config.model_fallback_chains;
+    app_config.proxy.model_capabilities = config.model_capabilities;
     crate::modules::config::save_app_config(&app_config).map_err(|e| e)?;
     
     Ok(())
@@ -447,3 +490,50 @@ pub async fn clear_proxy_session_bindings(
     }
 }
 
+
+/// 列出当前有效的会话绑定
+#[tauri::command]
+pub async fn list_proxy_sessions(
+    state: State<'_, ProxyServiceState>,
+) -> Result<Vec<crate::proxy::token_manager::SessionBindingInfo>, String> {
+    let instance_lock = state.instance.read().await;
+    Ok(instance_lock
+        .as_ref()
+        .map(|instance| instance.token_manager.list_sessions())
+        .unwrap_or_default())
+}
+
+/// 将会话重新绑定到指定账号 (account_id 或 email)
+#[tauri::command]
+pub async fn rebind_proxy_session(
+    state: State<'_, ProxyServiceState>,
+    session_id: String,
+    account: String,
+) -> Result<crate::proxy::token_manager::SessionBindingInfo, String> {
+    let instance_lock = state.instance.read().await;
+    match instance_lock.as_ref() {
+        Some(instance) => instance.token_manager.rebind_session(&session_id, &account),
+        None => Err("服务未运行".to_string()),
+    }
+}
+
+/// 移除单个会话绑定
+#[tauri::command]
+pub async fn evict_proxy_session(
+    state: State<'_, ProxyServiceState>,
+    session_id: String,
+) -> Result<bool, String> {
+    let instance_lock = state.instance.read().await;
+    match instance_lock.as_ref() {
+        Some(instance) => Ok(instance.token_manager.clear_session_binding(&session_id)),
+        None => Err("服务未运行".to_string()),
+    }
+}
+
+/// 获取各客户端密钥的当日 / 当月 token 用量
+#[tauri::command]
+pub async fn get_proxy_key_usage() -> Result<Vec<crate::proxy::usage::ApiKeyUsage>, String> {
+    let app_config = crate::modules::config::load_app_config()?;
+    let security = crate::proxy::ProxySecurityConfig::from_proxy_config(&app_config.proxy);
+    crate::proxy::usage::collect_all_usage(&security)
+}
@@ -0,0 +1,300 @@
+// Headless 反代服务入口
+// 不依赖 Tauri 桌面壳，直接运行 AxumServer + 智能预热调度器，适用于服务器 / 容器部署
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::time::Duration;
+use tracing::{error, info, warn};
+
+use crate::models::AppConfig;
+use crate::modules::{self, logger};
+use crate::proxy::monitor::ProxyMonitor;
+use crate::proxy::{AxumServer, ProxyConfig, ProxySecurityConfig, TokenManager};
+
+const USAGE: &str = "Usage: antigravity-proxy serve [--config <path>]
+
+Options:
+  -c, --config <path>  Proxy config file (ProxyConfig JSON, or a full gui_config.json).
+                       Defaults to the proxy section of <data_dir>/gui_config.json.
+  -h, --help           Print this help";
+
+/// 命令行解析结果
+#[derive(Debug, PartialEq)]
+enum HeadlessCommand {
+    Serve { config_path: Option<PathBuf> },
+    Help,
+}
+
+fn parse_args(args: &[String]) -> Result<HeadlessCommand, String> {
+    let mut iter = args.iter();
+    match iter.next().map(|s| s.as_str()) {
+        None | Some("-h") | Some("--help") | Some("help") => return Ok(HeadlessCommand::Help),
+        Some("serve") => {}
+        Some(other) => return Err(format!("Unknown command: {}", other)),
+    }
+
+    let mut config_path = None;
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-c" | "--config" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| format!("Missing value for {}", arg))?;
+                config_path = Some(PathBuf::from(value));
+            }
+            "-h" | "--help" => return Ok(HeadlessCommand::Help),
+            other => {
+                if let Some(value) = other.strip_prefix("--config=") {
+                    config_path = Some(PathBuf::from(value));
+                } else {
+                    return Err(format!("Unknown argument: {}", other));
+                }
+            }
+        }
+    }
+
+    Ok(HeadlessCommand::Serve { config_path })
+}
+
+/// 将 `patch` 递归合并到 `base` 上 (对象逐字段合并，其他类型直接覆盖)
+fn merge_json(base: &mut serde_json::Value, patch: serde_json::Value) {
+    match (base, patch) {
+        (serde_json::Value::Object(base_map), serde_json::Value::Object(patch_map)) => {
+            for (key, value) in patch_map {
+                match base_map.get_mut(&key) {
+                    Some(existing) => merge_json(existing, value),
+                    None => {
+                        base_map.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, patch) => *base = patch,
+    }
+}
+
+/// 解析配置文件内容
+///
+/// 同时兼容独立的 ProxyConfig 与完整的 AppConfig (含 `proxy` 字段)，
+/// 缺失字段使用默认值补齐，因此最小配置只需写出需要修改的字段
+fn parse_proxy_config(content: &str) -> Result<ProxyConfig, String> {
+    let value: serde_json::Value =
+        serde_json::from_str(content).map_err(|e| format!("解析配置文件失败: {}", e))?;
+
+    if value.get("proxy").is_some() {
+        let mut base = serde_json::to_value(AppConfig::new()).map_err(|e| e.to_string())?;
+        merge_json(&mut base, value);
+        let app_config: AppConfig =
+            serde_json::from_value(base).map_err(|e| format!("解析 AppConfig 失败: {}", e))?;
+        return Ok(app_config.proxy);
+    }
+
+    let mut base = serde_json::to_value(ProxyConfig::default()).map_err(|e| e.to_string())?;
+    merge_json(&mut base, value);
+    serde_json::from_value(base).map_err(|e| format!("解析 ProxyConfig 失败: {}", e))
+}
+
+fn load_proxy_config(config_path: Option<&Path>) -> Result<ProxyConfig, String> {
+    match config_path {
+        Some(path) => {
+            let content = std::fs::read_to_string(path)
+                .map_err(|e| format!("读取配置文件失败 ({}): {}", path.display(), e))?;
+            parse_proxy_config(&content)
+        }
+        None => Ok(modules::config::load_app_config()?.proxy),
+    }
+}
+
+/// 等待退出信号 (Ctrl+C / SIGTERM)
+async fn wait_for_shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("监听 Ctrl+C 失败: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sig) => {
+                sig.recv().await;
+            }
+            Err(e) => {
+                error!("监听 SIGTERM 失败: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => info!("收到 Ctrl+C，正在停止反代服务..."),
+        _ = terminate => info!("收到 SIGTERM，正在停止反代服务..."),
+    }
+}
+
+/// 调度器同步回调：刷新配额并重新加载反代账号池
+async fn sync_quotas(token_manager: Arc<TokenManager>) {
+    if let Err(e) = modules::account::refresh_all_quotas_logic().await {
+        warn!("[Headless] 刷新配额失败: {}", e);
+    }
+    if let Err(e) = token_manager.reload_all_accounts().await {
+        warn!("[Headless] 重新加载账号失败: {}", e);
+    }
+
+    if let Ok(config) = modules::config::load_app_config() {
+        if config.scheduled_warmup.enabled {
+            if let Ok(accounts) = modules::account::list_accounts() {
+                for acc in accounts {
+                    modules::scheduler::trigger_warmup_for_account(&acc).await;
+                }
+            }
+        }
+    }
+}
+
+/// 启动 Headless 反代服务并阻塞至收到退出信号
+pub async fn serve(config_path: Option<PathBuf>) -> Result<(), String> {
+    let config = load_proxy_config(config_path.as_deref())?;
+
+    let monitor = Arc::new(ProxyMonitor::new(1000, None));
+    monitor.set_enabled(config.enable_logging);
+
+    // 初始化 Token 管理器
+    let app_data_dir = modules::account::get_data_dir()?;
+    let _ = modules::account::get_accounts_dir()?;
+    let token_manager = Arc::new(TokenManager::new(app_data_dir));
+    token_manager.update_sticky_config(config.scheduling.clone()).await;
+
+    let active_accounts = token_manager
+        .load_accounts()
+        .await
+        .map_err(|e| format!("加载账号失败: {}", e))?;
+
+    // 仅配置了上游服务商 (z.ai / 自定义) 时允许无账号启动
+    if active_accounts == 0 && !config.has_active_provider() {
+        return Err("没有可用账号，请先添加账号".to_string());
+    }
+
+    crate::proxy::common::model_registry::ModelRegistry::global().reload(&config.model_capabilities);
+    // v1internal 端点顺序与熔断参数
+    monitor.endpoints.configure(&config.upstream_endpoints);
+    // access_token 后台刷新参数
+    monitor.token_refresh.configure(&config.token_refresh);
+
+    let (axum_server, server_handle) = AxumServer::start(
+        config.get_bind_address().to_string(),
+        config.port,
+        token_manager.clone(),
+        crate::proxy::common::model_router::ModelRouter::from_config(&config),
+        config.request_timeout,
+        config.upstream_proxy.clone(),
+        ProxySecurityConfig::from_proxy_config(&config),
+        config.zai.clone(),
+        config.providers.clone(),
+        monitor.clone(),
+        config.experimental.clone(),
+    )
+    .await
+    .map_err(|e| format!("启动 Axum 服务器失败: {}", e))?;
+
+    info!(
+        "[Headless] 反代服务已启动: {}:{} (账号数: {})",
+        config.get_bind_address(),
+        config.port,
+        active_accounts
+    );
+
+    // 启动智能预热调度器
+    let scheduler_token_manager = token_manager.clone();
+    let scheduler = tokio::spawn(modules::scheduler::run_scheduler(move || {
+        sync_quotas(scheduler_token_manager.clone())
+    }));
+
+    wait_for_shutdown_signal().await;
+
+    scheduler.abort();
+    axum_server.stop();
+    let _ = server_handle.await;
+
+    info!("[Headless] 反代服务已停止");
+    Ok(())
+}
+
+/// Headless 二进制入口
+pub fn run() {
+    logger::init_logger();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let config_path = match parse_args(&args) {
+        Ok(HeadlessCommand::Serve { config_path }) => config_path,
+        Ok(HeadlessCommand::Help) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to build tokio runtime");
+
+    let result = runtime.block_on(serve(config_path));
+    // 给仍在处理中的连接留出收尾时间
+    runtime.shutdown_timeout(Duration::from_secs(5));
+
+    if let Err(e) = result {
+        error!("反代服务运行失败: {}", e);
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(parse_args(&args(&[])).unwrap(), HeadlessCommand::Help);
+        assert_eq!(
+            parse_args(&args(&["serve"])).unwrap(),
+            HeadlessCommand::Serve { config_path: None }
+        );
+        assert_eq!(
+            parse_args(&args(&["serve", "--config", "proxy.json"])).unwrap(),
+            HeadlessCommand::Serve { config_path: Some(PathBuf::from("proxy.json")) }
+        );
+        assert_eq!(
+            parse_args(&args(&["serve", "--config=/etc/proxy.json"])).unwrap(),
+            HeadlessCommand::Serve { config_path: Some(PathBuf::from("/etc/proxy.json")) }
+        );
+        assert!(parse_args(&args(&["serve", "--config"])).is_err());
+        assert!(parse_args(&args(&["start"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_partial_proxy_config() {
+        let config = parse_proxy_config(r#"{"port": 9000, "api_key": "sk-test"}"#).unwrap();
+        assert_eq!(config.port, 9000);
+        assert_eq!(config.api_key, "sk-test");
+        assert_eq!(config.request_timeout, ProxyConfig::default().request_timeout);
+    }
+
+    #[test]
+    fn test_parse_app_config_proxy_section() {
+        let config = parse_proxy_config(r#"{"language": "en", "proxy": {"port": 9100}}"#).unwrap();
+        assert_eq!(config.port, 9100);
+    }
+}
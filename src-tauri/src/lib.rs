@@ -4,6 +4,7 @@ mod commands;
 mod utils;
 mod proxy;  // 反代服务模块
 pub mod error;
+pub mod headless;  // Headless 反代服务入口 (无桌面壳)
 
 use tauri::Manager;
 use modules::logger;
@@ -134,6 +135,9 @@ pub fn run() {
             commands::proxy::stop_proxy_service,
             commands::proxy::get_proxy_status,
             commands::proxy::get_proxy_stats,
+            commands::proxy::get_upstream_endpoint_health,
+            commands::proxy::get_token_refresh_health,
+            commands::proxy::get_account_in_flight,
             commands::proxy::get_proxy_logs,
             commands::proxy::get_proxy_logs_paginated,
             commands::proxy::get_proxy_log_detail,
@@ -146,6 +150,10 @@ pub fn run() {
             commands::proxy::get_proxy_scheduling_config,
             commands::proxy::update_proxy_scheduling_config,
             commands::proxy::clear_proxy_session_bindings,
+            commands::proxy::list_proxy_sessions,
+            commands::proxy::rebind_proxy_session,
+            commands::proxy::evict_proxy_session,
+            commands::proxy::get_proxy_key_usage,
             // Autostart 命令
             commands::autostart::toggle_auto_launch,
             commands::autostart::is_auto_launch_enabled,
@@ -156,6 +164,16 @@ pub fn run() {
         .build(tauri::generate_context!())
         .expect("error while building tauri application")
         .run(|app_handle, event| {
+            // 退出前同步写入尚未落盘的会话绑定 (进程退出时不会执行析构)
+            if let tauri::RunEvent::Exit = event {
+                let state = app_handle.state::<commands::proxy::ProxyServiceState>();
+                if let Ok(instance) = state.instance.try_read() {
+                    if let Some(instance) = instance.as_ref() {
+                        instance.token_manager.flush_sessions();
+                    }
+                };
+            }
+
             // Handle macOS dock icon click to reopen window
             #[cfg(target_os = "macos")]
             if let tauri::RunEvent::Reopen { .. } = event {
@@ -154,6 +154,11 @@ pub async fn refresh_access_token(refresh_token: &str) -> Result<TokenResponse,
     }
 }
 
+/// 刷新失败是否因 refresh_token 已被撤销 / 过期 (需重新授权，重试无意义)
+pub fn is_invalid_grant(error: &str) -> bool {
+    error.contains("invalid_grant")
+}
+
 /// 获取用户信息
 pub async fn get_user_info(access_token: &str) -> Result<UserInfo, String> {
     let client = crate::utils::http::create_client(15);
@@ -32,6 +32,8 @@ pub fn init_db() -> Result<(), String> {
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN output_tokens INTEGER", []);
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN account_email TEXT", []);
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN mapped_model TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN api_key_name TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN fallback_from TEXT", []);
 
     conn.execute(
         "CREATE INDEX IF NOT EXISTS idx_timestamp ON request_logs (timestamp DESC)",
@@ -44,16 +46,86 @@ pub fn init_db() -> Result<(), String> {
         [],
     ).map_err(|e| e.to_string())?;
 
+    // 客户端密钥用量 (按天聚合，月用量由当月各天求和)
+    // 独立于 request_logs，不受日志清理 / 监控开关影响
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS api_key_usage (
+            key_name TEXT NOT NULL,
+            day TEXT NOT NULL,
+            input_tokens INTEGER NOT NULL DEFAULT 0,
+            output_tokens INTEGER NOT NULL DEFAULT 0,
+            request_count INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (key_name, day)
+        )",
+        [],
+    ).map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// 累加客户端密钥在某一天 (YYYY-MM-DD) 的用量
+pub fn record_key_usage(key_name: &str, day: &str, input_tokens: u64, output_tokens: u64) -> Result<(), String> {
+    let db_path = get_proxy_db_path()?;
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+
+    conn.execute(
+        "INSERT INTO api_key_usage (key_name, day, input_tokens, output_tokens, request_count)
+         VALUES (?1, ?2, ?3, ?4, 1)
+         ON CONFLICT(key_name, day) DO UPDATE SET
+            input_tokens = input_tokens + excluded.input_tokens,
+            output_tokens = output_tokens + excluded.output_tokens,
+            request_count = request_count + 1",
+        params![key_name, day, input_tokens as i64, output_tokens as i64],
+    ).map_err(|e| e.to_string())?;
+
     Ok(())
 }
 
+/// 查询密钥在指定日期前缀下的用量合计 (input_tokens, output_tokens, request_count)
+///
+/// `day_prefix` 为 `YYYY-MM-DD` 时返回当日用量，为 `YYYY-MM` 时返回当月用量
+pub fn get_key_usage(key_name: &str, day_prefix: &str) -> Result<(u64, u64, u64), String> {
+    let db_path = get_proxy_db_path()?;
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+
+    conn.query_row(
+        "SELECT COALESCE(SUM(input_tokens), 0), COALESCE(SUM(output_tokens), 0), COALESCE(SUM(request_count), 0)
+         FROM api_key_usage
+         WHERE key_name = ?1 AND day LIKE ?2 || '%'",
+        params![key_name, day_prefix],
+        |row| {
+            let input: i64 = row.get(0)?;
+            let output: i64 = row.get(1)?;
+            let count: i64 = row.get(2)?;
+            Ok((input as u64, output as u64, count as u64))
+        },
+    ).map_err(|e| e.to_string())
+}
+
+/// 列出在指定日期前缀下有用量记录的所有密钥名称
+pub fn list_usage_key_names(day_prefix: &str) -> Result<Vec<String>, String> {
+    let db_path = get_proxy_db_path()?;
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT key_name FROM api_key_usage WHERE day LIKE ?1 || '%' ORDER BY key_name"
+    ).map_err(|e| e.to_string())?;
+
+    let names = stmt
+        .query_map([day_prefix], |row| row.get::<_, String>(0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(names)
+}
+
 pub fn save_log(log: &ProxyRequestLog) -> Result<(), String> {
     let db_path = get_proxy_db_path()?;
     let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
 
     conn.execute(
-        "INSERT INTO request_logs (id, timestamp, method, url, status, duration, model, error, request_body, response_body, input_tokens, output_tokens, account_email, mapped_model)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        "INSERT INTO request_logs (id, timestamp, method, url, status, duration, model, error, request_body, response_body, input_tokens, output_tokens, account_email, mapped_model, api_key_name, fallback_from)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
         params![
             log.id,
             log.timestamp,
@@ -69,6 +141,8 @@ pub fn save_log(log: &ProxyRequestLog) -> Result<(), String> {
             log.output_tokens,
             log.account_email,
             log.mapped_model,
+            log.api_key_name,
+            log.fallback_from,
         ],
     ).map_err(|e| e.to_string())?;
 
@@ -83,7 +157,7 @@ pub fn get_logs_summary(limit: usize, offset: usize) -> Result<Vec<ProxyRequestL
     let mut stmt = conn.prepare(
         "SELECT id, timestamp, method, url, status, duration, model, error, 
                 NULL as request_body, NULL as response_body,
-                input_tokens, output_tokens, account_email, mapped_model
+                input_tokens, output_tokens, account_email, mapped_model, api_key_name, fallback_from
          FROM request_logs 
          ORDER BY timestamp DESC 
          LIMIT ?1 OFFSET ?2"
@@ -100,6 +174,8 @@ pub fn get_logs_summary(limit: usize, offset: usize) -> Result<Vec<ProxyRequestL
             model: row.get(6)?,
             mapped_model: row.get(13).unwrap_or(None),
             account_email: row.get(12).unwrap_or(None),
+            api_key_name: row.get(14).unwrap_or(None),
+            fallback_from: row.get(15).unwrap_or(None),
             error: row.get(7)?,
             request_body: None,  // Don't query large fields for list view
             response_body: None, // Don't query large fields for list view
@@ -150,7 +226,7 @@ pub fn get_log_detail(log_id: &str) -> Result<ProxyRequestLog, String> {
     let mut stmt = conn.prepare(
         "SELECT id, timestamp, method, url, status, duration, model, error, 
                 request_body, response_body, input_tokens, output_tokens, 
-                account_email, mapped_model
+                account_email, mapped_model, api_key_name, fallback_from
          FROM request_logs 
          WHERE id = ?1"
     ).map_err(|e| e.to_string())?;
@@ -166,6 +242,8 @@ pub fn get_log_detail(log_id: &str) -> Result<ProxyRequestLog, String> {
             model: row.get(6)?,
             mapped_model: row.get(13).unwrap_or(None),
             account_email: row.get(12).unwrap_or(None),
+            api_key_name: row.get(14).unwrap_or(None),
+            fallback_from: row.get(15).unwrap_or(None),
             error: row.get(7)?,
             request_body: row.get(8).unwrap_or(None),
             response_body: row.get(9).unwrap_or(None),
@@ -0,0 +1,185 @@
+// OpenAI Responses API 会话存储
+// 每条记录保存本轮输入项与完整的 response 对象，previous_response_id 串联成会话链
+// owner 记录创建该 response 的 API Key 名称 (未使用客户端 Key 时为 NULL)，读取 / 删除 / 续接仅限同一 owner
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::Value;
+use once_cell::sync::OnceCell;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// 沿 previous_response_id 回溯的最大深度 (防止环形引用)
+const MAX_CHAIN_DEPTH: usize = 1000;
+
+/// response 保留天数
+pub const RETENTION_DAYS: i64 = 30;
+
+/// 过期 response 的清理间隔
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+pub fn get_response_db_path() -> Result<PathBuf, String> {
+    let data_dir = crate::modules::account::get_data_dir()?;
+    Ok(data_dir.join("responses.db"))
+}
+
+/// 建表与列迁移在进程内只执行一次
+static SCHEMA_READY: OnceCell<()> = OnceCell::new();
+
+fn connect() -> Result<Connection, String> {
+    let db_path = get_response_db_path()?;
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    SCHEMA_READY.get_or_try_init(|| init_schema(&conn))?;
+    Ok(conn)
+}
+
+fn init_schema(conn: &Connection) -> Result<(), String> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS responses (
+            id TEXT PRIMARY KEY,
+            created_at INTEGER NOT NULL,
+            previous_response_id TEXT,
+            model TEXT,
+            input_items TEXT NOT NULL,
+            response TEXT NOT NULL
+        )",
+        [],
+    ).map_err(|e| e.to_string())?;
+
+    // Try to add new columns (ignore errors if they exist)
+    let _ = conn.execute("ALTER TABLE responses ADD COLUMN owner TEXT", []);
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_responses_created_at ON responses (created_at)",
+        [],
+    ).map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// 保存一次 response (同 id 重复写入时覆盖)
+pub fn save_response(owner: Option<&str>, input_items: &[Value], response: &Value) -> Result<(), String> {
+    let id = response
+        .get("id")
+        .and_then(|v| v.as_str())
+        .ok_or("response has no id")?;
+    let conn = connect()?;
+
+    conn.execute(
+        "INSERT OR REPLACE INTO responses (id, created_at, previous_response_id, model, input_items, response, owner)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            id,
+            response.get("created_at").and_then(|v| v.as_i64()).unwrap_or_else(|| chrono::Utc::now().timestamp()),
+            response.get("previous_response_id").and_then(|v| v.as_str()),
+            response.get("model").and_then(|v| v.as_str()),
+            serde_json::to_string(input_items).map_err(|e| e.to_string())?,
+            serde_json::to_string(response).map_err(|e| e.to_string())?,
+            owner,
+        ],
+    ).map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+fn load_row(conn: &Connection, owner: Option<&str>, id: &str) -> Result<Option<(Vec<Value>, Value)>, String> {
+    let row: Option<(String, String)> = conn
+        .query_row(
+            "SELECT input_items, response FROM responses WHERE id = ?1 AND owner IS ?2",
+            params![id, owner],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+
+    match row {
+        Some((input, response)) => {
+            let input: Vec<Value> = serde_json::from_str(&input).map_err(|e| e.to_string())?;
+            let response: Value = serde_json::from_str(&response).map_err(|e| e.to_string())?;
+            Ok(Some((input, response)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// 读取已存储的 response 对象
+pub fn get_response(owner: Option<&str>, id: &str) -> Result<Option<Value>, String> {
+    let conn = connect()?;
+    Ok(load_row(&conn, owner, id)?.map(|(_, response)| response))
+}
+
+/// 读取 response 对应的本轮输入项
+pub fn get_input_items(owner: Option<&str>, id: &str) -> Result<Option<Vec<Value>>, String> {
+    let conn = connect()?;
+    Ok(load_row(&conn, owner, id)?.map(|(input, _)| input))
+}
+
+/// 删除 response，返回是否存在
+pub fn delete_response(owner: Option<&str>, id: &str) -> Result<bool, String> {
+    let conn = connect()?;
+    let deleted = conn
+        .execute("DELETE FROM responses WHERE id = ?1 AND owner IS ?2", params![id, owner])
+        .map_err(|e| e.to_string())?;
+    Ok(deleted > 0)
+}
+
+/// 还原截至 `id` (含) 的完整会话: 按时间顺序依次为每轮的输入项与输出项
+///
+/// 链中任一 response 不存在 (或不属于该 owner) 时返回 `None`
+pub fn load_conversation(owner: Option<&str>, id: &str) -> Result<Option<Vec<Value>>, String> {
+    let conn = connect()?;
+    let mut turns: Vec<(Vec<Value>, Vec<Value>)> = Vec::new();
+    let mut cursor = Some(id.to_string());
+
+    while let Some(current) = cursor.take() {
+        if turns.len() >= MAX_CHAIN_DEPTH {
+            return Err(format!("response chain deeper than {} turns", MAX_CHAIN_DEPTH));
+        }
+        let Some((input, response)) = load_row(&conn, owner, &current)? else {
+            return Ok(None);
+        };
+        let output = response
+            .get("output")
+            .and_then(|v| v.as_array())
+            .cloned()
+            .unwrap_or_default();
+        cursor = response
+            .get("previous_response_id")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
+        turns.push((input, output));
+    }
+
+    Ok(Some(
+        turns
+            .into_iter()
+            .rev()
+            .flat_map(|(input, output)| input.into_iter().chain(output))
+            .collect(),
+    ))
+}
+
+/// 清理超过 `days` 天的 response
+pub fn cleanup_old_responses(days: i64) -> Result<usize, String> {
+    let conn = connect()?;
+    let cutoff_timestamp = chrono::Utc::now().timestamp() - (days * 24 * 3600);
+    conn.execute("DELETE FROM responses WHERE created_at < ?1", [cutoff_timestamp])
+        .map_err(|e| e.to_string())
+}
+
+/// 定期清理超过保留期的 response (首次在启动时执行)
+pub fn spawn_cleanup_task() -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async {
+        let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+        loop {
+            interval.tick().await;
+            match tokio::task::spawn_blocking(|| cleanup_old_responses(RETENTION_DAYS)).await {
+                Ok(Ok(deleted)) if deleted > 0 => {
+                    tracing::info!("Auto cleanup: removed {} stored responses (>{} days)", deleted, RETENTION_DAYS);
+                }
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => tracing::warn!("Failed to cleanup stored responses: {}", e),
+                Err(e) => tracing::warn!("Stored response cleanup task failed: {}", e),
+            }
+        }
+    })
+}
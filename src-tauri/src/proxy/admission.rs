@@ -0,0 +1,154 @@
+// 准入队列
+// 账号池中没有可用账号 (全部限流 / 并发已满) 时请求在此排队，而不是立即失败
+// 按 API Key 优先级放行，同优先级先到先得 (FIFO)；只有队首请求会检查账号池
+// 放行后凭证保留在队首，直到处理器取得账号租约，避免放行与占用槽位之间被其他请求抢占
+
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use tokio::sync::futures::Notified;
+use tokio::sync::Notify;
+
+/// 排队顺序: 优先级降序，其次入队序号升序
+type QueueKey = (Reverse<i32>, u64);
+
+/// 排队中的请求状态 (推送给流式客户端)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct QueueStatus {
+    /// 当前位置 (1 表示队首)
+    pub position: usize,
+    /// 队列中的请求总数
+    pub depth: usize,
+    /// 最早的限流重置时间 (秒)，None 表示等待并发槽位释放
+    pub retry_in_secs: Option<u64>,
+}
+
+tokio::task_local! {
+    /// 已放行、尚未取得账号租约的排队凭证
+    static ADMITTED: RefCell<Option<QueueTicket>>;
+}
+
+/// 持有已放行的凭证运行处理器，处理器取得租约 (`release_admitted`) 或结束时出队
+pub async fn hold_until_leased<F: Future>(ticket: QueueTicket, handler: F) -> F::Output {
+    ADMITTED.scope(RefCell::new(Some(ticket)), handler).await
+}
+
+/// 当前请求已取得账号租约，释放其排队凭证 (非排队请求无操作)
+pub fn release_admitted() {
+    let _ = ADMITTED.try_with(|ticket| ticket.borrow_mut().take());
+}
+
+#[derive(Default)]
+pub struct AdmissionQueue {
+    waiters: Mutex<BTreeSet<QueueKey>>,
+    next_seq: AtomicU64,
+    /// 出队、租约释放时唤醒所有等待者重新检查
+    notify: Notify,
+}
+
+/// 排队凭证，Drop 时出队并唤醒后续请求
+pub struct QueueTicket {
+    queue: Arc<AdmissionQueue>,
+    key: QueueKey,
+}
+
+impl Drop for QueueTicket {
+    fn drop(&mut self) {
+        self.queue.lock().remove(&self.key);
+        self.queue.notify_all();
+    }
+}
+
+impl AdmissionQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeSet<QueueKey>> {
+        self.waiters.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 入队，`max_depth` 为 0 表示不限制队列长度
+    pub fn enqueue(self: &Arc<Self>, priority: i32, max_depth: usize) -> Result<QueueTicket, String> {
+        let mut waiters = self.lock();
+        if max_depth > 0 && waiters.len() >= max_depth {
+            return Err(format!("Request queue is full ({} waiting)", waiters.len()));
+        }
+        let key = (Reverse(priority), self.next_seq.fetch_add(1, Ordering::Relaxed));
+        waiters.insert(key);
+        Ok(QueueTicket {
+            queue: self.clone(),
+            key,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// 排在该请求之前的请求数 (0 表示位于队首)
+    pub fn ahead_of(&self, ticket: &QueueTicket) -> usize {
+        self.lock().range(..ticket.key).count()
+    }
+
+    /// 唤醒所有等待者 (账号释放 / 队列变化)
+    pub fn notify_all(&self) {
+        self.notify.notify_waiters();
+    }
+
+    /// 等待下一次唤醒 (需在检查条件之前创建，避免错过通知)
+    pub fn notified(&self) -> Notified<'_> {
+        self.notify.notified()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_priority_then_fifo_order() {
+        let queue = Arc::new(AdmissionQueue::new());
+        let low = queue.enqueue(0, 3).unwrap();
+        let low2 = queue.enqueue(0, 3).unwrap();
+        let high = queue.enqueue(10, 3).unwrap();
+        assert!(queue.enqueue(5, 3).is_err());
+
+        assert_eq!(queue.ahead_of(&high), 0);
+        assert_eq!(queue.ahead_of(&low), 1);
+        assert_eq!(queue.ahead_of(&low2), 2);
+
+        // 出队后后续请求前移
+        drop(high);
+        assert_eq!(queue.ahead_of(&low), 0);
+        assert_eq!(queue.ahead_of(&low2), 1);
+        assert_eq!(queue.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_admitted_ticket_released_on_lease() {
+        let queue = Arc::new(AdmissionQueue::new());
+        let ticket = queue.enqueue(0, 0).unwrap();
+        let waiting = queue.enqueue(0, 0).unwrap();
+
+        hold_until_leased(ticket, async {
+            // 取得租约前仍占据队首
+            assert_eq!(queue.ahead_of(&waiting), 1);
+            release_admitted();
+            assert_eq!(queue.ahead_of(&waiting), 0);
+        })
+        .await;
+        // 作用域外调用无影响
+        release_admitted();
+        assert_eq!(queue.len(), 1);
+    }
+}
@@ -3,5 +3,11 @@
 // pub mod error;
 // pub mod rate_limiter;
 pub mod model_mapping;
+pub mod model_router; // 有序模型路由规则
+pub mod model_fallback; // 模型回退链
+pub mod model_registry; // 模型能力注册表
 pub mod utils;
 pub mod json_schema;
+pub mod protocol_error;
+pub mod token_counter;
+pub mod schema_validator; // 结构化输出校验
@@ -0,0 +1,124 @@
+// 模型回退链
+// 映射后的模型在整个账号池中都处于配额 / 容量耗尽锁定时，按配置的回退链切换到仍可用的模型
+
+use axum::{http::HeaderValue, response::Response};
+use tokio::sync::RwLock;
+
+use super::model_mapping::resolve_model_route;
+use super::model_router::{ModelRouter, RouteContext};
+use crate::proxy::TokenManager;
+
+/// 记录发生回退时原始模型的响应头 (监控日志中的 fallback_from 字段读取此头)
+pub const FALLBACK_HEADER: &str = "X-Model-Fallback-From";
+
+/// 模型选择结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelSelection {
+    /// 实际使用的模型
+    pub model: String,
+    /// 发生回退时为原始映射模型
+    pub fallback_from: Option<String>,
+    /// 原始映射模型是否配置了回退链 (配额耗尽时继续尝试而非直接返回)
+    pub has_fallback: bool,
+}
+
+/// 依次检查主模型与回退链，返回首个仍有可用账号的模型
+///
+/// 全部不可用时保留主模型，由正常的错误路径返回限流信息
+pub fn select_available_model(primary: &str, chain: &[String], is_available: impl Fn(&str) -> bool) -> ModelSelection {
+    let unchanged = ModelSelection {
+        model: primary.to_string(),
+        fallback_from: None,
+        has_fallback: !chain.is_empty(),
+    };
+    if chain.is_empty() || is_available(primary) {
+        return unchanged;
+    }
+
+    match chain.iter().find(|m| m.as_str() != primary && is_available(m)) {
+        Some(fallback) => ModelSelection {
+            model: fallback.clone(),
+            fallback_from: Some(primary.to_string()),
+            has_fallback: true,
+        },
+        None => unchanged,
+    }
+}
+
+/// 根据 router 中配置的回退链与 TokenManager 的模型级别限流选择模型
+pub fn select_model(router: &ModelRouter, token_manager: &TokenManager, primary: &str) -> ModelSelection {
+    let selection = select_available_model(primary, router.fallback_chain(primary), |m| {
+        token_manager.is_model_available(m)
+    });
+    if let Some(from) = &selection.fallback_from {
+        tracing::warn!(
+            "[Fallback] 模型 {} 在所有账号上均已限流，回退到 {}",
+            from,
+            selection.model
+        );
+    }
+    selection
+}
+
+/// 解析路由后按回退链选择本次尝试使用的模型
+pub async fn resolve_with_fallback(
+    router: &RwLock<ModelRouter>,
+    token_manager: &TokenManager,
+    original: &str,
+    ctx: &RouteContext,
+) -> ModelSelection {
+    let router = router.read().await;
+    let primary = resolve_model_route(original, &router, ctx);
+    select_model(&router, token_manager, &primary)
+}
+
+/// 回退链额外占用的重试次数 (链上每个模型一次)
+pub async fn fallback_budget(router: &RwLock<ModelRouter>, original: &str, ctx: &RouteContext) -> usize {
+    let router = router.read().await;
+    let primary = resolve_model_route(original, &router, ctx);
+    router.fallback_chain(&primary).len()
+}
+
+/// 发生回退时在响应头中记录原始模型
+pub fn annotate_response(mut response: Response, selection: &ModelSelection) -> Response {
+    if let Some(from) = &selection.fallback_from {
+        if let Ok(value) = HeaderValue::from_str(from) {
+            response.headers_mut().insert(FALLBACK_HEADER, value);
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_available_model() {
+        let chain = vec![
+            "claude-sonnet-4-5-thinking".to_string(),
+            "gemini-3-pro-high".to_string(),
+        ];
+        let locked = ["claude-opus-4-5-thinking", "claude-sonnet-4-5-thinking"];
+        let available = |m: &str| !locked.contains(&m);
+
+        let selection = select_available_model("claude-opus-4-5-thinking", &chain, available);
+        assert_eq!(selection.model, "gemini-3-pro-high");
+        assert_eq!(selection.fallback_from.as_deref(), Some("claude-opus-4-5-thinking"));
+        assert!(selection.has_fallback);
+
+        // 主模型可用时不回退
+        let selection = select_available_model("gemini-3-flash", &chain, available);
+        assert_eq!(selection.model, "gemini-3-flash");
+        assert!(selection.fallback_from.is_none());
+
+        // 整条链都不可用时保留主模型
+        let selection = select_available_model("claude-opus-4-5-thinking", &chain, |_| false);
+        assert_eq!(selection.model, "claude-opus-4-5-thinking");
+        assert!(selection.fallback_from.is_none());
+
+        // 未配置回退链
+        let selection = select_available_model("claude-opus-4-5-thinking", &[], |_| false);
+        assert!(!selection.has_fallback);
+    }
+}
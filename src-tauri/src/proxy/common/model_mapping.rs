@@ -2,6 +2,14 @@
 use std::collections::HashMap;
 use once_cell::sync::Lazy;
 
+use super::model_registry::ModelRegistry;
+use super::model_router::{ModelRouter, RouteContext};
+use crate::proxy::config::ModelCapabilities;
+use crate::proxy::model_catalog::{ModelCatalog, PoolModel};
+
+/// 图像生成模型基础名
+const IMAGE_BASE_MODEL: &str = "gemini-3-pro-image";
+
 static CLAUDE_TO_GEMINI: Lazy<HashMap<&'static str, &'static str>> = Lazy::new(|| {
     let mut m = HashMap::new();
 
@@ -77,8 +85,13 @@ pub fn get_supported_models() -> Vec<String> {
 
 /// 动态获取所有可用模型列表 (包含内置与用户自定义)
 pub async fn get_all_dynamic_models(
-    custom_mapping: &tokio::sync::RwLock<std::collections::HashMap<String, String>>,
+    model_router: &tokio::sync::RwLock<ModelRouter>,
 ) -> Vec<String> {
+    static_model_ids(&*model_router.read().await)
+}
+
+/// 内置映射、自定义映射、能力注册表与常用模型的静态列表 (已排序)
+fn static_model_ids(router: &ModelRouter) -> Vec<String> {
     use std::collections::HashSet;
     let mut model_ids = HashSet::new();
 
@@ -87,30 +100,21 @@ pub async fn get_all_dynamic_models(
         model_ids.insert(m);
     }
 
-    // 2. 获取所有自定义映射模型 (Custom)
-    {
-        let mapping = custom_mapping.read().await;
-        for key in mapping.keys() {
-            model_ids.insert(key.clone());
-        }
+    // 2. 获取所有自定义映射模型 (精确路由规则 + Custom)
+    for key in router.listed_models() {
+        model_ids.insert(key);
+    }
+
+    // 3. 能力注册表中登记的模型 (含配置新增)
+    for key in ModelRegistry::global().listed_models() {
+        model_ids.insert(key);
     }
 
     // 5. 确保包含常用的 Gemini/画画模型 ID
     model_ids.insert("gemini-3-pro-low".to_string());
     
     // [NEW] Issue #247: Dynamically generate all Image Gen Combinations
-    let base = "gemini-3-pro-image";
-    let resolutions = vec!["", "-2k", "-4k"];
-    let ratios = vec!["", "-1x1", "-4x3", "-3x4", "-16x9", "-9x16", "-21x9"];
-    
-    for res in resolutions {
-        for ratio in ratios.iter() {
-            let mut id = base.to_string();
-            id.push_str(res);
-            id.push_str(ratio);
-            model_ids.insert(id);
-        }
-    }
+    model_ids.extend(image_model_variants());
 
     model_ids.insert("gemini-2.0-flash-exp".to_string());
     model_ids.insert("gemini-2.5-flash".to_string());
@@ -125,6 +129,78 @@ pub async fn get_all_dynamic_models(
     sorted_ids
 }
 
+/// 图像生成模型的分辨率 / 比例组合
+fn image_model_variants() -> Vec<String> {
+    let resolutions = ["", "-2k", "-4k"];
+    let ratios = ["", "-1x1", "-4x3", "-3x4", "-16x9", "-9x16", "-21x9"];
+    resolutions
+        .iter()
+        .flat_map(|res| ratios.iter().map(move |ratio| format!("{}{}{}", IMAGE_BASE_MODEL, res, ratio)))
+        .collect()
+}
+
+/// 模型列表条目
+pub struct ListedModel {
+    pub id: String,
+    pub capabilities: Option<ModelCapabilities>,
+    /// 账号池中的可用情况 (别名取其路由目标)
+    pub quota: Option<PoolModel>,
+}
+
+/// 获取所有可用模型及其能力与配额
+///
+/// 已从账号拉取到模型目录时，仅列出账号池可服务的模型: 目录中的模型，以及路由目标可服务的
+/// 内置别名、自定义映射与常用模型；否则回退到内置 + 自定义的静态列表
+pub async fn get_all_models_with_capabilities(
+    model_router: &tokio::sync::RwLock<ModelRouter>,
+    catalog: &ModelCatalog,
+) -> Vec<ListedModel> {
+    let pool: HashMap<String, PoolModel> = catalog
+        .pool_models()
+        .into_iter()
+        .map(|m| (m.id.clone(), m))
+        .collect();
+
+    let model_ids = if pool.is_empty() {
+        get_all_dynamic_models(model_router).await
+    } else {
+        pool_model_ids(&pool, &*model_router.read().await)
+    };
+
+    let router = model_router.read().await;
+    let registry = ModelRegistry::global();
+    model_ids
+        .into_iter()
+        .map(|id| {
+            let target = listed_model_target(&router, &id);
+            let capabilities = registry.get(&id).or_else(|| registry.get(&target));
+            let quota = pool.get(&id).or_else(|| pool.get(&target)).cloned();
+            ListedModel { id, capabilities, quota }
+        })
+        .collect()
+}
+
+/// 列表中的模型按默认上下文解析到的目标模型 (不输出路由日志)
+fn listed_model_target(router: &ModelRouter, id: &str) -> String {
+    router
+        .route(id, &RouteContext::default())
+        .map(|m| m.target)
+        .unwrap_or_else(|| map_claude_model_to_gemini(id))
+}
+
+fn pool_model_ids(pool: &HashMap<String, PoolModel>, router: &ModelRouter) -> Vec<String> {
+    let mut ids: std::collections::BTreeSet<String> = pool.keys().cloned().collect();
+    for alias in static_model_ids(router) {
+        if pool.contains_key(&alias) || pool.contains_key(&listed_model_target(router, &alias)) {
+            ids.insert(alias);
+        }
+    }
+    if pool.contains_key(IMAGE_BASE_MODEL) {
+        ids.extend(image_model_variants());
+    }
+    ids.into_iter().collect()
+}
+
 /// 通配符匹配辅助函数
 /// 支持简单的 * 通配符匹配
 /// 
@@ -132,7 +208,7 @@ pub async fn get_all_dynamic_models(
 /// - `gpt-4*` 匹配 `gpt-4`, `gpt-4-turbo`, `gpt-4-0613` 等
 /// - `claude-3-5-sonnet-*` 匹配所有 3.5 sonnet 版本
 /// - `*-thinking` 匹配所有以 `-thinking` 结尾的模型
-fn wildcard_match(pattern: &str, text: &str) -> bool {
+pub(crate) fn wildcard_match(pattern: &str, text: &str) -> bool {
     if let Some(star_pos) = pattern.find('*') {
         let prefix = &pattern[..star_pos];
         let suffix = &pattern[star_pos + 1..];
@@ -143,33 +219,27 @@ fn wildcard_match(pattern: &str, text: &str) -> bool {
 }
 
 /// 核心模型路由解析引擎
-/// 优先级：精确匹配 > 通配符匹配 > 系统默认映射
+/// 优先级：有序路由规则 > custom_mapping (精确 > 通配符) > 系统默认映射
 /// 
 /// # 参数
 /// - `original_model`: 原始模型名称
-/// - `custom_mapping`: 用户自定义映射表
+/// - `router`: 编译后的路由表
+/// - `ctx`: 请求上下文 (协议 / 密钥 / 特征)，用于规则条件判断
 /// 
 /// # 返回
 /// 映射后的目标模型名称
 pub fn resolve_model_route(
     original_model: &str,
-    custom_mapping: &std::collections::HashMap<String, String>,
+    router: &ModelRouter,
+    ctx: &RouteContext,
 ) -> String {
-    // 1. 精确匹配 (最高优先级)
-    if let Some(target) = custom_mapping.get(original_model) {
-        crate::modules::logger::log_info(&format!("[Router] 精确映射: {} -> {}", original_model, target));
-        return target.clone();
-    }
-    
-    // 2. 通配符匹配
-    for (pattern, target) in custom_mapping.iter() {
-        if pattern.contains('*') && wildcard_match(pattern, original_model) {
-            crate::modules::logger::log_info(&format!("[Router] 通配符映射: {} -> {} (规则: {})", original_model, target, pattern));
-            return target.clone();
-        }
+    // 1. 有序路由规则 (model_routing_rules 优先，其次 custom_mapping)
+    if let Some(matched) = router.route(original_model, ctx) {
+        crate::modules::logger::log_info(&format!("[Router] 规则映射: {} -> {} ({})", original_model, matched.target, matched.rule));
+        return matched.target;
     }
     
-    // 3. 系统默认映射
+    // 2. 系统默认映射
     let result = map_claude_model_to_gemini(original_model);
     if result != original_model {
         crate::modules::logger::log_info(&format!("[Router] 系统默认映射: {} -> {}", original_model, result));
@@ -177,6 +247,32 @@ pub fn resolve_model_route(
     result
 }
 
+/// 默认 Embedding 模型
+pub const DEFAULT_EMBEDDING_MODEL: &str = "gemini-embedding-001";
+
+/// 解析 Embedding 模型路由
+///
+/// 不走对话模型的默认映射 (否则未知模型会落到 claude-sonnet-4-5)，
+/// OpenAI 的 text-embedding-3-* / ada-002 映射到默认 Embedding 模型，其余 Gemini Embedding 模型原样透传
+pub fn resolve_embedding_model_route(
+    original_model: &str,
+    router: &ModelRouter,
+    ctx: &RouteContext,
+) -> String {
+    if let Some(matched) = router.route(original_model, ctx) {
+        crate::modules::logger::log_info(&format!("[Router] 规则映射: {} -> {} ({})", original_model, matched.target, matched.rule));
+        return matched.target;
+    }
+
+    let model = original_model.trim_start_matches("models/");
+    let is_openai_model = model.starts_with("text-embedding-3") || model.starts_with("text-embedding-ada");
+    if model.is_empty() || is_openai_model || !model.contains("embedding") {
+        crate::modules::logger::log_info(&format!("[Router] Embedding 默认映射: {} -> {}", original_model, DEFAULT_EMBEDDING_MODEL));
+        return DEFAULT_EMBEDDING_MODEL.to_string();
+    }
+    model.to_string()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -201,4 +297,43 @@ mod tests {
             "claude-sonnet-4-5"
         );
     }
+
+    #[test]
+    fn test_embedding_model_route() {
+        let ctx = RouteContext::default();
+        let mut custom = HashMap::new();
+        let router = ModelRouter::new(&[], &custom);
+        assert_eq!(resolve_embedding_model_route("text-embedding-3-small", &router, &ctx), DEFAULT_EMBEDDING_MODEL);
+        assert_eq!(resolve_embedding_model_route("text-embedding-004", &router, &ctx), "text-embedding-004");
+        assert_eq!(resolve_embedding_model_route("models/gemini-embedding-001", &router, &ctx), "gemini-embedding-001");
+        assert_eq!(resolve_embedding_model_route("gpt-4o", &router, &ctx), DEFAULT_EMBEDDING_MODEL);
+
+        custom.insert("text-embedding-3-*".to_string(), "text-embedding-004".to_string());
+        let router = ModelRouter::new(&[], &custom);
+        assert_eq!(resolve_embedding_model_route("text-embedding-3-large", &router, &ctx), "text-embedding-004");
+    }
+
+    #[test]
+    fn test_pool_model_ids_keep_servable_aliases() {
+        let pool: HashMap<String, PoolModel> = ["claude-sonnet-4-5", "gemini-3-pro-low"]
+            .iter()
+            .map(|id| {
+                let model = PoolModel { id: id.to_string(), accounts: 1, remaining_fraction: None, reset_time: None };
+                (id.to_string(), model)
+            })
+            .collect();
+        let mut custom = HashMap::new();
+        custom.insert("my-fast".to_string(), "claude-sonnet-4-5".to_string());
+        custom.insert("my-missing".to_string(), "gemini-2.5-pro".to_string());
+        let router = ModelRouter::new(&[], &custom);
+
+        let ids = pool_model_ids(&pool, &router);
+        // 内置别名、自定义映射与常用模型在目标可服务时保留
+        assert!(ids.contains(&"claude-3-5-sonnet-20241022".to_string()));
+        assert!(ids.contains(&"my-fast".to_string()));
+        assert!(ids.contains(&"gemini-3-pro-low".to_string()));
+        // 目标不在账号池中的不列出
+        assert!(!ids.contains(&"my-missing".to_string()));
+        assert!(!ids.contains(&"claude-opus-4".to_string()));
+    }
 }
@@ -0,0 +1,262 @@
+// 模型能力注册表
+// 内置默认值 + 配置覆盖 (model_capabilities)，供 /v1/models、请求校验与请求整形统一查询
+// 查找顺序: 精确匹配 > 通配符 (字面字符数多者优先)；未登记的模型返回 None，由调用方保持原有行为
+
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+
+use super::model_mapping::wildcard_match;
+use super::model_router::RouteContext;
+use crate::proxy::config::ModelCapabilities;
+
+/// 唯一支持 googleSearch 的模型，联网请求在目标模型不支持时降级到此模型
+pub const DEFAULT_SEARCH_MODEL: &str = "gemini-2.5-flash";
+
+const GEMINI_CONTEXT_WINDOW: u32 = 1_048_576;
+const GEMINI_MAX_OUTPUT: u32 = 65_536;
+const CLAUDE_CONTEXT_WINDOW: u32 = 200_000;
+const CLAUDE_MAX_OUTPUT: u32 = 64_000;
+
+fn caps(context_window: u32, max_output_tokens: u32) -> ModelCapabilities {
+    ModelCapabilities {
+        context_window,
+        max_output_tokens,
+        tools: true,
+        vision: true,
+        ..Default::default()
+    }
+}
+
+/// 内置模型能力
+fn builtin_capabilities() -> Vec<(&'static str, ModelCapabilities)> {
+    let claude = caps(CLAUDE_CONTEXT_WINDOW, CLAUDE_MAX_OUTPUT);
+    let gemini = caps(GEMINI_CONTEXT_WINDOW, GEMINI_MAX_OUTPUT);
+    vec![
+        (
+            "claude-sonnet-4-5",
+            ModelCapabilities { thinking: true, thinking_passthrough: true, ..claude.clone() },
+        ),
+        (
+            "claude-sonnet-4-5-thinking",
+            ModelCapabilities {
+                thinking: true,
+                thinking_passthrough: true,
+                thinking_by_default: true,
+                ..claude.clone()
+            },
+        ),
+        (
+            "claude-opus-4-5-thinking",
+            ModelCapabilities {
+                thinking: true,
+                thinking_passthrough: true,
+                thinking_by_default: true,
+                ..claude
+            },
+        ),
+        ("gemini-2.5-flash", ModelCapabilities { thinking: true, search: true, ..gemini.clone() }),
+        ("gemini-2.5-flash-lite", gemini.clone()),
+        (
+            "gemini-2.5-flash-thinking",
+            ModelCapabilities {
+                thinking: true,
+                thinking_passthrough: true,
+                thinking_by_default: true,
+                ..gemini.clone()
+            },
+        ),
+        ("gemini-2.5-pro", ModelCapabilities { thinking: true, ..gemini.clone() }),
+        ("gemini-2.0-flash-exp", caps(GEMINI_CONTEXT_WINDOW, 8_192)),
+        ("gemini-3-flash", gemini.clone()),
+        ("gemini-3-pro*", ModelCapabilities { thinking: true, ..gemini }),
+        (
+            "gemini-3-pro-image*",
+            ModelCapabilities {
+                context_window: 65_536,
+                max_output_tokens: 32_768,
+                vision: true,
+                image_output: true,
+                ..Default::default()
+            },
+        ),
+    ]
+}
+
+/// 按匹配优先级排序: 精确匹配在前，通配符按字面字符数降序
+fn sort_entries(entries: &mut [(String, ModelCapabilities)]) {
+    entries.sort_by(|(a, _), (b, _)| {
+        let key = |p: &str| (p.contains('*'), std::cmp::Reverse(p.len() - p.matches('*').count()));
+        key(a).cmp(&key(b)).then_with(|| a.cmp(b))
+    });
+}
+
+pub struct ModelRegistry {
+    entries: RwLock<Vec<(String, ModelCapabilities)>>,
+}
+
+impl ModelRegistry {
+    fn new(overrides: &HashMap<String, ModelCapabilities>) -> Self {
+        Self {
+            entries: RwLock::new(Self::build(overrides)),
+        }
+    }
+
+    fn build(overrides: &HashMap<String, ModelCapabilities>) -> Vec<(String, ModelCapabilities)> {
+        let mut merged: HashMap<String, ModelCapabilities> = builtin_capabilities()
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect();
+        for (model, caps) in overrides {
+            merged.insert(model.clone(), caps.clone());
+        }
+        let mut entries: Vec<_> = merged.into_iter().collect();
+        sort_entries(&mut entries);
+        entries
+    }
+
+    /// Global singleton instance (未加载配置前仅包含内置默认值)
+    pub fn global() -> &'static ModelRegistry {
+        static INSTANCE: OnceLock<ModelRegistry> = OnceLock::new();
+        INSTANCE.get_or_init(|| ModelRegistry::new(&HashMap::new()))
+    }
+
+    /// 使用配置中的覆盖项重建注册表 (启动与配置热更新时调用)
+    pub fn reload(&self, overrides: &HashMap<String, ModelCapabilities>) {
+        if let Ok(mut entries) = self.entries.write() {
+            *entries = Self::build(overrides);
+            tracing::debug!("[Registry] 模型能力注册表已更新 ({} 条)", entries.len());
+        }
+    }
+
+    /// 查询模型能力，未登记返回 None
+    pub fn get(&self, model: &str) -> Option<ModelCapabilities> {
+        let entries = self.entries.read().ok()?;
+        entries
+            .iter()
+            .find(|(pattern, _)| wildcard_match(pattern, model))
+            .map(|(_, caps)| caps.clone())
+    }
+
+    /// 注册表中的精确模型名 (用于 /v1/models 列表)
+    pub fn listed_models(&self) -> Vec<String> {
+        self.entries
+            .read()
+            .map(|entries| {
+                entries
+                    .iter()
+                    .filter(|(pattern, _)| !pattern.contains('*'))
+                    .map(|(pattern, _)| pattern.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// 上游是否接受该模型的 thinking 配置 (未登记时: claude-* 或 -thinking 变体)
+    pub fn supports_thinking(&self, model: &str) -> bool {
+        self.get(model)
+            .map(|c| c.thinking)
+            .unwrap_or_else(|| model.starts_with("claude-") || model.contains("-thinking"))
+    }
+
+    /// Claude 协议的 thinking 请求能否透传到该模型 (未登记时: claude-* 或 -thinking 变体)
+    ///
+    /// 普通 Gemini 模型 (gemini-2.5-flash / gemini-2.5-pro / gemini-3-pro) 虽接受 thinkingBudget，
+    /// 但不返回 Claude 客户端所需的 thinking 块，因此不透传
+    pub fn supports_thinking_passthrough(&self, model: &str) -> bool {
+        self.get(model)
+            .map(|c| c.thinking_passthrough)
+            .unwrap_or_else(|| model.starts_with("claude-") || model.contains("-thinking"))
+    }
+
+    /// 客户端未指定时是否默认开启 thinking (未登记时: Opus 4.5 或 -thinking 变体)
+    pub fn thinking_by_default(&self, model: &str) -> bool {
+        if let Some(caps) = self.get(model) {
+            return caps.thinking_by_default;
+        }
+        let model_lower = model.to_lowercase();
+        model_lower.contains("opus-4-5") || model_lower.contains("opus-4.5") || model_lower.contains("-thinking")
+    }
+
+    pub fn supports_search(&self, model: &str) -> bool {
+        self.get(model).map(|c| c.search).unwrap_or(model == DEFAULT_SEARCH_MODEL)
+    }
+
+    pub fn is_image_model(&self, model: &str) -> bool {
+        self.get(model)
+            .map(|c| c.image_output)
+            .unwrap_or_else(|| model.starts_with("gemini-3-pro-image"))
+    }
+
+    /// 将请求的输出 tokens 限制在模型上限内
+    pub fn clamp_output_tokens(&self, model: &str, requested: u64) -> u64 {
+        match self.get(model) {
+            Some(caps) if caps.max_output_tokens > 0 => requested.min(caps.max_output_tokens as u64),
+            _ => requested,
+        }
+    }
+
+    /// 校验请求特征是否被目标模型支持 (未登记的模型不做限制)
+    pub fn validate(&self, model: &str, ctx: &RouteContext) -> Result<(), String> {
+        let Some(caps) = self.get(model) else {
+            return Ok(());
+        };
+        if ctx.has_tools && !caps.tools {
+            return Err(format!("Model '{}' does not support tools", model));
+        }
+        if ctx.has_images && !caps.vision {
+            return Err(format!("Model '{}' does not support image input", model));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_precedence_and_overrides() {
+        let registry = ModelRegistry::new(&HashMap::new());
+        // 更具体的通配符优先
+        assert!(registry.get("gemini-3-pro-image-4k").unwrap().image_output);
+        assert!(registry.get("gemini-3-pro-high").unwrap().thinking);
+        assert!(!registry.supports_thinking_passthrough("gemini-3-pro-high"));
+        assert!(!registry.supports_thinking_passthrough("gemini-2.5-pro"));
+        assert!(registry.supports_thinking_passthrough("gemini-2.5-flash-thinking"));
+        assert!(registry.supports_thinking_passthrough("claude-sonnet-4-5"));
+        assert!(registry.supports_thinking_passthrough("claude-3-7-sonnet"));
+        assert!(!registry.get("gemini-3-pro-high").unwrap().image_output);
+        assert!(registry.get("unknown-model").is_none());
+
+        assert!(registry.supports_search("gemini-2.5-flash"));
+        assert!(!registry.supports_search("gemini-3-flash"));
+        assert_eq!(registry.clamp_output_tokens("gemini-2.0-flash-exp", 64_000), 8_192);
+        assert_eq!(registry.clamp_output_tokens("unknown-model", 64_000), 64_000);
+
+        // 未登记模型沿用名称规则
+        assert!(registry.thinking_by_default("claude-opus-4-5-20251101"));
+        assert!(!registry.thinking_by_default("claude-sonnet-4-5"));
+
+        let mut overrides = HashMap::new();
+        overrides.insert(
+            "gemini-3-flash".to_string(),
+            ModelCapabilities { search: true, ..caps(1000, 100) },
+        );
+        overrides.insert("my-model-*".to_string(), caps(32_000, 4_096));
+        registry.reload(&overrides);
+        assert!(registry.supports_search("gemini-3-flash"));
+        assert_eq!(registry.get("my-model-v2").unwrap().context_window, 32_000);
+    }
+
+    #[test]
+    fn test_validate_features() {
+        let registry = ModelRegistry::new(&HashMap::new());
+        let ctx = RouteContext {
+            has_tools: true,
+            ..Default::default()
+        };
+        assert!(registry.validate("gemini-3-pro-image", &ctx).is_err());
+        assert!(registry.validate("gemini-3-flash", &ctx).is_ok());
+        assert!(registry.validate("unknown-model", &ctx).is_ok());
+    }
+}
@@ -0,0 +1,533 @@
+// 有序模型路由
+// 将 model_routing_rules 与旧版 custom_mapping 编译为顺序确定的规则列表，首条命中生效
+// 旧版 custom_mapping 排在显式规则之后: 精确匹配优先，通配符按具体程度 (字面字符数) 降序
+
+use regex::Regex;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::proxy::common::model_mapping::wildcard_match;
+use crate::proxy::common::protocol_error::ClientProtocol;
+use crate::proxy::config::{ClientApiKey, ModelMatchType, ModelRoutingRule, ProxyConfig, RouteFeatureConditions};
+
+/// 特征探测时递归的最大深度
+const MAX_SCAN_DEPTH: usize = 8;
+
+/// 路由判定所需的请求上下文
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RouteContext {
+    pub protocol: Option<ClientProtocol>,
+    pub api_key: Option<String>,
+    pub has_tools: bool,
+    pub has_images: bool,
+    pub has_thinking: bool,
+}
+
+impl RouteContext {
+    /// 仅携带协议信息 (countTokens / embeddings 等无需特征判断的场景)
+    pub fn new(protocol: ClientProtocol) -> Self {
+        Self {
+            protocol: Some(protocol),
+            ..Default::default()
+        }
+    }
+
+    /// 从原始请求体探测特征 (兼容 OpenAI Chat / Responses、Claude、Gemini 格式)
+    pub fn from_request(protocol: ClientProtocol, body: &Value, client_key: Option<&ClientApiKey>) -> Self {
+        Self {
+            protocol: Some(protocol),
+            api_key: client_key.map(|k| k.name.clone()),
+            has_tools: detect_tools(body),
+            has_images: ["messages", "contents", "input"]
+                .iter()
+                .filter_map(|k| body.get(*k))
+                .any(|v| contains_image(v, 0)),
+            has_thinking: detect_thinking(body),
+        }
+    }
+}
+
+fn detect_tools(body: &Value) -> bool {
+    ["tools", "functions"].iter().any(|k| {
+        body.get(*k)
+            .and_then(|v| v.as_array())
+            .map(|arr| !arr.is_empty())
+            .unwrap_or(false)
+    })
+}
+
+fn contains_image(value: &Value, depth: usize) -> bool {
+    if depth > MAX_SCAN_DEPTH {
+        return false;
+    }
+    match value {
+        Value::Array(items) => items.iter().any(|v| contains_image(v, depth + 1)),
+        Value::Object(obj) => {
+            if let Some(ty) = obj.get("type").and_then(|v| v.as_str()) {
+                if matches!(ty, "image_url" | "image" | "input_image") {
+                    return true;
+                }
+            }
+            for key in ["inlineData", "fileData"] {
+                let is_image = obj
+                    .get(key)
+                    .and_then(|d| d.get("mimeType"))
+                    .and_then(|m| m.as_str())
+                    .map(|m| m.starts_with("image/"))
+                    .unwrap_or(false);
+                if is_image {
+                    return true;
+                }
+            }
+            obj.values().any(|v| contains_image(v, depth + 1))
+        }
+        _ => false,
+    }
+}
+
+fn detect_thinking(body: &Value) -> bool {
+    // Claude: thinking.type = enabled
+    if body["thinking"]["type"].as_str() == Some("enabled") {
+        return true;
+    }
+    // OpenAI Chat: reasoning_effort / Responses: reasoning.effort
+    let effort = body["reasoning_effort"]
+        .as_str()
+        .or_else(|| body["reasoning"]["effort"].as_str());
+    if let Some(effort) = effort {
+        return effort != "none";
+    }
+    // Gemini: generationConfig.thinkingConfig (thinkingBudget = 0 表示关闭)
+    if let Some(cfg) = body["generationConfig"].get("thinkingConfig") {
+        return cfg.get("thinkingBudget").and_then(|v| v.as_i64()) != Some(0);
+    }
+    false
+}
+
+/// 规则来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleSource {
+    /// model_routing_rules
+    Rule,
+    /// 旧版 custom_mapping
+    CustomMapping,
+}
+
+struct CompiledRule {
+    source: RuleSource,
+    /// 在 model_routing_rules 中的下标 (custom_mapping 为 None)
+    index: Option<usize>,
+    name: String,
+    enabled: bool,
+    match_type: ModelMatchType,
+    pattern: String,
+    target: String,
+    /// 精确匹配为 None；编译失败时为 Err
+    matcher: Option<Result<Regex, String>>,
+    /// 是否对 target 做捕获组替换 (旧版 custom_mapping 不替换，保持原样)
+    expand: bool,
+    protocols: Vec<ClientProtocol>,
+    api_keys: Vec<String>,
+    features: RouteFeatureConditions,
+}
+
+fn glob_to_regex(pattern: &str) -> String {
+    let mut re = String::from("^");
+    for c in pattern.chars() {
+        match c {
+            '*' => re.push_str("(.*)"),
+            '?' => re.push_str("(.)"),
+            _ => re.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    re.push('$');
+    re
+}
+
+impl CompiledRule {
+    fn compile(rule: &ModelRoutingRule, index: Option<usize>, source: RuleSource) -> Self {
+        let matcher = match rule.match_type {
+            // 旧版 custom_mapping 的通配符在 evaluate 中按原有的前缀 / 后缀语义匹配
+            ModelMatchType::Exact => None,
+            ModelMatchType::Glob if source == RuleSource::CustomMapping => None,
+            ModelMatchType::Glob => Some(Regex::new(&glob_to_regex(&rule.pattern)).map_err(|e| e.to_string())),
+            ModelMatchType::Regex => {
+                Some(Regex::new(&format!("^(?:{})$", rule.pattern)).map_err(|e| e.to_string()))
+            }
+        };
+        if let Some(Err(e)) = &matcher {
+            tracing::warn!("[Router] 路由规则 '{}' 编译失败，已跳过: {}", rule.pattern, e);
+        }
+        Self {
+            source,
+            index,
+            name: rule.name.clone(),
+            enabled: rule.enabled,
+            match_type: rule.match_type,
+            pattern: rule.pattern.clone(),
+            target: rule.target.clone(),
+            matcher,
+            expand: source == RuleSource::Rule,
+            protocols: rule.protocols.clone(),
+            api_keys: rule.api_keys.clone(),
+            features: rule.features.clone(),
+        }
+    }
+
+    fn legacy(pattern: &str, target: &str) -> Self {
+        let rule = ModelRoutingRule {
+            name: String::new(),
+            enabled: true,
+            match_type: if pattern.contains('*') { ModelMatchType::Glob } else { ModelMatchType::Exact },
+            pattern: pattern.to_string(),
+            target: target.to_string(),
+            protocols: Vec::new(),
+            api_keys: Vec::new(),
+            features: RouteFeatureConditions::default(),
+        };
+        Self::compile(&rule, None, RuleSource::CustomMapping)
+    }
+
+    /// 命中时返回目标模型，否则返回未命中原因
+    fn evaluate(&self, model: &str, ctx: &RouteContext) -> Result<String, String> {
+        if !self.enabled {
+            return Err("disabled".to_string());
+        }
+
+        let target = match &self.matcher {
+            // 仅首个 `*` 为通配符，`?` 等字符按字面匹配
+            None if self.match_type == ModelMatchType::Glob => {
+                if !wildcard_match(&self.pattern, model) {
+                    return Err("pattern mismatch".to_string());
+                }
+                self.target.clone()
+            }
+            None if self.pattern == model => self.target.clone(),
+            None => return Err("pattern mismatch".to_string()),
+            Some(Err(e)) => return Err(format!("invalid pattern: {}", e)),
+            Some(Ok(re)) => {
+                let Some(caps) = re.captures(model) else {
+                    return Err("pattern mismatch".to_string());
+                };
+                if self.expand {
+                    let mut out = String::new();
+                    caps.expand(&self.target, &mut out);
+                    out
+                } else {
+                    self.target.clone()
+                }
+            }
+        };
+
+        if !self.protocols.is_empty() && !ctx.protocol.map(|p| self.protocols.contains(&p)).unwrap_or(false) {
+            return Err("protocol mismatch".to_string());
+        }
+        if !self.api_keys.is_empty()
+            && !ctx.api_key.as_ref().map(|k| self.api_keys.contains(k)).unwrap_or(false)
+        {
+            return Err("api key mismatch".to_string());
+        }
+        let conditions = [
+            ("tools", self.features.tools, ctx.has_tools),
+            ("images", self.features.images, ctx.has_images),
+            ("thinking", self.features.thinking, ctx.has_thinking),
+        ];
+        for (feature, expected, actual) in conditions {
+            if let Some(expected) = expected {
+                if expected != actual {
+                    return Err(format!("{} condition not met (expected {})", feature, expected));
+                }
+            }
+        }
+
+        Ok(target)
+    }
+
+    fn summary(&self) -> RuleSummary {
+        RuleSummary {
+            source: self.source,
+            index: self.index,
+            name: self.name.clone(),
+            match_type: self.match_type,
+            pattern: self.pattern.clone(),
+            target: self.target.clone(),
+        }
+    }
+}
+
+/// 规则描述 (日志与 explain 输出)
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleSummary {
+    pub source: RuleSource,
+    pub index: Option<usize>,
+    pub name: String,
+    pub match_type: ModelMatchType,
+    pub pattern: String,
+    pub target: String,
+}
+
+impl std::fmt::Display for RuleSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.source, self.name.is_empty()) {
+            (RuleSource::Rule, false) => write!(f, "规则 #{} {} ({})", self.index.unwrap_or_default(), self.name, self.pattern),
+            (RuleSource::Rule, true) => write!(f, "规则 #{} ({})", self.index.unwrap_or_default(), self.pattern),
+            (RuleSource::CustomMapping, _) => write!(f, "custom_mapping ({})", self.pattern),
+        }
+    }
+}
+
+/// 命中结果
+#[derive(Debug, Clone)]
+pub struct RouteMatch {
+    pub target: String,
+    pub rule: RuleSummary,
+}
+
+/// 单条规则的判定结果
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleEvaluation {
+    #[serde(flatten)]
+    pub rule: RuleSummary,
+    pub matched: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// 编译后的模型路由表
+#[derive(Default)]
+pub struct ModelRouter {
+    rules: Vec<CompiledRule>,
+    /// 映射后模型 -> 回退链
+    fallback_chains: HashMap<String, Vec<String>>,
+    /// 映射后模型 (支持 `*`) -> 对冲阈值 (ms)
+    hedging: HashMap<String, u64>,
+}
+
+impl ModelRouter {
+    pub fn new(rules: &[ModelRoutingRule], custom_mapping: &HashMap<String, String>) -> Self {
+        let mut compiled: Vec<CompiledRule> = rules
+            .iter()
+            .enumerate()
+            .map(|(i, r)| CompiledRule::compile(r, Some(i), RuleSource::Rule))
+            .collect();
+
+        let mut exact: Vec<(&String, &String)> = custom_mapping.iter().filter(|(k, _)| !k.contains('*')).collect();
+        exact.sort();
+        let mut wildcard: Vec<(&String, &String)> = custom_mapping.iter().filter(|(k, _)| k.contains('*')).collect();
+        wildcard.sort_by(|(a, _), (b, _)| {
+            let literal = |p: &str| p.chars().filter(|c| *c != '*').count();
+            literal(b).cmp(&literal(a)).then_with(|| a.cmp(b))
+        });
+        compiled.extend(exact.into_iter().chain(wildcard).map(|(k, v)| CompiledRule::legacy(k, v)));
+
+        Self {
+            rules: compiled,
+            fallback_chains: HashMap::new(),
+            hedging: HashMap::new(),
+        }
+    }
+
+    pub fn with_fallback_chains(mut self, chains: HashMap<String, Vec<String>>) -> Self {
+        self.fallback_chains = chains;
+        self
+    }
+
+    pub fn with_hedging(mut self, hedging: HashMap<String, u64>) -> Self {
+        self.hedging = hedging;
+        self
+    }
+
+    pub fn from_config(config: &ProxyConfig) -> Self {
+        Self::new(&config.model_routing_rules, &config.custom_mapping)
+            .with_fallback_chains(config.model_fallback_chains.clone())
+            .with_hedging(config.model_hedging.clone())
+    }
+
+    /// 映射后模型的回退链 (未配置时为空)
+    pub fn fallback_chain(&self, model: &str) -> &[String] {
+        self.fallback_chains.get(model).map(|c| c.as_slice()).unwrap_or(&[])
+    }
+
+    /// 映射后模型的对冲阈值 (精确匹配优先，其次最具体的通配符；未配置或为 0 时不对冲)
+    pub fn hedge_after(&self, model: &str) -> Option<std::time::Duration> {
+        let literal = |p: &str| p.chars().filter(|c| *c != '*').count();
+        self.hedging
+            .get(model)
+            .copied()
+            .or_else(|| {
+                self.hedging
+                    .iter()
+                    .filter(|(pattern, _)| pattern.contains('*') && wildcard_match(pattern, model))
+                    .max_by(|(a, _), (b, _)| literal(a).cmp(&literal(b)).then_with(|| b.cmp(a)))
+                    .map(|(_, ms)| *ms)
+            })
+            .filter(|ms| *ms > 0)
+            .map(std::time::Duration::from_millis)
+    }
+
+    /// 按顺序匹配，返回首条命中的规则
+    pub fn route(&self, model: &str, ctx: &RouteContext) -> Option<RouteMatch> {
+        self.rules.iter().find_map(|rule| {
+            rule.evaluate(model, ctx).ok().map(|target| RouteMatch {
+                target,
+                rule: rule.summary(),
+            })
+        })
+    }
+
+    /// 逐条给出判定结果，直到首条命中的规则 (含)
+    pub fn explain(&self, model: &str, ctx: &RouteContext) -> Vec<RuleEvaluation> {
+        let mut evaluations = Vec::new();
+        for rule in &self.rules {
+            let result = rule.evaluate(model, ctx);
+            let matched = result.is_ok();
+            evaluations.push(RuleEvaluation {
+                rule: rule.summary(),
+                matched,
+                reason: result.err(),
+            });
+            if matched {
+                break;
+            }
+        }
+        evaluations
+    }
+
+    /// 模型列表中需要额外展示的模型名 (精确规则的 pattern 与 custom_mapping 的 key)
+    pub fn listed_models(&self) -> Vec<String> {
+        self.rules
+            .iter()
+            .filter(|r| r.enabled && (r.source == RuleSource::CustomMapping || r.match_type == ModelMatchType::Exact))
+            .map(|r| r.pattern.clone())
+            .collect()
+    }
+
+    /// 启用规则的固定目标模型 (不含捕获组替换的目标)
+    pub fn listed_targets(&self) -> Vec<String> {
+        self.rules
+            .iter()
+            .filter(|r| r.enabled && !(r.expand && r.target.contains('$')))
+            .map(|r| r.target.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rule(match_type: ModelMatchType, pattern: &str, target: &str) -> ModelRoutingRule {
+        ModelRoutingRule {
+            name: String::new(),
+            enabled: true,
+            match_type,
+            pattern: pattern.to_string(),
+            target: target.to_string(),
+            protocols: Vec::new(),
+            api_keys: Vec::new(),
+            features: RouteFeatureConditions::default(),
+        }
+    }
+
+    #[test]
+    fn test_rules_are_ordered_and_expand_captures() {
+        let rules = vec![
+            rule(ModelMatchType::Regex, r"gpt-(?P<ver>\d+)o-mini", "gemini-${ver}.5-flash"),
+            rule(ModelMatchType::Glob, "gpt-*", "gemini-2.5-pro"),
+            rule(ModelMatchType::Glob, "claude-*-?", "claude-$1-x$2"),
+            rule(ModelMatchType::Regex, "([", "never"),
+        ];
+        let router = ModelRouter::new(&rules, &HashMap::new());
+        let ctx = RouteContext::default();
+
+        assert_eq!(router.route("gpt-4o-mini", &ctx).unwrap().target, "gemini-4.5-flash");
+        assert_eq!(router.route("gpt-4o", &ctx).unwrap().target, "gemini-2.5-pro");
+        assert_eq!(router.route("claude-opus-4", &ctx).unwrap().target, "claude-opus-x4");
+        assert!(router.route("o3", &ctx).is_none());
+
+        let explained = router.explain("o3", &ctx);
+        assert_eq!(explained.len(), 4);
+        assert!(explained[3].reason.as_deref().unwrap().starts_with("invalid pattern"));
+    }
+
+    #[test]
+    fn test_conditions() {
+        let mut vision = rule(ModelMatchType::Glob, "*", "gemini-3-pro-high");
+        vision.protocols = vec![ClientProtocol::Claude];
+        vision.features.images = Some(true);
+        let mut keyed = rule(ModelMatchType::Exact, "fast", "gemini-3-flash");
+        keyed.api_keys = vec!["ci".to_string()];
+        let router = ModelRouter::new(&[vision, keyed], &HashMap::new());
+
+        let body = json!({ "messages": [{ "role": "user", "content": [
+            { "type": "image", "source": { "type": "base64", "data": "..." } }
+        ]}]});
+        let ctx = RouteContext::from_request(ClientProtocol::Claude, &body, None);
+        assert!(ctx.has_images && !ctx.has_tools);
+        assert_eq!(router.route("any", &ctx).unwrap().target, "gemini-3-pro-high");
+
+        let openai_ctx = RouteContext::from_request(ClientProtocol::OpenAI, &body, None);
+        assert!(router.route("any", &openai_ctx).is_none());
+
+        let mut key_ctx = RouteContext::new(ClientProtocol::OpenAI);
+        assert!(router.route("fast", &key_ctx).is_none());
+        key_ctx.api_key = Some("ci".to_string());
+        assert_eq!(router.route("fast", &key_ctx).unwrap().target, "gemini-3-flash");
+    }
+
+    #[test]
+    fn test_legacy_custom_mapping_is_deterministic() {
+        let mut legacy = HashMap::new();
+        legacy.insert("gpt-*".to_string(), "a".to_string());
+        legacy.insert("gpt-4*".to_string(), "b".to_string());
+        legacy.insert("gpt-4o".to_string(), "c".to_string());
+        let router = ModelRouter::new(&[rule(ModelMatchType::Exact, "gpt-4o", "rule")], &legacy);
+        let ctx = RouteContext::default();
+
+        assert_eq!(router.route("gpt-4o", &ctx).unwrap().target, "rule");
+        assert_eq!(router.route("gpt-4-turbo", &ctx).unwrap().target, "b");
+        assert_eq!(router.route("gpt-3.5", &ctx).unwrap().target, "a");
+        assert_eq!(router.route("gpt-3.5", &ctx).unwrap().rule.source, RuleSource::CustomMapping);
+    }
+
+    #[test]
+    fn test_legacy_wildcard_keeps_prefix_suffix_semantics() {
+        let mut legacy = HashMap::new();
+        legacy.insert("ab*bc".to_string(), "overlap".to_string());
+        legacy.insert("m?-*-x*".to_string(), "literal".to_string());
+        let router = ModelRouter::new(&[], &legacy);
+        let ctx = RouteContext::default();
+
+        // 前缀与后缀可重叠
+        assert_eq!(router.route("abc", &ctx).unwrap().target, "overlap");
+        // `?` 与第二个 `*` 按字面匹配
+        assert_eq!(router.route("m?-1-x*", &ctx).unwrap().target, "literal");
+        assert!(router.route("m1-1-x2", &ctx).is_none());
+    }
+
+    #[test]
+    fn test_hedge_after() {
+        let mut hedging = HashMap::new();
+        hedging.insert("gemini-*".to_string(), 4000);
+        hedging.insert("gemini-3-pro-*".to_string(), 2500);
+        hedging.insert("gemini-3-flash".to_string(), 0);
+        let router = ModelRouter::default().with_hedging(hedging);
+
+        assert_eq!(router.hedge_after("gemini-3-pro-high"), Some(std::time::Duration::from_millis(2500)));
+        assert_eq!(router.hedge_after("gemini-2.5-flash"), Some(std::time::Duration::from_millis(4000)));
+        assert_eq!(router.hedge_after("gemini-3-flash"), None);
+        assert_eq!(router.hedge_after("claude-sonnet-4-5"), None);
+    }
+
+    #[test]
+    fn test_detect_thinking() {
+        assert!(detect_thinking(&json!({ "thinking": { "type": "enabled", "budget_tokens": 1024 } })));
+        assert!(detect_thinking(&json!({ "reasoning_effort": "high" })));
+        assert!(!detect_thinking(&json!({ "reasoning": { "effort": "none" } })));
+        assert!(!detect_thinking(&json!({ "generationConfig": { "thinkingConfig": { "thinkingBudget": 0 } } })));
+        assert!(detect_thinking(&json!({ "generationConfig": { "thinkingConfig": { "includeThoughts": true } } })));
+    }
+}
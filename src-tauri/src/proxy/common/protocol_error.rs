@@ -0,0 +1,142 @@
+// 按客户端协议构造错误响应
+// OpenAI / Claude / Gemini 客户端对错误体格式的解析各不相同，中间件层返回的错误需与请求协议保持一致
+
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientProtocol {
+    OpenAI,
+    Claude,
+    Gemini,
+}
+
+impl ClientProtocol {
+    /// 根据请求路径推断客户端协议 (无法识别时按 OpenAI 处理)
+    pub fn from_path(path: &str) -> Self {
+        if path.starts_with("/v1/messages") || path == "/v1/models/claude" {
+            Self::Claude
+        } else if path.starts_with("/v1beta") {
+            Self::Gemini
+        } else {
+            Self::OpenAI
+        }
+    }
+}
+
+/// 构造协议对应的错误体
+pub fn error_body(protocol: ClientProtocol, status: StatusCode, message: &str) -> Value {
+    match protocol {
+        ClientProtocol::OpenAI => {
+            let (error_type, code) = match status.as_u16() {
+                400 => ("invalid_request_error", Value::Null),
+                401 => ("authentication_error", json!("invalid_api_key")),
+                403 => ("permission_error", Value::Null),
+                404 => ("not_found_error", Value::Null),
+                429 => ("rate_limit_error", json!("rate_limit_exceeded")),
+                503 | 529 => ("service_unavailable", Value::Null),
+                _ => ("api_error", Value::Null),
+            };
+            json!({
+                "error": {
+                    "message": message,
+                    "type": error_type,
+                    "param": null,
+                    "code": code
+                }
+            })
+        }
+        ClientProtocol::Claude => {
+            let error_type = match status.as_u16() {
+                400 => "invalid_request_error",
+                401 => "authentication_error",
+                403 => "permission_error",
+                404 => "not_found_error",
+                413 => "request_too_large",
+                429 => "rate_limit_error",
+                503 | 529 => "overloaded_error",
+                _ => "api_error",
+            };
+            json!({
+                "type": "error",
+                "error": {
+                    "type": error_type,
+                    "message": message
+                }
+            })
+        }
+        ClientProtocol::Gemini => {
+            let status_text = match status.as_u16() {
+                400 => "INVALID_ARGUMENT",
+                401 => "UNAUTHENTICATED",
+                403 => "PERMISSION_DENIED",
+                404 => "NOT_FOUND",
+                429 => "RESOURCE_EXHAUSTED",
+                503 => "UNAVAILABLE",
+                504 => "DEADLINE_EXCEEDED",
+                _ => "INTERNAL",
+            };
+            json!({
+                "error": {
+                    "code": status.as_u16(),
+                    "message": message,
+                    "status": status_text
+                }
+            })
+        }
+    }
+}
+
+/// 构造协议对应的错误响应，可选附带 Retry-After (秒)
+pub fn error_response(
+    protocol: ClientProtocol,
+    status: StatusCode,
+    message: &str,
+    retry_after_secs: Option<u64>,
+) -> Response {
+    let mut response = (status, Json(error_body(protocol, status, message))).into_response();
+    if let Some(secs) = retry_after_secs {
+        if let Ok(value) = HeaderValue::from_str(&secs.to_string()) {
+            response.headers_mut().insert(header::RETRY_AFTER, value);
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_protocol_from_path() {
+        assert_eq!(ClientProtocol::from_path("/v1/messages"), ClientProtocol::Claude);
+        assert_eq!(ClientProtocol::from_path("/v1beta/models/gemini-2.5-pro:generateContent"), ClientProtocol::Gemini);
+        assert_eq!(ClientProtocol::from_path("/v1/chat/completions"), ClientProtocol::OpenAI);
+    }
+
+    #[test]
+    fn test_rate_limit_bodies() {
+        let openai = error_body(ClientProtocol::OpenAI, StatusCode::TOO_MANY_REQUESTS, "slow down");
+        assert_eq!(openai["error"]["code"], "rate_limit_exceeded");
+
+        let claude = error_body(ClientProtocol::Claude, StatusCode::TOO_MANY_REQUESTS, "slow down");
+        assert_eq!(claude["type"], "error");
+        assert_eq!(claude["error"]["type"], "rate_limit_error");
+
+        let gemini = error_body(ClientProtocol::Gemini, StatusCode::TOO_MANY_REQUESTS, "slow down");
+        assert_eq!(gemini["error"]["status"], "RESOURCE_EXHAUSTED");
+        assert_eq!(gemini["error"]["code"], 429);
+    }
+
+    #[test]
+    fn test_retry_after_header() {
+        let resp = error_response(ClientProtocol::OpenAI, StatusCode::TOO_MANY_REQUESTS, "x", Some(30));
+        assert_eq!(resp.headers().get(header::RETRY_AFTER).unwrap(), "30");
+    }
+}
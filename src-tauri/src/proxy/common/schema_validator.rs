@@ -0,0 +1,251 @@
+// 轻量 JSON Schema 校验 (结构化输出)
+// 仅覆盖 response_format.json_schema 常用的关键字: type / properties / required / additionalProperties /
+// items / enum / const / anyOf / oneOf / allOf / minItems / maxItems / minLength / maxLength / minimum / maximum / $ref
+// 未识别的关键字直接忽略，避免对合法输出误报
+
+use serde_json::{Map, Value};
+
+/// 校验 `value` 是否符合 `schema`，失败时返回首个不匹配项的路径与原因
+pub fn validate(value: &Value, schema: &Value) -> Result<(), String> {
+    validate_at(value, schema, schema, "$")
+}
+
+/// 将模型输出文本解析为 JSON 后校验
+///
+/// 兼容模型偶尔用 ```json 代码块包裹输出的情况
+pub fn validate_text(text: &str, schema: &Value) -> Result<Value, String> {
+    let trimmed = strip_code_fence(text.trim());
+    let value: Value = serde_json::from_str(trimmed)
+        .map_err(|e| format!("output is not valid JSON: {}", e))?;
+    validate(&value, schema)?;
+    Ok(value)
+}
+
+fn strip_code_fence(text: &str) -> &str {
+    let Some(rest) = text.strip_prefix("```") else {
+        return text;
+    };
+    let rest = rest.trim_start_matches(|c: char| c.is_ascii_alphanumeric());
+    rest.strip_suffix("```").unwrap_or(rest).trim()
+}
+
+fn resolve_ref<'a>(root: &'a Value, reference: &str) -> Option<&'a Value> {
+    let pointer = reference.strip_prefix('#')?;
+    if pointer.is_empty() {
+        return Some(root);
+    }
+    root.pointer(pointer)
+}
+
+fn type_matches(value: &Value, ty: &str) -> bool {
+    match ty.to_ascii_lowercase().as_str() {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => value.as_i64().is_some()
+            || value.as_u64().is_some()
+            || value.as_f64().map(|f| f.fract() == 0.0).unwrap_or(false),
+        _ => true,
+    }
+}
+
+fn validate_at(value: &Value, schema: &Value, root: &Value, path: &str) -> Result<(), String> {
+    let Some(schema) = schema.as_object() else {
+        // `true` / 空 schema 接受任意值，`false` 拒绝任意值
+        return match schema {
+            Value::Bool(false) => Err(format!("{}: no value is allowed here", path)),
+            _ => Ok(()),
+        };
+    };
+
+    if let Some(reference) = schema.get("$ref").and_then(|v| v.as_str()) {
+        let target = resolve_ref(root, reference)
+            .ok_or_else(|| format!("{}: unresolvable $ref '{}'", path, reference))?;
+        return validate_at(value, target, root, path);
+    }
+
+    if let Some(ty) = schema.get("type") {
+        let ok = match ty {
+            Value::String(t) => type_matches(value, t),
+            Value::Array(types) => types
+                .iter()
+                .filter_map(|t| t.as_str())
+                .any(|t| type_matches(value, t)),
+            _ => true,
+        };
+        if !ok {
+            return Err(format!("{}: expected type {}, got {}", path, ty, json_type_name(value)));
+        }
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(|v| v.as_array()) {
+        if !allowed.contains(value) {
+            return Err(format!("{}: value {} is not one of {}", path, value, Value::Array(allowed.clone())));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            return Err(format!("{}: expected constant {}", path, expected));
+        }
+    }
+
+    if let Some(all) = schema.get("allOf").and_then(|v| v.as_array()) {
+        for sub in all {
+            validate_at(value, sub, root, path)?;
+        }
+    }
+    for key in ["anyOf", "oneOf"] {
+        if let Some(options) = schema.get(key).and_then(|v| v.as_array()) {
+            if !options.iter().any(|sub| validate_at(value, sub, root, path).is_ok()) {
+                return Err(format!("{}: value does not match any schema in {}", path, key));
+            }
+        }
+    }
+
+    match value {
+        Value::Object(obj) => validate_object(obj, schema, root, path)?,
+        Value::Array(arr) => {
+            if let Some(min) = schema.get("minItems").and_then(|v| v.as_u64()) {
+                if (arr.len() as u64) < min {
+                    return Err(format!("{}: expected at least {} items, got {}", path, min, arr.len()));
+                }
+            }
+            if let Some(max) = schema.get("maxItems").and_then(|v| v.as_u64()) {
+                if arr.len() as u64 > max {
+                    return Err(format!("{}: expected at most {} items, got {}", path, max, arr.len()));
+                }
+            }
+            if let Some(items) = schema.get("items") {
+                for (idx, item) in arr.iter().enumerate() {
+                    validate_at(item, items, root, &format!("{}[{}]", path, idx))?;
+                }
+            }
+        }
+        Value::String(s) => {
+            let len = s.chars().count() as u64;
+            if let Some(min) = schema.get("minLength").and_then(|v| v.as_u64()) {
+                if len < min {
+                    return Err(format!("{}: string shorter than {}", path, min));
+                }
+            }
+            if let Some(max) = schema.get("maxLength").and_then(|v| v.as_u64()) {
+                if len > max {
+                    return Err(format!("{}: string longer than {}", path, max));
+                }
+            }
+        }
+        Value::Number(n) => {
+            let n = n.as_f64().unwrap_or_default();
+            if let Some(min) = schema.get("minimum").and_then(|v| v.as_f64()) {
+                if n < min {
+                    return Err(format!("{}: {} is less than minimum {}", path, n, min));
+                }
+            }
+            if let Some(max) = schema.get("maximum").and_then(|v| v.as_f64()) {
+                if n > max {
+                    return Err(format!("{}: {} is greater than maximum {}", path, n, max));
+                }
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn validate_object(
+    obj: &Map<String, Value>,
+    schema: &Map<String, Value>,
+    root: &Value,
+    path: &str,
+) -> Result<(), String> {
+    if let Some(required) = schema.get("required").and_then(|v| v.as_array()) {
+        for key in required.iter().filter_map(|k| k.as_str()) {
+            if !obj.contains_key(key) {
+                return Err(format!("{}: missing required property '{}'", path, key));
+            }
+        }
+    }
+
+    let properties = schema.get("properties").and_then(|v| v.as_object());
+    for (key, child) in obj {
+        let child_path = format!("{}.{}", path, key);
+        match properties.and_then(|p| p.get(key)) {
+            Some(child_schema) => validate_at(child, child_schema, root, &child_path)?,
+            None => match schema.get("additionalProperties") {
+                Some(Value::Bool(false)) => {
+                    return Err(format!("{}: unexpected property '{}'", path, key));
+                }
+                Some(extra @ Value::Object(_)) => validate_at(child, extra, root, &child_path)?,
+                _ => {}
+            },
+        }
+    }
+
+    Ok(())
+}
+
+fn json_type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn person_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "age": { "type": "integer", "minimum": 0 },
+                "tags": { "type": "array", "items": { "$ref": "#/$defs/tag" } }
+            },
+            "required": ["name", "age"],
+            "additionalProperties": false,
+            "$defs": {
+                "tag": { "type": "string", "enum": ["a", "b"] }
+            }
+        })
+    }
+
+    #[test]
+    fn test_valid_document() {
+        let value = json!({ "name": "Ann", "age": 30, "tags": ["a", "b"] });
+        assert!(validate(&value, &person_schema()).is_ok());
+    }
+
+    #[test]
+    fn test_reports_path_of_first_mismatch() {
+        let schema = person_schema();
+        let err = validate(&json!({ "name": "Ann" }), &schema).unwrap_err();
+        assert!(err.contains("missing required property 'age'"));
+
+        let err = validate(&json!({ "name": "Ann", "age": 1, "tags": ["c"] }), &schema).unwrap_err();
+        assert!(err.starts_with("$.tags[0]"));
+
+        let err = validate(&json!({ "name": "Ann", "age": 1, "extra": 1 }), &schema).unwrap_err();
+        assert!(err.contains("unexpected property 'extra'"));
+
+        let err = validate(&json!({ "name": "Ann", "age": 1.5 }), &schema).unwrap_err();
+        assert!(err.contains("expected type"));
+    }
+
+    #[test]
+    fn test_validate_text_strips_code_fence() {
+        let text = "```json\n{\"name\":\"Ann\",\"age\":3}\n```";
+        assert!(validate_text(text, &person_schema()).is_ok());
+        assert!(validate_text("not json", &person_schema()).is_err());
+    }
+}
@@ -0,0 +1,172 @@
+// Token 计数辅助
+// 上游 countTokens 请求体构造，以及无可用账号 / 上游失败时使用的本地估算器
+// 本地估算按字符类别粗略计算，宁可略微高估以避免上下文溢出
+
+use serde_json::{json, Value};
+
+/// 无法得知尺寸时单张图片的估算 token 数
+const IMAGE_TOKEN_ESTIMATE: u64 = 1600;
+
+/// 估算一段文本的 token 数
+///
+/// - ASCII 字符约 4 个对应 1 个 token
+/// - CJK 等非 ASCII 字符约 1 个对应 1 个 token
+pub fn estimate_text_tokens(text: &str) -> u64 {
+    let mut ascii = 0u64;
+    let mut non_ascii = 0u64;
+    for c in text.chars() {
+        if c.is_ascii() {
+            ascii += 1;
+        } else {
+            non_ascii += 1;
+        }
+    }
+    ascii.div_ceil(4) + non_ascii
+}
+
+/// 递归估算 JSON 结构 (Claude / Gemini 请求体) 中的 token 数
+///
+/// 字符串值按文本估算；图片 / 内联二进制数据按固定值计入，不按 base64 长度计算
+pub fn estimate_value_tokens(value: &Value) -> u64 {
+    match value {
+        Value::String(s) => estimate_text_tokens(s),
+        Value::Array(items) => items.iter().map(estimate_value_tokens).sum(),
+        Value::Object(map) => {
+            let is_image = map.get("type").and_then(|t| t.as_str()) == Some("image")
+                || map.contains_key("inlineData")
+                || map.contains_key("inline_data");
+            if is_image {
+                return IMAGE_TOKEN_ESTIMATE;
+            }
+            map.iter()
+                .filter(|(k, _)| !matches!(k.as_str(), "signature" | "thoughtSignature" | "cache_control" | "id" | "tool_use_id"))
+                .map(|(k, v)| {
+                    // 工具定义的字段名同样会占用 token
+                    let key_cost = if matches!(v, Value::Object(_) | Value::Array(_)) { 0 } else { estimate_text_tokens(k) };
+                    key_cost + estimate_value_tokens(v)
+                })
+                .sum()
+        }
+        Value::Number(n) => estimate_text_tokens(&n.to_string()),
+        Value::Bool(_) | Value::Null => 0,
+    }
+}
+
+/// 估算 Claude Messages 请求的输入 token (system + messages + tools)
+pub fn estimate_claude_request_tokens(body: &Value) -> u64 {
+    let mut total = 0;
+    for field in ["system", "messages", "tools"] {
+        if let Some(v) = body.get(field) {
+            total += estimate_value_tokens(v);
+        }
+    }
+    // 每条消息的角色与分隔符开销
+    let message_count = body
+        .get("messages")
+        .and_then(|m| m.as_array())
+        .map(|m| m.len() as u64)
+        .unwrap_or(0);
+    total + message_count * 4
+}
+
+/// 估算 Gemini 请求的输入 token (contents / systemInstruction / tools)
+pub fn estimate_gemini_request_tokens(body: &Value) -> u64 {
+    let request = body.get("generateContentRequest").unwrap_or(body);
+    let mut total = 0;
+    for field in ["contents", "systemInstruction", "system_instruction", "tools"] {
+        if let Some(v) = request.get(field) {
+            total += estimate_value_tokens(v);
+        }
+    }
+    total
+}
+
+/// 将 Gemini generateContent 请求折叠为 countTokens 可接受的 contents
+///
+/// v1internal countTokens 只接受 `model` + `contents`，systemInstruction 与工具声明
+/// 以前置 user 文本的形式计入，使结果覆盖实际发送到上游的全部输入
+pub fn build_count_tokens_contents(request: &Value) -> Value {
+    let mut contents: Vec<Value> = Vec::new();
+
+    let system = request
+        .get("systemInstruction")
+        .or_else(|| request.get("system_instruction"));
+    if let Some(parts) = system.and_then(|s| s.get("parts")).and_then(|p| p.as_array()) {
+        if !parts.is_empty() {
+            contents.push(json!({"role": "user", "parts": parts}));
+        }
+    }
+
+    if let Some(tools) = request.get("tools").filter(|t| t.as_array().map(|a| !a.is_empty()).unwrap_or(false)) {
+        contents.push(json!({
+            "role": "user",
+            "parts": [{"text": tools.to_string()}]
+        }));
+    }
+
+    if let Some(items) = request.get("contents").and_then(|c| c.as_array()) {
+        contents.extend(items.iter().cloned());
+    }
+
+    Value::Array(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_estimate_text_tokens() {
+        assert_eq!(estimate_text_tokens(""), 0);
+        assert_eq!(estimate_text_tokens("abcd"), 1);
+        assert_eq!(estimate_text_tokens("abcde"), 2);
+        assert_eq!(estimate_text_tokens("你好"), 2);
+    }
+
+    #[test]
+    fn test_estimate_claude_request_ignores_signatures_and_image_data() {
+        let body = json!({
+            "model": "claude-sonnet-4-5",
+            "system": "You are helpful.",
+            "messages": [
+                {"role": "user", "content": [
+                    {"type": "text", "text": "Describe this image please."},
+                    {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "A".repeat(100_000)}}
+                ]},
+                {"role": "assistant", "content": [
+                    {"type": "thinking", "thinking": "hmm", "signature": "S".repeat(10_000)}
+                ]}
+            ]
+        });
+        let tokens = estimate_claude_request_tokens(&body);
+        assert!(tokens >= IMAGE_TOKEN_ESTIMATE);
+        assert!(tokens < IMAGE_TOKEN_ESTIMATE + 100);
+    }
+
+    #[test]
+    fn test_build_count_tokens_contents_includes_system_and_tools() {
+        let request = json!({
+            "systemInstruction": {"parts": [{"text": "be brief"}]},
+            "tools": [{"functionDeclarations": [{"name": "ls"}]}],
+            "contents": [{"role": "user", "parts": [{"text": "hi"}]}],
+            "generationConfig": {"maxOutputTokens": 10}
+        });
+        let contents = build_count_tokens_contents(&request);
+        let arr = contents.as_array().unwrap();
+        assert_eq!(arr.len(), 3);
+        assert_eq!(arr[0]["parts"][0]["text"], "be brief");
+        assert!(arr[1]["parts"][0]["text"].as_str().unwrap().contains("functionDeclarations"));
+        assert_eq!(arr[2]["parts"][0]["text"], "hi");
+    }
+
+    #[test]
+    fn test_estimate_gemini_request_tokens() {
+        let body = json!({
+            "generateContentRequest": {
+                "contents": [{"role": "user", "parts": [{"text": "Hello world, how are you?"}]}]
+            }
+        });
+        assert!(estimate_gemini_request_tokens(&body) > 0);
+    }
+}
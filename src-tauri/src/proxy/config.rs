@@ -17,22 +17,70 @@ impl Default for ProxyAuthMode {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+/// 上游服务商 (z.ai 及自定义服务商) 的调度方式
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
 #[serde(rename_all = "snake_case")]
-pub enum ZaiDispatchMode {
-    /// Never use z.ai.
+pub enum ProviderDispatchMode {
+    /// Never use this provider.
+    #[default]
     Off,
-    /// Use z.ai for all Anthropic protocol requests.
+    /// Use this provider for all requests of its protocol.
     Exclusive,
-    /// Treat z.ai as one additional slot in the shared pool.
+    /// Treat this provider as one additional slot in the shared pool.
     Pooled,
-    /// Use z.ai only when the Google pool is unavailable.
+    /// Use this provider only when the Google pool is unavailable.
     Fallback,
+    /// Route `weight` percent of requests to this provider.
+    Weighted,
 }
 
-impl Default for ZaiDispatchMode {
-    fn default() -> Self {
-        Self::Off
+/// z.ai 沿用通用服务商的调度方式
+pub type ZaiDispatchMode = ProviderDispatchMode;
+
+/// 上游服务商使用的 API 协议
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderProtocol {
+    /// Anthropic Messages API (`/v1/messages`)
+    Anthropic,
+    /// OpenAI Chat Completions API (`/v1/chat/completions`)
+    OpenAI,
+    /// Gemini API (`/v1beta/models/{model}:generateContent`)
+    Gemini,
+}
+
+/// 自定义上游服务商 (自建 vLLM、其他厂商等)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpstreamProviderConfig {
+    /// 唯一标识，请求中可用 `{id}:{model}` 直接指定上游模型
+    pub id: String,
+    /// 显示名称
+    #[serde(default)]
+    pub name: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    pub base_url: String,
+    /// 留空时不附加认证头 (如本地部署的 vLLM)
+    #[serde(default)]
+    pub api_key: String,
+    pub protocol: ProviderProtocol,
+    #[serde(default)]
+    pub dispatch_mode: ProviderDispatchMode,
+    /// Weighted 模式下分配到的请求百分比 (0-100)
+    #[serde(default)]
+    pub weight: u32,
+    /// 入站模型 -> 上游模型 (支持 `*` 通配符)，未命中时原样转发
+    #[serde(default)]
+    pub model_mapping: HashMap<String, String>,
+    /// 同时承接其他协议的请求 (经协议转换，目前为 Anthropic 服务商承接 OpenAI `/v1/chat/completions`)
+    #[serde(default)]
+    pub cross_protocol: bool,
+}
+
+impl UpstreamProviderConfig {
+    /// 已启用且配置完整
+    pub fn is_active(&self) -> bool {
+        self.enabled && self.dispatch_mode != ProviderDispatchMode::Off && !self.base_url.trim().is_empty()
     }
 }
 
@@ -92,6 +140,9 @@ pub struct ZaiConfig {
     pub api_key: String,
     #[serde(default)]
     pub dispatch_mode: ZaiDispatchMode,
+    /// Weighted 模式下分配到的请求百分比 (0-100)
+    #[serde(default)]
+    pub weight: u32,
     /// Optional per-model mapping overrides for Anthropic/Claude model ids.
     /// Key: incoming `model` string, Value: upstream z.ai model id (e.g. `glm-4.7`).
     #[serde(default)]
@@ -100,6 +151,9 @@ pub struct ZaiConfig {
     pub models: ZaiModelDefaults,
     #[serde(default)]
     pub mcp: ZaiMcpConfig,
+    /// 同时承接 OpenAI `/v1/chat/completions` 请求 (经协议转换)
+    #[serde(default)]
+    pub cross_protocol: bool,
 }
 
 impl Default for ZaiConfig {
@@ -109,9 +163,11 @@ impl Default for ZaiConfig {
             base_url: default_zai_base_url(),
             api_key: String::new(),
             dispatch_mode: ZaiDispatchMode::Off,
+            weight: 0,
             model_mapping: HashMap::new(),
             models: ZaiModelDefaults::default(),
             mcp: ZaiMcpConfig::default(),
+            cross_protocol: false,
         }
     }
 }
@@ -144,6 +200,235 @@ impl Default for ExperimentalConfig {
 
 fn default_true() -> bool { true }
 
+/// 客户端 API Key 可访问的路由范围
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiKeyScope {
+    /// `/v1/chat/completions`, `/v1/completions`, `/v1/responses`, `/v1/embeddings`, `/v1/models`
+    OpenAI,
+    /// `/v1/messages*`, `/v1/models/claude`
+    Claude,
+    /// `/v1beta/*`
+    Gemini,
+    /// `/mcp/*`
+    Mcp,
+    /// `/v1/images/*`
+    Images,
+    /// `/v1/audio/*`
+    Audio,
+}
+
+impl ApiKeyScope {
+    /// 根据请求路径判断所需的 scope，返回 None 表示该路由不受 scope 限制 (如 /healthz)
+    pub fn from_path(path: &str) -> Option<Self> {
+        if path.starts_with("/v1/images/") {
+            Some(Self::Images)
+        } else if path.starts_with("/v1/audio/") {
+            Some(Self::Audio)
+        } else if path.starts_with("/v1/messages") || path == "/v1/models/claude" {
+            Some(Self::Claude)
+        } else if path.starts_with("/v1beta/") || path == "/v1beta" {
+            Some(Self::Gemini)
+        } else if path.starts_with("/mcp/") {
+            Some(Self::Mcp)
+        } else if path == "/v1/models"
+            || path.starts_with("/v1/chat/")
+            || path.starts_with("/v1/completions")
+            || path.starts_with("/v1/responses")
+            || path == "/v1/embeddings"
+        {
+            Some(Self::OpenAI)
+        } else {
+            None
+        }
+    }
+}
+
+/// 具名客户端 API Key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientApiKey {
+    /// 显示名称 (记录在请求日志中，用于区分调用方)
+    pub name: String,
+    /// 密钥内容
+    pub key: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// 允许访问的路由范围，空列表表示不限制
+    #[serde(default)]
+    pub scopes: Vec<ApiKeyScope>,
+    /// 允许请求的模型 (支持 `*` 通配符)，空列表表示不限制
+    #[serde(default)]
+    pub allowed_models: Vec<String>,
+    /// 过期时间 (Unix 秒)，None 表示永不过期
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+    /// 每日 token 预算 (输入 + 输出)，None 表示不限制
+    #[serde(default)]
+    pub daily_token_budget: Option<u64>,
+    /// 每月 token 预算 (输入 + 输出)，None 表示不限制
+    #[serde(default)]
+    pub monthly_token_budget: Option<u64>,
+    /// 账号池耗尽排队时的优先级 (越大越先放行，同优先级先到先得)
+    #[serde(default)]
+    pub priority: i32,
+}
+
+impl ClientApiKey {
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at.map(|t| now >= t).unwrap_or(false)
+    }
+
+    pub fn allows_scope(&self, scope: ApiKeyScope) -> bool {
+        self.scopes.is_empty() || self.scopes.contains(&scope)
+    }
+
+    pub fn allows_model(&self, model: &str) -> bool {
+        self.allowed_models.is_empty()
+            || self
+                .allowed_models
+                .iter()
+                .any(|p| crate::proxy::common::model_mapping::wildcard_match(p, model))
+    }
+}
+
+
+/// 模型路由规则的匹配方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelMatchType {
+    /// 完全相等
+    Exact,
+    /// `*` / `?` 通配符，每个通配符按顺序对应 `$1`, `$2`...
+    Glob,
+    /// 正则表达式 (整体匹配)，target 中可用 `$1` / `${name}` 引用捕获组
+    Regex,
+}
+
+/// 请求特征条件，None 表示不限制
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RouteFeatureConditions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub images: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thinking: Option<bool>,
+}
+
+/// 有序模型路由规则 (按列表顺序匹配，首条命中生效)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelRoutingRule {
+    /// 规则名称 (用于日志与 explain 输出)
+    #[serde(default)]
+    pub name: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    pub match_type: ModelMatchType,
+    pub pattern: String,
+    /// 目标模型，glob / regex 规则支持捕获组替换
+    pub target: String,
+    /// 限定入站协议，空列表表示不限制
+    #[serde(default)]
+    pub protocols: Vec<crate::proxy::common::protocol_error::ClientProtocol>,
+    /// 限定客户端密钥名称 (ClientApiKey.name，主密钥为 `default`)，空列表表示不限制
+    #[serde(default)]
+    pub api_keys: Vec<String>,
+    /// 限定请求特征
+    #[serde(default)]
+    pub features: RouteFeatureConditions,
+}
+
+/// 模型能力描述 (上下文窗口、输出上限与功能支持)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelCapabilities {
+    /// 输入上下文窗口 (tokens)
+    pub context_window: u32,
+    /// 最大输出 tokens
+    pub max_output_tokens: u32,
+    /// 函数调用 / 工具
+    pub tools: bool,
+    /// 图片输入
+    pub vision: bool,
+    /// 上游接受 thinking 配置 (thinkingBudget，如 OpenAI reasoning_effort)
+    pub thinking: bool,
+    /// Claude 协议的 thinking 请求可透传 (返回带签名的 thinking 块)
+    pub thinking_passthrough: bool,
+    /// 客户端未指定时默认开启 thinking
+    pub thinking_by_default: bool,
+    /// googleSearch 联网
+    pub search: bool,
+    /// 图片生成
+    pub image_output: bool,
+}
+
+
+/// v1internal 上游端点与熔断配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UpstreamEndpointConfig {
+    /// 按优先级排列的 v1internal 基础地址 (如 `https://cloudcode-pa.googleapis.com/v1internal`)，
+    /// 空列表表示使用内置的 prod → daily
+    #[serde(default)]
+    pub base_urls: Vec<String>,
+    /// 滚动窗口内错误率达到该值 (0-1) 时熔断
+    #[serde(default = "default_breaker_error_rate")]
+    pub error_rate_threshold: f64,
+    /// 触发熔断所需的最少样本数
+    #[serde(default = "default_breaker_min_samples")]
+    pub min_samples: u32,
+    /// 熔断持续时间 (秒)，到期后放行一个半开探测请求
+    #[serde(default = "default_breaker_open_secs")]
+    pub open_secs: u64,
+}
+
+impl Default for UpstreamEndpointConfig {
+    fn default() -> Self {
+        Self {
+            base_urls: Vec::new(),
+            error_rate_threshold: default_breaker_error_rate(),
+            min_samples: default_breaker_min_samples(),
+            open_secs: default_breaker_open_secs(),
+        }
+    }
+}
+
+fn default_breaker_error_rate() -> f64 { 0.5 }
+fn default_breaker_min_samples() -> u32 { 5 }
+fn default_breaker_open_secs() -> u64 { 30 }
+
+/// 账号池 access_token 后台主动刷新配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TokenRefreshConfig {
+    #[serde(default = "default_token_refresh_enabled")]
+    pub enabled: bool,
+    /// 在过期前多少秒刷新
+    #[serde(default = "default_token_refresh_margin_secs")]
+    pub margin_secs: u64,
+    /// 按账号错开的最大额外提前秒数，避免同一批导入的账号同时刷新 (不超过 margin 的一半)
+    #[serde(default = "default_token_refresh_jitter_secs")]
+    pub jitter_secs: u64,
+    /// 同时进行的刷新请求上限
+    #[serde(default = "default_token_refresh_concurrency")]
+    pub max_concurrency: usize,
+}
+
+impl Default for TokenRefreshConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_token_refresh_enabled(),
+            margin_secs: default_token_refresh_margin_secs(),
+            jitter_secs: default_token_refresh_jitter_secs(),
+            max_concurrency: default_token_refresh_concurrency(),
+        }
+    }
+}
+
+fn default_token_refresh_enabled() -> bool { true }
+fn default_token_refresh_margin_secs() -> u64 { 600 }
+fn default_token_refresh_jitter_secs() -> u64 { 120 }
+fn default_token_refresh_concurrency() -> usize { 4 }
+
+
 /// 反代服务配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProxyConfig {
@@ -167,17 +452,39 @@ pub struct ProxyConfig {
     /// 监听端口
     pub port: u16,
     
-    /// API 密钥
+    /// API 密钥 (主密钥，拥有全部权限)
     pub api_key: String,
-    
+
+    /// 额外的具名客户端密钥 (按调用方分配 scope / 模型 / 过期时间)
+    #[serde(default)]
+    pub api_keys: Vec<ClientApiKey>,
 
     /// 是否自动启动
     pub auto_start: bool,
 
     /// 自定义精确模型映射表 (key: 原始模型名, value: 目标模型名)
+    /// 兼容旧配置，优先级低于 model_routing_rules
     #[serde(default)]
     pub custom_mapping: std::collections::HashMap<String, String>,
 
+    /// 有序模型路由规则 (优先于 custom_mapping)
+    #[serde(default)]
+    pub model_routing_rules: Vec<ModelRoutingRule>,
+
+    /// 模型回退链 (key: 映射后的模型, value: 按顺序尝试的替代模型)
+    /// 当该模型在所有账号上均因配额 / 容量耗尽被锁定时自动切换
+    #[serde(default)]
+    pub model_fallback_chains: std::collections::HashMap<String, Vec<String>>,
+
+    /// 对冲请求 (key: 映射后的模型，支持 `*` 通配符, value: 首字节等待阈值 ms)
+    /// 首个上游调用超过阈值仍未响应时换账号再发一次，取先返回者；会额外消耗配额，仅对配置的模型启用
+    #[serde(default)]
+    pub model_hedging: std::collections::HashMap<String, u64>,
+
+    /// 模型能力覆盖 (key: 模型名，支持 `*` 通配符)，与内置默认值合并，同名时覆盖
+    #[serde(default)]
+    pub model_capabilities: std::collections::HashMap<String, ModelCapabilities>,
+
     /// API 请求超时时间(秒)
     #[serde(default = "default_request_timeout")]
     pub request_timeout: u64,
@@ -190,9 +497,21 @@ pub struct ProxyConfig {
     #[serde(default)]
     pub upstream_proxy: UpstreamProxyConfig,
 
+    /// v1internal 端点顺序与熔断参数
+    #[serde(default)]
+    pub upstream_endpoints: UpstreamEndpointConfig,
+
+    /// access_token 后台主动刷新 (过期前提前刷新，避免请求路径上的刷新延迟)
+    #[serde(default)]
+    pub token_refresh: TokenRefreshConfig,
+
     /// z.ai provider configuration (Anthropic-compatible).
     #[serde(default)]
     pub zai: ZaiConfig,
+
+    /// 自定义上游服务商 (与 z.ai 共用调度逻辑)
+    #[serde(default)]
+    pub providers: Vec<UpstreamProviderConfig>,
     
     /// 账号调度配置 (粘性会话/限流重试)
     #[serde(default)]
@@ -220,12 +539,20 @@ impl Default for ProxyConfig {
             auth_mode: ProxyAuthMode::default(),
             port: 8045,
             api_key: format!("sk-{}", uuid::Uuid::new_v4().simple()),
+            api_keys: Vec::new(),
             auto_start: false,
             custom_mapping: std::collections::HashMap::new(),
+            model_routing_rules: Vec::new(),
+            model_fallback_chains: std::collections::HashMap::new(),
+            model_hedging: std::collections::HashMap::new(),
+            model_capabilities: std::collections::HashMap::new(),
             request_timeout: default_request_timeout(),
             enable_logging: false, // 默认关闭，节省性能
             upstream_proxy: UpstreamProxyConfig::default(),
+            upstream_endpoints: UpstreamEndpointConfig::default(),
+            token_refresh: TokenRefreshConfig::default(),
             zai: ZaiConfig::default(),
+            providers: Vec::new(),
             scheduling: crate::proxy::sticky_config::StickySessionConfig::default(),
             experimental: ExperimentalConfig::default(),
         }
@@ -263,4 +590,10 @@ impl ProxyConfig {
             "127.0.0.1"
         }
     }
+
+    /// 是否启用了任一上游服务商 (无 Google 账号时仍可启动服务)
+    pub fn has_active_provider(&self) -> bool {
+        crate::proxy::providers::zai_anthropic::provider_config(&self.zai).is_active()
+            || self.providers.iter().any(|p| p.is_active())
+    }
 }
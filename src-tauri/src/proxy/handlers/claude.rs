@@ -2,7 +2,7 @@
 
 use axum::{
     body::Body,
-    extract::{Json, State},
+    extract::{Extension, Json, State},
     http::{header, StatusCode},
     response::{IntoResponse, Response},
 };
@@ -16,9 +16,17 @@ use crate::proxy::mappers::claude::{
     transform_claude_request_in, transform_response, create_claude_sse_stream, ClaudeRequest,
     close_tool_loop_for_thinking,
 };
+use crate::proxy::common::model_fallback;
+use crate::proxy::common::model_registry::ModelRegistry;
+use crate::proxy::common::model_router::RouteContext;
+use crate::proxy::common::protocol_error::ClientProtocol;
+use crate::proxy::config::{ClientApiKey, ProviderProtocol};
+use crate::proxy::providers::forward;
 use crate::proxy::server::AppState;
+use crate::proxy::upstream::hedge::HedgeOptions;
+use crate::proxy::mappers::claude::streaming::is_content_event;
+use crate::proxy::mappers::stream_guard::{buffer_until_content, StreamStart};
 use axum::http::HeaderMap;
-use std::sync::atomic::Ordering;
 
 const MAX_RETRY_ATTEMPTS: usize = 3;
 const MIN_SIGNATURE_LENGTH: usize = 10;  // 最小有效签名长度
@@ -307,9 +315,11 @@ fn should_rotate_account(status_code: u16) -> bool {
 pub async fn handle_messages(
     State(state): State<AppState>,
     headers: HeaderMap,
+    client_key: Option<Extension<ClientApiKey>>,
     Json(body): Json<Value>,
 ) -> Response {
     tracing::debug!("handle_messages called. Body JSON len: {}", body.to_string().len());
+    let route_ctx = RouteContext::from_request(ClientProtocol::Claude, &body, client_key.as_deref());
     
     // 生成随机 Trace ID 用户追踪
     let trace_id: String = rand::Rng::sample_iter(rand::thread_rng(), &rand::distributions::Alphanumeric)
@@ -317,29 +327,16 @@ pub async fn handle_messages(
         .map(char::from)
         .collect::<String>().to_lowercase();
         
-    // Decide whether this request should be handled by z.ai (Anthropic passthrough) or the existing Google flow.
-    let zai = state.zai.read().await.clone();
-    let zai_enabled = zai.enabled && !matches!(zai.dispatch_mode, crate::proxy::ZaiDispatchMode::Off);
-    let google_accounts = state.token_manager.len();
-
-    let use_zai = if !zai_enabled {
-        false
-    } else {
-        match zai.dispatch_mode {
-            crate::proxy::ZaiDispatchMode::Off => false,
-            crate::proxy::ZaiDispatchMode::Exclusive => true,
-            crate::proxy::ZaiDispatchMode::Fallback => google_accounts == 0,
-            crate::proxy::ZaiDispatchMode::Pooled => {
-                // Treat z.ai as exactly one extra slot in the pool.
-                // No strict guarantees: it may get 0 requests if selection never hits.
-                let total = google_accounts.saturating_add(1).max(1);
-                let slot = state.provider_rr.fetch_add(1, Ordering::Relaxed) % total;
-                slot == 0
-            }
-        }
+    // 按调度方式决定交给 Anthropic 兼容服务商 (z.ai / 自定义) 还是 Google 账号池
+    let (provider, fallback) = {
+        let providers = state.providers.read().await;
+        (
+            providers.select(&[ProviderProtocol::Anthropic], state.token_manager.len(), &state.provider_rr),
+            providers.fallback(&[ProviderProtocol::Anthropic]),
+        )
     };
 
-    // [CRITICAL REFACTOR] 优先解析并过滤 Thinking 块，确保 z.ai 也是用修复后的 Body
+    // [CRITICAL REFACTOR] 优先解析并过滤 Thinking 块，确保服务商也使用修复后的 Body
     let mut request: crate::proxy::mappers::claude::models::ClaudeRequest = match serde_json::from_value(body) {
         Ok(r) => r,
         Err(e) => {
@@ -376,18 +373,19 @@ pub async fn handle_messages(
         return create_warmup_response(&request, request.stream);
     }
 
-    if use_zai {
+    if let Some(provider) = provider {
         // 重新序列化修复后的请求体
         let new_body = match serde_json::to_value(&request) {
             Ok(v) => v,
             Err(e) => {
-                tracing::error!("Failed to serialize fixed request for z.ai: {}", e);
+                tracing::error!("Failed to serialize fixed request for provider {}: {}", provider.id(), e);
                 return StatusCode::INTERNAL_SERVER_ERROR.into_response();
             }
         };
 
-        return crate::proxy::providers::zai_anthropic::forward_anthropic_json(
+        return forward::forward_json(
             &state,
+            &provider,
             axum::http::Method::POST,
             "/v1/messages",
             &headers,
@@ -396,6 +394,11 @@ pub async fn handle_messages(
         .await;
     }
     
+    // 配置了 Fallback 服务商时保留修复后的请求体，账号池耗尽 (全部限流 / 失败) 时转发
+    let mut fallback = fallback.and_then(|provider| {
+        serde_json::to_value(&request).ok().map(|body| (provider, body))
+    });
+
     // Google Flow 继续使用 request 对象
     // (后续代码不需要再次 filter_invalid_thinking_blocks)
 
@@ -499,21 +502,41 @@ pub async fn handle_messages(
     
     // 3. 准备闭包
     let mut request_for_body = request.clone();
-    let token_manager = state.token_manager;
+    let token_manager = state.token_manager.clone();
     
     let pool_size = token_manager.len();
-    let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
+    // 回退链上的每个模型额外允许一次尝试
+    let fallback_budget = model_fallback::fallback_budget(&state.model_router, &request_for_body.model, &route_ctx).await;
+    let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1) + fallback_budget;
 
     let mut last_error = String::new();
     let mut retried_without_thinking = false;
     let mut last_email: Option<String> = None;
     
     for attempt in 0..max_attempts {
-        // 2. 模型路由解析
-        let mut mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
+        // 2. 模型路由解析 (主模型在所有账号上均已限流时按回退链切换)
+        let selection = model_fallback::resolve_with_fallback(
+            &state.model_router,
+            &token_manager,
             &request_for_body.model,
-            &*state.custom_mapping.read().await,
-        );
+            &route_ctx,
+        )
+        .await;
+        let mut mapped_model = selection.model.clone();
+
+        // 能力校验 (工具 / 图片输入)
+        if let Err(e) = ModelRegistry::global().validate(&mapped_model, &route_ctx) {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "type": "error",
+                    "error": {
+                        "type": "invalid_request_error",
+                        "message": e
+                    }
+                }))
+            ).into_response();
+        }
         
         // 将 Claude 工具转为 Value 数组以便探测联网
         let tools_val: Option<Vec<Value>> = request_for_body.tools.as_ref().map(|list| {
@@ -528,9 +551,13 @@ pub async fn handle_messages(
         let session_id = Some(session_id_str.as_str());
 
         let force_rotate_token = attempt > 0;
-        let (access_token, project_id, email) = match token_manager.get_token(&config.request_type, force_rotate_token, session_id).await {
+        let (access_token, project_id, email, lease) = match token_manager.get_token_for_model(&config.request_type, force_rotate_token, session_id, &mapped_model).await {
             Ok(t) => t,
             Err(e) => {
+                if let Some((provider, body)) = fallback.take() {
+                    tracing::warn!("[{}] 账号池不可用 ({})，转发到 Fallback 服务商 {}", trace_id, e, provider.id());
+                    return forward::forward_json(&state, &provider, axum::http::Method::POST, "/v1/messages", &headers, body).await;
+                }
                 let safe_message = if e.contains("invalid_grant") {
                     "OAuth refresh failed (invalid_grant): refresh_token likely revoked/expired; reauthorize account(s) to restore service.".to_string()
                 } else {
@@ -549,7 +576,6 @@ pub async fn handle_messages(
             }
         };
 
-        last_email = Some(email.clone());
         info!("✓ Using account: {} (type: {})", email, config.request_type);
         
         
@@ -648,12 +674,32 @@ pub async fn handle_messages(
     let method = if actual_stream { "streamGenerateContent" } else { "generateContent" };
     let query = if actual_stream { Some("alt=sse") } else { None };
 
-    let response = match upstream.call_v1_internal(
+    // [Hedge] 按模型配置在首字节超时后换账号对冲
+    let hedge = state
+        .model_router
+        .read()
+        .await
+        .hedge_after(&request_with_mapped.model)
+        .map(|after| HedgeOptions {
+            after,
+            token_manager: &token_manager,
+            monitor: &state.monitor,
+            quota_group: &config.request_type,
+            model: &request_with_mapped.model,
+        });
+    let call = upstream.call_v1_internal_hedged(
         method,
         &access_token,
+        &email,
         gemini_body,
-        query
-    ).await {
+        query,
+        hedge,
+    ).await;
+    let email = call.email;
+    let lease = call.lease.unwrap_or(lease);
+    last_email = Some(email.clone());
+
+    let response = match call.result {
             Ok(r) => r,
             Err(e) => {
                 last_error = e.clone();
@@ -673,72 +719,65 @@ pub async fn handle_messages(
             if actual_stream {
                 let stream = response.bytes_stream();
                 let gemini_stream = Box::pin(stream);
-                let mut claude_stream = create_claude_sse_stream(gemini_stream, trace_id.clone(), email.clone());
-
-                // [FIX #530/#529] Peek first chunk to detect empty response and allow retry
-                // If the stream is empty or fails immediately, we should retry instead of sending 200 OK + empty body
-                let first_chunk = claude_stream.next().await;
-
-                match first_chunk {
-                    Some(Ok(bytes)) => {
-                        if bytes.is_empty() {
-                            tracing::warn!("[{}] Empty first chunk received, treating as Empty Response and retrying...", trace_id);
-                            last_error = "Empty response stream (0 bytes)".to_string();
-                            continue;
-                        }
-                        
-                        // We have data! Construct the combined stream
-                        let stream_rest = claude_stream;
-                        let combined_stream = Box::pin(futures::stream::once(async move { Ok(bytes) })
-                            .chain(stream_rest.map(|result| -> Result<Bytes, std::io::Error> {
-                                match result {
-                                    Ok(b) => Ok(b),
-                                    Err(e) => Ok(Bytes::from(format!("data: {{\"error\":\"{}\"}}\n\n", e))),
-                                }
-                            })));
+                let claude_stream = create_claude_sse_stream(gemini_stream, trace_id.clone(), email.clone());
+
+                // [FIX #530/#529] 首个内容块到达前缓冲，不向客户端输出任何字节
+                // 此前上游出错或返回空候选时换账号重试，客户端无感知；最后一次尝试的空响应照常返回
+                let buffered = match buffer_until_content(claude_stream, is_content_event).await {
+                    StreamStart::Ready(stream) => stream,
+                    StreamStart::Failed(e) => {
+                        tracing::warn!("[{}] Stream error before first content: {}, retrying...", trace_id, e);
+                        last_error = format!("Stream error: {}", e);
+                        continue;
+                    }
+                    StreamStart::Empty(stream) if attempt + 1 >= max_attempts => stream,
+                    StreamStart::Empty(_) => {
+                        tracing::warn!("[{}] Stream ended without content (Empty Response), retrying...", trace_id);
+                        last_error = "Empty response stream (no content)".to_string();
+                        continue;
+                    }
+                };
 
-                        // 判断客户端期望的格式
-                        if client_wants_stream {
-                            // 客户端本就要 Stream，直接返回 SSE
-                            return Response::builder()
+                let combined_stream = Box::pin(buffered.map(|result| -> Result<Bytes, std::io::Error> {
+                    match result {
+                        Ok(b) => Ok(b),
+                        Err(e) => Ok(Bytes::from(format!("data: {{\"error\":\"{}\"}}\n\n", e))),
+                    }
+                }));
+
+                // 判断客户端期望的格式
+                if client_wants_stream {
+                    // 客户端本就要 Stream，直接返回 SSE
+                    let response = Response::builder()
+                        .status(StatusCode::OK)
+                        .header(header::CONTENT_TYPE, "text/event-stream")
+                        .header(header::CACHE_CONTROL, "no-cache")
+                        .header(header::CONNECTION, "keep-alive")
+                        .header("X-Account-Email", &email)
+                        .header("X-Mapped-Model", &request_with_mapped.model)
+                        .body(Body::from_stream(combined_stream))
+                        .unwrap();
+                    // 账号并发槽位持有至流结束
+                    return lease.hold(model_fallback::annotate_response(response, &selection));
+                } else {
+                    // 客户端要非 Stream，需要收集完整响应并转换为 JSON
+                    use crate::proxy::mappers::claude::collect_stream_to_json;
+                    
+                    match collect_stream_to_json(combined_stream).await {
+                        Ok(full_response) => {
+                            info!("[{}] ✓ Stream collected and converted to JSON", trace_id);
+                            let response = Response::builder()
                                 .status(StatusCode::OK)
-                                .header(header::CONTENT_TYPE, "text/event-stream")
-                                .header(header::CACHE_CONTROL, "no-cache")
-                                .header(header::CONNECTION, "keep-alive")
+                                .header(header::CONTENT_TYPE, "application/json")
                                 .header("X-Account-Email", &email)
                                 .header("X-Mapped-Model", &request_with_mapped.model)
-                                .body(Body::from_stream(combined_stream))
+                                .body(Body::from(serde_json::to_string(&full_response).unwrap()))
                                 .unwrap();
-                        } else {
-                            // 客户端要非 Stream，需要收集完整响应并转换为 JSON
-                            use crate::proxy::mappers::claude::collect_stream_to_json;
-                            
-                            match collect_stream_to_json(combined_stream).await {
-                                Ok(full_response) => {
-                                    info!("[{}] ✓ Stream collected and converted to JSON", trace_id);
-                                    return Response::builder()
-                                        .status(StatusCode::OK)
-                                        .header(header::CONTENT_TYPE, "application/json")
-                                        .header("X-Account-Email", &email)
-                                        .header("X-Mapped-Model", &request_with_mapped.model)
-                                        .body(Body::from(serde_json::to_string(&full_response).unwrap()))
-                                        .unwrap();
-                                }
-                                Err(e) => {
-                                    return (StatusCode::INTERNAL_SERVER_ERROR, format!("Stream collection error: {}", e)).into_response();
-                                }
-                            }
+                            return model_fallback::annotate_response(response, &selection);
+                        }
+                        Err(e) => {
+                            return (StatusCode::INTERNAL_SERVER_ERROR, format!("Stream collection error: {}", e)).into_response();
                         }
-                    },
-                    Some(Err(e)) => {
-                        tracing::warn!("[{}] Stream error on first chunk: {}, retrying...", trace_id, e);
-                        last_error = format!("Stream error: {}", e);
-                        continue;
-                    },
-                    None => {
-                        tracing::warn!("[{}] Stream ended immediately (Empty Response), retrying...", trace_id);
-                        last_error = "Empty response stream (None)".to_string();
-                        continue;
                     }
                 }
             } else {
@@ -789,7 +828,8 @@ pub async fn handle_messages(
                     cache_info
                 );
 
-                return (StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", request_with_mapped.model.as_str())], Json(claude_response)).into_response();
+                let response = (StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", request_with_mapped.model.as_str())], Json(claude_response)).into_response();
+                return model_fallback::annotate_response(response, &selection);
             }
         }
         
@@ -803,9 +843,10 @@ pub async fn handle_messages(
         debug!("[{}] Upstream Error Response: {}", trace_id, error_text);
         
         // 3. 标记限流状态(用于 UI 显示) - 使用异步版本以支持实时配额刷新
-        // 🆕 传入实际使用的模型,实现模型级别限流,避免不同模型配额互相影响
+        // 仅配额 / 容量耗尽时锁定实际使用的模型 (驱动模型回退链)，其余 429 / 5xx 锁定整个账号
         if status_code == 429 || status_code == 529 || status_code == 503 || status_code == 500 {
-            token_manager.mark_rate_limited_async(&email, status_code, retry_after.as_deref(), &error_text, Some(&request_with_mapped.model)).await;
+            let model = crate::proxy::TokenManager::model_lock_scope(&error_text, &request_with_mapped.model);
+            token_manager.mark_rate_limited_async(&email, status_code, retry_after.as_deref(), &error_text, model).await;
         }
 
         // 4. 处理 400 错误 (Thinking 签名失效)
@@ -884,7 +925,12 @@ pub async fn handle_messages(
             return (status, [("X-Account-Email", email.as_str())], error_text).into_response();
         }
     }
-    
+
+    if let Some((provider, body)) = fallback {
+        tracing::warn!("[{}] 所有账号均已耗尽，转发到 Fallback 服务商 {}", trace_id, provider.id());
+        return forward::forward_json(&state, &provider, axum::http::Method::POST, "/v1/messages", &headers, body).await;
+    }
+
     if let Some(email) = last_email {
         (StatusCode::TOO_MANY_REQUESTS, [("X-Account-Email", email)], Json(json!({
             "type": "error",
@@ -906,20 +952,17 @@ pub async fn handle_messages(
 
 /// 列出可用模型
 pub async fn handle_list_models(State(state): State<AppState>) -> impl IntoResponse {
-    use crate::proxy::common::model_mapping::get_all_dynamic_models;
+    use crate::proxy::common::model_mapping::get_all_models_with_capabilities;
 
-    let model_ids = get_all_dynamic_models(
-        &state.custom_mapping,
+    let models = get_all_models_with_capabilities(
+        &state.model_router,
+        &state.model_catalog,
     ).await;
 
-    let data: Vec<_> = model_ids.into_iter().map(|id| {
-        json!({
-            "id": id,
-            "object": "model",
-            "created": 1706745600,
-            "owned_by": "antigravity"
-        })
-    }).collect();
+    let data: Vec<_> = models
+        .iter()
+        .map(crate::proxy::handlers::common::openai_model_entry)
+        .collect();
 
     Json(json!({
         "object": "list",
@@ -933,12 +976,17 @@ pub async fn handle_count_tokens(
     headers: HeaderMap,
     Json(body): Json<Value>,
 ) -> Response {
-    let zai = state.zai.read().await.clone();
-    let zai_enabled = zai.enabled && !matches!(zai.dispatch_mode, crate::proxy::ZaiDispatchMode::Off);
-
-    if zai_enabled {
-        return crate::proxy::providers::zai_anthropic::forward_anthropic_json(
+    // 只读查询，不影响生成请求的轮询与权重分流
+    let provider = state
+        .providers
+        .read()
+        .await
+        .peek(&[ProviderProtocol::Anthropic], state.token_manager.len());
+
+    if let Some(provider) = provider {
+        return forward::forward_json(
             &state,
+            &provider,
             axum::http::Method::POST,
             "/v1/messages/count_tokens",
             &headers,
@@ -947,11 +995,59 @@ pub async fn handle_count_tokens(
         .await;
     }
 
-    Json(json!({
-        "input_tokens": 0,
-        "output_tokens": 0
-    }))
-    .into_response()
+    let request: ClaudeRequest = match serde_json::from_value(body.clone()) {
+        Ok(r) => r,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "type": "error",
+                    "error": {
+                        "type": "invalid_request_error",
+                        "message": format!("Invalid request body: {}", e)
+                    }
+                }))
+            ).into_response();
+        }
+    };
+
+    let input_tokens = match count_tokens_upstream(&state, &request).await {
+        Ok(n) => n,
+        Err(e) => {
+            let estimated = crate::proxy::common::token_counter::estimate_claude_request_tokens(&body);
+            debug!("[CountTokens] Upstream unavailable ({}), using local estimate: {}", e, estimated);
+            estimated
+        }
+    };
+
+    Json(json!({ "input_tokens": input_tokens })).into_response()
+}
+
+/// 通过上游 countTokens 计算 Claude 请求的输入 token
+async fn count_tokens_upstream(state: &AppState, request: &ClaudeRequest) -> Result<u64, String> {
+    let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
+        &request.model,
+        &*state.model_router.read().await,
+        &RouteContext::new(ClientProtocol::Claude),
+    );
+    let tools_val: Option<Vec<Value>> = request.tools.as_ref().map(|list| {
+        list.iter().map(|t| serde_json::to_value(t).unwrap_or(json!({}))).collect()
+    });
+    let config = crate::proxy::mappers::common_utils::resolve_request_config(&request.model, &mapped_model, &tools_val);
+
+    // 只读取号，不影响生成请求的轮换、60s 锁定与并发计数
+    let (access_token, project_id, _email) = state.token_manager.peek_token(&config.final_model).await?;
+
+    let mut request_with_mapped = request.clone();
+    request_with_mapped.model = config.final_model.clone();
+    let gemini_body = transform_claude_request_in(&request_with_mapped, &project_id)?;
+    let inner = gemini_body.get("request").unwrap_or(&gemini_body);
+    let contents = crate::proxy::common::token_counter::build_count_tokens_contents(inner);
+
+    state
+        .upstream
+        .count_tokens(&access_token, &config.final_model, contents)
+        .await
 }
 
 // 移除已失效的简单单元测试，后续将补全完整的集成测试
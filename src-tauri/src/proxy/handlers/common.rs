@@ -1,7 +1,47 @@
-use axum::{extract::State, extract::Json, http::StatusCode, response::IntoResponse};
+use axum::{extract::Extension, extract::State, extract::Json, http::StatusCode, response::IntoResponse};
 use serde_json::{json, Value};
+use crate::proxy::common::model_registry::ModelRegistry;
+use crate::proxy::common::model_router::RouteContext;
+use crate::proxy::common::protocol_error::ClientProtocol;
+use crate::proxy::common::model_mapping::ListedModel;
+use crate::proxy::config::ClientApiKey;
+use crate::proxy::model_catalog::PoolModel;
 use crate::proxy::server::AppState;
 
+/// 模型列表的配额扩展字段 (账号池中可用账号数、最大剩余比例与最早重置时间)
+pub fn quota_extension(quota: &PoolModel) -> Value {
+    json!({
+        "accounts": quota.accounts,
+        "remaining_fraction": quota.remaining_fraction,
+        "reset_time": quota.reset_time
+    })
+}
+
+/// OpenAI 格式的模型条目 (/v1/models)，附带上下文窗口、能力与配额信息
+pub fn openai_model_entry(model: &ListedModel) -> Value {
+    let mut entry = json!({
+        "id": model.id,
+        "object": "model",
+        "created": 1706745600,
+        "owned_by": "antigravity"
+    });
+    if let Some(quota) = &model.quota {
+        entry["quota"] = quota_extension(quota);
+    }
+    if let Some(caps) = &model.capabilities {
+        entry["context_window"] = json!(caps.context_window);
+        entry["max_output_tokens"] = json!(caps.max_output_tokens);
+        entry["capabilities"] = json!({
+            "tools": caps.tools,
+            "vision": caps.vision,
+            "thinking": caps.thinking,
+            "search": caps.search,
+            "image_output": caps.image_output
+        });
+    }
+    entry
+}
+
 /// Detects model capabilities and configuration
 /// POST /v1/models/detect
 pub async fn handle_detect_model(
@@ -17,7 +57,8 @@ pub async fn handle_detect_model(
     // 1. Resolve mapping
     let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
         model_name,
-        &*state.custom_mapping.read().await,
+        &*state.model_router.read().await,
+        &RouteContext::default(),
     );
 
     // 2. Resolve capabilities
@@ -35,7 +76,8 @@ pub async fn handle_detect_model(
         "features": {
             "has_web_search": config.inject_google_search,
             "is_image_gen": config.request_type == "image_gen"
-        }
+        },
+        "capabilities": ModelRegistry::global().get(&mapped_model)
     });
 
     if let Some(img_conf) = config.image_config {
@@ -46,3 +88,74 @@ pub async fn handle_detect_model(
 
     Json(response).into_response()
 }
+
+/// 从 explain 请求体构造路由上下文
+///
+/// 支持直接给出 `protocol` / `api_key` / `tools` / `images` / `thinking`，
+/// 或通过 `request` 传入完整请求体自动探测特征；显式字段优先
+fn explain_context(body: &Value, client_key: Option<&ClientApiKey>) -> Result<RouteContext, String> {
+    let protocol = match body.get("protocol") {
+        Some(p) => Some(
+            serde_json::from_value::<ClientProtocol>(p.clone())
+                .map_err(|_| format!("Invalid 'protocol': {}", p))?,
+        ),
+        None => None,
+    };
+
+    let mut ctx = match body.get("request") {
+        Some(request) => RouteContext::from_request(protocol.unwrap_or(ClientProtocol::OpenAI), request, client_key),
+        None => RouteContext {
+            api_key: client_key.map(|k| k.name.clone()),
+            ..Default::default()
+        },
+    };
+    if protocol.is_some() {
+        ctx.protocol = protocol;
+    }
+    if let Some(key) = body.get("api_key").and_then(|v| v.as_str()) {
+        ctx.api_key = Some(key.to_string());
+    }
+    if let Some(v) = body.get("tools").and_then(|v| v.as_bool()) {
+        ctx.has_tools = v;
+    }
+    if let Some(v) = body.get("images").and_then(|v| v.as_bool()) {
+        ctx.has_images = v;
+    }
+    if let Some(v) = body.get("thinking").and_then(|v| v.as_bool()) {
+        ctx.has_thinking = v;
+    }
+    Ok(ctx)
+}
+
+/// Explains which routing rule a model resolves through
+/// POST /v1/models/explain
+pub async fn handle_explain_route(
+    State(state): State<AppState>,
+    client_key: Option<Extension<ClientApiKey>>,
+    Json(body): Json<Value>,
+) -> impl IntoResponse {
+    let model_name = body.get("model").and_then(|v| v.as_str()).unwrap_or("");
+    if model_name.is_empty() {
+        return (StatusCode::BAD_REQUEST, "Missing 'model' field").into_response();
+    }
+    let ctx = match explain_context(&body, client_key.as_deref()) {
+        Ok(ctx) => ctx,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+
+    let router = state.model_router.read().await;
+    let evaluations = router.explain(model_name, &ctx);
+    let matched = evaluations.iter().find(|e| e.matched).map(|e| e.rule.clone());
+    let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(model_name, &router, &ctx);
+
+    Json(json!({
+        "model": model_name,
+        "fallback_chain": router.fallback_chain(&mapped_model),
+        "mapped_model": mapped_model,
+        "source": matched.as_ref().map(|r| json!(r.source)).unwrap_or_else(|| json!("default")),
+        "matched_rule": matched,
+        "context": ctx,
+        "evaluations": evaluations
+    }))
+    .into_response()
+}
@@ -0,0 +1,223 @@
+// Embeddings 处理器
+// OpenAI /v1/embeddings 与 Gemini 原生 embedContent / batchEmbedContents 共用同一套账号轮换逻辑
+use axum::{
+    extract::{Extension, Json, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde_json::{json, Value};
+use tracing::{debug, error, info};
+
+use crate::proxy::common::model_router::RouteContext;
+use crate::proxy::common::protocol_error::{error_response, ClientProtocol};
+use crate::proxy::config::ClientApiKey;
+use crate::proxy::mappers::gemini::{
+    extract_embed_requests, extract_embedding_values, unwrap_response, wrap_embed_request,
+    EMBEDDING_REQUEST_TYPE,
+};
+use crate::proxy::mappers::openai::embeddings::{
+    build_embed_requests, build_embeddings_response, parse_input, EmbeddingsRequest,
+};
+use crate::proxy::server::AppState;
+
+const MAX_RETRY_ATTEMPTS: usize = 3;
+
+/// 成功的 Embedding 调用结果
+struct EmbedOutcome {
+    embeddings: Vec<Vec<f32>>,
+    email: String,
+}
+
+/// POST /v1/embeddings
+pub async fn handle_embeddings(
+    State(state): State<AppState>,
+    client_key: Option<Extension<ClientApiKey>>,
+    Json(body): Json<Value>,
+) -> Response {
+    let route_ctx = RouteContext::from_request(ClientProtocol::OpenAI, &body, client_key.as_deref());
+    let req: EmbeddingsRequest = match serde_json::from_value(body) {
+        Ok(r) => r,
+        Err(e) => {
+            return error_response(
+                ClientProtocol::OpenAI,
+                StatusCode::BAD_REQUEST,
+                &format!("Invalid request: {}", e),
+                None,
+            )
+        }
+    };
+    let texts = match parse_input(&req.input) {
+        Ok(t) => t,
+        Err(e) => return error_response(ClientProtocol::OpenAI, StatusCode::BAD_REQUEST, &e, None),
+    };
+
+    let mapped_model = crate::proxy::common::model_mapping::resolve_embedding_model_route(
+        &req.model,
+        &*state.model_router.read().await,
+        &route_ctx,
+    );
+    debug!(
+        "[Embeddings] {} inputs, model {} -> {}, dimensions: {:?}",
+        texts.len(),
+        req.model,
+        mapped_model,
+        req.dimensions
+    );
+
+    let requests = build_embed_requests(&texts, &mapped_model, req.dimensions);
+    let outcome = match embed_with_rotation(&state, &mapped_model, requests, ClientProtocol::OpenAI).await {
+        Ok(o) => o,
+        Err(resp) => return resp,
+    };
+
+    let prompt_tokens: u64 = texts
+        .iter()
+        .map(|t| crate::proxy::common::token_counter::estimate_text_tokens(t))
+        .sum();
+    let body = build_embeddings_response(&req.model, outcome.embeddings, req.wants_base64(), prompt_tokens);
+
+    (
+        StatusCode::OK,
+        [("X-Account-Email", outcome.email.as_str()), ("X-Mapped-Model", mapped_model.as_str())],
+        Json(body),
+    )
+        .into_response()
+}
+
+/// Gemini 原生 embedContent / batchEmbedContents (由 gemini::handle_generate 分发)
+pub async fn handle_gemini_embed(
+    state: &AppState,
+    model_name: &str,
+    method: &str,
+    body: &Value,
+    route_ctx: &RouteContext,
+) -> Response {
+    let mapped_model = crate::proxy::common::model_mapping::resolve_embedding_model_route(
+        model_name,
+        &*state.model_router.read().await,
+        route_ctx,
+    );
+    let requests = match extract_embed_requests(method, body, &mapped_model) {
+        Ok(r) => r,
+        Err(e) => return error_response(ClientProtocol::Gemini, StatusCode::BAD_REQUEST, &e, None),
+    };
+
+    let outcome = match embed_with_rotation(state, &mapped_model, requests, ClientProtocol::Gemini).await {
+        Ok(o) => o,
+        Err(resp) => return resp,
+    };
+
+    let mut embeddings: Vec<Value> = outcome
+        .embeddings
+        .into_iter()
+        .map(|values| json!({ "values": values }))
+        .collect();
+    let body = if method == "batchEmbedContents" {
+        json!({ "embeddings": embeddings })
+    } else {
+        json!({ "embedding": embeddings.pop().unwrap_or_else(|| json!({ "values": [] })) })
+    };
+
+    (
+        StatusCode::OK,
+        [("X-Account-Email", outcome.email.as_str()), ("X-Mapped-Model", mapped_model.as_str())],
+        Json(body),
+    )
+        .into_response()
+}
+
+/// 使用独立的 embedding 配额分组获取账号并调用上游，限流/鉴权失败时轮换账号
+async fn embed_with_rotation(
+    state: &AppState,
+    mapped_model: &str,
+    requests: Vec<Value>,
+    protocol: ClientProtocol,
+) -> Result<EmbedOutcome, Response> {
+    let expected = requests.len();
+    let upstream = state.upstream.clone();
+    let token_manager = state.token_manager.clone();
+    let max_attempts = MAX_RETRY_ATTEMPTS.min(token_manager.len()).max(1);
+    let mut last_error = String::new();
+
+    for attempt in 0..max_attempts {
+        let (access_token, project_id, email, _lease) = match token_manager
+            .get_token_for_model(EMBEDDING_REQUEST_TYPE, attempt > 0, None, mapped_model)
+            .await
+        {
+            Ok(t) => t,
+            Err(e) => {
+                return Err(error_response(
+                    protocol,
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    &format!("Token error: {}", e),
+                    None,
+                ))
+            }
+        };
+        info!("✓ Using account: {} (type: {})", email, EMBEDDING_REQUEST_TYPE);
+
+        let wrapped_body = wrap_embed_request(requests.clone(), &project_id, mapped_model);
+        let response = match upstream
+            .call_v1_internal("batchEmbedContents", &access_token, wrapped_body, None)
+            .await
+        {
+            Ok(r) => r,
+            Err(e) => {
+                debug!("[Embeddings] Request failed on attempt {}/{}: {}", attempt + 1, max_attempts, e);
+                last_error = e;
+                continue;
+            }
+        };
+
+        let status = response.status();
+        if status.is_success() {
+            let resp: Value = response.json().await.map_err(|e| {
+                error_response(protocol, StatusCode::BAD_GATEWAY, &format!("Parse error: {}", e), None)
+            })?;
+            let embeddings = extract_embedding_values(&unwrap_response(&resp))
+                .map_err(|e| error_response(protocol, StatusCode::BAD_GATEWAY, &e, None))?;
+            if embeddings.len() != expected {
+                return Err(error_response(
+                    protocol,
+                    StatusCode::BAD_GATEWAY,
+                    &format!("Upstream returned {} embeddings for {} inputs", embeddings.len(), expected),
+                    None,
+                ));
+            }
+            return Ok(EmbedOutcome { embeddings, email });
+        }
+
+        let status_code = status.as_u16();
+        let retry_after = response
+            .headers()
+            .get("Retry-After")
+            .and_then(|h| h.to_str().ok())
+            .map(|s| s.to_string());
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| format!("HTTP {}", status_code));
+        last_error = format!("HTTP {}: {}", status_code, error_text);
+        error!("[Embeddings-Upstream] Error Response {}: {}", status_code, error_text);
+
+        if status_code == 429 || status_code == 529 || status_code == 503 || status_code == 500 {
+            // 配额 / 容量耗尽仅锁定 embedding 模型，不影响该账号的对话请求
+            token_manager.mark_model_rate_limited(&email, status_code, retry_after.as_deref(), &error_text, mapped_model);
+            if error_text.contains("QUOTA_EXHAUSTED") {
+                return Err(error_response(protocol, status, &error_text, None));
+            }
+            continue;
+        }
+        if status_code == 403 || status_code == 401 {
+            continue;
+        }
+        return Err(error_response(protocol, status, &error_text, None));
+    }
+
+    Err(error_response(
+        protocol,
+        StatusCode::TOO_MANY_REQUESTS,
+        &format!("All accounts exhausted. Last error: {}", last_error),
+        None,
+    ))
+}
@@ -1,10 +1,17 @@
 // Gemini Handler
-use axum::{extract::State, extract::{Json, Path}, http::StatusCode, response::IntoResponse};
+use axum::{extract::State, extract::{Extension, Json, Path}, http::{HeaderMap, StatusCode}, response::IntoResponse};
 use serde_json::{json, Value};
 use tracing::{debug, error, info};
 
 use crate::proxy::mappers::gemini::{wrap_request, unwrap_response};
+use crate::proxy::common::model_fallback;
+use crate::proxy::common::model_registry::ModelRegistry;
+use crate::proxy::common::model_router::RouteContext;
+use crate::proxy::common::protocol_error::ClientProtocol;
+use crate::proxy::config::{ClientApiKey, ProviderProtocol};
+use crate::proxy::providers::forward;
 use crate::proxy::server::AppState;
+use crate::proxy::upstream::hedge::HedgeOptions;
 use crate::proxy::session_manager::SessionManager;
  
 const MAX_RETRY_ATTEMPTS: usize = 3;
@@ -14,8 +21,11 @@ const MAX_RETRY_ATTEMPTS: usize = 3;
 pub async fn handle_generate(
     State(state): State<AppState>,
     Path(model_action): Path<String>,
+    headers: HeaderMap,
+    client_key: Option<Extension<ClientApiKey>>,
     Json(body): Json<Value>
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let route_ctx = RouteContext::from_request(ClientProtocol::Gemini, &body, client_key.as_deref());
     // 解析 model:method
     let (model_name, method) = if let Some((m, action)) = model_action.rsplit_once(':') {
         (m.to_string(), action.to_string())
@@ -26,26 +36,54 @@ pub async fn handle_generate(
     crate::modules::logger::log_info(&format!("Received Gemini request: {}/{}", model_name, method));
 
     // 1. 验证方法
+    if method == "countTokens" {
+        return Ok(Json(count_tokens_inner(&state, &model_name, &body).await).into_response());
+    }
+    if method == "embedContent" || method == "batchEmbedContents" {
+        return Ok(super::embeddings::handle_gemini_embed(&state, &model_name, &method, &body, &route_ctx).await);
+    }
     if method != "generateContent" && method != "streamGenerateContent" {
         return Err((StatusCode::BAD_REQUEST, format!("Unsupported method: {}", method)));
     }
     let is_stream = method == "streamGenerateContent";
 
+    // Gemini 兼容服务商按调度方式分流
+    let (provider, fallback) = {
+        let providers = state.providers.read().await;
+        (
+            providers.select(&[ProviderProtocol::Gemini], state.token_manager.len(), &state.provider_rr),
+            providers.fallback(&[ProviderProtocol::Gemini]),
+        )
+    };
+    if let Some(provider) = provider {
+        return Ok(forward::forward_gemini(&state, &provider, &model_name, &method, &headers, body).await);
+    }
+
     // 2. 获取 UpstreamClient 和 TokenManager
     let upstream = state.upstream.clone();
-    let token_manager = state.token_manager;
+    let token_manager = state.token_manager.clone();
     let pool_size = token_manager.len();
-    let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
+    // 回退链上的每个模型额外允许一次尝试
+    let fallback_budget = model_fallback::fallback_budget(&state.model_router, &model_name, &route_ctx).await;
+    let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1) + fallback_budget;
     
     let mut last_error = String::new();
     let mut last_email: Option<String> = None;
 
     for attempt in 0..max_attempts {
-        // 3. 模型路由解析
-        let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
+        // 3. 模型路由解析 (主模型在所有账号上均已限流时按回退链切换)
+        let selection = model_fallback::resolve_with_fallback(
+            &state.model_router,
+            &token_manager,
             &model_name,
-            &*state.custom_mapping.read().await,
-        );
+            &route_ctx,
+        )
+        .await;
+        let mapped_model = selection.model.clone();
+        // 能力校验 (工具 / 图片输入)
+        if let Err(e) = ModelRegistry::global().validate(&mapped_model, &route_ctx) {
+            return Err((StatusCode::BAD_REQUEST, e));
+        }
         // 提取 tools 列表以进行联网探测 (Gemini 风格可能是嵌套的)
         let tools_val: Option<Vec<Value>> = body.get("tools").and_then(|t| t.as_array()).map(|arr| {
             let mut flattened = Vec::new();
@@ -66,14 +104,18 @@ pub async fn handle_generate(
         let session_id = SessionManager::extract_gemini_session_id(&body, &model_name);
 
         // 关键：在重试尝试 (attempt > 0) 时强制轮换账号
-        let (access_token, project_id, email) = match token_manager.get_token(&config.request_type, attempt > 0, Some(&session_id)).await {
+        let (access_token, project_id, email, lease) = match token_manager.get_token_for_model(&config.request_type, attempt > 0, Some(&session_id), &mapped_model).await {
             Ok(t) => t,
             Err(e) => {
+                // 账号池耗尽 (全部限流 / 失败) 时转发到 Fallback 服务商
+                if let Some(provider) = fallback {
+                    tracing::warn!("[Provider] 账号池不可用 ({})，转发到 Fallback 服务商 {}", e, provider.id());
+                    return Ok(forward::forward_gemini(&state, &provider, &model_name, &method, &headers, body).await);
+                }
                 return Err((StatusCode::SERVICE_UNAVAILABLE, format!("Token error: {}", e)));
             }
         };
 
-        last_email = Some(email.clone());
         info!("✓ Using account: {} (type: {})", email, config.request_type);
 
         // 5. 包装请求 (project injection)
@@ -83,9 +125,27 @@ pub async fn handle_generate(
         let query_string = if is_stream { Some("alt=sse") } else { None };
         let upstream_method = if is_stream { "streamGenerateContent" } else { "generateContent" };
 
-        let response = match upstream
-            .call_v1_internal(upstream_method, &access_token, wrapped_body, query_string)
-            .await {
+        // [Hedge] 按模型配置在首字节超时后换账号对冲
+        let hedge = state
+            .model_router
+            .read()
+            .await
+            .hedge_after(&mapped_model)
+            .map(|after| HedgeOptions {
+                after,
+                token_manager: &token_manager,
+                monitor: &state.monitor,
+                quota_group: &config.request_type,
+                model: &mapped_model,
+            });
+        let call = upstream
+            .call_v1_internal_hedged(upstream_method, &access_token, &email, wrapped_body, query_string, hedge)
+            .await;
+        let email = call.email;
+        let lease = call.lease.unwrap_or(lease);
+        last_email = Some(email.clone());
+
+        let response = match call.result {
                 Ok(r) => r,
                 Err(e) => {
                     last_error = e.clone();
@@ -160,7 +220,7 @@ pub async fn handle_generate(
                 };
                 
                 let body = Body::from_stream(stream);
-                return Ok(Response::builder()
+                let response = Response::builder()
                     .header("Content-Type", "text/event-stream")
                     .header("Cache-Control", "no-cache")
                     .header("Connection", "keep-alive")
@@ -168,7 +228,9 @@ pub async fn handle_generate(
                     .header("X-Mapped-Model", &mapped_model)
                     .body(body)
                     .unwrap()
-                    .into_response());
+                    .into_response();
+                // 账号并发槽位持有至流结束
+                return Ok(lease.hold(model_fallback::annotate_response(response, &selection)));
             }
 
             let gemini_resp: Value = response
@@ -177,7 +239,8 @@ pub async fn handle_generate(
                 .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)))?;
 
             let unwrapped = unwrap_response(&gemini_resp);
-            return Ok((StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(unwrapped)).into_response());
+            let response = (StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(unwrapped)).into_response();
+            return Ok(model_fallback::annotate_response(response, &selection));
         }
 
         // 处理错误并重试
@@ -188,11 +251,12 @@ pub async fn handle_generate(
  
         // 只有 429 (限流), 529 (过载), 503, 403 (权限) 和 401 (认证失效) 触发账号轮换
         if status_code == 429 || status_code == 529 || status_code == 503 || status_code == 500 || status_code == 403 || status_code == 401 {
-            // 记录限流信息 (全局同步)
-            token_manager.mark_rate_limited(&email, status_code, retry_after.as_deref(), &error_text);
+            // 记录限流信息 (全局同步，配额/容量耗尽仅锁定当前模型)
+            token_manager.mark_model_rate_limited(&email, status_code, retry_after.as_deref(), &error_text, &mapped_model);
 
             // 只有明确包含 "QUOTA_EXHAUSTED" 才停止，避免误判上游的频率限制提示 (如 "check quota")
-            if status_code == 429 && error_text.contains("QUOTA_EXHAUSTED") {
+            // 配置了回退链时继续尝试，由下一轮选择回退模型
+            if status_code == 429 && error_text.contains("QUOTA_EXHAUSTED") && !selection.has_fallback {
                 error!("Gemini Quota exhausted (429) on account {} attempt {}/{}, stopping to protect pool.", email, attempt + 1, max_attempts);
                 return Ok((status, [("X-Account-Email", email.as_str())], error_text).into_response());
             }
@@ -206,6 +270,10 @@ pub async fn handle_generate(
         return Ok((status, [("X-Account-Email", email.as_str())], error_text).into_response());
     }
 
+    if let Some(provider) = fallback {
+        tracing::warn!("[Provider] 所有账号均已耗尽，转发到 Fallback 服务商 {}", provider.id());
+        return Ok(forward::forward_gemini(&state, &provider, &model_name, &method, &headers, body).await);
+    }
     if let Some(email) = last_email {
         Ok((StatusCode::TOO_MANY_REQUESTS, [("X-Account-Email", email)], format!("All accounts exhausted. Last error: {}", last_error)).into_response())
     } else {
@@ -214,27 +282,37 @@ pub async fn handle_generate(
 }
 
 pub async fn handle_list_models(State(state): State<AppState>) -> Result<impl IntoResponse, (StatusCode, String)> {
-    use crate::proxy::common::model_mapping::get_all_dynamic_models;
+    use crate::proxy::common::model_mapping::get_all_models_with_capabilities;
 
     // 获取所有动态模型列表（与 /v1/models 一致）
-    let model_ids = get_all_dynamic_models(
-        &state.custom_mapping,
+    let model_entries = get_all_models_with_capabilities(
+        &state.model_router,
+        &state.model_catalog,
     ).await;
 
-    // 转换为 Gemini API 格式
-    let models: Vec<_> = model_ids.into_iter().map(|id| {
-        json!({
+    // 转换为 Gemini API 格式 (未登记能力的模型沿用默认上限)
+    let models: Vec<_> = model_entries.into_iter().map(|model| {
+        let (input_limit, output_limit) = model
+            .capabilities
+            .map(|c| (c.context_window, c.max_output_tokens))
+            .unwrap_or((128000, 8192));
+        let id = model.id;
+        let mut entry = json!({
             "name": format!("models/{}", id),
             "version": "001",
             "displayName": id.clone(),
             "description": "",
-            "inputTokenLimit": 128000,
-            "outputTokenLimit": 8192,
+            "inputTokenLimit": input_limit,
+            "outputTokenLimit": output_limit,
             "supportedGenerationMethods": ["generateContent", "countTokens"],
             "temperature": 1.0,
             "topP": 0.95,
             "topK": 64
-        })
+        });
+        if let Some(quota) = &model.quota {
+            entry["quota"] = crate::proxy::handlers::common::quota_extension(quota);
+        }
+        entry
     }).collect();
 
     Ok(Json(json!({ "models": models })))
@@ -247,10 +325,42 @@ pub async fn handle_get_model(Path(model_name): Path<String>) -> impl IntoRespon
     }))
 }
 
-pub async fn handle_count_tokens(State(state): State<AppState>, Path(_model_name): Path<String>, Json(_body): Json<Value>) -> Result<impl IntoResponse, (StatusCode, String)> {
-    let model_group = "gemini";
-    let (_access_token, _project_id, _) = state.token_manager.get_token(model_group, false, None).await
-        .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("Token error: {}", e)))?;
-    
-    Ok(Json(json!({"totalTokens": 0})))
+pub async fn handle_count_tokens(State(state): State<AppState>, Path(model_name): Path<String>, Json(body): Json<Value>) -> Result<impl IntoResponse, (StatusCode, String)> {
+    Ok(Json(count_tokens_inner(&state, &model_name, &body).await))
+}
+
+/// 计算 Gemini 请求的输入 token：优先调用上游 countTokens，无可用账号或失败时回退到本地估算
+async fn count_tokens_inner(state: &AppState, model_name: &str, body: &Value) -> Value {
+    use crate::proxy::common::token_counter::{build_count_tokens_contents, estimate_gemini_request_tokens};
+
+    // countTokens 支持直接传 contents 或包装在 generateContentRequest 中
+    let request = body.get("generateContentRequest").unwrap_or(body);
+    let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
+        model_name,
+        &*state.model_router.read().await,
+        &RouteContext::from_request(ClientProtocol::Gemini, request, None),
+    );
+    let config = crate::proxy::mappers::common_utils::resolve_request_config(model_name, &mapped_model, &None);
+
+    // 只读取号，不影响生成请求的轮换、60s 锁定与并发计数
+    let upstream_result = match state.token_manager.peek_token(&config.final_model).await {
+        Ok((access_token, _project_id, _email)) => {
+            state
+                .upstream
+                .count_tokens(&access_token, &config.final_model, build_count_tokens_contents(request))
+                .await
+        }
+        Err(e) => Err(e),
+    };
+
+    let total_tokens = match upstream_result {
+        Ok(n) => n,
+        Err(e) => {
+            let estimated = estimate_gemini_request_tokens(body);
+            debug!("[CountTokens] Upstream unavailable ({}), using local estimate: {}", e, estimated);
+            estimated
+        }
+    };
+
+    json!({"totalTokens": total_tokens})
 }
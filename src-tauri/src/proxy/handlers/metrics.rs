@@ -0,0 +1,35 @@
+// Prometheus 指标端点 (仅主密钥或未启用认证时可用，指标中包含账号邮箱与 token 过期时间)
+use axum::{
+    extract::{Extension, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+
+use crate::proxy::config::ClientApiKey;
+use crate::proxy::server::AppState;
+use crate::proxy::usage;
+
+/// GET /metrics (Prometheus text exposition format)
+pub async fn handle_metrics(
+    State(state): State<AppState>,
+    client_key: Option<Extension<ClientApiKey>>,
+) -> Response {
+    if let Some(Extension(key)) = &client_key {
+        if !usage::is_admin_key(key) {
+            return (StatusCode::FORBIDDEN, "Metrics require the primary API key").into_response();
+        }
+    }
+
+    let mut body = state.monitor.metrics.render(&state.token_manager);
+    body.push_str(&crate::proxy::metrics::render_endpoint_health(
+        &state.monitor.endpoints.snapshot(),
+    ));
+    body.push_str(&crate::proxy::metrics::render_token_refresh(
+        &state.monitor.token_refresh.snapshot(),
+    ));
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        body,
+    )
+        .into_response()
+}
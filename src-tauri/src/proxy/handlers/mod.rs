@@ -8,4 +8,9 @@ pub mod mcp;
 pub mod common;
 pub mod audio;  // 音频转录处理器 (PR #311)
 pub mod warmup; // 预热处理器
+pub mod usage;  // 密钥用量查询
+pub mod sessions; // 粘性会话管理
+pub mod metrics; // Prometheus 指标
+pub mod responses; // OpenAI Responses API (有状态)
+pub mod embeddings; // Embeddings (OpenAI /v1/embeddings + Gemini embedContent)
 
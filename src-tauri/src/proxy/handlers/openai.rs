@@ -1,5 +1,5 @@
 // OpenAI Handler
-use axum::{extract::Json, extract::State, http::StatusCode, response::IntoResponse};
+use axum::{extract::Extension, extract::Json, extract::State, http::{HeaderMap, Method, StatusCode}, response::IntoResponse};
 use base64::Engine as _; 
 use bytes::Bytes;
 use serde_json::{json, Value};
@@ -9,15 +9,57 @@ use crate::proxy::mappers::openai::{
     transform_openai_request, transform_openai_response, OpenAIRequest,
 };
 // use crate::proxy::upstream::client::UpstreamClient; // 通过 state 获取
+use crate::proxy::common::model_fallback;
+use crate::proxy::common::model_registry::ModelRegistry;
+use crate::proxy::common::model_router::RouteContext;
+use crate::proxy::common::protocol_error::ClientProtocol;
+use crate::proxy::config::{ClientApiKey, ProviderProtocol};
+use crate::proxy::providers::forward;
 use crate::proxy::server::AppState;
+use crate::proxy::upstream::hedge::HedgeOptions;
+use crate::proxy::mappers::openai::streaming::is_content_chunk;
+use crate::proxy::mappers::stream_guard::{buffer_until_content, StreamStart};
 
 const MAX_RETRY_ATTEMPTS: usize = 3;
 use crate::proxy::session_manager::SessionManager;
 
+/// Chat Completions 由 OpenAI 服务商直接承接，Anthropic 服务商需开启 cross_protocol
+const CHAT_PROTOCOLS: [ProviderProtocol; 2] = [ProviderProtocol::OpenAI, ProviderProtocol::Anthropic];
+
+/// 转发到服务商：OpenAI 兼容服务商直接转发；Anthropic 兼容服务商经协议转换后转发
+async fn forward_chat(
+    state: &AppState,
+    provider: &crate::proxy::providers::Provider,
+    headers: &HeaderMap,
+    body: Value,
+) -> axum::response::Response {
+    match provider.config.protocol {
+        ProviderProtocol::Anthropic => forward::forward_openai_chat_to_anthropic(state, provider, body).await,
+        _ => forward::forward_json(state, provider, Method::POST, "/v1/chat/completions", headers, body).await,
+    }
+}
+
 pub async fn handle_chat_completions(
     State(state): State<AppState>,
+    headers: HeaderMap,
+    client_key: Option<Extension<ClientApiKey>>,
     Json(body): Json<Value>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let route_ctx = RouteContext::from_request(ClientProtocol::OpenAI, &body, client_key.as_deref());
+
+    let (provider, fallback) = {
+        let providers = state.providers.read().await;
+        (
+            providers.select(&CHAT_PROTOCOLS, state.token_manager.len(), &state.provider_rr),
+            providers.fallback(&CHAT_PROTOCOLS),
+        )
+    };
+    if let Some(provider) = provider {
+        return Ok(forward_chat(&state, &provider, &headers, body).await);
+    }
+    // 配置了 Fallback 服务商时保留原始请求体，账号池耗尽 (全部限流 / 失败) 时转发
+    let mut fallback = fallback.map(|provider| (provider, body.clone()));
+
     let mut openai_req: OpenAIRequest = serde_json::from_value(body)
         .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid request: {}", e)))?;
 
@@ -42,19 +84,39 @@ pub async fn handle_chat_completions(
 
     // 1. 获取 UpstreamClient (Clone handle)
     let upstream = state.upstream.clone();
-    let token_manager = state.token_manager;
+    let token_manager = state.token_manager.clone();
     let pool_size = token_manager.len();
-    let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
+    // [Structured Outputs] strict json_schema 需要校验输出，校验失败时额外允许重试一次
+    let strict_schema = openai_req
+        .response_format
+        .as_ref()
+        .and_then(|f| f.strict_schema())
+        .cloned();
+    // 回退链上的每个模型额外允许一次尝试
+    let fallback_budget =
+        model_fallback::fallback_budget(&state.model_router, &openai_req.model, &route_ctx).await;
+    let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1)
+        + usize::from(strict_schema.is_some())
+        + fallback_budget;
+    let mut schema_retry_used = false;
 
     let mut last_error = String::new();
     let mut last_email: Option<String> = None;
 
     for attempt in 0..max_attempts {
-        // 2. 模型路由解析
-        let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
+        // 2. 模型路由解析 (主模型在所有账号上均已限流时按回退链切换)
+        let selection = model_fallback::resolve_with_fallback(
+            &state.model_router,
+            &token_manager,
             &openai_req.model,
-            &*state.custom_mapping.read().await,
-        );
+            &route_ctx,
+        )
+        .await;
+        let mapped_model = selection.model.clone();
+        // 能力校验 (工具 / 图片输入)
+        if let Err(e) = ModelRegistry::global().validate(&mapped_model, &route_ctx) {
+            return Err((StatusCode::BAD_REQUEST, e));
+        }
         // 将 OpenAI 工具转为 Value 数组以便探测联网
         let tools_val: Option<Vec<Value>> = openai_req
             .tools
@@ -71,12 +133,16 @@ pub async fn handle_chat_completions(
 
         // 4. 获取 Token (使用准确的 request_type)
         // 关键：在重试尝试 (attempt > 0) 时强制轮换账号
-        let (access_token, project_id, email) = match token_manager
-            .get_token(&config.request_type, attempt > 0, Some(&session_id))
+        let (access_token, project_id, email, lease) = match token_manager
+            .get_token_for_model(&config.request_type, attempt > 0, Some(&session_id), &mapped_model)
             .await
         {
             Ok(t) => t,
             Err(e) => {
+                if let Some((provider, body)) = fallback.take() {
+                    tracing::warn!("[Provider] 账号池不可用 ({})，转发到 Fallback 服务商 {}", e, provider.id());
+                    return Ok(forward_chat(&state, &provider, &headers, body).await);
+                }
                 return Err((
                     StatusCode::SERVICE_UNAVAILABLE,
                     format!("Token error: {}", e),
@@ -84,7 +150,6 @@ pub async fn handle_chat_completions(
             }
         };
 
-        last_email = Some(email.clone());
         info!("✓ Using account: {} (type: {})", email, config.request_type);
 
         // 4. 转换请求
@@ -112,10 +177,27 @@ pub async fn handle_chat_completions(
         };
         let query_string = if actual_stream { Some("alt=sse") } else { None };
 
-        let response = match upstream
-            .call_v1_internal(method, &access_token, gemini_body, query_string)
+        // [Hedge] 按模型配置在首字节超时后换账号对冲
+        let hedge = state
+            .model_router
+            .read()
             .await
-        {
+            .hedge_after(&mapped_model)
+            .map(|after| HedgeOptions {
+                after,
+                token_manager: &token_manager,
+                monitor: &state.monitor,
+                quota_group: &config.request_type,
+                model: &mapped_model,
+            });
+        let call = upstream
+            .call_v1_internal_hedged(method, &access_token, &email, gemini_body, query_string, hedge)
+            .await;
+        let email = call.email;
+        let lease = call.lease.unwrap_or(lease);
+        last_email = Some(email.clone());
+
+        let response = match call.result {
             Ok(r) => r,
             Err(e) => {
                 last_error = e.clone();
@@ -138,14 +220,33 @@ pub async fn handle_chat_completions(
                 use axum::response::Response;
 
                 let gemini_stream = response.bytes_stream();
-                let openai_stream =
-                    create_openai_sse_stream(Box::pin(gemini_stream), openai_req.model.clone());
+                let openai_stream = create_openai_sse_stream(
+                    Box::pin(gemini_stream),
+                    openai_req.model.clone(),
+                    client_wants_stream && openai_req.include_stream_usage(),
+                );
+
+                // 首个内容 chunk 到达前缓冲，此前上游出错或返回空候选时换账号重试 (客户端无感知)
+                let openai_stream = match buffer_until_content(openai_stream, is_content_chunk).await {
+                    StreamStart::Ready(stream) => stream,
+                    StreamStart::Failed(e) => {
+                        tracing::warn!("[OpenAI] Stream error before first content: {}, retrying...", e);
+                        last_error = format!("Stream error: {}", e);
+                        continue;
+                    }
+                    StreamStart::Empty(stream) if attempt + 1 >= max_attempts => stream,
+                    StreamStart::Empty(_) => {
+                        tracing::warn!("[OpenAI] Stream ended without content (Empty Response), retrying...");
+                        last_error = "Empty response stream (no content)".to_string();
+                        continue;
+                    }
+                };
                 
                 // 判断客户端期望的格式
                 if client_wants_stream {
                     // 客户端本就要 Stream，直接返回 SSE
                     let body = Body::from_stream(openai_stream);
-                    return Ok(Response::builder()
+                    let response = Response::builder()
                         .header("Content-Type", "text/event-stream")
                         .header("Cache-Control", "no-cache")
                         .header("Connection", "keep-alive")
@@ -153,7 +254,9 @@ pub async fn handle_chat_completions(
                         .header("X-Mapped-Model", &mapped_model)
                         .body(body)
                         .unwrap()
-                        .into_response());
+                        .into_response();
+                    // 账号并发槽位持有至流结束
+                    return Ok(lease.hold(model_fallback::annotate_response(response, &selection)));
                 } else {
                     // 客户端要非 Stream，需要收集完整响应并转换为 JSON
                     use crate::proxy::mappers::openai::collect_openai_stream_to_json;
@@ -170,7 +273,19 @@ pub async fn handle_chat_completions(
                     match collect_openai_stream_to_json(sse_stream).await {
                         Ok(full_response) => {
                             info!("[OpenAI] ✓ Stream collected and converted to JSON");
-                            return Ok((StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(full_response)).into_response());
+                            if let Some(schema) = &strict_schema {
+                                if let Err(e) = validate_structured_output(&full_response, schema) {
+                                    last_error = format!("Structured output validation failed: {}", e);
+                                    if !schema_retry_used && attempt + 1 < max_attempts {
+                                        schema_retry_used = true;
+                                        tracing::warn!("[OpenAI] {}, retrying once", last_error);
+                                        continue;
+                                    }
+                                    return Ok(schema_validation_error(&last_error, &email));
+                                }
+                            }
+                            let response = (StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(full_response)).into_response();
+                            return Ok(model_fallback::annotate_response(response, &selection));
                         }
                         Err(e) => {
                             return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Stream collection error: {}", e)));
@@ -185,7 +300,19 @@ pub async fn handle_chat_completions(
                 .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)))?;
 
             let openai_response = transform_openai_response(&gemini_resp);
-            return Ok((StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(openai_response)).into_response());
+            if let Some(schema) = &strict_schema {
+                if let Err(e) = validate_structured_output(&openai_response, schema) {
+                    last_error = format!("Structured output validation failed: {}", e);
+                    if !schema_retry_used && attempt + 1 < max_attempts {
+                        schema_retry_used = true;
+                        tracing::warn!("[OpenAI] {}, retrying once", last_error);
+                        continue;
+                    }
+                    return Ok(schema_validation_error(&last_error, &email));
+                }
+            }
+            let response = (StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(openai_response)).into_response();
+            return Ok(model_fallback::annotate_response(response, &selection));
         }
 
         // 处理特定错误并重试
@@ -203,8 +330,8 @@ pub async fn handle_chat_completions(
 
         // 429/529/503 智能处理
         if status_code == 429 || status_code == 529 || status_code == 503 || status_code == 500 {
-            // 记录限流信息 (全局同步)
-            token_manager.mark_rate_limited(&email, status_code, retry_after.as_deref(), &error_text);
+            // 记录限流信息 (全局同步，配额/容量耗尽仅锁定当前模型)
+            token_manager.mark_model_rate_limited(&email, status_code, retry_after.as_deref(), &error_text, &mapped_model);
 
             // 1. 优先尝试解析 RetryInfo (由 Google Cloud 直接下发)
             if let Some(delay_ms) = crate::proxy::upstream::retry::parse_retry_delay(&error_text) {
@@ -222,7 +349,8 @@ pub async fn handle_chat_completions(
             }
 
             // 2. 只有明确包含 "QUOTA_EXHAUSTED" 才停止，避免误判频率提示 (如 "check quota")
-            if error_text.contains("QUOTA_EXHAUSTED") {
+            //    配置了回退链时继续尝试，由下一轮选择回退模型
+            if error_text.contains("QUOTA_EXHAUSTED") && !selection.has_fallback {
                 error!(
                     "OpenAI Quota exhausted (429) on account {} attempt {}/{}, stopping to protect pool.",
                     email,
@@ -264,6 +392,10 @@ pub async fn handle_chat_completions(
     }
 
     // 所有尝试均失败
+    if let Some((provider, body)) = fallback {
+        tracing::warn!("[Provider] 所有账号均已耗尽，转发到 Fallback 服务商 {}", provider.id());
+        return Ok(forward_chat(&state, &provider, &headers, body).await);
+    }
     if let Some(email) = last_email {
         Ok((
             StatusCode::TOO_MANY_REQUESTS,
@@ -278,14 +410,55 @@ pub async fn handle_chat_completions(
     }
 }
 
+/// 按 response_format.json_schema 校验每个候选的输出 (工具调用类候选跳过)
+fn validate_structured_output(
+    response: &crate::proxy::mappers::openai::OpenAIResponse,
+    schema: &Value,
+) -> Result<(), String> {
+    use crate::proxy::mappers::openai::{OpenAIContent, OpenAIContentBlock};
+
+    for choice in &response.choices {
+        let text = match &choice.message.content {
+            Some(OpenAIContent::String(s)) => s.clone(),
+            Some(OpenAIContent::Array(blocks)) => blocks
+                .iter()
+                .filter_map(|b| match b {
+                    OpenAIContentBlock::Text { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join(""),
+            None => String::new(),
+        };
+        if text.trim().is_empty() && choice.message.tool_calls.is_some() {
+            continue;
+        }
+        crate::proxy::common::schema_validator::validate_text(&text, schema)
+            .map_err(|e| format!("choice {}: {}", choice.index, e))?;
+    }
+    Ok(())
+}
+
+fn schema_validation_error(message: &str, email: &str) -> axum::response::Response {
+    use crate::proxy::common::protocol_error::{error_response, ClientProtocol};
+
+    let mut response = error_response(ClientProtocol::OpenAI, StatusCode::BAD_GATEWAY, message, None);
+    if let Ok(value) = axum::http::HeaderValue::from_str(email) {
+        response.headers_mut().insert("X-Account-Email", value);
+    }
+    response
+}
+
 /// 处理 Legacy Completions API (/v1/completions)
 /// 将 Prompt 转换为 Chat Message 格式，复用 handle_chat_completions
 pub async fn handle_completions(
     State(state): State<AppState>,
+    client_key: Option<Extension<ClientApiKey>>,
     Json(mut body): Json<Value>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let route_ctx = RouteContext::from_request(ClientProtocol::OpenAI, &body, client_key.as_deref());
     info!(
-        "Received /v1/completions or /v1/responses payload: {:?}",
+        "Received /v1/completions payload: {:?}",
         body
     );
 
@@ -297,221 +470,11 @@ pub async fn handle_completions(
             .get("instructions")
             .and_then(|v| v.as_str())
             .unwrap_or_default();
-        let input_items = body.get("input").and_then(|v| v.as_array());
-
-        let mut messages = Vec::new();
-
-        // System Instructions
-        if !instructions.is_empty() {
-            messages.push(json!({ "role": "system", "content": instructions }));
-        }
-
-        let mut call_id_to_name = std::collections::HashMap::new();
-
-        // Pass 1: Build Call ID to Name Map
-        if let Some(items) = input_items {
-            for item in items {
-                let item_type = item.get("type").and_then(|v| v.as_str()).unwrap_or("");
-                match item_type {
-                    "function_call" | "local_shell_call" | "web_search_call" => {
-                        let call_id = item
-                            .get("call_id")
-                            .and_then(|v| v.as_str())
-                            .or_else(|| item.get("id").and_then(|v| v.as_str()))
-                            .unwrap_or("unknown");
-
-                        let name = if item_type == "local_shell_call" {
-                            "shell"
-                        } else if item_type == "web_search_call" {
-                            "google_search"
-                        } else {
-                            item.get("name")
-                                .and_then(|v| v.as_str())
-                                .unwrap_or("unknown")
-                        };
-
-                        call_id_to_name.insert(call_id.to_string(), name.to_string());
-                        tracing::debug!("Mapped call_id {} to name {}", call_id, name);
-                    }
-                    _ => {}
-                }
-            }
-        }
-
-        // Pass 2: Map Input Items to Messages
-        if let Some(items) = input_items {
-            for item in items {
-                let item_type = item.get("type").and_then(|v| v.as_str()).unwrap_or("");
-                match item_type {
-                    "message" => {
-                        let role = item.get("role").and_then(|v| v.as_str()).unwrap_or("user");
-                        let content = item.get("content").and_then(|v| v.as_array());
-                        let mut text_parts = Vec::new();
-                        let mut image_parts: Vec<Value> = Vec::new();
-
-                        if let Some(parts) = content {
-                            for part in parts {
-                                // 处理文本块
-                                if let Some(text) = part.get("text").and_then(|v| v.as_str()) {
-                                    text_parts.push(text.to_string());
-                                }
-                                // [NEW] 处理图像块 (Codex input_image 格式)
-                                else if part.get("type").and_then(|v| v.as_str())
-                                    == Some("input_image")
-                                {
-                                    if let Some(image_url) =
-                                        part.get("image_url").and_then(|v| v.as_str())
-                                    {
-                                        image_parts.push(json!({
-                                            "type": "image_url",
-                                            "image_url": { "url": image_url }
-                                        }));
-                                        debug!("[Codex] Found input_image: {}", image_url);
-                                    }
-                                }
-                                // [NEW] 兼容标准 OpenAI image_url 格式
-                                else if part.get("type").and_then(|v| v.as_str())
-                                    == Some("image_url")
-                                {
-                                    if let Some(url_obj) = part.get("image_url") {
-                                        image_parts.push(json!({
-                                            "type": "image_url",
-                                            "image_url": url_obj.clone()
-                                        }));
-                                    }
-                                }
-                            }
-                        }
-
-                        // 构造消息内容：如果有图像则使用数组格式
-                        if image_parts.is_empty() {
-                            messages.push(json!({
-                                "role": role,
-                                "content": text_parts.join("\n")
-                            }));
-                        } else {
-                            let mut content_blocks: Vec<Value> = Vec::new();
-                            if !text_parts.is_empty() {
-                                content_blocks.push(json!({
-                                    "type": "text",
-                                    "text": text_parts.join("\n")
-                                }));
-                            }
-                            content_blocks.extend(image_parts);
-                            messages.push(json!({
-                                "role": role,
-                                "content": content_blocks
-                            }));
-                        }
-                    }
-                    "function_call" | "local_shell_call" | "web_search_call" => {
-                        let mut name = item
-                            .get("name")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or("unknown");
-                        let mut args_str = item
-                            .get("arguments")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or("{}")
-                            .to_string();
-                        let call_id = item
-                            .get("call_id")
-                            .and_then(|v| v.as_str())
-                            .or_else(|| item.get("id").and_then(|v| v.as_str()))
-                            .unwrap_or("unknown");
-
-                        // Handle native shell calls
-                        if item_type == "local_shell_call" {
-                            name = "shell";
-                            if let Some(action) = item.get("action") {
-                                if let Some(exec) = action.get("exec") {
-                                    // Map to ShellCommandToolCallParams (string command) or ShellToolCallParams (array command)
-                                    // Most LLMs prefer a single string for shell
-                                    let mut args_obj = serde_json::Map::new();
-                                    if let Some(cmd) = exec.get("command") {
-                                        // CRITICAL FIX: The 'shell' tool schema defines 'command' as an ARRAY of strings.
-                                        // We MUST pass it as an array, not a joined string, otherwise Gemini rejects with 400 INVALID_ARGUMENT.
-                                        let cmd_val = if cmd.is_string() {
-                                            json!([cmd]) // Wrap in array
-                                        } else {
-                                            cmd.clone() // Assume already array
-                                        };
-                                        args_obj.insert("command".to_string(), cmd_val);
-                                    }
-                                    if let Some(wd) =
-                                        exec.get("working_directory").or(exec.get("workdir"))
-                                    {
-                                        args_obj.insert("workdir".to_string(), wd.clone());
-                                    }
-                                    args_str = serde_json::to_string(&args_obj)
-                                        .unwrap_or("{}".to_string());
-                                }
-                            }
-                        } else if item_type == "web_search_call" {
-                            name = "google_search";
-                            if let Some(action) = item.get("action") {
-                                let mut args_obj = serde_json::Map::new();
-                                if let Some(q) = action.get("query") {
-                                    args_obj.insert("query".to_string(), q.clone());
-                                }
-                                args_str =
-                                    serde_json::to_string(&args_obj).unwrap_or("{}".to_string());
-                            }
-                        }
-
-                        messages.push(json!({
-                            "role": "assistant",
-                            "tool_calls": [
-                                {
-                                    "id": call_id,
-                                    "type": "function",
-                                    "function": {
-                                        "name": name,
-                                        "arguments": args_str
-                                    }
-                                }
-                            ]
-                        }));
-                    }
-                    "function_call_output" | "custom_tool_call_output" => {
-                        let call_id = item
-                            .get("call_id")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or("unknown");
-                        let output = item.get("output");
-                        let output_str = if let Some(o) = output {
-                            if o.is_string() {
-                                o.as_str().unwrap().to_string()
-                            } else if let Some(content) = o.get("content").and_then(|v| v.as_str())
-                            {
-                                content.to_string()
-                            } else {
-                                o.to_string()
-                            }
-                        } else {
-                            "".to_string()
-                        };
-
-                        let name = call_id_to_name.get(call_id).cloned().unwrap_or_else(|| {
-                            // Fallback: if unknown and we see function_call_output, it's likely "shell" in this context
-                            tracing::warn!(
-                                "Unknown tool name for call_id {}, defaulting to 'shell'",
-                                call_id
-                            );
-                            "shell".to_string()
-                        });
-
-                        messages.push(json!({
-                            "role": "tool",
-                            "tool_call_id": call_id,
-                            "name": name,
-                            "content": output_str
-                        }));
-                    }
-                    _ => {}
-                }
-            }
-        }
+        let input_items = crate::proxy::mappers::openai::responses::normalize_input(body.get("input"));
+        let messages = crate::proxy::mappers::openai::responses::input_items_to_messages(
+            instructions,
+            &input_items,
+        );
 
         if let Some(obj) = body.as_object_mut() {
             obj.insert("messages".to_string(), json!(messages));
@@ -560,16 +523,26 @@ pub async fn handle_completions(
     let upstream = state.upstream.clone();
     let token_manager = state.token_manager;
     let pool_size = token_manager.len();
-    let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
+    let fallback_budget =
+        model_fallback::fallback_budget(&state.model_router, &openai_req.model, &route_ctx).await;
+    let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1) + fallback_budget;
 
     let mut last_error = String::new();
 
     for _attempt in 0..max_attempts {
-        // 1. 模型路由解析
-        let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
+        // 1. 模型路由解析 (含回退链)
+        let selection = model_fallback::resolve_with_fallback(
+            &state.model_router,
+            &token_manager,
             &openai_req.model,
-            &*state.custom_mapping.read().await,
-        );
+            &route_ctx,
+        )
+        .await;
+        let mapped_model = selection.model.clone();
+        // 能力校验 (工具 / 图片输入)
+        if let Err(e) = ModelRegistry::global().validate(&mapped_model, &route_ctx) {
+            return Err((StatusCode::BAD_REQUEST, e));
+        }
         // 将 OpenAI 工具转为 Value 数组以便探测联网
         let tools_val: Option<Vec<Value>> = openai_req
             .tools
@@ -581,8 +554,8 @@ pub async fn handle_completions(
             &tools_val,
         );
 
-        let (access_token, project_id, email) =
-            match token_manager.get_token(&config.request_type, false, None).await {
+        let (access_token, project_id, email, lease) =
+            match token_manager.get_token_for_model(&config.request_type, false, None, &mapped_model).await {
                 Ok(t) => t,
                 Err(e) => {
                     return Err((
@@ -629,8 +602,12 @@ pub async fn handle_completions(
                 let gemini_stream = response.bytes_stream();
                 let body = if is_codex_style {
                     use crate::proxy::mappers::openai::streaming::create_codex_sse_stream;
-                    let s =
-                        create_codex_sse_stream(Box::pin(gemini_stream), openai_req.model.clone());
+                    let s = create_codex_sse_stream(
+                        Box::pin(gemini_stream),
+                        openai_req.model.clone(),
+                        json!({}),
+                        None,
+                    );
                     Body::from_stream(s)
                 } else {
                     use crate::proxy::mappers::openai::streaming::create_legacy_sse_stream;
@@ -639,7 +616,7 @@ pub async fn handle_completions(
                     Body::from_stream(s)
                 };
 
-                return Ok(Response::builder()
+                let response = Response::builder()
                     .header("Content-Type", "text/event-stream")
                     .header("Cache-Control", "no-cache")
                     .header("Connection", "keep-alive")
@@ -647,7 +624,8 @@ pub async fn handle_completions(
                     .header("X-Mapped-Model", &mapped_model)
                     .body(body)
                     .unwrap()
-                    .into_response());
+                    .into_response();
+                return Ok(lease.hold(model_fallback::annotate_response(response, &selection)));
             }
 
             let gemini_resp: Value = response
@@ -678,15 +656,24 @@ pub async fn handle_completions(
                 "choices": choices
             });
 
-            return Ok(axum::Json(legacy_resp).into_response());
+            return Ok(model_fallback::annotate_response(
+                axum::Json(legacy_resp).into_response(),
+                &selection,
+            ));
         }
 
         // Handle errors and retry
         let status_code = status.as_u16();
+        let retry_after = response.headers().get("Retry-After").and_then(|h| h.to_str().ok()).map(|s| s.to_string());
         let error_text = response.text().await.unwrap_or_default();
         last_error = format!("HTTP {}: {}", status_code, error_text);
 
-        if status_code == 429 || status_code == 403 || status_code == 401 {
+        if status_code == 429 {
+            // 记录限流，使下一轮可跳过该账号或切换到回退模型
+            token_manager.mark_model_rate_limited(&email, status_code, retry_after.as_deref(), &error_text, &mapped_model);
+            continue;
+        }
+        if status_code == 403 || status_code == 401 {
             continue;
         }
         return Err((status, error_text));
@@ -699,20 +686,17 @@ pub async fn handle_completions(
 }
 
 pub async fn handle_list_models(State(state): State<AppState>) -> impl IntoResponse {
-    use crate::proxy::common::model_mapping::get_all_dynamic_models;
+    use crate::proxy::common::model_mapping::get_all_models_with_capabilities;
 
-    let model_ids = get_all_dynamic_models(
-        &state.custom_mapping,
+    let models = get_all_models_with_capabilities(
+        &state.model_router,
+        &state.model_catalog,
     ).await;
 
-    let data: Vec<_> = model_ids.into_iter().map(|id| {
-        json!({
-            "id": id,
-            "object": "model",
-            "created": 1706745600,
-            "owned_by": "antigravity"
-        })
-    }).collect();
+    let data: Vec<_> = models
+        .iter()
+        .map(crate::proxy::handlers::common::openai_model_entry)
+        .collect();
 
     Json(json!({
         "object": "list",
@@ -793,7 +777,7 @@ pub async fn handle_images_generations(
     let upstream = state.upstream.clone();
     let token_manager = state.token_manager;
 
-    let (access_token, project_id, email) = match token_manager.get_token("image_gen", false, None).await
+    let (access_token, project_id, email, _lease) = match token_manager.get_token("image_gen", false, None).await
     {
         Ok(t) => t,
         Err(e) => {
@@ -1043,7 +1027,7 @@ pub async fn handle_images_edits(
     let upstream = state.upstream.clone();
     let token_manager = state.token_manager;
     // Fix: Proper get_token call with correct signature and unwrap (using image_gen quota)
-    let (access_token, project_id, _email) = match token_manager.get_token("image_gen", false, None).await
+    let (access_token, project_id, _email, _lease) = match token_manager.get_token("image_gen", false, None).await
     {
         Ok(t) => t,
         Err(e) => {
@@ -0,0 +1,323 @@
+// OpenAI Responses API 处理器 (有状态)
+// response 持久化到 responses.db，支持 previous_response_id 续接与 store: false
+// 存储的 response 归属创建它的 API Key，其他 Key 无法读取、删除或续接
+use axum::{
+    body::Body,
+    extract::{Extension, Json, Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use futures::StreamExt;
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use tracing::{debug, error, info};
+
+use crate::modules::response_db;
+use crate::proxy::common::model_fallback;
+use crate::proxy::common::model_registry::ModelRegistry;
+use crate::proxy::common::model_router::RouteContext;
+use crate::proxy::common::protocol_error::{error_response, ClientProtocol};
+use crate::proxy::config::ClientApiKey;
+use crate::proxy::mappers::openai::responses::{
+    build_chat_request, build_response_template, input_items_to_messages, normalize_input,
+};
+use crate::proxy::mappers::openai::streaming::{create_codex_sse_stream, ResponseCompletedHook};
+use crate::proxy::mappers::openai::{transform_openai_request, OpenAIRequest};
+use crate::proxy::server::AppState;
+use crate::proxy::session_manager::SessionManager;
+
+const MAX_RETRY_ATTEMPTS: usize = 3;
+
+fn openai_error(status: StatusCode, message: &str) -> Response {
+    error_response(ClientProtocol::OpenAI, status, message, None)
+}
+
+/// response 的归属 (客户端 API Key 名称)
+fn response_owner(client_key: &Option<Extension<ClientApiKey>>) -> Option<String> {
+    client_key.as_ref().map(|key| key.name.clone())
+}
+
+fn response_not_found(id: &str) -> Response {
+    openai_error(
+        StatusCode::NOT_FOUND,
+        &format!("Response with id '{}' not found.", id),
+    )
+}
+
+/// POST /v1/responses
+pub async fn handle_create_response(
+    State(state): State<AppState>,
+    client_key: Option<Extension<ClientApiKey>>,
+    Json(body): Json<Value>,
+) -> Response {
+    let route_ctx = RouteContext::from_request(ClientProtocol::OpenAI, &body, client_key.as_deref());
+    let owner = response_owner(&client_key);
+    let client_wants_stream = body.get("stream").and_then(|v| v.as_bool()).unwrap_or(false);
+    let store = body.get("store").and_then(|v| v.as_bool()).unwrap_or(true);
+    let instructions = body
+        .get("instructions")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .to_string();
+    let previous_response_id = body
+        .get("previous_response_id")
+        .and_then(|v| v.as_str())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string());
+
+    // 1. 还原会话历史 (instructions 不继承，仅使用本轮提供的值)
+    let mut conversation = match &previous_response_id {
+        Some(prev_id) => match response_db::load_conversation(owner.as_deref(), prev_id) {
+            Ok(Some(items)) => items,
+            Ok(None) => {
+                return openai_error(
+                    StatusCode::NOT_FOUND,
+                    &format!("Previous response with id '{}' not found.", prev_id),
+                )
+            }
+            Err(e) => {
+                return openai_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    &format!("Failed to load previous response: {}", e),
+                )
+            }
+        },
+        None => Vec::new(),
+    };
+    let input_items = normalize_input(body.get("input"));
+    conversation.extend(input_items.iter().cloned());
+
+    // 2. 转换为 Chat 请求复用 OpenAI → Gemini 映射
+    let mut messages = input_items_to_messages(&instructions, &conversation);
+    if messages.is_empty() {
+        messages.push(json!({ "role": "user", "content": " " }));
+    }
+    let openai_req: OpenAIRequest = match serde_json::from_value(build_chat_request(&body, messages)) {
+        Ok(req) => req,
+        Err(e) => {
+            return openai_error(StatusCode::BAD_REQUEST, &format!("Invalid request: {}", e))
+        }
+    };
+
+    let response_id = format!("resp_{}", uuid::Uuid::new_v4().simple());
+    let template = build_response_template(&body, &response_id, chrono::Utc::now().timestamp());
+    debug!(
+        "[Responses] {} (previous: {:?}, store: {}, stream: {})",
+        response_id, previous_response_id, store, client_wants_stream
+    );
+
+    let upstream = state.upstream.clone();
+    let token_manager = state.token_manager;
+    let pool_size = token_manager.len();
+    let fallback_budget =
+        model_fallback::fallback_budget(&state.model_router, &openai_req.model, &route_ctx).await;
+    let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1) + fallback_budget;
+    let mut last_error = String::new();
+
+    for attempt in 0..max_attempts {
+        let selection = model_fallback::resolve_with_fallback(
+            &state.model_router,
+            &token_manager,
+            &openai_req.model,
+            &route_ctx,
+        )
+        .await;
+        let mapped_model = selection.model.clone();
+        if let Err(e) = ModelRegistry::global().validate(&mapped_model, &route_ctx) {
+            return openai_error(StatusCode::BAD_REQUEST, &e);
+        }
+        let config = crate::proxy::mappers::common_utils::resolve_request_config(
+            &openai_req.model,
+            &mapped_model,
+            &openai_req.tools,
+        );
+        let session_id = SessionManager::extract_openai_session_id(&openai_req);
+
+        let (access_token, project_id, email, lease) = match token_manager
+            .get_token_for_model(&config.request_type, attempt > 0, Some(&session_id), &mapped_model)
+            .await
+        {
+            Ok(t) => t,
+            Err(e) => {
+                return openai_error(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    &format!("Token error: {}", e),
+                )
+            }
+        };
+        info!("✓ Using account: {} (type: {})", email, config.request_type);
+
+        let gemini_body = transform_openai_request(&openai_req, &project_id, &mapped_model);
+
+        // 始终以流式请求上游，非流式客户端在本地聚合
+        let response = match upstream
+            .call_v1_internal("streamGenerateContent", &access_token, gemini_body, Some("alt=sse"))
+            .await
+        {
+            Ok(r) => r,
+            Err(e) => {
+                last_error = e;
+                continue;
+            }
+        };
+
+        let status = response.status();
+        if status.is_success() {
+            let final_response: Arc<Mutex<Option<Value>>> = Arc::new(Mutex::new(None));
+            let hook: ResponseCompletedHook = {
+                let final_response = final_response.clone();
+                let input_items = input_items.clone();
+                let owner = owner.clone();
+                Box::new(move |resp: Value| {
+                    let persistable = resp.get("status").and_then(|s| s.as_str()) != Some("failed");
+                    if store && persistable {
+                        if let Err(e) = response_db::save_response(owner.as_deref(), &input_items, &resp) {
+                            error!("[Responses] Failed to store response: {}", e);
+                        }
+                    }
+                    if let Ok(mut slot) = final_response.lock() {
+                        *slot = Some(resp);
+                    }
+                })
+            };
+
+            let event_stream = create_codex_sse_stream(
+                Box::pin(response.bytes_stream()),
+                openai_req.model.clone(),
+                template.clone(),
+                Some(hook),
+            );
+
+            if client_wants_stream {
+                let response = Response::builder()
+                    .header("Content-Type", "text/event-stream")
+                    .header("Cache-Control", "no-cache")
+                    .header("Connection", "keep-alive")
+                    .header("X-Account-Email", &email)
+                    .header("X-Mapped-Model", &mapped_model)
+                    .body(Body::from_stream(event_stream))
+                    .unwrap()
+                    .into_response();
+                // 账号并发槽位持有至流结束
+                return lease.hold(model_fallback::annotate_response(response, &selection));
+            }
+
+            // 非流式: 消费事件流，返回最终 response 对象
+            let mut event_stream = event_stream;
+            while let Some(chunk) = event_stream.next().await {
+                if let Err(e) = chunk {
+                    last_error = e;
+                    break;
+                }
+            }
+            let result = final_response.lock().ok().and_then(|mut slot| slot.take());
+            return match result {
+                Some(resp) if resp.get("status").and_then(|s| s.as_str()) != Some("failed") => {
+                    let response = (
+                        StatusCode::OK,
+                        [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())],
+                        Json(resp),
+                    )
+                        .into_response();
+                    model_fallback::annotate_response(response, &selection)
+                }
+                Some(resp) => openai_error(
+                    StatusCode::BAD_GATEWAY,
+                    resp["error"]["message"].as_str().unwrap_or("Upstream stream failed"),
+                ),
+                None => openai_error(
+                    StatusCode::BAD_GATEWAY,
+                    &format!("Upstream stream ended unexpectedly: {}", last_error),
+                ),
+            };
+        }
+
+        let status_code = status.as_u16();
+        let retry_after = response
+            .headers()
+            .get("Retry-After")
+            .and_then(|h| h.to_str().ok())
+            .map(|s| s.to_string());
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| format!("HTTP {}", status_code));
+        last_error = format!("HTTP {}: {}", status_code, error_text);
+        error!("[Responses-Upstream] Error Response {}: {}", status_code, error_text);
+
+        if status_code == 429 || status_code == 529 || status_code == 503 || status_code == 500 {
+            token_manager.mark_model_rate_limited(
+                &email,
+                status_code,
+                retry_after.as_deref(),
+                &error_text,
+                &mapped_model,
+            );
+            if error_text.contains("QUOTA_EXHAUSTED") && !selection.has_fallback {
+                return openai_error(status, &error_text);
+            }
+            continue;
+        }
+        if status_code == 403 || status_code == 401 {
+            continue;
+        }
+        return openai_error(status, &error_text);
+    }
+
+    openai_error(
+        StatusCode::TOO_MANY_REQUESTS,
+        &format!("All accounts exhausted. Last error: {}", last_error),
+    )
+}
+
+/// GET /v1/responses/:id
+pub async fn handle_get_response(
+    client_key: Option<Extension<ClientApiKey>>,
+    Path(id): Path<String>,
+) -> Response {
+    match response_db::get_response(response_owner(&client_key).as_deref(), &id) {
+        Ok(Some(resp)) => Json(resp).into_response(),
+        Ok(None) => response_not_found(&id),
+        Err(e) => openai_error(StatusCode::INTERNAL_SERVER_ERROR, &e),
+    }
+}
+
+/// GET /v1/responses/:id/input_items
+pub async fn handle_list_input_items(
+    client_key: Option<Extension<ClientApiKey>>,
+    Path(id): Path<String>,
+) -> Response {
+    match response_db::get_input_items(response_owner(&client_key).as_deref(), &id) {
+        Ok(Some(items)) => {
+            let first_id = items.first().and_then(|i| i.get("id")).cloned().unwrap_or(Value::Null);
+            let last_id = items.last().and_then(|i| i.get("id")).cloned().unwrap_or(Value::Null);
+            Json(json!({
+                "object": "list",
+                "data": items,
+                "first_id": first_id,
+                "last_id": last_id,
+                "has_more": false
+            }))
+            .into_response()
+        }
+        Ok(None) => response_not_found(&id),
+        Err(e) => openai_error(StatusCode::INTERNAL_SERVER_ERROR, &e),
+    }
+}
+
+/// DELETE /v1/responses/:id
+pub async fn handle_delete_response(
+    client_key: Option<Extension<ClientApiKey>>,
+    Path(id): Path<String>,
+) -> Response {
+    match response_db::delete_response(response_owner(&client_key).as_deref(), &id) {
+        Ok(true) => Json(json!({
+            "id": id,
+            "object": "response",
+            "deleted": true
+        }))
+        .into_response(),
+        Ok(false) => response_not_found(&id),
+        Err(e) => openai_error(StatusCode::INTERNAL_SERVER_ERROR, &e),
+    }
+}
@@ -0,0 +1,96 @@
+// 粘性会话管理处理器 (仅主密钥或未启用认证时可用)
+use axum::{
+    extract::{Extension, Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::proxy::config::ClientApiKey;
+use crate::proxy::server::AppState;
+use crate::proxy::usage;
+
+#[derive(Debug, Deserialize)]
+pub struct RebindRequest {
+    /// account_id 或 email
+    pub account: String,
+}
+
+fn error_response(status: StatusCode, message: String, error_type: &str) -> Response {
+    (
+        status,
+        Json(json!({
+            "error": {
+                "message": message,
+                "type": error_type
+            }
+        })),
+    )
+        .into_response()
+}
+
+/// 具名密钥无权管理会话
+fn forbidden(client_key: &Option<Extension<ClientApiKey>>) -> Option<Response> {
+    match client_key {
+        Some(Extension(key)) if !usage::is_admin_key(key) => Some(error_response(
+            StatusCode::FORBIDDEN,
+            "Session management requires the primary API key".to_string(),
+            "permission_error",
+        )),
+        _ => None,
+    }
+}
+
+/// GET /v1/sessions
+pub async fn handle_list_sessions(
+    State(state): State<AppState>,
+    client_key: Option<Extension<ClientApiKey>>,
+) -> Response {
+    if let Some(resp) = forbidden(&client_key) {
+        return resp;
+    }
+    Json(json!({
+        "object": "list",
+        "data": state.token_manager.list_sessions(),
+    }))
+    .into_response()
+}
+
+/// PUT /v1/sessions/:id  body: {"account": "<account_id | email>"}
+pub async fn handle_rebind_session(
+    State(state): State<AppState>,
+    client_key: Option<Extension<ClientApiKey>>,
+    Path(session_id): Path<String>,
+    Json(body): Json<RebindRequest>,
+) -> Response {
+    if let Some(resp) = forbidden(&client_key) {
+        return resp;
+    }
+    match state.token_manager.rebind_session(&session_id, &body.account) {
+        Ok(info) => Json(info).into_response(),
+        Err(e) => error_response(StatusCode::NOT_FOUND, e, "invalid_request_error"),
+    }
+}
+
+/// DELETE /v1/sessions/:id
+pub async fn handle_evict_session(
+    State(state): State<AppState>,
+    client_key: Option<Extension<ClientApiKey>>,
+    Path(session_id): Path<String>,
+) -> Response {
+    if let Some(resp) = forbidden(&client_key) {
+        return resp;
+    }
+    let deleted = state.token_manager.clear_session_binding(&session_id);
+    let status = if deleted { StatusCode::OK } else { StatusCode::NOT_FOUND };
+    (
+        status,
+        Json(json!({
+            "id": session_id,
+            "object": "session",
+            "deleted": deleted,
+        })),
+    )
+        .into_response()
+}
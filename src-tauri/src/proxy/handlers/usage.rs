@@ -0,0 +1,45 @@
+// 客户端密钥用量查询处理器
+use axum::{
+    extract::{Extension, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::json;
+
+use crate::proxy::config::ClientApiKey;
+use crate::proxy::server::AppState;
+use crate::proxy::usage;
+
+/// GET /v1/usage
+///
+/// 具名密钥只能查看自身用量；主密钥 (或未启用认证时) 返回全部密钥的用量
+pub async fn handle_get_usage(
+    State(state): State<AppState>,
+    client_key: Option<Extension<ClientApiKey>>,
+) -> Response {
+    let result = match client_key {
+        Some(Extension(key)) if !usage::is_admin_key(&key) => usage::get_usage(&key).map(|u| vec![u]),
+        _ => {
+            let security = state.security.read().await.clone();
+            usage::collect_all_usage(&security)
+        }
+    };
+
+    match result {
+        Ok(data) => Json(json!({
+            "object": "list",
+            "data": data,
+        }))
+        .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({
+                "error": {
+                    "message": format!("Failed to read usage: {}", e),
+                    "type": "api_error"
+                }
+            })),
+        )
+            .into_response(),
+    }
+}
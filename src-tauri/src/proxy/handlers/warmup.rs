@@ -97,6 +97,8 @@ pub async fn handle_warmup(
             metadata: None,
             thinking: None,
             output_config: None,
+            tool_choice: None,
+            stop_sequences: None,
         };
 
         match crate::proxy::mappers::claude::transform_claude_request_in(
@@ -179,6 +181,8 @@ pub async fn handle_warmup(
                 model: Some(req.model.clone()),
                 mapped_model: None,
                 account_email: Some(req.email.clone()),
+                api_key_name: None,
+                fallback_from: None,
                 error: if !status.is_success() {
                     Some(format!("HTTP {}", status.as_u16()))
                 } else {
@@ -233,6 +237,8 @@ pub async fn handle_warmup(
                 model: Some(req.model.clone()),
                 mapped_model: None,
                 account_email: Some(req.email.clone()),
+                api_key_name: None,
+                fallback_from: None,
                 error: Some(e.clone()),
                 request_body: None,
                 response_body: None,
@@ -0,0 +1,136 @@
+// 负载均衡调度 (LeastLoaded / QuotaWeighted)
+// 记录每个账号近期的请求与失败次数，并提供与账号池无关的选号算法
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// 错误率统计窗口
+pub const OUTCOME_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+/// 配额未提供重置时间时假定的重置窗口
+const DEFAULT_RESET_WINDOW_SECS: f64 = 5.0 * 3600.0;
+
+/// 距重置时间的下限，避免即将重置的账号权重趋于无穷大
+const MIN_RESET_SECS: f64 = 60.0;
+
+/// 单个账号在统计窗口内的请求与失败时间点
+#[derive(Debug, Default)]
+pub struct RecentOutcomes {
+    requests: VecDeque<Instant>,
+    failures: VecDeque<Instant>,
+}
+
+impl RecentOutcomes {
+    pub fn record_request(&mut self, now: Instant) {
+        self.prune(now);
+        self.requests.push_back(now);
+    }
+
+    pub fn record_failure(&mut self, now: Instant) {
+        self.prune(now);
+        self.failures.push_back(now);
+    }
+
+    /// 窗口内的错误率 (0.0 - 1.0)，无请求记录时为 0
+    pub fn error_rate(&mut self, now: Instant) -> f64 {
+        self.prune(now);
+        if self.failures.is_empty() {
+            return 0.0;
+        }
+        // 失败可能来自窗口外发起的请求，分母至少取失败次数
+        let total = self.requests.len().max(self.failures.len());
+        self.failures.len() as f64 / total as f64
+    }
+
+    fn prune(&mut self, now: Instant) {
+        for queue in [&mut self.requests, &mut self.failures] {
+            while queue.front().is_some_and(|t| now.duration_since(*t) > OUTCOME_WINDOW) {
+                queue.pop_front();
+            }
+        }
+    }
+}
+
+/// 选出 (进行中请求数, 错误率) 最小的候选
+///
+/// 先比较进行中请求数，再比较错误率；完全相同时从 `start` 开始按顺序取第一个，
+/// 使空闲账号之间仍然轮询
+pub fn pick_least_loaded(loads: &[(usize, f64)], start: usize) -> Option<usize> {
+    let len = loads.len();
+    (0..len)
+        .map(|offset| (start + offset) % len)
+        .min_by(|&a, &b| {
+            loads[a]
+                .0
+                .cmp(&loads[b].0)
+                .then(loads[a].1.total_cmp(&loads[b].1))
+        })
+}
+
+/// 配额权重: 剩余比例 / 距重置的秒数
+///
+/// 即该账号在重置前每秒可消耗的配额，重置越近、剩余越多的账号越优先，
+/// 从而让各账号的配额在各自的重置窗口内同步耗尽。未知剩余比例按 1.0 处理
+pub fn quota_weight(remaining_fraction: Option<f64>, secs_to_reset: Option<f64>) -> f64 {
+    let fraction = remaining_fraction.unwrap_or(1.0).clamp(0.0, 1.0);
+    let secs = secs_to_reset.unwrap_or(DEFAULT_RESET_WINDOW_SECS).max(MIN_RESET_SECS);
+    fraction / secs
+}
+
+/// 按权重随机选择，`roll` 取值 [0, 1)；权重全为 0 时返回 None
+pub fn pick_weighted(weights: &[f64], roll: f64) -> Option<usize> {
+    let total: f64 = weights.iter().sum();
+    if total <= 0.0 {
+        return None;
+    }
+    let mut target = roll * total;
+    for (idx, weight) in weights.iter().enumerate() {
+        if *weight <= 0.0 {
+            continue;
+        }
+        if target < *weight {
+            return Some(idx);
+        }
+        target -= weight;
+    }
+    // 浮点误差兜底: 取最后一个正权重
+    weights.iter().rposition(|w| *w > 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_least_loaded_and_error_rate() {
+        let now = Instant::now();
+        let mut outcomes = RecentOutcomes::default();
+        for _ in 0..4 {
+            outcomes.record_request(now);
+        }
+        outcomes.record_failure(now);
+        assert_eq!(outcomes.error_rate(now), 0.25);
+        assert_eq!(outcomes.error_rate(now + OUTCOME_WINDOW + Duration::from_secs(1)), 0.0);
+
+        let loads = [(2, 0.0), (1, 0.5), (1, 0.0), (1, 0.0)];
+        assert_eq!(pick_least_loaded(&loads, 0), Some(2));
+        // 负载与错误率相同时按起点轮询
+        assert_eq!(pick_least_loaded(&loads, 3), Some(3));
+        assert_eq!(pick_least_loaded(&[], 0), None);
+    }
+
+    #[test]
+    fn test_quota_weighted() {
+        // 同样剩余 50%，1 小时后重置的账号权重是 4 小时后重置的 4 倍
+        let soon = quota_weight(Some(0.5), Some(3600.0));
+        let later = quota_weight(Some(0.5), Some(4.0 * 3600.0));
+        assert!((soon / later - 4.0).abs() < 1e-9);
+        assert_eq!(quota_weight(Some(0.0), Some(3600.0)), 0.0);
+
+        let weights = [1.0, 0.0, 3.0];
+        assert_eq!(pick_weighted(&weights, 0.1), Some(0));
+        assert_eq!(pick_weighted(&weights, 0.3), Some(2));
+        assert_eq!(pick_weighted(&weights, 0.999), Some(2));
+        assert_eq!(pick_weighted(&[0.0, 0.0], 0.5), None);
+    }
+}
@@ -202,15 +202,15 @@ pub fn transform_claude_request_in(
     let system_instruction = build_system_instruction(&claude_req.system, &claude_req.model);
 
     //  Map model name (Use standard mapping)
-    // [IMPROVED] 提取 web search 模型为常量，便于维护
-    const WEB_SEARCH_FALLBACK_MODEL: &str = "gemini-2.5-flash";
+    // [IMPROVED] web search 模型由能力注册表统一维护
+    use crate::proxy::common::model_registry::{ModelRegistry, DEFAULT_SEARCH_MODEL};
 
     let mapped_model = if has_web_search_tool {
         tracing::debug!(
             "[Claude-Request] Web search tool detected, using fallback model: {}",
-            WEB_SEARCH_FALLBACK_MODEL
+            DEFAULT_SEARCH_MODEL
         );
-        WEB_SEARCH_FALLBACK_MODEL.to_string()
+        DEFAULT_SEARCH_MODEL.to_string()
     } else {
         crate::proxy::common::model_mapping::map_claude_model_to_gemini(&claude_req.model)
     };
@@ -237,15 +237,14 @@ pub fn transform_claude_request_in(
         .map(|t| t.type_ == "enabled")
         .unwrap_or_else(|| {
             // [Claude Code v2.0.67+] Default thinking enabled for Opus 4.5
-            // If no thinking config is provided, enable by default for Opus models
-            should_enable_thinking_by_default(&claude_req.model)
+            // If no thinking config is provided, consult the capability registry
+            ModelRegistry::global().thinking_by_default(&claude_req.model)
         });
 
-    // [NEW FIX] Check if target model supports thinking
-    // Only models with "-thinking" suffix or Claude models support thinking
-    // Regular Gemini models (gemini-2.5-flash, gemini-2.5-pro) do NOT support thinking
-    let target_model_supports_thinking = mapped_model.contains("-thinking") 
-        || mapped_model.starts_with("claude-");
+    // [NEW FIX] Check if target model supports thinking (capability registry)
+    // Only models with "-thinking" suffix or Claude models support thinking passthrough
+    // Regular Gemini models (gemini-2.5-flash, gemini-2.5-pro) do NOT
+    let target_model_supports_thinking = ModelRegistry::global().supports_thinking_passthrough(&mapped_model);
     
     if is_thinking_enabled && !target_model_supports_thinking {
         tracing::warn!(
@@ -315,7 +314,7 @@ pub fn transform_claude_request_in(
     }
 
     // 4. Generation Config & Thinking (Pass final is_thinking_enabled)
-    let generation_config = build_generation_config(claude_req, has_web_search_tool, is_thinking_enabled);
+    let generation_config = build_generation_config(claude_req, &mapped_model, has_web_search_tool, is_thinking_enabled);
 
     // 2. Contents (Messages)
     let contents = build_contents(
@@ -350,13 +349,8 @@ pub fn transform_claude_request_in(
     }
 
     if let Some(tools_val) = tools {
+        inner_request["toolConfig"] = build_tool_config(claude_req.tool_choice.as_ref(), &tools_val);
         inner_request["tools"] = tools_val;
-        // 显式设置工具配置模式为 VALIDATED
-        inner_request["toolConfig"] = json!({
-            "functionCallingConfig": {
-                "mode": "VALIDATED"
-            }
-        });
     }
 
     // Inject googleSearch tool if needed (and not already done by build_tools)
@@ -436,31 +430,6 @@ fn should_disable_thinking_due_to_history(messages: &[Message]) -> bool {
     false
 }
 
-/// Check if thinking mode should be enabled by default for a given model
-///
-/// Claude Code v2.0.67+ enables thinking by default for Opus 4.5 models.
-/// This function determines if the model should have thinking enabled
-/// when no explicit thinking configuration is provided.
-fn should_enable_thinking_by_default(model: &str) -> bool {
-    let model_lower = model.to_lowercase();
-
-    // Enable thinking by default for Opus 4.5 variants
-    if model_lower.contains("opus-4-5") || model_lower.contains("opus-4.5") {
-        tracing::debug!(
-            "[Thinking-Mode] Auto-enabling thinking for Opus 4.5 model: {}",
-            model
-        );
-        return true;
-    }
-
-    // Also enable for explicit thinking model variants
-    if model_lower.contains("-thinking") {
-        return true;
-    }
-
-    false
-}
-
 /// Minimum length for a valid thought_signature
 const MIN_SIGNATURE_LENGTH: usize = 50;
 
@@ -1084,9 +1053,52 @@ fn build_tools(tools: &Option<Vec<Tool>>, has_web_search: bool) -> Result<Option
     Ok(None)
 }
 
+/// 根据 Claude tool_choice 构建 Gemini toolConfig
+///
+/// - 未指定: 保持 VALIDATED (原有行为)
+/// - auto → AUTO, any → ANY, none → NONE
+/// - tool → ANY + allowedFunctionNames，强制调用指定函数
+///
+/// 强制类模式仅在存在 functionDeclarations 时生效 (纯 googleSearch 工具不支持函数调用配置)
+fn build_tool_config(tool_choice: Option<&ToolChoice>, tools: &Value) -> Value {
+    let declared: Vec<&str> = tools
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|t| t.get("functionDeclarations").and_then(|d| d.as_array()))
+        .flatten()
+        .filter_map(|d| d.get("name").and_then(|n| n.as_str()))
+        .collect();
+
+    let function_calling_config = match tool_choice {
+        Some(_) if declared.is_empty() => json!({ "mode": "VALIDATED" }),
+        Some(ToolChoice::Auto { .. }) => json!({ "mode": "AUTO" }),
+        Some(ToolChoice::Any { .. }) => json!({ "mode": "ANY" }),
+        Some(ToolChoice::None) => json!({ "mode": "NONE" }),
+        Some(ToolChoice::Tool { name, .. }) => {
+            if declared.contains(&name.as_str()) {
+                json!({ "mode": "ANY", "allowedFunctionNames": [name] })
+            } else {
+                tracing::warn!(
+                    "[Claude-Request] tool_choice references unknown tool '{}', falling back to ANY",
+                    name
+                );
+                json!({ "mode": "ANY" })
+            }
+        }
+        None => json!({ "mode": "VALIDATED" }),
+    };
+
+    json!({ "functionCallingConfig": function_calling_config })
+}
+
+/// Gemini 最多接受 5 个 stopSequences
+const MAX_STOP_SEQUENCES: usize = 5;
+
 /// 构建 Generation Config
 fn build_generation_config(
     claude_req: &ClaudeRequest,
+    mapped_model: &str,
     has_web_search: bool,
     is_thinking_enabled: bool
 ) -> Value {
@@ -1147,17 +1159,35 @@ fn build_generation_config(
         config["candidateCount"] = json!(1);
     }*/
 
-    // max_tokens 映射为 maxOutputTokens
-    config["maxOutputTokens"] = json!(64000);
+    // max_tokens 映射为 maxOutputTokens (不超过目标模型的输出上限)
+    config["maxOutputTokens"] = json!(crate::proxy::common::model_registry::ModelRegistry::global()
+        .clamp_output_tokens(mapped_model, 64000));
 
     // [优化] 设置全局停止序列,防止流式输出冗余
-    config["stopSequences"] = json!([
-        "<|user|>",
-        "<|endoftext|>",
-        "<|end_of_turn|>",
-        "[DONE]",
-        "\n\nHuman:"
-    ]);
+    // 客户端 stop_sequences 优先，剩余名额再由默认序列补齐
+    let mut stop_sequences: Vec<String> = Vec::new();
+    for seq in claude_req.stop_sequences.iter().flatten() {
+        if !seq.is_empty() && !stop_sequences.contains(seq) {
+            stop_sequences.push(seq.clone());
+        }
+    }
+    if stop_sequences.len() > MAX_STOP_SEQUENCES {
+        tracing::warn!(
+            "[Generation-Config] {} stop_sequences provided, only the first {} are forwarded",
+            stop_sequences.len(),
+            MAX_STOP_SEQUENCES
+        );
+        stop_sequences.truncate(MAX_STOP_SEQUENCES);
+    }
+    for default in ["<|user|>", "<|endoftext|>", "<|end_of_turn|>", "[DONE]", "\n\nHuman:"] {
+        if stop_sequences.len() >= MAX_STOP_SEQUENCES {
+            break;
+        }
+        if !stop_sequences.iter().any(|s| s == default) {
+            stop_sequences.push(default.to_string());
+        }
+    }
+    config["stopSequences"] = json!(stop_sequences);
 
     config
 }
@@ -1228,6 +1258,8 @@ mod tests {
             thinking: None,
             metadata: None,
             output_config: None,
+            tool_choice: None,
+            stop_sequences: None,
         };
 
         let result = transform_claude_request_in(&req, "test-project");
@@ -1325,6 +1357,8 @@ mod tests {
             thinking: None,
             metadata: None,
             output_config: None,
+            tool_choice: None,
+            stop_sequences: None,
         };
 
         let result = transform_claude_request_in(&req, "test-project");
@@ -1395,6 +1429,8 @@ mod tests {
             thinking: None,
             metadata: None,
             output_config: None,
+            tool_choice: None,
+            stop_sequences: None,
         };
 
         let result = transform_claude_request_in(&req, "test-project");
@@ -1470,6 +1506,8 @@ mod tests {
             }),
             metadata: None,
             output_config: None,
+            tool_choice: None,
+            stop_sequences: None,
         };
 
         let result = transform_claude_request_in(&req, "test-project");
@@ -1519,6 +1557,8 @@ mod tests {
             thinking: None, // 未启用 thinking
             metadata: None,
             output_config: None,
+            tool_choice: None,
+            stop_sequences: None,
         };
 
         let result = transform_claude_request_in(&req, "test-project");
@@ -1572,6 +1612,8 @@ mod tests {
             }),
             metadata: None,
             output_config: None,
+            tool_choice: None,
+            stop_sequences: None,
         };
 
         let result = transform_claude_request_in(&req, "test-project");
@@ -1612,6 +1654,8 @@ mod tests {
             thinking: None,
             metadata: None,
             output_config: None,
+            tool_choice: None,
+            stop_sequences: None,
         };
 
         let result = transform_claude_request_in(&req, "test-project");
@@ -1692,5 +1736,41 @@ mod tests {
             assert!(matches!(blocks[1], ContentBlock::Text { .. }), "Text should still be second");
         }
     }
-}
 
+    #[test]
+    fn test_tool_choice_and_stop_sequences() {
+        let req: ClaudeRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "messages": [{ "role": "user", "content": "weather?" }],
+            "tools": [
+                { "name": "get_weather", "input_schema": { "type": "object", "properties": {} } },
+                { "name": "get_time", "input_schema": { "type": "object", "properties": {} } }
+            ],
+            "tool_choice": { "type": "tool", "name": "get_weather" },
+            "stop_sequences": ["END", "END", "STOP"]
+        }))
+        .unwrap();
+
+        let body = transform_claude_request_in(&req, "test-project").unwrap();
+        let fcc = &body["request"]["toolConfig"]["functionCallingConfig"];
+        assert_eq!(fcc["mode"], "ANY");
+        assert_eq!(fcc["allowedFunctionNames"], json!(["get_weather"]));
+
+        let stops = body["request"]["generationConfig"]["stopSequences"].as_array().unwrap();
+        assert_eq!(stops.len(), MAX_STOP_SEQUENCES);
+        assert_eq!(stops[0], "END");
+        assert_eq!(stops[1], "STOP");
+
+        let tools = json!([{ "functionDeclarations": [{ "name": "get_weather" }] }]);
+        assert_eq!(
+            build_tool_config(Some(&ToolChoice::None), &tools)["functionCallingConfig"]["mode"],
+            "NONE"
+        );
+        assert_eq!(
+            build_tool_config(Some(&ToolChoice::Any { disable_parallel_tool_use: None }), &tools)
+                ["functionCallingConfig"]["mode"],
+            "ANY"
+        );
+        assert_eq!(build_tool_config(None, &tools)["functionCallingConfig"]["mode"], "VALIDATED");
+    }
+}
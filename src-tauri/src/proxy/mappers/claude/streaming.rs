@@ -808,6 +808,11 @@ impl<'a> PartProcessor<'a> {
     }
 }
 
+/// 是否为有效内容事件 (用于首包缓冲，message_start / ping / message_stop 不计)
+pub fn is_content_event(chunk: &[u8]) -> bool {
+    chunk.starts_with(b"event: content_block_start")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -21,8 +21,10 @@ pub fn resolve_request_config(
     mapped_model: &str,
     tools: &Option<Vec<Value>>
 ) -> RequestConfig {
+    let registry = crate::proxy::common::model_registry::ModelRegistry::global();
+
     // 1. Image Generation Check (Priority)
-    if mapped_model.starts_with("gemini-3-pro-image") {
+    if registry.is_image_model(mapped_model) {
         let (image_config, parsed_base_model) = parse_image_config(original_model);
         
         return RequestConfig {
@@ -64,14 +66,16 @@ pub fn resolve_request_config(
     // Force a stable search model for search requests.
     let mut final_model = mapped_model.trim_end_matches("-online").to_string();
     if enable_networking {
-        // [FIX] Only gemini-2.5-flash supports googleSearch tool
+        // [FIX] Only models with search capability (gemini-2.5-flash by default) support googleSearch tool
         // All other models (including Gemini 3 Pro, thinking models, Claude aliases) must downgrade
-        if final_model != "gemini-2.5-flash" {
+        if !registry.supports_search(&final_model) {
             tracing::info!(
-                "[Common-Utils] Downgrading {} to gemini-2.5-flash for web search (only gemini-2.5-flash supports googleSearch)",
+                "[Common-Utils] Downgrading {} to {} for web search ({} does not support googleSearch)",
+                final_model,
+                crate::proxy::common::model_registry::DEFAULT_SEARCH_MODEL,
                 final_model
             );
-            final_model = "gemini-2.5-flash".to_string();
+            final_model = crate::proxy::common::model_registry::DEFAULT_SEARCH_MODEL.to_string();
         }
     }
 
@@ -142,6 +142,67 @@ pub fn unwrap_response(response: &Value) -> Value {
     response.get("response").unwrap_or(response).clone()
 }
 
+/// Embedding 请求使用的配额分组
+pub const EMBEDDING_REQUEST_TYPE: &str = "embedding";
+
+/// 将原生 embedContent / batchEmbedContents 请求体统一为 EmbedContentRequest 列表
+///
+/// 每个请求的 model 字段统一改写为映射后的模型
+pub fn extract_embed_requests(method: &str, body: &Value, mapped_model: &str) -> Result<Vec<Value>, String> {
+    let mut requests = if method == "batchEmbedContents" {
+        body.get("requests")
+            .and_then(|v| v.as_array())
+            .cloned()
+            .ok_or("batchEmbedContents requires a 'requests' array")?
+    } else {
+        vec![body.clone()]
+    };
+    if requests.is_empty() {
+        return Err("No embedding requests provided".to_string());
+    }
+
+    for req in requests.iter_mut() {
+        if req.get("content").is_none() {
+            return Err("Each embedding request requires 'content'".to_string());
+        }
+        if let Some(obj) = req.as_object_mut() {
+            obj.insert("model".to_string(), json!(format!("models/{}", mapped_model)));
+        }
+    }
+    Ok(requests)
+}
+
+/// 包装 Embedding 请求为 v1internal batchEmbedContents 格式
+pub fn wrap_embed_request(requests: Vec<Value>, project_id: &str, mapped_model: &str) -> Value {
+    json!({
+        "project": project_id,
+        "requestId": format!("agent-{}", uuid::Uuid::new_v4()),
+        "request": { "requests": requests },
+        "model": mapped_model,
+        "userAgent": "antigravity",
+        "requestType": EMBEDDING_REQUEST_TYPE
+    })
+}
+
+/// 从 (解包后的) batchEmbedContents / embedContent 响应中提取向量
+pub fn extract_embedding_values(response: &Value) -> Result<Vec<Vec<f32>>, String> {
+    let to_vec = |embedding: &Value| -> Result<Vec<f32>, String> {
+        embedding
+            .get("values")
+            .and_then(|v| v.as_array())
+            .map(|values| values.iter().map(|x| x.as_f64().unwrap_or_default() as f32).collect())
+            .ok_or_else(|| "embedding has no 'values'".to_string())
+    };
+
+    if let Some(embeddings) = response.get("embeddings").and_then(|v| v.as_array()) {
+        return embeddings.iter().map(to_vec).collect();
+    }
+    if let Some(embedding) = response.get("embedding") {
+        return Ok(vec![to_vec(embedding)?]);
+    }
+    Err(format!("Upstream response has no embeddings: {}", response))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -231,4 +292,31 @@ mod tests {
         // Should NOT inject duplicate, so only 1 part remains
         assert_eq!(parts.len(), 1);
     }
+
+    #[test]
+    fn test_embed_requests_roundtrip() {
+        let single = json!({ "content": { "parts": [{ "text": "hi" }] }, "outputDimensionality": 8 });
+        let reqs = extract_embed_requests("embedContent", &single, "gemini-embedding-001").unwrap();
+        assert_eq!(reqs.len(), 1);
+        assert_eq!(reqs[0]["model"], "models/gemini-embedding-001");
+        assert_eq!(reqs[0]["outputDimensionality"], 8);
+
+        let batch = json!({ "requests": [
+            { "model": "models/x", "content": { "parts": [{ "text": "a" }] } },
+            { "content": { "parts": [{ "text": "b" }] } }
+        ]});
+        let reqs = extract_embed_requests("batchEmbedContents", &batch, "text-embedding-004").unwrap();
+        assert_eq!(reqs.len(), 2);
+        assert!(reqs.iter().all(|r| r["model"] == "models/text-embedding-004"));
+        assert!(extract_embed_requests("batchEmbedContents", &json!({ "requests": [{}] }), "m").is_err());
+
+        let wrapped = wrap_embed_request(reqs, "proj", "text-embedding-004");
+        assert_eq!(wrapped["requestType"], EMBEDDING_REQUEST_TYPE);
+        assert_eq!(wrapped["request"]["requests"].as_array().unwrap().len(), 2);
+
+        let resp = json!({ "embeddings": [{ "values": [0.5, 1.0] }, { "values": [0.25] }] });
+        assert_eq!(extract_embedding_values(&resp).unwrap(), vec![vec![0.5, 1.0], vec![0.25]]);
+        let resp = json!({ "embedding": { "values": [2.0] } });
+        assert_eq!(extract_embedding_values(&resp).unwrap(), vec![vec![2.0]]);
+    }
 }
@@ -0,0 +1,513 @@
+// OpenAI Chat ↔ Anthropic Messages 转换
+// OpenAI 协议请求分流到 Anthropic 兼容服务商 (z.ai 等) 时使用
+
+use bytes::{Bytes, BytesMut};
+use chrono::Utc;
+use futures::{Stream, StreamExt};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::pin::Pin;
+use uuid::Uuid;
+
+use super::models::{OpenAIContent, OpenAIContentBlock, OpenAIMessage, OpenAIRequest};
+use super::request::{reasoning_effort_budget, ThinkingEffort};
+
+/// 客户端未指定输出上限时的默认值 (Anthropic 要求 max_tokens 必填)
+const DEFAULT_MAX_TOKENS: u32 = 8192;
+
+fn content_text(content: Option<&OpenAIContent>) -> String {
+    match content {
+        Some(OpenAIContent::String(s)) => s.clone(),
+        Some(OpenAIContent::Array(blocks)) => blocks
+            .iter()
+            .filter_map(|b| match b {
+                OpenAIContentBlock::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        None => String::new(),
+    }
+}
+
+fn image_block(url: &str) -> Option<Value> {
+    if let Some(rest) = url.strip_prefix("data:") {
+        let (meta, data) = rest.split_once(',')?;
+        let media_type = meta.split(';').next().unwrap_or("image/jpeg");
+        return Some(json!({
+            "type": "image",
+            "source": { "type": "base64", "media_type": media_type, "data": data }
+        }));
+    }
+    if url.starts_with("http") {
+        return Some(json!({ "type": "image", "source": { "type": "url", "url": url } }));
+    }
+    None
+}
+
+fn user_blocks(content: Option<&OpenAIContent>) -> Vec<Value> {
+    match content {
+        Some(OpenAIContent::String(s)) => vec![json!({ "type": "text", "text": s })],
+        Some(OpenAIContent::Array(blocks)) => blocks
+            .iter()
+            .filter_map(|b| match b {
+                OpenAIContentBlock::Text { text } => Some(json!({ "type": "text", "text": text })),
+                OpenAIContentBlock::ImageUrl { image_url } => image_block(&image_url.url),
+                // Anthropic Messages 不支持音频输入
+                OpenAIContentBlock::AudioUrl { .. } => None,
+            })
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+fn assistant_blocks(msg: &OpenAIMessage) -> Vec<Value> {
+    let mut blocks = Vec::new();
+    let text = content_text(msg.content.as_ref());
+    if !text.is_empty() {
+        blocks.push(json!({ "type": "text", "text": text }));
+    }
+    for call in msg.tool_calls.iter().flatten() {
+        let input = serde_json::from_str::<Value>(&call.function.arguments)
+            .ok()
+            .filter(|v| v.is_object())
+            .unwrap_or_else(|| json!({}));
+        blocks.push(json!({
+            "type": "tool_use",
+            "id": call.id,
+            "name": call.function.name,
+            "input": input
+        }));
+    }
+    blocks
+}
+
+/// 追加消息，相邻同角色消息合并 (Anthropic 要求 user / assistant 交替)
+fn push_message(messages: &mut Vec<Value>, role: &str, blocks: Vec<Value>) {
+    if blocks.is_empty() {
+        return;
+    }
+    if let Some(last) = messages.last_mut() {
+        if last["role"] == role {
+            if let Some(content) = last["content"].as_array_mut() {
+                content.extend(blocks);
+                return;
+            }
+        }
+    }
+    messages.push(json!({ "role": role, "content": blocks }));
+}
+
+fn convert_tool(tool: &Value) -> Option<Value> {
+    let function = tool.get("function")?;
+    let mut out = json!({
+        "name": function.get("name")?.as_str()?,
+        "input_schema": function
+            .get("parameters")
+            .cloned()
+            .unwrap_or_else(|| json!({ "type": "object", "properties": {} }))
+    });
+    if let Some(desc) = function.get("description").and_then(|d| d.as_str()) {
+        out["description"] = json!(desc);
+    }
+    Some(out)
+}
+
+fn convert_tool_choice(choice: &Value) -> Option<Value> {
+    match choice {
+        Value::String(s) => match s.as_str() {
+            "auto" => Some(json!({ "type": "auto" })),
+            "none" => Some(json!({ "type": "none" })),
+            "required" => Some(json!({ "type": "any" })),
+            _ => None,
+        },
+        Value::Object(_) => {
+            let name = choice.get("function")?.get("name")?.as_str()?;
+            Some(json!({ "type": "tool", "name": name }))
+        }
+        _ => None,
+    }
+}
+
+/// OpenAI Chat 请求 -> Anthropic Messages 请求
+pub fn openai_to_anthropic_request(request: &OpenAIRequest, mapped_model: &str) -> Value {
+    let mut system_parts: Vec<String> = request.instructions.iter().cloned().collect();
+    let mut messages: Vec<Value> = Vec::new();
+
+    for msg in &request.messages {
+        match msg.role.as_str() {
+            "system" | "developer" => {
+                let text = content_text(msg.content.as_ref());
+                if !text.is_empty() {
+                    system_parts.push(text);
+                }
+            }
+            "assistant" => push_message(&mut messages, "assistant", assistant_blocks(msg)),
+            "tool" | "function" => {
+                let block = json!({
+                    "type": "tool_result",
+                    "tool_use_id": msg.tool_call_id.clone().unwrap_or_default(),
+                    "content": content_text(msg.content.as_ref())
+                });
+                push_message(&mut messages, "user", vec![block]);
+            }
+            _ => push_message(&mut messages, "user", user_blocks(msg.content.as_ref())),
+        }
+    }
+
+    let mut max_tokens = request
+        .max_completion_tokens
+        .or(request.max_tokens)
+        .unwrap_or(DEFAULT_MAX_TOKENS);
+
+    let mut body = json!({
+        "model": mapped_model,
+        "messages": messages,
+        "stream": request.stream,
+    });
+    if !system_parts.is_empty() {
+        body["system"] = json!(system_parts.join("\n\n"));
+    }
+
+    let thinking_budget = request
+        .reasoning_effort
+        .as_deref()
+        .and_then(|effort| match reasoning_effort_budget(effort, mapped_model) {
+            Some(ThinkingEffort::Budget(budget)) => Some(budget),
+            _ => None,
+        });
+    if let Some(budget) = thinking_budget {
+        // budget_tokens 必须小于 max_tokens；开启思考时不接受自定义 temperature / top_p
+        max_tokens = max_tokens.max(budget + DEFAULT_MAX_TOKENS);
+        body["thinking"] = json!({ "type": "enabled", "budget_tokens": budget });
+    } else {
+        if let Some(t) = request.temperature {
+            body["temperature"] = json!(t);
+        }
+        if let Some(p) = request.top_p {
+            body["top_p"] = json!(p);
+        }
+    }
+    body["max_tokens"] = json!(max_tokens);
+
+    match &request.stop {
+        Some(Value::String(s)) => body["stop_sequences"] = json!([s]),
+        Some(Value::Array(arr)) => body["stop_sequences"] = json!(arr),
+        _ => {}
+    }
+
+    let tools: Vec<Value> = request.tools.iter().flatten().filter_map(convert_tool).collect();
+    if !tools.is_empty() {
+        body["tools"] = json!(tools);
+        let mut choice = request
+            .tool_choice
+            .as_ref()
+            .and_then(convert_tool_choice)
+            .unwrap_or_else(|| json!({ "type": "auto" }));
+        if request.parallel_tool_calls == Some(false) && choice["type"] != "none" {
+            choice["disable_parallel_tool_use"] = json!(true);
+        }
+        body["tool_choice"] = choice;
+    }
+
+    if let Some(user) = &request.user {
+        body["metadata"] = json!({ "user_id": user });
+    }
+
+    body
+}
+
+fn finish_reason(stop_reason: &str) -> &'static str {
+    match stop_reason {
+        "max_tokens" => "length",
+        "tool_use" => "tool_calls",
+        "refusal" => "content_filter",
+        _ => "stop",
+    }
+}
+
+/// Anthropic usage -> OpenAI usage (缓存读写计入 prompt_tokens)
+fn openai_usage(input: &Value, output_tokens: u64) -> Value {
+    let get = |key: &str| input.get(key).and_then(|v| v.as_u64()).unwrap_or(0);
+    let cached = get("cache_read_input_tokens");
+    let prompt_tokens = get("input_tokens") + cached + get("cache_creation_input_tokens");
+    json!({
+        "prompt_tokens": prompt_tokens,
+        "completion_tokens": output_tokens,
+        "total_tokens": prompt_tokens + output_tokens,
+        "prompt_tokens_details": { "cached_tokens": cached }
+    })
+}
+
+/// Anthropic Messages 非流式响应 -> OpenAI Chat Completion
+pub fn anthropic_to_openai_response(resp: &Value, model: &str) -> Value {
+    let mut text = String::new();
+    let mut reasoning = String::new();
+    let mut tool_calls = Vec::new();
+
+    for block in resp.get("content").and_then(|c| c.as_array()).into_iter().flatten() {
+        match block.get("type").and_then(|t| t.as_str()) {
+            Some("text") => text.push_str(block.get("text").and_then(|t| t.as_str()).unwrap_or("")),
+            Some("thinking") => {
+                reasoning.push_str(block.get("thinking").and_then(|t| t.as_str()).unwrap_or(""))
+            }
+            Some("tool_use") => tool_calls.push(json!({
+                "id": block.get("id").cloned().unwrap_or(Value::Null),
+                "type": "function",
+                "function": {
+                    "name": block.get("name").cloned().unwrap_or(Value::Null),
+                    "arguments": block.get("input").map(|i| i.to_string()).unwrap_or_else(|| "{}".to_string())
+                }
+            })),
+            _ => {}
+        }
+    }
+
+    let mut message = json!({ "role": "assistant", "content": text });
+    if !reasoning.is_empty() {
+        message["reasoning_content"] = json!(reasoning);
+    }
+    if !tool_calls.is_empty() {
+        message["tool_calls"] = json!(tool_calls);
+    }
+
+    let usage = resp.get("usage").cloned().unwrap_or_else(|| json!({}));
+    let output_tokens = usage.get("output_tokens").and_then(|v| v.as_u64()).unwrap_or(0);
+
+    json!({
+        "id": format!("chatcmpl-{}", Uuid::new_v4()),
+        "object": "chat.completion",
+        "created": Utc::now().timestamp(),
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "message": message,
+            "finish_reason": finish_reason(resp.get("stop_reason").and_then(|s| s.as_str()).unwrap_or("end_turn"))
+        }],
+        "usage": openai_usage(&usage, output_tokens)
+    })
+}
+
+/// Anthropic SSE 事件 -> OpenAI chunk 的流式状态
+pub struct AnthropicStreamState {
+    id: String,
+    created: i64,
+    model: String,
+    /// content block index -> tool_calls index
+    tool_indices: HashMap<u64, usize>,
+    input_usage: Value,
+    output_tokens: u64,
+}
+
+impl AnthropicStreamState {
+    pub fn new(model: String) -> Self {
+        Self {
+            id: format!("chatcmpl-{}", Uuid::new_v4()),
+            created: Utc::now().timestamp(),
+            model,
+            tool_indices: HashMap::new(),
+            input_usage: json!({}),
+            output_tokens: 0,
+        }
+    }
+
+    fn chunk(&self, delta: Value, finish_reason: Option<&str>) -> Value {
+        json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }]
+        })
+    }
+
+    /// 处理一个 Anthropic 事件 (data JSON)，返回需要发送的 OpenAI chunk
+    pub fn process_event(&mut self, event: &Value) -> Vec<Value> {
+        match event.get("type").and_then(|t| t.as_str()).unwrap_or("") {
+            "message_start" => {
+                if let Some(usage) = event.get("message").and_then(|m| m.get("usage")) {
+                    self.input_usage = usage.clone();
+                }
+                vec![self.chunk(json!({ "role": "assistant", "content": "" }), None)]
+            }
+            "content_block_start" => {
+                let block = &event["content_block"];
+                if block["type"] != "tool_use" {
+                    return Vec::new();
+                }
+                let tool_index = self.tool_indices.len();
+                self.tool_indices
+                    .insert(event["index"].as_u64().unwrap_or(0), tool_index);
+                vec![self.chunk(
+                    json!({ "tool_calls": [{
+                        "index": tool_index,
+                        "id": block["id"],
+                        "type": "function",
+                        "function": { "name": block["name"], "arguments": "" }
+                    }]}),
+                    None,
+                )]
+            }
+            "content_block_delta" => {
+                let delta = &event["delta"];
+                match delta["type"].as_str().unwrap_or("") {
+                    "text_delta" => vec![self.chunk(json!({ "content": delta["text"] }), None)],
+                    "thinking_delta" => {
+                        vec![self.chunk(json!({ "reasoning_content": delta["thinking"] }), None)]
+                    }
+                    "input_json_delta" => {
+                        let Some(tool_index) = event["index"]
+                            .as_u64()
+                            .and_then(|i| self.tool_indices.get(&i).copied())
+                        else {
+                            return Vec::new();
+                        };
+                        vec![self.chunk(
+                            json!({ "tool_calls": [{
+                                "index": tool_index,
+                                "function": { "arguments": delta["partial_json"] }
+                            }]}),
+                            None,
+                        )]
+                    }
+                    _ => Vec::new(),
+                }
+            }
+            "message_delta" => {
+                if let Some(tokens) = event
+                    .get("usage")
+                    .and_then(|u| u.get("output_tokens"))
+                    .and_then(|v| v.as_u64())
+                {
+                    self.output_tokens = tokens;
+                }
+                match event["delta"]["stop_reason"].as_str() {
+                    Some(reason) => vec![self.chunk(json!({}), Some(finish_reason(reason)))],
+                    None => Vec::new(),
+                }
+            }
+            "error" => vec![json!({ "error": event.get("error").cloned().unwrap_or(Value::Null) })],
+            _ => Vec::new(),
+        }
+    }
+
+    /// [stream_options.include_usage] 结束前的 usage chunk
+    pub fn usage_chunk(&self) -> Value {
+        json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [],
+            "usage": openai_usage(&self.input_usage, self.output_tokens)
+        })
+    }
+}
+
+/// 将 Anthropic SSE 流转换为 OpenAI SSE 流
+pub fn create_openai_sse_from_anthropic(
+    mut upstream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
+    model: String,
+    include_usage: bool,
+) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
+    let mut buffer = BytesMut::new();
+    let mut state = AnthropicStreamState::new(model);
+
+    let stream = async_stream::stream! {
+        while let Some(item) = upstream.next().await {
+            match item {
+                Ok(bytes) => {
+                    buffer.extend_from_slice(&bytes);
+                    while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+                        let line_raw = buffer.split_to(pos + 1);
+                        let Ok(line) = std::str::from_utf8(&line_raw) else { continue };
+                        // 仅处理 data 行，事件类型同样包含在 data 的 type 字段中
+                        let Some(data) = line.trim().strip_prefix("data:") else { continue };
+                        let Ok(event) = serde_json::from_str::<Value>(data.trim()) else { continue };
+                        for chunk in state.process_event(&event) {
+                            let sse_out = format!("data: {}\n\n", serde_json::to_string(&chunk).unwrap_or_default());
+                            yield Ok::<Bytes, String>(Bytes::from(sse_out));
+                        }
+                    }
+                }
+                Err(e) => {
+                    yield Err(format!("Upstream error: {}", e));
+                }
+            }
+        }
+        if include_usage {
+            let sse_out = format!("data: {}\n\n", serde_json::to_string(&state.usage_chunk()).unwrap_or_default());
+            yield Ok::<Bytes, String>(Bytes::from(sse_out));
+        }
+        yield Ok::<Bytes, String>(Bytes::from("data: [DONE]\n\n"));
+    };
+
+    Box::pin(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openai_to_anthropic_request() {
+        let request: OpenAIRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [
+                { "role": "system", "content": "Be brief." },
+                { "role": "user", "content": "Weather in Paris?" },
+                { "role": "assistant", "content": null, "tool_calls": [{
+                    "id": "call_1", "type": "function",
+                    "function": { "name": "get_weather", "arguments": "{\"city\":\"Paris\"}" }
+                }]},
+                { "role": "tool", "tool_call_id": "call_1", "content": "18C" }
+            ],
+            "tools": [{ "type": "function", "function": { "name": "get_weather", "parameters": { "type": "object" } } }],
+            "tool_choice": "required",
+            "stop": "END",
+            "reasoning_effort": "low",
+            "temperature": 0.2
+        }))
+        .unwrap();
+
+        let body = openai_to_anthropic_request(&request, "glm-4.7");
+        assert_eq!(body["model"], "glm-4.7");
+        assert_eq!(body["system"], "Be brief.");
+        assert_eq!(body["messages"].as_array().unwrap().len(), 3);
+        assert_eq!(body["messages"][1]["content"][0]["input"]["city"], "Paris");
+        assert_eq!(body["messages"][2]["content"][0]["tool_use_id"], "call_1");
+        assert_eq!(body["tools"][0]["input_schema"]["type"], "object");
+        assert_eq!(body["tool_choice"]["type"], "any");
+        assert_eq!(body["stop_sequences"][0], "END");
+        assert_eq!(body["thinking"]["budget_tokens"], 4096);
+        assert!(body["max_tokens"].as_u64().unwrap() > 4096);
+        assert!(body.get("temperature").is_none());
+    }
+
+    #[test]
+    fn test_stream_events_to_openai_chunks() {
+        let mut state = AnthropicStreamState::new("gpt-4o".to_string());
+        let events = [
+            json!({ "type": "message_start", "message": { "usage": { "input_tokens": 10, "cache_read_input_tokens": 5 } } }),
+            json!({ "type": "content_block_delta", "index": 0, "delta": { "type": "thinking_delta", "thinking": "hmm" } }),
+            json!({ "type": "content_block_delta", "index": 1, "delta": { "type": "text_delta", "text": "Hi" } }),
+            json!({ "type": "content_block_start", "index": 2, "content_block": { "type": "tool_use", "id": "toolu_1", "name": "get_weather" } }),
+            json!({ "type": "content_block_delta", "index": 2, "delta": { "type": "input_json_delta", "partial_json": "{\"city\"" } }),
+            json!({ "type": "message_delta", "delta": { "stop_reason": "tool_use" }, "usage": { "output_tokens": 7 } }),
+            json!({ "type": "message_stop" }),
+        ];
+        let chunks: Vec<Value> = events.iter().flat_map(|e| state.process_event(e)).collect();
+
+        assert_eq!(chunks.len(), 6);
+        assert_eq!(chunks[1]["choices"][0]["delta"]["reasoning_content"], "hmm");
+        assert_eq!(chunks[2]["choices"][0]["delta"]["content"], "Hi");
+        assert_eq!(chunks[3]["choices"][0]["delta"]["tool_calls"][0]["id"], "toolu_1");
+        assert_eq!(chunks[4]["choices"][0]["delta"]["tool_calls"][0]["index"], 0);
+        assert_eq!(chunks[5]["choices"][0]["finish_reason"], "tool_calls");
+
+        let usage = &state.usage_chunk()["usage"];
+        assert_eq!(usage["prompt_tokens"], 15);
+        assert_eq!(usage["completion_tokens"], 7);
+        assert_eq!(usage["prompt_tokens_details"]["cached_tokens"], 5);
+    }
+}
@@ -0,0 +1,145 @@
+// OpenAI Embeddings ↔ Gemini EmbedContentRequest 转换
+use base64::Engine as _;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmbeddingsRequest {
+    pub model: String,
+    pub input: Value,
+    #[serde(default)]
+    pub dimensions: Option<u32>,
+    /// "float" (默认) 或 "base64"
+    #[serde(default)]
+    pub encoding_format: Option<String>,
+}
+
+impl EmbeddingsRequest {
+    pub fn wants_base64(&self) -> bool {
+        self.encoding_format.as_deref() == Some("base64")
+    }
+}
+
+/// 解析 input: 支持单个字符串或字符串数组
+///
+/// token 数组 (`[1, 2]` / `[[1, 2]]`) 依赖 OpenAI 分词器，上游无法还原，直接拒绝
+pub fn parse_input(input: &Value) -> Result<Vec<String>, String> {
+    let texts = match input {
+        Value::String(s) => vec![s.clone()],
+        Value::Array(items) => items
+            .iter()
+            .map(|item| match item {
+                Value::String(s) => Ok(s.clone()),
+                Value::Number(_) | Value::Array(_) => {
+                    Err("Token array input is not supported, please send text".to_string())
+                }
+                other => Err(format!("Invalid input item: {}", other)),
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        _ => return Err("'input' must be a string or an array of strings".to_string()),
+    };
+
+    if texts.is_empty() {
+        return Err("'input' must not be empty".to_string());
+    }
+    if texts.iter().any(|t| t.is_empty()) {
+        return Err("'input' must not contain empty strings".to_string());
+    }
+    Ok(texts)
+}
+
+/// 构建 Gemini EmbedContentRequest 列表 (每条 input 一个请求)
+pub fn build_embed_requests(texts: &[String], mapped_model: &str, dimensions: Option<u32>) -> Vec<Value> {
+    texts
+        .iter()
+        .map(|text| {
+            let mut req = json!({
+                "model": format!("models/{}", mapped_model),
+                "content": { "parts": [{ "text": text }] }
+            });
+            if let Some(dim) = dimensions {
+                req["outputDimensionality"] = json!(dim);
+            }
+            req
+        })
+        .collect()
+}
+
+/// 按 OpenAI 约定将向量编码为 little-endian float32 字节的 base64
+pub fn encode_embedding_base64(values: &[f32]) -> String {
+    let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+    base64::engine::general_purpose::STANDARD.encode(bytes)
+}
+
+/// 构建 OpenAI 格式的 embeddings 响应
+pub fn build_embeddings_response(
+    model: &str,
+    embeddings: Vec<Vec<f32>>,
+    base64: bool,
+    prompt_tokens: u64,
+) -> Value {
+    let data: Vec<Value> = embeddings
+        .into_iter()
+        .enumerate()
+        .map(|(index, values)| {
+            let embedding = if base64 {
+                json!(encode_embedding_base64(&values))
+            } else {
+                json!(values)
+            };
+            json!({
+                "object": "embedding",
+                "index": index,
+                "embedding": embedding
+            })
+        })
+        .collect();
+
+    json!({
+        "object": "list",
+        "data": data,
+        "model": model,
+        "usage": {
+            "prompt_tokens": prompt_tokens,
+            "total_tokens": prompt_tokens
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_input() {
+        assert_eq!(parse_input(&json!("hello")).unwrap(), vec!["hello"]);
+        assert_eq!(parse_input(&json!(["a", "b"])).unwrap(), vec!["a", "b"]);
+        assert!(parse_input(&json!([1, 2, 3])).is_err());
+        assert!(parse_input(&json!([[1, 2]])).is_err());
+        assert!(parse_input(&json!([])).is_err());
+        assert!(parse_input(&json!([""])).is_err());
+    }
+
+    #[test]
+    fn test_build_requests_and_response() {
+        let texts = vec!["a".to_string(), "b".to_string()];
+        let reqs = build_embed_requests(&texts, "gemini-embedding-001", Some(256));
+        assert_eq!(reqs.len(), 2);
+        assert_eq!(reqs[1]["content"]["parts"][0]["text"], "b");
+        assert_eq!(reqs[0]["outputDimensionality"], 256);
+        assert!(build_embed_requests(&texts, "m", None)[0].get("outputDimensionality").is_none());
+
+        let resp = build_embeddings_response("text-embedding-3-small", vec![vec![1.0, -2.0]], false, 3);
+        assert_eq!(resp["data"][0]["embedding"], json!([1.0, -2.0]));
+        assert_eq!(resp["usage"]["total_tokens"], 3);
+
+        let resp = build_embeddings_response("m", vec![vec![1.0, -2.0]], true, 3);
+        let encoded = resp["data"][0]["embedding"].as_str().unwrap();
+        let bytes = base64::engine::general_purpose::STANDARD.decode(encoded).unwrap();
+        let decoded: Vec<f32> = bytes
+            .chunks_exact(4)
+            .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+            .collect();
+        assert_eq!(decoded, vec![1.0, -2.0]);
+    }
+}
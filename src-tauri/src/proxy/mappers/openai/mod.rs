@@ -6,6 +6,9 @@ pub mod request;
 pub mod response;
 pub mod streaming;
 pub mod collector;
+pub mod responses; // Responses API 输入项转换
+pub mod embeddings; // Embeddings 转换
+pub mod anthropic; // Anthropic 兼容服务商 (Messages API) 转换
 
 pub use models::*;
 pub use request::*;
@@ -16,10 +16,27 @@ pub struct OpenAIRequest {
     pub n: Option<u32>, // [NEW] 支持多候选结果数量
     #[serde(rename = "max_tokens")]
     pub max_tokens: Option<u32>,
+    /// 新版 SDK 用于替代 max_tokens (优先级更高)
+    #[serde(default)]
+    pub max_completion_tokens: Option<u32>,
     pub temperature: Option<f32>,
     #[serde(rename = "top_p")]
     pub top_p: Option<f32>,
     pub stop: Option<Value>,
+    #[serde(default)]
+    pub seed: Option<i64>,
+    #[serde(default)]
+    pub presence_penalty: Option<f32>,
+    #[serde(default)]
+    pub frequency_penalty: Option<f32>,
+    /// minimal / low / medium / high (映射为 thinkingBudget)
+    #[serde(default)]
+    pub reasoning_effort: Option<String>,
+    #[serde(default)]
+    pub stream_options: Option<StreamOptions>,
+    /// 终端用户标识，用作粘性会话指纹
+    #[serde(default)]
+    pub user: Option<String>,
     pub response_format: Option<ResponseFormat>,
     #[serde(default)]
     pub tools: Option<Vec<Value>>,
@@ -32,9 +49,54 @@ pub struct OpenAIRequest {
     pub input: Option<Value>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct StreamOptions {
+    #[serde(default)]
+    pub include_usage: Option<bool>,
+}
+
+impl OpenAIRequest {
+    /// 流式响应结束前是否需要追加 usage chunk
+    pub fn include_stream_usage(&self) -> bool {
+        self.stream_options
+            .as_ref()
+            .and_then(|o| o.include_usage)
+            .unwrap_or(false)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ResponseFormat {
     pub r#type: String,
+    /// `type: "json_schema"` 时携带的 Schema 定义
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub json_schema: Option<JsonSchemaFormat>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonSchemaFormat {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>,
+}
+
+impl ResponseFormat {
+    /// 需要校验的原始 Schema (仅 `json_schema` 且 `strict: true` 时启用校验)
+    pub fn strict_schema(&self) -> Option<&Value> {
+        if self.r#type != "json_schema" {
+            return None;
+        }
+        let format = self.json_schema.as_ref()?;
+        if format.strict != Some(true) {
+            return None;
+        }
+        format.schema.as_ref()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
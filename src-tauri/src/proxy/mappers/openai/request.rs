@@ -222,8 +222,12 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
     let is_gemini_3_thinking = mapped_model.contains("gemini-3") && 
         (mapped_model.ends_with("-high") || mapped_model.ends_with("-low") || mapped_model.contains("-pro"));
 
+    let registry = crate::proxy::common::model_registry::ModelRegistry::global();
     let mut gen_config = json!({
-        "maxOutputTokens": request.max_tokens.unwrap_or(64000),
+        "maxOutputTokens": registry.clamp_output_tokens(
+            mapped_model,
+            request.max_completion_tokens.or(request.max_tokens).unwrap_or(64000) as u64,
+        ),
         "temperature": request.temperature.unwrap_or(1.0),
         "topP": request.top_p.unwrap_or(1.0), 
     });
@@ -242,6 +246,52 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
         tracing::debug!("[OpenAI-Request] Injected thinkingConfig for Gemini 3 Pro: thinkingBudget=16000");
     }
 
+    // reasoning_effort → thinkingBudget (仅对支持思考的模型生效，"none" 表示关闭思考)
+    if let Some(effort) = request.reasoning_effort.as_deref() {
+        let supports_thinking = is_gemini_3_thinking || registry.supports_thinking(mapped_model);
+        match reasoning_effort_budget(effort, mapped_model) {
+            Some(ThinkingEffort::Budget(budget)) if supports_thinking => {
+                gen_config["thinkingConfig"] = json!({
+                    "includeThoughts": true,
+                    "thinkingBudget": budget
+                });
+                tracing::debug!("[OpenAI-Request] reasoning_effort={} -> thinkingBudget={}", effort, budget);
+            }
+            Some(ThinkingEffort::Off) => {
+                if let Some(obj) = gen_config.as_object_mut() {
+                    obj.remove("thinkingConfig");
+                }
+            }
+            Some(ThinkingEffort::Budget(_)) => {}
+            None => {
+                tracing::warn!("[OpenAI-Request] Ignoring unknown reasoning_effort: {}", effort);
+            }
+        }
+    }
+
+    // thinkingBudget 计入 maxOutputTokens: 预算不小于输出上限时，为回答额外预留客户端请求的长度
+    // (不超过模型输出上限)，仍放不下时将预算收紧为输出上限的一半
+    if let Some(budget) = gen_config["thinkingConfig"]["thinkingBudget"].as_u64() {
+        let max_output = gen_config["maxOutputTokens"].as_u64().unwrap_or(0);
+        if budget >= max_output {
+            let raised = registry.clamp_output_tokens(mapped_model, budget + max_output);
+            gen_config["maxOutputTokens"] = json!(raised);
+            if budget >= raised {
+                gen_config["thinkingConfig"]["thinkingBudget"] = json!(raised / 2);
+            }
+        }
+    }
+
+    if let Some(seed) = request.seed {
+        gen_config["seed"] = json!(seed);
+    }
+    if let Some(penalty) = request.presence_penalty {
+        gen_config["presencePenalty"] = json!(penalty);
+    }
+    if let Some(penalty) = request.frequency_penalty {
+        gen_config["frequencyPenalty"] = json!(penalty);
+    }
+
 
     if let Some(stop) = &request.stop {
         if stop.is_string() { gen_config["stopSequences"] = json!([stop]); }
@@ -251,6 +301,15 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
     if let Some(fmt) = &request.response_format {
         if fmt.r#type == "json_object" {
             gen_config["responseMimeType"] = json!("application/json");
+        } else if fmt.r#type == "json_schema" {
+            gen_config["responseMimeType"] = json!("application/json");
+            // 结构化输出: Schema 复用工具参数的清洗流程 ($ref 展开 / 移除不支持的字段 / 类型大写)
+            if let Some(schema) = fmt.json_schema.as_ref().and_then(|f| f.schema.as_ref()) {
+                let mut response_schema = schema.clone();
+                crate::proxy::common::json_schema::clean_json_schema(&mut response_schema);
+                enforce_uppercase_types(&mut response_schema);
+                gen_config["responseSchema"] = response_schema;
+            }
         }
     }
 
@@ -369,6 +428,7 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
              if let Some(gen_obj) = gen_config.as_object_mut() {
                  gen_obj.remove("thinkingConfig");
                  gen_obj.remove("responseMimeType"); 
+                 gen_obj.remove("responseSchema");
                  gen_obj.remove("responseModalities");
                  gen_obj.insert("imageConfig".to_string(), image_config);
              }
@@ -385,6 +445,32 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
     })
 }
 
+/// reasoning_effort 解析结果
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ThinkingEffort {
+    /// "none": 关闭思考
+    Off,
+    Budget(u32),
+}
+
+/// reasoning_effort 对应的思考预算，未知取值返回 None (由调用方忽略)
+pub(crate) fn reasoning_effort_budget(effort: &str, mapped_model: &str) -> Option<ThinkingEffort> {
+    let budget = match effort.to_lowercase().as_str() {
+        "none" => return Some(ThinkingEffort::Off),
+        "minimal" => 1024,
+        "low" => 4096,
+        "medium" => 16000,
+        "high" | "xhigh" => 32768,
+        _ => return None,
+    };
+    // gemini-2.5-flash 上限 24576
+    if mapped_model.contains("flash") {
+        Some(ThinkingEffort::Budget(budget.min(24576)))
+    } else {
+        Some(ThinkingEffort::Budget(budget))
+    }
+}
+
 fn enforce_uppercase_types(value: &mut Value) {
     if let Value::Object(map) = value {
         if let Some(type_val) = map.get_mut("type") {
@@ -434,9 +520,16 @@ mod tests {
             stream: false,
             n: None,
             max_tokens: None,
+            max_completion_tokens: None,
             temperature: None,
             top_p: None,
             stop: None,
+            seed: None,
+            presence_penalty: None,
+            frequency_penalty: None,
+            reasoning_effort: None,
+            stream_options: None,
+            user: None,
             response_format: None,
             tools: None,
             tool_choice: None,
@@ -452,4 +545,103 @@ mod tests {
         assert_eq!(parts[0]["text"].as_str().unwrap(), "What is in this image?");
         assert_eq!(parts[1]["inlineData"]["mimeType"].as_str().unwrap(), "image/png");
     }
+
+    #[test]
+    fn test_json_schema_response_format() {
+        let req: OpenAIRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [{ "role": "user", "content": "extract" }],
+            "response_format": {
+                "type": "json_schema",
+                "json_schema": {
+                    "name": "person",
+                    "strict": true,
+                    "schema": {
+                        "type": "object",
+                        "properties": { "name": { "type": "string" } },
+                        "required": ["name"],
+                        "additionalProperties": false
+                    }
+                }
+            }
+        }))
+        .unwrap();
+
+        let fmt = req.response_format.as_ref().unwrap();
+        assert!(fmt.strict_schema().is_some());
+
+        let result = transform_openai_request(&req, "test-v", "gemini-2.5-flash");
+        let gen_config = &result["request"]["generationConfig"];
+        assert_eq!(gen_config["responseMimeType"], "application/json");
+        assert_eq!(gen_config["responseSchema"]["type"], "OBJECT");
+        assert_eq!(gen_config["responseSchema"]["properties"]["name"]["type"], "STRING");
+        assert!(gen_config["responseSchema"].get("additionalProperties").is_none());
+    }
+
+    #[test]
+    fn test_sampling_parameters_mapping() {
+        let req: OpenAIRequest = serde_json::from_value(json!({
+            "model": "gemini-2.5-flash",
+            "messages": [{ "role": "user", "content": "hi" }],
+            "max_tokens": 100,
+            "max_completion_tokens": 2048,
+            "seed": 42,
+            "presence_penalty": 0.5,
+            "frequency_penalty": -0.5,
+            "reasoning_effort": "high",
+            "stream_options": { "include_usage": true },
+            "user": "user-123"
+        }))
+        .unwrap();
+        assert!(req.include_stream_usage());
+
+        let result = transform_openai_request(&req, "test-v", "gemini-2.5-flash");
+        let gen_config = &result["request"]["generationConfig"];
+        // 思考预算之外仍为回答保留客户端请求的 2048 tokens
+        assert_eq!(gen_config["maxOutputTokens"], 24576 + 2048);
+        assert_eq!(gen_config["seed"], 42);
+        assert_eq!(gen_config["presencePenalty"], 0.5);
+        assert_eq!(gen_config["frequencyPenalty"], -0.5);
+        assert_eq!(gen_config["thinkingConfig"]["thinkingBudget"], 24576);
+
+        assert_eq!(reasoning_effort_budget("low", "gemini-3-pro-high"), Some(ThinkingEffort::Budget(4096)));
+        assert_eq!(reasoning_effort_budget("none", "gemini-3-pro-high"), Some(ThinkingEffort::Off));
+        assert_eq!(reasoning_effort_budget("extreme", "gemini-3-pro-high"), None);
+    }
+
+    #[test]
+    fn test_thinking_budget_fits_model_output_limit() {
+        // 预算不小于请求长度时提高输出上限，请求长度足够时保持不变
+        let req: OpenAIRequest = serde_json::from_value(json!({
+            "model": "gemini-3-pro-high",
+            "messages": [{ "role": "user", "content": "hi" }],
+            "max_tokens": 20000,
+            "reasoning_effort": "high"
+        }))
+        .unwrap();
+        let result = transform_openai_request(&req, "test-v", "gemini-3-pro-high");
+        let gen_config = &result["request"]["generationConfig"];
+        assert_eq!(gen_config["thinkingConfig"]["thinkingBudget"], 32768);
+        assert_eq!(gen_config["maxOutputTokens"], 32768 + 20000);
+
+        let req: OpenAIRequest = serde_json::from_value(json!({
+            "model": "gemini-3-pro-high",
+            "messages": [{ "role": "user", "content": "hi" }],
+            "max_tokens": 40000,
+            "reasoning_effort": "high"
+        }))
+        .unwrap();
+        let result = transform_openai_request(&req, "test-v", "gemini-3-pro-high");
+        assert_eq!(result["request"]["generationConfig"]["maxOutputTokens"], 40000);
+
+        // 未知的 reasoning_effort 被忽略
+        let req: OpenAIRequest = serde_json::from_value(json!({
+            "model": "gemini-2.5-flash",
+            "messages": [{ "role": "user", "content": "hi" }],
+            "reasoning_effort": "extreme"
+        }))
+        .unwrap();
+        let result = transform_openai_request(&req, "test-v", "gemini-2.5-flash");
+        assert!(result["request"]["generationConfig"].get("thinkingConfig").is_none());
+    }
 }
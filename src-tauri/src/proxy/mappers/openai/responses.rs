@@ -0,0 +1,393 @@
+// OpenAI Responses API 映射
+// 负责 Responses 输入项 (input items) ↔ Chat Completions 消息的转换，供 /v1/responses 与 Codex 风格请求共用
+
+use serde_json::{json, Value};
+use tracing::debug;
+
+/// 将 `input` 规范化为输入项数组 (字符串输入视为单条 user 消息)
+pub fn normalize_input(input: Option<&Value>) -> Vec<Value> {
+    match input {
+        Some(Value::String(text)) => vec![json!({
+            "type": "message",
+            "role": "user",
+            "content": [{ "type": "input_text", "text": text }]
+        })],
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(|item| match item {
+                Value::String(text) => json!({
+                    "type": "message",
+                    "role": "user",
+                    "content": [{ "type": "input_text", "text": text }]
+                }),
+                other => other.clone(),
+            })
+            .collect(),
+        Some(obj @ Value::Object(_)) => vec![obj.clone()],
+        _ => Vec::new(),
+    }
+}
+
+/// 将 Responses 输入项转换为 Chat Completions 消息
+///
+/// `instructions` 作为 system 消息置于最前；未显式声明 type 的 `{role, content}` 项按 message 处理
+pub fn input_items_to_messages(instructions: &str, input_items: &[Value]) -> Vec<Value> {
+    let mut messages = Vec::new();
+
+    // System Instructions
+    if !instructions.is_empty() {
+        messages.push(json!({ "role": "system", "content": instructions }));
+    }
+
+    let mut call_id_to_name = std::collections::HashMap::new();
+
+    // Pass 1: Build Call ID to Name Map
+    for item in input_items {
+        let item_type = item_type_of(item);
+        match item_type {
+            "function_call" | "local_shell_call" | "web_search_call" => {
+                let call_id = item
+                    .get("call_id")
+                    .and_then(|v| v.as_str())
+                    .or_else(|| item.get("id").and_then(|v| v.as_str()))
+                    .unwrap_or("unknown");
+
+                let name = if item_type == "local_shell_call" {
+                    "shell"
+                } else if item_type == "web_search_call" {
+                    "google_search"
+                } else {
+                    item.get("name")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("unknown")
+                };
+
+                call_id_to_name.insert(call_id.to_string(), name.to_string());
+                tracing::debug!("Mapped call_id {} to name {}", call_id, name);
+            }
+            _ => {}
+        }
+    }
+
+    // Pass 2: Map Input Items to Messages
+    for item in input_items {
+        let item_type = item_type_of(item);
+        match item_type {
+            "message" => {
+                let role = match item.get("role").and_then(|v| v.as_str()).unwrap_or("user") {
+                    "developer" => "system",
+                    other => other,
+                };
+                let content = item.get("content").and_then(|v| v.as_array());
+                let mut text_parts = Vec::new();
+                let mut image_parts: Vec<Value> = Vec::new();
+
+                // 简写形式: content 为纯字符串
+                if let Some(text) = item.get("content").and_then(|v| v.as_str()) {
+                    text_parts.push(text.to_string());
+                }
+
+                if let Some(parts) = content {
+                    for part in parts {
+                        // 处理文本块
+                        if let Some(text) = part.get("text").and_then(|v| v.as_str()) {
+                            text_parts.push(text.to_string());
+                        }
+                        // [NEW] 处理图像块 (Codex input_image 格式)
+                        else if part.get("type").and_then(|v| v.as_str())
+                            == Some("input_image")
+                        {
+                            if let Some(image_url) =
+                                part.get("image_url").and_then(|v| v.as_str())
+                            {
+                                image_parts.push(json!({
+                                    "type": "image_url",
+                                    "image_url": { "url": image_url }
+                                }));
+                                debug!("[Codex] Found input_image: {}", image_url);
+                            }
+                        }
+                        // [NEW] 兼容标准 OpenAI image_url 格式
+                        else if part.get("type").and_then(|v| v.as_str())
+                            == Some("image_url")
+                        {
+                            if let Some(url_obj) = part.get("image_url") {
+                                image_parts.push(json!({
+                                    "type": "image_url",
+                                    "image_url": url_obj.clone()
+                                }));
+                            }
+                        }
+                    }
+                }
+
+                // 构造消息内容：如果有图像则使用数组格式
+                if image_parts.is_empty() {
+                    messages.push(json!({
+                        "role": role,
+                        "content": text_parts.join("\n")
+                    }));
+                } else {
+                    let mut content_blocks: Vec<Value> = Vec::new();
+                    if !text_parts.is_empty() {
+                        content_blocks.push(json!({
+                            "type": "text",
+                            "text": text_parts.join("\n")
+                        }));
+                    }
+                    content_blocks.extend(image_parts);
+                    messages.push(json!({
+                        "role": role,
+                        "content": content_blocks
+                    }));
+                }
+            }
+            "function_call" | "local_shell_call" | "web_search_call" => {
+                let mut name = item
+                    .get("name")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("unknown");
+                let mut args_str = item
+                    .get("arguments")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("{}")
+                    .to_string();
+                let call_id = item
+                    .get("call_id")
+                    .and_then(|v| v.as_str())
+                    .or_else(|| item.get("id").and_then(|v| v.as_str()))
+                    .unwrap_or("unknown");
+
+                // Handle native shell calls
+                if item_type == "local_shell_call" {
+                    name = "shell";
+                    if let Some(action) = item.get("action") {
+                        // 兼容 {"exec": {...}} 与 {"type": "exec", "command": [...]} 两种形式
+                        if let Some(exec) = action.get("exec").or(Some(action).filter(|a| a.get("command").is_some())) {
+                            // Map to ShellCommandToolCallParams (string command) or ShellToolCallParams (array command)
+                            // Most LLMs prefer a single string for shell
+                            let mut args_obj = serde_json::Map::new();
+                            if let Some(cmd) = exec.get("command") {
+                                // CRITICAL FIX: The 'shell' tool schema defines 'command' as an ARRAY of strings.
+                                // We MUST pass it as an array, not a joined string, otherwise Gemini rejects with 400 INVALID_ARGUMENT.
+                                let cmd_val = if cmd.is_string() {
+                                    json!([cmd]) // Wrap in array
+                                } else {
+                                    cmd.clone() // Assume already array
+                                };
+                                args_obj.insert("command".to_string(), cmd_val);
+                            }
+                            if let Some(wd) =
+                                exec.get("working_directory").or(exec.get("workdir"))
+                            {
+                                args_obj.insert("workdir".to_string(), wd.clone());
+                            }
+                            args_str = serde_json::to_string(&args_obj)
+                                .unwrap_or("{}".to_string());
+                        }
+                    }
+                } else if item_type == "web_search_call" {
+                    name = "google_search";
+                    if let Some(action) = item.get("action") {
+                        let mut args_obj = serde_json::Map::new();
+                        if let Some(q) = action.get("query") {
+                            args_obj.insert("query".to_string(), q.clone());
+                        }
+                        args_str =
+                            serde_json::to_string(&args_obj).unwrap_or("{}".to_string());
+                    }
+                }
+
+                messages.push(json!({
+                    "role": "assistant",
+                    "tool_calls": [
+                        {
+                            "id": call_id,
+                            "type": "function",
+                            "function": {
+                                "name": name,
+                                "arguments": args_str
+                            }
+                        }
+                    ]
+                }));
+            }
+            "function_call_output" | "custom_tool_call_output" => {
+                let call_id = item
+                    .get("call_id")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("unknown");
+                let output = item.get("output");
+                let output_str = if let Some(o) = output {
+                    if o.is_string() {
+                        o.as_str().unwrap().to_string()
+                    } else if let Some(content) = o.get("content").and_then(|v| v.as_str())
+                    {
+                        content.to_string()
+                    } else {
+                        o.to_string()
+                    }
+                } else {
+                    "".to_string()
+                };
+
+                let name = call_id_to_name.get(call_id).cloned().unwrap_or_else(|| {
+                    // Fallback: if unknown and we see function_call_output, it's likely "shell" in this context
+                    tracing::warn!(
+                        "Unknown tool name for call_id {}, defaulting to 'shell'",
+                        call_id
+                    );
+                    "shell".to_string()
+                });
+
+                messages.push(json!({
+                    "role": "tool",
+                    "tool_call_id": call_id,
+                    "name": name,
+                    "content": output_str
+                }));
+            }
+            _ => {}
+        }
+    }
+
+    messages
+}
+
+/// 将 Responses 请求参数映射为 Chat Completions 请求体 (messages 已由调用方构造)
+pub fn build_chat_request(body: &Value, messages: Vec<Value>) -> Value {
+    let mut chat = json!({
+        "model": body.get("model").cloned().unwrap_or(json!("")),
+        "messages": messages,
+        "stream": true,
+    });
+
+    for key in ["temperature", "top_p", "tool_choice", "parallel_tool_calls", "user"] {
+        if let Some(v) = body.get(key).filter(|v| !v.is_null()) {
+            chat[key] = v.clone();
+        }
+    }
+    if let Some(v) = body.get("max_output_tokens").filter(|v| !v.is_null()) {
+        chat["max_completion_tokens"] = v.clone();
+    }
+    if let Some(effort) = body.get("reasoning").and_then(|r| r.get("effort")).and_then(|v| v.as_str()) {
+        chat["reasoning_effort"] = json!(effort);
+    }
+
+    // text.format → response_format
+    if let Some(format) = body.get("text").and_then(|t| t.get("format")) {
+        match format.get("type").and_then(|v| v.as_str()) {
+            Some("json_schema") => {
+                chat["response_format"] = json!({
+                    "type": "json_schema",
+                    "json_schema": {
+                        "name": format.get("name").cloned().unwrap_or(json!("response")),
+                        "description": format.get("description").cloned().unwrap_or(Value::Null),
+                        "schema": format.get("schema").cloned().unwrap_or(Value::Null),
+                        "strict": format.get("strict").cloned().unwrap_or(Value::Null)
+                    }
+                });
+            }
+            Some("json_object") => chat["response_format"] = json!({ "type": "json_object" }),
+            _ => {}
+        }
+    }
+
+    // 内置联网工具统一改写为 web_search，由 googleSearch 注入逻辑处理
+    if let Some(tools) = body.get("tools").and_then(|v| v.as_array()) {
+        let mapped: Vec<Value> = tools
+            .iter()
+            .map(|tool| match tool.get("type").and_then(|v| v.as_str()) {
+                Some(t) if t.starts_with("web_search") => json!({ "type": "web_search", "name": "web_search" }),
+                _ => tool.clone(),
+            })
+            .collect();
+        chat["tools"] = json!(mapped);
+    }
+
+    chat
+}
+
+/// 构造 response 对象的静态字段 (输出 / 用量 / 状态由事件流填充)
+pub fn build_response_template(body: &Value, response_id: &str, created_at: i64) -> Value {
+    json!({
+        "id": response_id,
+        "object": "response",
+        "created_at": created_at,
+        "model": body.get("model").cloned().unwrap_or(Value::Null),
+        "instructions": body.get("instructions").cloned().unwrap_or(Value::Null),
+        "previous_response_id": body.get("previous_response_id").cloned().unwrap_or(Value::Null),
+        "store": body.get("store").and_then(|v| v.as_bool()).unwrap_or(true),
+        "metadata": body.get("metadata").cloned().unwrap_or(json!({})),
+        "max_output_tokens": body.get("max_output_tokens").cloned().unwrap_or(Value::Null),
+        "temperature": body.get("temperature").cloned().unwrap_or(Value::Null),
+        "top_p": body.get("top_p").cloned().unwrap_or(Value::Null),
+        "parallel_tool_calls": body.get("parallel_tool_calls").cloned().unwrap_or(json!(true)),
+        "tool_choice": body.get("tool_choice").cloned().unwrap_or(json!("auto")),
+        "tools": body.get("tools").cloned().unwrap_or(json!([])),
+        "text": body.get("text").cloned().unwrap_or(json!({ "format": { "type": "text" } })),
+        "reasoning": body.get("reasoning").cloned().unwrap_or(Value::Null),
+        "user": body.get("user").cloned().unwrap_or(Value::Null)
+    })
+}
+
+/// 输入项类型 (缺省 type 但带 role 的简写项视为 message)
+fn item_type_of(item: &Value) -> &str {
+    match item.get("type").and_then(|v| v.as_str()) {
+        Some(t) => t,
+        None if item.get("role").is_some() => "message",
+        None => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_string_input_becomes_user_message() {
+        let items = normalize_input(Some(&json!("hello")));
+        let messages = input_items_to_messages("be brief", &items);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0]["role"], "system");
+        assert_eq!(messages[1]["role"], "user");
+        assert_eq!(messages[1]["content"], "hello");
+    }
+
+    #[test]
+    fn test_replays_previous_output_items() {
+        let items = vec![
+            json!({ "role": "developer", "content": "rules" }),
+            json!({ "type": "message", "role": "user", "content": [{ "type": "input_text", "text": "list files" }] }),
+            json!({ "type": "local_shell_call", "call_id": "call_1", "action": { "type": "exec", "command": ["ls"] } }),
+            json!({ "type": "function_call_output", "call_id": "call_1", "output": "a.txt" }),
+            json!({ "type": "message", "role": "assistant", "content": [{ "type": "output_text", "text": "a.txt" }] }),
+            json!({ "type": "reasoning", "summary": [] }),
+        ];
+        let messages = input_items_to_messages("", &items);
+        assert_eq!(messages.len(), 5);
+        assert_eq!(messages[0]["role"], "system");
+        assert_eq!(messages[2]["tool_calls"][0]["function"]["name"], "shell");
+        assert_eq!(messages[2]["tool_calls"][0]["function"]["arguments"], "{\"command\":[\"ls\"]}");
+        assert_eq!(messages[3]["role"], "tool");
+        assert_eq!(messages[3]["name"], "shell");
+        assert_eq!(messages[4]["role"], "assistant");
+    }
+
+    #[test]
+    fn test_build_chat_request_maps_parameters() {
+        let body = json!({
+            "model": "gpt-5",
+            "max_output_tokens": 512,
+            "reasoning": { "effort": "low" },
+            "text": { "format": { "type": "json_schema", "name": "x", "schema": { "type": "object" }, "strict": true } },
+            "tools": [{ "type": "web_search_preview" }, { "type": "function", "name": "f", "parameters": {} }]
+        });
+        let chat = build_chat_request(&body, vec![]);
+        assert_eq!(chat["max_completion_tokens"], 512);
+        assert_eq!(chat["reasoning_effort"], "low");
+        assert_eq!(chat["response_format"]["json_schema"]["strict"], true);
+        assert_eq!(chat["tools"][0]["name"], "web_search");
+        assert_eq!(chat["tools"][1]["name"], "f");
+    }
+}
@@ -53,8 +53,11 @@ pub fn get_thought_signature() -> Option<String> {
 pub fn create_openai_sse_stream(
     mut gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
     model: String,
+    include_usage: bool,
 ) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
     let mut buffer = BytesMut::new();
+    // 记录最近一次 usageMetadata (Gemini 在后续 chunk 中累计上报)
+    let mut last_usage: Option<Value> = None;
     
     // 在流开始时生成固定的 ID 和 timestamp，所有 chunk 共用
     let stream_id = format!("chatcmpl-{}", Uuid::new_v4());
@@ -92,6 +95,10 @@ pub fn create_openai_sse_stream(
                                         json
                                     };
 
+                                    if let Some(usage) = actual_data.get("usageMetadata") {
+                                        last_usage = Some(usage.clone());
+                                    }
+
                                     // Extract candidates
                                     if let Some(candidates) = actual_data.get("candidates").and_then(|c| c.as_array()) {
                                         for (idx, candidate) in candidates.iter().enumerate() {
@@ -239,6 +246,20 @@ pub fn create_openai_sse_stream(
                 }
             }
         }
+        // [stream_options.include_usage] 结束前追加一个 choices 为空的 usage chunk
+        if include_usage {
+            let usage_chunk = json!({
+                "id": &stream_id,
+                "object": "chat.completion.chunk",
+                "created": created_ts,
+                "model": model,
+                "choices": [],
+                "usage": openai_usage_from_gemini(last_usage.as_ref())
+            });
+            let sse_out = format!("data: {}\n\n", serde_json::to_string(&usage_chunk).unwrap_or_default());
+            yield Ok::<Bytes, String>(Bytes::from(sse_out));
+        }
+
         // End of stream signal for OpenAI
         yield Ok::<Bytes, String>(Bytes::from("data: [DONE]\n\n"));
     };
@@ -246,6 +267,52 @@ pub fn create_openai_sse_stream(
     Box::pin(stream)
 }
 
+/// 是否为携带内容的 chunk (正文 / 思考 / 工具调用)，仅含 finish_reason 或 usage 的 chunk 不计
+pub fn is_content_chunk(chunk: &[u8]) -> bool {
+    let Ok(text) = std::str::from_utf8(chunk) else {
+        return false;
+    };
+    text.lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .filter_map(|data| serde_json::from_str::<Value>(data.trim()).ok())
+        .any(|json| {
+            json["choices"].as_array().is_some_and(|choices| {
+                choices.iter().any(|choice| {
+                    let delta = &choice["delta"];
+                    ["content", "reasoning_content"]
+                        .iter()
+                        .any(|key| delta[*key].as_str().is_some_and(|s| !s.is_empty()))
+                        || delta.get("tool_calls").is_some()
+                })
+            })
+        })
+}
+
+/// 将 Gemini usageMetadata 转换为 OpenAI usage 对象 (思考 token 计入 completion_tokens)
+fn openai_usage_from_gemini(usage: Option<&Value>) -> Value {
+    let get = |key: &str| {
+        usage
+            .and_then(|u| u.get(key))
+            .and_then(|v| v.as_u64())
+            .unwrap_or(0)
+    };
+    let prompt_tokens = get("promptTokenCount");
+    let reasoning_tokens = get("thoughtsTokenCount");
+    let completion_tokens = get("candidatesTokenCount") + reasoning_tokens;
+    let total_tokens = usage
+        .and_then(|u| u.get("totalTokenCount"))
+        .and_then(|v| v.as_u64())
+        .unwrap_or(prompt_tokens + completion_tokens);
+
+    json!({
+        "prompt_tokens": prompt_tokens,
+        "completion_tokens": completion_tokens,
+        "total_tokens": total_tokens,
+        "prompt_tokens_details": { "cached_tokens": get("cachedContentTokenCount") },
+        "completion_tokens_details": { "reasoning_tokens": reasoning_tokens }
+    })
+}
+
 pub fn create_legacy_sse_stream(
     mut gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
     model: String,
@@ -352,364 +419,573 @@ pub fn create_legacy_sse_stream(
     Box::pin(stream)
 }
 
+/// Responses 流结束时的回调，参数为最终的 response 对象 (用于持久化 / 非流式聚合)
+pub type ResponseCompletedHook = Box<dyn FnOnce(Value) + Send>;
+
+/// Responses API 事件序列化 (附带递增的 sequence_number)
+struct ResponsesEventWriter {
+    sequence: u64,
+}
+
+impl ResponsesEventWriter {
+    fn event(&mut self, mut ev: Value) -> Bytes {
+        ev["sequence_number"] = json!(self.sequence);
+        self.sequence += 1;
+        let event_type = ev.get("type").and_then(|t| t.as_str()).unwrap_or("message").to_string();
+        Bytes::from(format!(
+            "event: {}\ndata: {}\n\n",
+            event_type,
+            serde_json::to_string(&ev).unwrap_or_default()
+        ))
+    }
+}
+
+/// 正在输出中的 message / reasoning 输出项
+enum OpenOutputItem {
+    Message { id: String, index: usize, text: String },
+    Reasoning { id: String, index: usize, text: String },
+}
+
+fn new_item_id(prefix: &str) -> String {
+    format!("{}_{}", prefix, Uuid::new_v4().simple())
+}
+
+fn open_item_events(w: &mut ResponsesEventWriter, item: &OpenOutputItem) -> Vec<Bytes> {
+    match item {
+        OpenOutputItem::Message { id, index, .. } => vec![
+            w.event(json!({
+                "type": "response.output_item.added",
+                "output_index": index,
+                "item": { "id": id, "type": "message", "status": "in_progress", "role": "assistant", "content": [] }
+            })),
+            w.event(json!({
+                "type": "response.content_part.added",
+                "item_id": id,
+                "output_index": index,
+                "content_index": 0,
+                "part": { "type": "output_text", "text": "", "annotations": [] }
+            })),
+        ],
+        OpenOutputItem::Reasoning { id, index, .. } => vec![
+            w.event(json!({
+                "type": "response.output_item.added",
+                "output_index": index,
+                "item": { "id": id, "type": "reasoning", "summary": [] }
+            })),
+            w.event(json!({
+                "type": "response.reasoning_summary_part.added",
+                "item_id": id,
+                "output_index": index,
+                "summary_index": 0,
+                "part": { "type": "summary_text", "text": "" }
+            })),
+        ],
+    }
+}
+
+fn delta_event(w: &mut ResponsesEventWriter, item: &OpenOutputItem, delta: &str) -> Bytes {
+    match item {
+        OpenOutputItem::Message { id, index, .. } => w.event(json!({
+            "type": "response.output_text.delta",
+            "item_id": id,
+            "output_index": index,
+            "content_index": 0,
+            "delta": delta
+        })),
+        OpenOutputItem::Reasoning { id, index, .. } => w.event(json!({
+            "type": "response.reasoning_summary_text.delta",
+            "item_id": id,
+            "output_index": index,
+            "summary_index": 0,
+            "delta": delta
+        })),
+    }
+}
+
+/// 结束输出项，返回事件与最终的 item
+fn close_item_events(w: &mut ResponsesEventWriter, item: OpenOutputItem) -> (Vec<Bytes>, Value) {
+    match item {
+        OpenOutputItem::Message { id, index, text } => {
+            let part = json!({ "type": "output_text", "text": text, "annotations": [] });
+            let done_item = json!({
+                "id": id,
+                "type": "message",
+                "status": "completed",
+                "role": "assistant",
+                "content": [part.clone()]
+            });
+            let events = vec![
+                w.event(json!({
+                    "type": "response.output_text.done",
+                    "item_id": id,
+                    "output_index": index,
+                    "content_index": 0,
+                    "text": text
+                })),
+                w.event(json!({
+                    "type": "response.content_part.done",
+                    "item_id": id,
+                    "output_index": index,
+                    "content_index": 0,
+                    "part": part
+                })),
+                w.event(json!({
+                    "type": "response.output_item.done",
+                    "output_index": index,
+                    "item": done_item.clone()
+                })),
+            ];
+            (events, done_item)
+        }
+        OpenOutputItem::Reasoning { id, index, text } => {
+            let part = json!({ "type": "summary_text", "text": text });
+            let done_item = json!({ "id": id, "type": "reasoning", "summary": [part.clone()] });
+            let events = vec![
+                w.event(json!({
+                    "type": "response.reasoning_summary_text.done",
+                    "item_id": id,
+                    "output_index": index,
+                    "summary_index": 0,
+                    "text": text
+                })),
+                w.event(json!({
+                    "type": "response.reasoning_summary_part.done",
+                    "item_id": id,
+                    "output_index": index,
+                    "summary_index": 0,
+                    "part": part
+                })),
+                w.event(json!({
+                    "type": "response.output_item.done",
+                    "output_index": index,
+                    "item": done_item.clone()
+                })),
+            ];
+            (events, done_item)
+        }
+    }
+}
+
+/// 工具调用输出项 (added → [arguments delta/done] → done)
+fn tool_call_events(w: &mut ResponsesEventWriter, item: &Value, index: usize) -> Vec<Bytes> {
+    let mut events = Vec::new();
+    let is_function_call = item.get("type").and_then(|t| t.as_str()) == Some("function_call");
+    let mut added_item = item.clone();
+    if is_function_call {
+        added_item["status"] = json!("in_progress");
+        added_item["arguments"] = json!("");
+    }
+    events.push(w.event(json!({
+        "type": "response.output_item.added",
+        "output_index": index,
+        "item": added_item
+    })));
+    if is_function_call {
+        let item_id = item.get("id").cloned().unwrap_or(Value::Null);
+        let arguments = item.get("arguments").cloned().unwrap_or(json!("{}"));
+        events.push(w.event(json!({
+            "type": "response.function_call_arguments.delta",
+            "item_id": item_id,
+            "output_index": index,
+            "delta": arguments
+        })));
+        events.push(w.event(json!({
+            "type": "response.function_call_arguments.done",
+            "item_id": item_id,
+            "output_index": index,
+            "arguments": arguments
+        })));
+    }
+    events.push(w.event(json!({
+        "type": "response.output_item.done",
+        "output_index": index,
+        "item": item
+    })));
+    events
+}
+
+/// 将 Gemini functionCall 映射为 Responses 输出项 (shell → local_shell_call, 搜索 → web_search_call)
+fn function_call_to_output_item(func_call: &Value) -> Value {
+    let name = func_call.get("name").and_then(|v| v.as_str()).unwrap_or("unknown");
+
+    // Stable ID generation based on hashed content to be consistent
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    use std::hash::{Hash, Hasher};
+    serde_json::to_string(func_call).unwrap_or_default().hash(&mut hasher);
+    let call_id = format!("call_{:x}", hasher.finish());
+
+    let fallback_args = json!({});
+    let args_obj = func_call.get("args").unwrap_or(&fallback_args);
+
+    if name == "shell" || name == "local_shell" {
+        tracing::debug!("[Debug] func_call: {}", serde_json::to_string(&func_call).unwrap_or_default());
+
+        // 解析命令：支持数组格式、字符串格式，以及空 args 情况
+        let cmd_vec: Vec<String> = if args_obj.as_object().map(|o| o.is_empty()).unwrap_or(true) {
+            // args 为空时使用静默成功命令，避免任务中断
+            tracing::debug!("shell command args 为空，使用静默成功命令继续流程");
+            vec!["powershell.exe".to_string(), "-Command".to_string(), "exit 0".to_string()]
+        } else if let Some(arr) = args_obj.get("command").and_then(|v| v.as_array()) {
+            // 数组格式
+            arr.iter().filter_map(|v| v.as_str()).map(|s| s.to_string()).collect()
+        } else if let Some(cmd_str) = args_obj.get("command").and_then(|v| v.as_str()) {
+            // 字符串格式
+            if cmd_str.contains(' ') {
+                vec!["powershell.exe".to_string(), "-Command".to_string(), cmd_str.to_string()]
+            } else {
+                vec![cmd_str.to_string()]
+            }
+        } else {
+            // command 字段缺失，使用静默成功命令
+            tracing::debug!("shell command 缺少 command 字段，使用静默成功命令");
+            vec!["powershell.exe".to_string(), "-Command".to_string(), "exit 0".to_string()]
+        };
+
+        tracing::debug!("Shell 命令解析: {:?}", cmd_vec);
+        json!({
+            "id": new_item_id("lsh"),
+            "type": "local_shell_call",
+            "status": "in_progress",
+            "call_id": call_id,
+            "action": { "type": "exec", "command": cmd_vec }
+        })
+    } else if name == "googleSearch" || name == "web_search" || name == "google_search" {
+        let query_val = args_obj.get("query").and_then(|v| v.as_str()).unwrap_or("");
+        json!({
+            "id": new_item_id("ws"),
+            "type": "web_search_call",
+            "status": "in_progress",
+            "call_id": call_id,
+            "action": { "type": "search", "query": query_val }
+        })
+    } else {
+        json!({
+            "id": new_item_id("fc"),
+            "type": "function_call",
+            "status": "completed",
+            "name": name,
+            "arguments": args_obj.to_string(),
+            "call_id": call_id
+        })
+    }
+}
+
+/// 将 Gemini usageMetadata 转换为 Responses usage 对象
+fn responses_usage_from_gemini(usage: Option<&Value>) -> Value {
+    let get = |key: &str| {
+        usage
+            .and_then(|u| u.get(key))
+            .and_then(|v| v.as_u64())
+            .unwrap_or(0)
+    };
+    let input_tokens = get("promptTokenCount");
+    let reasoning_tokens = get("thoughtsTokenCount");
+    let output_tokens = get("candidatesTokenCount") + reasoning_tokens;
+    json!({
+        "input_tokens": input_tokens,
+        "input_tokens_details": { "cached_tokens": get("cachedContentTokenCount") },
+        "output_tokens": output_tokens,
+        "output_tokens_details": { "reasoning_tokens": reasoning_tokens },
+        "total_tokens": input_tokens + output_tokens
+    })
+}
+
+/// 将 Gemini SSE 转换为 OpenAI Responses API 事件流
+///
+/// `response_template` 提供 response 对象的静态字段 (id / instructions / previous_response_id / store 等)，
+/// 缺省时自动生成 id；流结束后通过 `on_completed` 交出最终的 response 对象
 pub fn create_codex_sse_stream(
     mut gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
-    _model: String,
+    model: String,
+    response_template: Value,
+    on_completed: Option<ResponseCompletedHook>,
 ) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
     let mut buffer = BytesMut::new();
-    
-    // Generate alphanumeric ID
-    let charset = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
-    let mut rng = rand::thread_rng();
-    let random_str: String = (0..24)
-        .map(|_| {
-            let idx = rng.gen_range(0..charset.len());
-            charset.chars().nth(idx).unwrap()
-        })
-        .collect();
-    let response_id = format!("resp-{}", random_str);
-    
+
+    let mut base_response = if response_template.is_object() { response_template } else { json!({}) };
+    if base_response.get("id").and_then(|v| v.as_str()).is_none() {
+        // Generate alphanumeric ID
+        let charset = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+        let mut rng = rand::thread_rng();
+        let random_str: String = (0..24)
+            .map(|_| {
+                let idx = rng.gen_range(0..charset.len());
+                charset.chars().nth(idx).unwrap()
+            })
+            .collect();
+        base_response["id"] = json!(format!("resp_{}", random_str));
+    }
+    base_response["object"] = json!("response");
+    if base_response.get("created_at").is_none() {
+        base_response["created_at"] = json!(Utc::now().timestamp());
+    }
+    if base_response.get("model").is_none() {
+        base_response["model"] = json!(model);
+    }
+
     let stream = async_stream::stream! {
-        // 1. Emit response.created
-        let created_ev = json!({
-            "type": "response.created",
-            "response": {
-                "id": &response_id,
-                "object": "response"
-            }
-        });
-        yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", serde_json::to_string(&created_ev).unwrap())));
+        let mut writer = ResponsesEventWriter { sequence: 0 };
+        let mut on_completed = on_completed;
 
+        // 1. Emit response.created / response.in_progress
+        let mut in_progress = base_response.clone();
+        in_progress["status"] = json!("in_progress");
+        in_progress["output"] = json!([]);
+        yield Ok::<Bytes, String>(writer.event(json!({ "type": "response.created", "response": in_progress.clone() })));
+        yield Ok::<Bytes, String>(writer.event(json!({ "type": "response.in_progress", "response": in_progress })));
+
+        let mut output_items: Vec<Value> = Vec::new();
+        let mut open_item: Option<OpenOutputItem> = None;
         let mut full_content = String::new();
         let mut emitted_tool_calls = std::collections::HashSet::new();
         let mut last_finish_reason = "stop".to_string();
+        let mut last_usage: Option<Value> = None;
+        let mut failure: Option<String> = None;
 
-        while let Some(item) = gemini_stream.next().await {
-            match item {
-                Ok(bytes) => {
-                    buffer.extend_from_slice(&bytes);
-                    while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
-                        let line_raw = buffer.split_to(pos + 1);
-                        if let Ok(line_str) = std::str::from_utf8(&line_raw) {
-                            let line = line_str.trim();
-                            if line.is_empty() || !line.starts_with("data: ") { continue; }
-                            
-                            let json_part = line.trim_start_matches("data: ").trim();
-                            if json_part == "[DONE]" { continue; }
-
-                            if let Ok(mut json) = serde_json::from_str::<Value>(json_part) {
-                                let actual_data = if let Some(inner) = json.get_mut("response").map(|v| v.take()) { inner } else { json };
-                                
-                                // Capture finish reason
-                                if let Some(candidates) = actual_data.get("candidates").and_then(|c| c.as_array()) {
-                                    if let Some(candidate) = candidates.get(0) {
-                                        if let Some(reason) = candidate.get("finishReason").and_then(|r| r.as_str()) {
-                                            last_finish_reason = match reason {
-                                                "STOP" => "stop".to_string(),
-                                                "MAX_TOKENS" => "length".to_string(),
-                                                _ => "stop".to_string(),
-                                            };
-                                        }
-                                    }
-                                }
+        'upstream: while let Some(item) = gemini_stream.next().await {
+            let bytes = match item {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    failure = Some(format!("Upstream error: {}", e));
+                    break 'upstream;
+                }
+            };
+            buffer.extend_from_slice(&bytes);
+            while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+                let line_raw = buffer.split_to(pos + 1);
+                let Ok(line_str) = std::str::from_utf8(&line_raw) else { continue; };
+                let line = line_str.trim();
+                if line.is_empty() || !line.starts_with("data: ") { continue; }
 
-                                // text delta
-                                let mut delta_text = String::new();
-                                if let Some(candidates) = actual_data.get("candidates").and_then(|c| c.as_array()) {
-                                    if let Some(candidate) = candidates.get(0) {
-                                        if let Some(parts) = candidate.get("content").and_then(|c| c.get("parts")).and_then(|p| p.as_array()) {
-                                            for part in parts {
-                                                if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
-                                                    // Sanitize smart quotes to standard quotes for JSON compatibility
-                                                    let clean_text = text.replace('“', "\"").replace('”', "\"");
-                                                    delta_text.push_str(&clean_text);
-                                                }
-                                                /* 禁用思维链输出到正文
-                                                if let Some(thought_text) = part.get("thought").and_then(|t| t.as_str()) {
-                                                    let clean_thought = thought_text.replace('"', "\"").replace('"', "\"");
-                                                    // delta_text.push_str(&clean_thought);
-                                                }
-                                                */
-                                                // 捕获 thoughtSignature (Gemini 3 工具调用必需)
-                                                // 存储到全局状态，不再嵌入到用户可见的文本中
-                                                if let Some(sig) = part.get("thoughtSignature").or(part.get("thought_signature")).and_then(|s| s.as_str()) {
-                                                    tracing::debug!("[Codex-SSE] 捕获 thoughtSignature (长度: {})", sig.len());
-                                                    store_thought_signature(sig);
-                                                }
-                                                // Handle function call in chunk with deduplication
-                                                if let Some(func_call) = part.get("functionCall") {
-                                                    let call_key = serde_json::to_string(func_call).unwrap_or_default();
-                                                    if !emitted_tool_calls.contains(&call_key) {
-                                                        emitted_tool_calls.insert(call_key);
-
-                                                                                let name = func_call.get("name").and_then(|v| v.as_str()).unwrap_or("unknown");
-                                                                                let _args = func_call.get("args").unwrap_or(&json!({})).to_string();                                                        
-                                                        // Stable ID generation based on hashed content to be consistent
-                                                        let mut hasher = std::collections::hash_map::DefaultHasher::new();
-                                                        use std::hash::{Hash, Hasher};
-                                                        serde_json::to_string(func_call).unwrap_or_default().hash(&mut hasher);
-                                                        let call_id = format!("call_{:x}", hasher.finish());
-                                                        
-                                                        // Parse args once
-                                                        let fallback_args = json!({});
-                                                        let args_obj = func_call.get("args").unwrap_or(&fallback_args);
-                                                        // Fallback for function_call arguments string
-                                                        let args_str = args_obj.to_string();
-
-                                                        let name_str = name.to_string();
-                                                        
-                                                        // Determine event type based on tool name
-                                                        // 使用 Option 来允许某些情况跳过工具调用
-                                                        let maybe_item_added_ev: Option<Value> = if name_str == "shell" || name_str == "local_shell" {
-                                                            // Map to local_shell_call
-                                                            tracing::debug!("[Debug] func_call: {}", serde_json::to_string(&func_call).unwrap_or_default());
-                                                            tracing::debug!("[Debug] args_obj: {}", serde_json::to_string(&args_obj).unwrap_or_default());
-                                                            
-                                                            // 解析命令：支持数组格式、字符串格式，以及空 args 情况
-                                                            let cmd_vec: Vec<String> = if args_obj.as_object().map(|o| o.is_empty()).unwrap_or(true) {
-                                                                // args 为空时使用静默成功命令，避免任务中断
-                                                                tracing::debug!("shell command args 为空，使用静默成功命令继续流程");
-                                                                vec!["powershell.exe".to_string(), "-Command".to_string(), "exit 0".to_string()]
-                                                            } else if let Some(arr) = args_obj.get("command").and_then(|v| v.as_array()) {
-                                                                // 数组格式
-                                                                arr.iter().filter_map(|v| v.as_str()).map(|s| s.to_string()).collect()
-                                                            } else if let Some(cmd_str) = args_obj.get("command").and_then(|v| v.as_str()) {
-                                                                // 字符串格式
-                                                                if cmd_str.contains(' ') {
-                                                                    vec!["powershell.exe".to_string(), "-Command".to_string(), cmd_str.to_string()]
-                                                                } else {
-                                                                    vec![cmd_str.to_string()]
-                                                                }
-                                                            } else {
-                                                                // command 字段缺失，使用静默成功命令
-                                                                tracing::debug!("shell command 缺少 command 字段，使用静默成功命令");
-                                                                vec!["powershell.exe".to_string(), "-Command".to_string(), "exit 0".to_string()]
-                                                            };
-                                                            
-                                                            tracing::debug!("Shell 命令解析: {:?}", cmd_vec);
-                                                            Some(json!({
-                                                                "type": "response.output_item.added",
-                                                                "item": {
-                                                                    "type": "local_shell_call",
-                                                                    "status": "in_progress",
-                                                                    "call_id": &call_id,
-                                                                    "action": {
-                                                                        "type": "exec",
-                                                                        "command": cmd_vec
-                                                                    }
-                                                                }
-                                                            }))
-                                                        } else if name_str == "googleSearch" || name_str == "web_search" || name_str == "google_search" {
-                                                            // Map to web_search_call
-                                                            let query_val = args_obj.get("query").and_then(|v| v.as_str()).unwrap_or("");
-                                                            Some(json!({
-                                                                "type": "response.output_item.added",
-                                                                "item": {
-                                                                    "type": "web_search_call",
-                                                                    "status": "in_progress",
-                                                                    "call_id": &call_id,
-                                                                    "action": {
-                                                                        "type": "search",
-                                                                        "query": query_val
-                                                                    }
-                                                                }
-                                                            }))
-                                                        } else {
-                                                            // Default function_call
-                                                            Some(json!({
-                                                                "type": "response.output_item.added",
-                                                                "item": {
-                                                                    "type": "function_call",
-                                                                    "name": name,
-                                                                    "arguments": args_str,
-                                                                    "call_id": &call_id
-                                                                }
-                                                            }))
-                                                        };
-
-                                                        // 只有在有事件时才发送
-                                                        if let Some(item_added_ev) = maybe_item_added_ev {
-                                                            yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", serde_json::to_string(&item_added_ev).unwrap())));
-
-                                                        // Emit response.output_item.done (matching the added event)
-                                                        // 复用相同的 cmd_vec 逻辑
-                                                        let item_done_ev = if name_str == "shell" || name_str == "local_shell" {
-                                                            let cmd_vec_done: Vec<String> = if let Some(arr) = args_obj.get("command").and_then(|v| v.as_array()) {
-                                                                arr.iter()
-                                                                    .filter_map(|v| v.as_str())
-                                                                    .map(|s| s.to_string())
-                                                                    .collect()
-                                                            } else if let Some(cmd_str) = args_obj.get("command").and_then(|v| v.as_str()) {
-                                                                if cmd_str.contains(' ') {
-                                                                    vec!["powershell.exe".to_string(), "-Command".to_string(), cmd_str.to_string()]
-                                                                } else {
-                                                                    vec![cmd_str.to_string()]
-                                                                }
-                                                            } else {
-                                                                vec!["powershell.exe".to_string(), "-Command".to_string(), "echo 'Invalid command'".to_string()]
-                                                            };
-                                                            json!({
-                                                                "type": "response.output_item.done",
-                                                                "item": {
-                                                                    "type": "local_shell_call",
-                                                                    "status": "in_progress",
-                                                                    "call_id": call_id,
-                                                                     "action": {
-                                                                        "type": "exec",
-                                                                        "command": cmd_vec_done
-                                                                    }
-                                                                }
-                                                            })
-                                                        } else if name_str == "googleSearch" || name_str == "web_search" || name_str == "google_search" {
-                                                            let query_val = args_obj.get("query").and_then(|v| v.as_str()).unwrap_or("");
-                                                             json!({
-                                                                "type": "response.output_item.done",
-                                                                "item": {
-                                                                    "type": "web_search_call",
-                                                                    "status": "in_progress",
-                                                                    "call_id": call_id,
-                                                                    "action": {
-                                                                        "type": "search",
-                                                                        "query": query_val
-                                                                    }
-                                                                }
-                                                            })
-                                                        } else {
-                                                            json!({
-                                                                "type": "response.output_item.done",
-                                                                "item": {
-                                                                    "type": "function_call",
-                                                                    "name": name,
-                                                                    "arguments": args_str,
-                                                                    "call_id": call_id
-                                                                }
-                                                            })
-                                                        };
-
-                                                        yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", serde_json::to_string(&item_done_ev).unwrap())));
-                                                        } // 关闭 if let Some(item_added_ev)
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
+                let json_part = line.trim_start_matches("data: ").trim();
+                if json_part == "[DONE]" { continue; }
 
-                                if !delta_text.is_empty() {
-                                    full_content.push_str(&delta_text);
-                                    // 2. Emit response.output_text.delta
-                                    let delta_ev = json!({
-                                        "type": "response.output_text.delta",
-                                        "delta": delta_text
-                                    });
-                                    yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", serde_json::to_string(&delta_ev).unwrap())));
-                                }
+                let Ok(mut json) = serde_json::from_str::<Value>(json_part) else { continue; };
+                let actual_data = if let Some(inner) = json.get_mut("response").map(|v| v.take()) { inner } else { json };
+
+                if let Some(usage) = actual_data.get("usageMetadata") {
+                    last_usage = Some(usage.clone());
+                }
+
+                let Some(candidate) = actual_data.get("candidates").and_then(|c| c.as_array()).and_then(|c| c.first()) else { continue; };
+
+                // Capture finish reason
+                if let Some(reason) = candidate.get("finishReason").and_then(|r| r.as_str()) {
+                    last_finish_reason = match reason {
+                        "MAX_TOKENS" => "length".to_string(),
+                        _ => "stop".to_string(),
+                    };
+                }
+
+                let Some(parts) = candidate.get("content").and_then(|c| c.get("parts")).and_then(|p| p.as_array()) else { continue; };
+                for part in parts {
+                    // 捕获 thoughtSignature (Gemini 3 工具调用必需)
+                    // 存储到全局状态，不再嵌入到用户可见的文本中
+                    if let Some(sig) = part.get("thoughtSignature").or(part.get("thought_signature")).and_then(|s| s.as_str()) {
+                        tracing::debug!("[Codex-SSE] 捕获 thoughtSignature (长度: {})", sig.len());
+                        store_thought_signature(sig);
+                    }
+
+                    if let Some(text) = part.get("text").and_then(|t| t.as_str()).filter(|t| !t.is_empty()) {
+                        let is_thought = part.get("thought").and_then(|v| v.as_bool()).unwrap_or(false);
+                        // Sanitize smart quotes to standard quotes for JSON compatibility
+                        let clean_text = if is_thought { text.to_string() } else { text.replace(['“', '”'], "\"") };
+
+                        // 输出项类型切换 (思考 ↔ 正文) 时先结束当前项
+                        let same_kind = matches!(
+                            (&open_item, is_thought),
+                            (Some(OpenOutputItem::Reasoning { .. }), true) | (Some(OpenOutputItem::Message { .. }), false)
+                        );
+                        if !same_kind {
+                            if let Some(prev) = open_item.take() {
+                                let (events, done_item) = close_item_events(&mut writer, prev);
+                                for ev in events { yield Ok::<Bytes, String>(ev); }
+                                output_items.push(done_item);
+                            }
+                            let index = output_items.len();
+                            let next = if is_thought {
+                                OpenOutputItem::Reasoning { id: new_item_id("rs"), index, text: String::new() }
+                            } else {
+                                OpenOutputItem::Message { id: new_item_id("msg"), index, text: String::new() }
+                            };
+                            for ev in open_item_events(&mut writer, &next) { yield Ok::<Bytes, String>(ev); }
+                            open_item = Some(next);
+                        }
+
+                        if let Some(current) = open_item.as_mut() {
+                            match current {
+                                OpenOutputItem::Message { text, .. } | OpenOutputItem::Reasoning { text, .. } => text.push_str(&clean_text),
                             }
                         }
+                        if !is_thought {
+                            full_content.push_str(&clean_text);
+                        }
+                        if let Some(current) = open_item.as_ref() {
+                            yield Ok::<Bytes, String>(delta_event(&mut writer, current, &clean_text));
+                        }
+                    }
+
+                    // Handle function call in chunk with deduplication
+                    if let Some(func_call) = part.get("functionCall") {
+                        let call_key = serde_json::to_string(func_call).unwrap_or_default();
+                        if emitted_tool_calls.contains(&call_key) { continue; }
+                        emitted_tool_calls.insert(call_key);
+
+                        if let Some(prev) = open_item.take() {
+                            let (events, done_item) = close_item_events(&mut writer, prev);
+                            for ev in events { yield Ok::<Bytes, String>(ev); }
+                            output_items.push(done_item);
+                        }
+                        let tool_item = function_call_to_output_item(func_call);
+                        for ev in tool_call_events(&mut writer, &tool_item, output_items.len()) { yield Ok::<Bytes, String>(ev); }
+                        output_items.push(tool_item);
                     }
                 }
-                Err(e) => yield Err(format!("Upstream error: {}", e)),
             }
         }
 
-        // 3. Emit response.output_item.done
-        let item_done_ev = json!({
-            "type": "response.output_item.done",
-            "item": {
-                "type": "message",
-                "role": "assistant",
-                "content": [
-                    {
-                        "type": "output_text",
-                        "text": full_content
-                    }
-                ]
-            }
-        });
-        yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", serde_json::to_string(&item_done_ev).unwrap())));
+        if let Some(prev) = open_item.take() {
+            let (events, done_item) = close_item_events(&mut writer, prev);
+            for ev in events { yield Ok::<Bytes, String>(ev); }
+            output_items.push(done_item);
+        }
+
+        if let Some(message) = failure {
+            // 上游中断: 以 response.failed 结束事件流
+            let mut failed = base_response.clone();
+            failed["status"] = json!("failed");
+            failed["output"] = json!(output_items);
+            failed["error"] = json!({ "code": "server_error", "message": message });
+            let failed_ev = writer.event(json!({ "type": "response.failed", "response": failed.clone() }));
+            if let Some(hook) = on_completed.take() { hook(failed); }
+            yield Ok::<Bytes, String>(failed_ev);
+            return;
+        }
 
         // SSOP: Check full_content for embedded JSON command signatures if no tools were emitted natively
         if emitted_tool_calls.is_empty() {
-            // Try to find a JSON block containing "command"
-            // Simple heuristic: look for { and }
-            // We search for the *last* valid JSON block that has a "command" field, as the model might output reasoning first.
-            
-            let mut detected_cmd_val = None;
-            let mut detected_cmd_type = "unknown";
-
-            // Find all potential JSON start/end indices
-            let chars: Vec<char> = full_content.chars().collect();
-            let mut depth = 0;
-            let mut start_idx = 0;
-            
-            // Scan for top-level JSON objects
-            for (i, c) in chars.iter().enumerate() {
-                if *c == '{' {
-                    if depth == 0 { start_idx = i; }
-                    depth += 1;
-                } else if *c == '}' {
-                    if depth > 0 {
-                        depth -= 1;
-                        if depth == 0 {
-                            // Found a potential JSON object block [start_idx..=i]
-                            let json_str: String = chars[start_idx..=i].iter().collect();
-                            if let Ok(val) = serde_json::from_str::<Value>(&json_str) {
-                                // Check for "command" field
-                                if let Some(cmd_val) = val.get("command") {
-                                    // Found a command! Identify type.
-                                    // Case 1: "command": ["shell", ...] or ["ls", ...]
-                                    if let Some(arr) = cmd_val.as_array() {
-                                        if let Some(first) = arr.get(0).and_then(|v| v.as_str()) {
-                                            if first == "shell" || first == "powershell" || first == "cmd" || first == "ls" || first == "git" || first == "echo" {
-                                                detected_cmd_type = "shell";
-                                                detected_cmd_val = Some(cmd_val.clone());
-                                            }
-                                        }
-                                    } 
-                                    // Case 2: "command": "shell" (String) and "args": { "command": "..." }
-                                    // This matches the user's latest screenshot which failed SSOP.
-                                    else if let Some(cmd_str) = cmd_val.as_str() {
-                                        if cmd_str == "shell" || cmd_str == "local_shell" {
-                                             // Enhanced matching for params/argument
-                                             if let Some(args) = val.get("args").or(val.get("arguments")).or(val.get("params")) {
-                                                  if let Some(inner_cmd) = args.get("command").or(args.get("code")).or(args.get("argument")) {
-                                                      // We construct a synthetic array: ["shell", inner_cmd]
-                                                      // So subsequent logic can process it.
-                                                      // Actually, let's just grab the inner command string.
-                                                      if let Some(inner_cmd_str) = inner_cmd.as_str() {
-                                                          detected_cmd_type = "shell";
-                                                          detected_cmd_val = Some(json!([inner_cmd_str]));
-                                                      }
-                                                  }
-                                              }
-                                        }
-                                    }
+            if let Some((call_id, final_cmd_vec)) = detect_ssop_shell_call(&full_content) {
+                let tool_item = json!({
+                    "id": new_item_id("lsh"),
+                    "type": "local_shell_call",
+                    "status": "in_progress",
+                    "call_id": call_id,
+                    "action": { "type": "exec", "command": final_cmd_vec }
+                });
+                for ev in tool_call_events(&mut writer, &tool_item, output_items.len()) { yield Ok::<Bytes, String>(ev); }
+                output_items.push(tool_item);
+            }
+        }
+
+        // 始终保证至少有一个 message 输出项 (空回复)
+        if output_items.is_empty() {
+            let empty = OpenOutputItem::Message { id: new_item_id("msg"), index: 0, text: String::new() };
+            for ev in open_item_events(&mut writer, &empty) { yield Ok::<Bytes, String>(ev); }
+            let (events, done_item) = close_item_events(&mut writer, empty);
+            for ev in events { yield Ok::<Bytes, String>(ev); }
+            output_items.push(done_item);
+        }
+
+        // 4. Emit response.completed / response.incomplete
+        let incomplete = last_finish_reason == "length";
+        let mut final_response = base_response.clone();
+        final_response["status"] = json!(if incomplete { "incomplete" } else { "completed" });
+        final_response["finish_reason"] = json!(last_finish_reason);
+        final_response["incomplete_details"] = if incomplete { json!({ "reason": "max_output_tokens" }) } else { Value::Null };
+        final_response["error"] = Value::Null;
+        final_response["output"] = json!(output_items);
+        final_response["usage"] = responses_usage_from_gemini(last_usage.as_ref());
+
+        let event_type = if incomplete { "response.incomplete" } else { "response.completed" };
+        let final_ev = writer.event(json!({ "type": event_type, "response": final_response.clone() }));
+        // 先交出最终结果 (持久化) 再发送终止事件，保证客户端收到事件后即可用 previous_response_id 续接
+        if let Some(hook) = on_completed.take() { hook(final_response); }
+        yield Ok::<Bytes, String>(final_ev);
+    };
+
+    Box::pin(stream)
+}
+
+/// SSOP: 模型未原生调用工具时，从正文中识别嵌入的 JSON shell 命令
+///
+/// 返回 (call_id, 最终执行的命令数组)
+fn detect_ssop_shell_call(full_content: &str) -> Option<(String, Vec<String>)> {
+    // Try to find a JSON block containing "command"
+    // Simple heuristic: look for { and }
+    // We search for the *last* valid JSON block that has a "command" field, as the model might output reasoning first.
+
+    let mut detected_cmd_val = None;
+    let mut detected_cmd_type = "unknown";
+
+    // Find all potential JSON start/end indices
+    let chars: Vec<char> = full_content.chars().collect();
+    let mut depth = 0;
+    let mut start_idx = 0;
+
+    // Scan for top-level JSON objects
+    for (i, c) in chars.iter().enumerate() {
+        if *c == '{' {
+            if depth == 0 { start_idx = i; }
+            depth += 1;
+        } else if *c == '}' && depth > 0 {
+            depth -= 1;
+            if depth == 0 {
+                // Found a potential JSON object block [start_idx..=i]
+                let json_str: String = chars[start_idx..=i].iter().collect();
+                if let Ok(val) = serde_json::from_str::<Value>(&json_str) {
+                    // Check for "command" field
+                    if let Some(cmd_val) = val.get("command") {
+                        // Found a command! Identify type.
+                        // Case 1: "command": ["shell", ...] or ["ls", ...]
+                        if let Some(arr) = cmd_val.as_array() {
+                            if let Some(first) = arr.first().and_then(|v| v.as_str()) {
+                                if first == "shell" || first == "powershell" || first == "cmd" || first == "ls" || first == "git" || first == "echo" {
+                                    detected_cmd_type = "shell";
+                                    detected_cmd_val = Some(cmd_val.clone());
                                 }
-                            } else {
-                                // Fallback for malformed JSON (e.g. unescaped quotes)
-                                // 注意: 使用安全的切片方法避免 UTF-8 边界 panic
-                                if (json_str.contains("\"command\": \"shell\"") || json_str.contains("\"command\": \"local_shell\"")) 
-                                   && (json_str.contains("\"argument\":") || json_str.contains("\"code\":")) {
-                                    
-                                    let keys = ["\"argument\":", "\"code\":", "\"command\":"];
-                                    for key in keys {
-                                        if let Some(pos) = json_str.find(key) {
-                                            // 使用安全的 get() 方法替代直接索引
-                                            let slice_start = pos + key.len();
-                                            if let Some(slice_after_key) = json_str.get(slice_start..) {
-                                                if let Some(quote_idx) = slice_after_key.find('"') {
-                                                    let val_start_abs = slice_start + quote_idx + 1;
-                                                    if let Some(last_quote_idx) = json_str.rfind('"') {
-                                                        if last_quote_idx > val_start_abs {
-                                                            // 使用 get() 安全获取子字符串
-                                                            if let Some(raw_cmd) = json_str.get(val_start_abs..last_quote_idx) {
-                                                                detected_cmd_type = "shell";
-                                                                detected_cmd_val = Some(json!([raw_cmd]));
-                                                                tracing::debug!("SSOP: Recovered malformed JSON command: {}", raw_cmd);
-                                                                break;
-                                                            }
-                                                        }
-                                                    }
+                            }
+                        } 
+                        // Case 2: "command": "shell" (String) and "args": { "command": "..." }
+                        // This matches the user's latest screenshot which failed SSOP.
+                        else if let Some(cmd_str) = cmd_val.as_str() {
+                            if cmd_str == "shell" || cmd_str == "local_shell" {
+                                 // Enhanced matching for params/argument
+                                 if let Some(args) = val.get("args").or(val.get("arguments")).or(val.get("params")) {
+                                      if let Some(inner_cmd) = args.get("command").or(args.get("code")).or(args.get("argument")) {
+                                          // We construct a synthetic array: ["shell", inner_cmd]
+                                          // So subsequent logic can process it.
+                                          // Actually, let's just grab the inner command string.
+                                          if let Some(inner_cmd_str) = inner_cmd.as_str() {
+                                              detected_cmd_type = "shell";
+                                              detected_cmd_val = Some(json!([inner_cmd_str]));
+                                          }
+                                      }
+                                  }
+                            }
+                        }
+                    }
+                } else {
+                    // Fallback for malformed JSON (e.g. unescaped quotes)
+                    // 注意: 使用安全的切片方法避免 UTF-8 边界 panic
+                    if (json_str.contains("\"command\": \"shell\"") || json_str.contains("\"command\": \"local_shell\"")) 
+                       && (json_str.contains("\"argument\":") || json_str.contains("\"code\":")) {
+
+                        let keys = ["\"argument\":", "\"code\":", "\"command\":"];
+                        for key in keys {
+                            if let Some(pos) = json_str.find(key) {
+                                // 使用安全的 get() 方法替代直接索引
+                                let slice_start = pos + key.len();
+                                if let Some(slice_after_key) = json_str.get(slice_start..) {
+                                    if let Some(quote_idx) = slice_after_key.find('"') {
+                                        let val_start_abs = slice_start + quote_idx + 1;
+                                        if let Some(last_quote_idx) = json_str.rfind('"') {
+                                            if last_quote_idx > val_start_abs {
+                                                // 使用 get() 安全获取子字符串
+                                                if let Some(raw_cmd) = json_str.get(val_start_abs..last_quote_idx) {
+                                                    detected_cmd_type = "shell";
+                                                    detected_cmd_val = Some(json!([raw_cmd]));
+                                                    tracing::debug!("SSOP: Recovered malformed JSON command: {}", raw_cmd);
+                                                    break;
                                                 }
                                             }
                                         }
@@ -720,100 +996,130 @@ pub fn create_codex_sse_stream(
                     }
                 }
             }
+        }
+    }
 
-            if let Some(cmd_val) = detected_cmd_val {
-                if detected_cmd_type == "shell" {
-                     let mut hasher = std::collections::hash_map::DefaultHasher::new();
-                     use std::hash::{Hash, Hasher};
-                     "ssop_shell_call".hash(&mut hasher); // Unique seed
-                     serde_json::to_string(&cmd_val).unwrap_or_default().hash(&mut hasher);
-                     let call_id = format!("call_{:x}", hasher.finish());
-
-                     let mut cmd_vec: Vec<String> = cmd_val.as_array().unwrap().iter().map(|v| v.as_str().unwrap_or("").to_string()).collect();
-                     
-                     // Helper to ensure it runs in shell properly
-                     // Problem: Model often outputs ["shell", "powershell", "-Command", ...]
-                     // "shell" is not a valid executable on Windows. We must strip it if it's acting as a label.
-                     if !cmd_vec.is_empty() && (cmd_vec[0] == "shell" || cmd_vec[0] == "local_shell") {
-                         cmd_vec.remove(0);
-                     }
-
-                     // Now check if empty or needs wrapping
-                     let final_cmd_vec = if cmd_vec.is_empty() {
-                         vec!["powershell".to_string(), "-Command".to_string(), "echo 'Empty command'".to_string()]
-                     } else if cmd_vec[0] == "powershell" || cmd_vec[0] == "cmd" || cmd_vec[0] == "git" || cmd_vec[0] == "python" || cmd_vec[0] == "node" {
-                         cmd_vec
-                     } else {
-                         // Wrap generic commands (ls, dir, echo, etc) in powershell for Windows safety
-                        // Use EncodedCommand to avoid quoting hell
-                        // AND pipe to Out-String to avoid CLIXML object output which breaks Gemini
-                        let raw_cmd = cmd_vec.join(" ");
-                        let joined = format!("& {{ {} }} | Out-String", raw_cmd);
-                        let utf16: Vec<u16> = joined.encode_utf16().collect();
-                        let mut bytes = Vec::with_capacity(utf16.len() * 2);
-                        for c in utf16 {
-                            bytes.extend_from_slice(&c.to_le_bytes());
-                        }
-                        use base64::Engine as _;
-                        let b64 = base64::engine::general_purpose::STANDARD.encode(&bytes);
-                        
-                        vec!["powershell".to_string(), "-EncodedCommand".to_string(), b64]
-                    };
+    if let Some(cmd_val) = detected_cmd_val {
+        if detected_cmd_type == "shell" {
+             let mut hasher = std::collections::hash_map::DefaultHasher::new();
+             use std::hash::{Hash, Hasher};
+             "ssop_shell_call".hash(&mut hasher); // Unique seed
+             serde_json::to_string(&cmd_val).unwrap_or_default().hash(&mut hasher);
+             let call_id = format!("call_{:x}", hasher.finish());
 
-                     tracing::debug!("SSOP: Detected Shell Command in Text, Injecting Event: {:?}", final_cmd_vec);
-
-                     // Emit added
-                     let item_added_ev = json!({
-                        "type": "response.output_item.added",
-                        "item": {
-                            "type": "local_shell_call",
-                            "status": "in_progress",
-                            "call_id": &call_id,
-                            "action": {
-                                "type": "exec",
-                                "command": final_cmd_vec
-                            }
-                        }
-                    });
-                    yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", serde_json::to_string(&item_added_ev).unwrap())));
-
-                    // Emit done
-                    let item_done_ev = json!({
-                        "type": "response.output_item.done",
-                        "item": {
-                            "type": "local_shell_call",
-                            "status": "in_progress",
-                            "call_id": &call_id,
-                             "action": {
-                                "type": "exec",
-                                "command": final_cmd_vec
-                            }
-                        }
-                    });
-                    yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", serde_json::to_string(&item_done_ev).unwrap())));
+             let mut cmd_vec: Vec<String> = cmd_val.as_array().unwrap().iter().map(|v| v.as_str().unwrap_or("").to_string()).collect();
+
+             // Helper to ensure it runs in shell properly
+             // Problem: Model often outputs ["shell", "powershell", "-Command", ...]
+             // "shell" is not a valid executable on Windows. We must strip it if it's acting as a label.
+             if !cmd_vec.is_empty() && (cmd_vec[0] == "shell" || cmd_vec[0] == "local_shell") {
+                 cmd_vec.remove(0);
+             }
+
+             // Now check if empty or needs wrapping
+             let final_cmd_vec = if cmd_vec.is_empty() {
+                 vec!["powershell".to_string(), "-Command".to_string(), "echo 'Empty command'".to_string()]
+             } else if cmd_vec[0] == "powershell" || cmd_vec[0] == "cmd" || cmd_vec[0] == "git" || cmd_vec[0] == "python" || cmd_vec[0] == "node" {
+                 cmd_vec
+             } else {
+                 // Wrap generic commands (ls, dir, echo, etc) in powershell for Windows safety
+                // Use EncodedCommand to avoid quoting hell
+                // AND pipe to Out-String to avoid CLIXML object output which breaks Gemini
+                let raw_cmd = cmd_vec.join(" ");
+                let joined = format!("& {{ {} }} | Out-String", raw_cmd);
+                let utf16: Vec<u16> = joined.encode_utf16().collect();
+                let mut bytes = Vec::with_capacity(utf16.len() * 2);
+                for c in utf16 {
+                    bytes.extend_from_slice(&c.to_le_bytes());
                 }
-            }
+                use base64::Engine as _;
+                let b64 = base64::engine::general_purpose::STANDARD.encode(&bytes);
+
+                vec!["powershell".to_string(), "-EncodedCommand".to_string(), b64]
+            };
+
+             tracing::debug!("SSOP: Detected Shell Command in Text, Injecting Event: {:?}", final_cmd_vec);
+             return Some((call_id, final_cmd_vec));
         }
+    }
 
-        // 4. Emit response.completed
-        let completed_ev = json!({
-            "type": "response.completed",
-            "response": {
-                "id": &response_id,
-                "object": "response",
-                "status": "completed",
-                "finish_reason": last_finish_reason,
-                "usage": {
-                    "input_tokens": 0,
-                    "input_tokens_details": { "cached_tokens": 0 },
-                    "output_tokens": 0,
-                    "output_tokens_details": { "reasoning_tokens": 0 },
-                    "total_tokens": 0
-                }
-            }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sse(chunks: &[Value]) -> Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>> {
+        let items: Vec<Result<Bytes, reqwest::Error>> = chunks
+            .iter()
+            .map(|c| Ok(Bytes::from(format!("data: {}\n\n", c))))
+            .collect();
+        Box::pin(futures::stream::iter(items))
+    }
+
+    fn events(bytes: Vec<Bytes>) -> Vec<Value> {
+        bytes
+            .iter()
+            .flat_map(|b| String::from_utf8_lossy(b).lines().map(|l| l.to_string()).collect::<Vec<_>>())
+            .filter_map(|l| l.strip_prefix("data: ").and_then(|d| serde_json::from_str(d).ok()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_empty_candidate_has_no_content_chunk() {
+        let empty = sse(&[json!({"response": {"candidates": [{"content": {"parts": []}, "finishReason": "STOP"}]}})]);
+        let chunks: Vec<Bytes> = create_openai_sse_stream(empty, "gpt-4o".to_string(), true)
+            .map(|c| c.unwrap())
+            .collect()
+            .await;
+        assert!(!chunks.is_empty());
+        assert!(!chunks.iter().any(|c| is_content_chunk(c)));
+
+        let text = sse(&[json!({"response": {"candidates": [{"content": {"parts": [{"text": "Hi"}]}}]}})]);
+        let chunks: Vec<Bytes> = create_openai_sse_stream(text, "gpt-4o".to_string(), false)
+            .map(|c| c.unwrap())
+            .collect()
+            .await;
+        assert!(is_content_chunk(&chunks[0]));
+    }
+
+    #[tokio::test]
+    async fn test_codex_stream_emits_typed_events() {
+        let upstream = sse(&[
+            json!({"response": {"candidates": [{"content": {"parts": [{"text": "Hel"}]}}]}}),
+            json!({"response": {"candidates": [{"content": {"parts": [{"text": "lo"}]}}]}}),
+            json!({"response": {
+                "candidates": [{"content": {"parts": [{"functionCall": {"name": "lookup", "args": {"q": "x"}}}]}, "finishReason": "STOP"}],
+                "usageMetadata": {"promptTokenCount": 10, "candidatesTokenCount": 4, "totalTokenCount": 14}
+            }}),
+        ]);
+
+        let completed = std::sync::Arc::new(std::sync::Mutex::new(None));
+        let slot = completed.clone();
+        let hook: ResponseCompletedHook = Box::new(move |resp| {
+            *slot.lock().unwrap() = Some(resp);
         });
-        yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", serde_json::to_string(&completed_ev).unwrap())));
-    };
+        let stream = create_codex_sse_stream(upstream, "gpt-5".to_string(), json!({ "id": "resp_test" }), Some(hook));
+        let chunks: Vec<Bytes> = stream.map(|c| c.unwrap()).collect().await;
+        let evs = events(chunks);
 
-    Box::pin(stream)
+        let types: Vec<&str> = evs.iter().map(|e| e["type"].as_str().unwrap()).collect();
+        assert_eq!(types.first(), Some(&"response.created"));
+        assert_eq!(types.last(), Some(&"response.completed"));
+        assert!(types.contains(&"response.output_text.done"));
+        assert!(types.contains(&"response.function_call_arguments.done"));
+        for (i, ev) in evs.iter().enumerate() {
+            assert_eq!(ev["sequence_number"], i as u64);
+        }
+
+        let resp = completed.lock().unwrap().take().unwrap();
+        assert_eq!(resp["id"], "resp_test");
+        assert_eq!(resp["status"], "completed");
+        assert_eq!(resp["output"][0]["content"][0]["text"], "Hello");
+        assert_eq!(resp["output"][1]["type"], "function_call");
+        assert_eq!(resp["output"][1]["arguments"], "{\"q\":\"x\"}");
+        assert_eq!(resp["usage"]["input_tokens"], 10);
+        assert_eq!(resp["usage"]["total_tokens"], 14);
+    }
 }
@@ -0,0 +1,74 @@
+// 流式响应首包缓冲
+// 在首个有效内容事件到达前不向客户端输出任何字节，上游在此之前失败或返回空候选时，
+// 处理器可以换账号重试而客户端无感知
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use std::pin::Pin;
+
+pub type SseStream = Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>>;
+
+/// 首个有效事件的缓冲结果
+pub enum StreamStart {
+    /// 已收到有效内容，返回 (已缓冲事件 + 剩余流)
+    Ready(SseStream),
+    /// 有效内容之前上游出错
+    Failed(String),
+    /// 流正常结束但没有任何有效内容 (空候选)，携带完整的已缓冲事件
+    Empty(SseStream),
+}
+
+/// 缓冲事件直到 `is_content` 判定为有效内容
+pub async fn buffer_until_content(mut stream: SseStream, is_content: fn(&[u8]) -> bool) -> StreamStart {
+    let mut buffered: Vec<Bytes> = Vec::new();
+
+    while let Some(item) = stream.next().await {
+        match item {
+            Ok(chunk) => {
+                let ready = is_content(&chunk);
+                buffered.push(chunk);
+                if ready {
+                    let head = futures::stream::iter(buffered.into_iter().map(Ok));
+                    return StreamStart::Ready(Box::pin(head.chain(stream)));
+                }
+            }
+            Err(e) => return StreamStart::Failed(e),
+        }
+    }
+
+    StreamStart::Empty(Box::pin(futures::stream::iter(buffered.into_iter().map(Ok))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_content(chunk: &[u8]) -> bool {
+        chunk.starts_with(b"content")
+    }
+
+    fn stream(items: Vec<Result<&'static str, &'static str>>) -> SseStream {
+        Box::pin(futures::stream::iter(
+            items
+                .into_iter()
+                .map(|i| i.map(Bytes::from).map_err(|e| e.to_string())),
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_buffer_until_content() {
+        let ready = buffer_until_content(stream(vec![Ok("start"), Ok("content"), Ok("stop")]), is_content).await;
+        let StreamStart::Ready(s) = ready else { panic!("expected content") };
+        let all: Vec<_> = s.map(|c| c.unwrap()).collect().await;
+        assert_eq!(all, vec!["start", "content", "stop"]);
+
+        assert!(matches!(
+            buffer_until_content(stream(vec![Ok("start"), Err("reset")]), is_content).await,
+            StreamStart::Failed(e) if e == "reset"
+        ));
+        assert!(matches!(
+            buffer_until_content(stream(vec![Ok("start"), Ok("stop")]), is_content).await,
+            StreamStart::Empty(_)
+        ));
+    }
+}
@@ -0,0 +1,396 @@
+// Prometheus 指标
+// 请求 / 延迟 / token 计数由 ProxyMonitor 在记录请求时累加，账号池状态在抓取时从 TokenManager 实时读取
+
+use dashmap::DashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::proxy::monitor::ProxyRequestLog;
+use crate::proxy::token_refresh::TokenRefreshSnapshot;
+use crate::proxy::upstream::health::{CircuitState, EndpointHealthSnapshot};
+use crate::proxy::TokenManager;
+
+/// 延迟直方图分桶 (秒)，覆盖从快速短请求到长时间流式输出
+const LATENCY_BUCKETS: [f64; 12] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0];
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct RequestLabels {
+    route: String,
+    model: String,
+    mapped_model: String,
+    status: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct TokenLabels {
+    model: String,
+    mapped_model: String,
+    kind: &'static str,
+}
+
+struct LatencySeries {
+    count: AtomicU64,
+    sum_ms: AtomicU64,
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+}
+
+impl LatencySeries {
+    fn new() -> Self {
+        Self {
+            count: AtomicU64::new(0),
+            sum_ms: AtomicU64::new(0),
+            buckets: Default::default(),
+        }
+    }
+
+    fn observe(&self, duration_ms: u64) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_ms.fetch_add(duration_ms, Ordering::Relaxed);
+        let secs = duration_ms as f64 / 1000.0;
+        for (idx, bound) in LATENCY_BUCKETS.iter().enumerate() {
+            if secs <= *bound {
+                self.buckets[idx].fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// 进程内的 Prometheus 指标存储
+pub struct ProxyMetrics {
+    requests: DashMap<RequestLabels, LatencySeries>,
+    tokens: DashMap<TokenLabels, AtomicU64>,
+}
+
+impl ProxyMetrics {
+    pub fn new() -> Self {
+        Self {
+            requests: DashMap::new(),
+            tokens: DashMap::new(),
+        }
+    }
+
+    /// 记录一次已完成的请求
+    ///
+    /// `route` 使用路由模板 (如 `/v1beta/models/:model`)，`model` / `mapped_model` 为已知模型名或 `other`，避免客户端输入导致标签爆炸
+    pub fn observe_request(&self, route: &str, model: &str, mapped_model: &str, log: &ProxyRequestLog) {
+        let model = model.to_string();
+        let mapped_model = mapped_model.to_string();
+
+        self.requests
+            .entry(RequestLabels {
+                route: route.to_string(),
+                model: model.clone(),
+                mapped_model: mapped_model.clone(),
+                status: log.status,
+            })
+            .or_insert_with(LatencySeries::new)
+            .observe(log.duration);
+
+        for (kind, value) in [("input", log.input_tokens), ("output", log.output_tokens)] {
+            if let Some(v) = value.filter(|v| *v > 0) {
+                self.tokens
+                    .entry(TokenLabels {
+                        model: model.clone(),
+                        mapped_model: mapped_model.clone(),
+                        kind,
+                    })
+                    .or_insert_with(|| AtomicU64::new(0))
+                    .fetch_add(v as u64, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// 以 Prometheus text exposition format (0.0.4) 输出全部指标
+    pub fn render(&self, token_manager: &TokenManager) -> String {
+        let mut out = String::new();
+
+        // 请求计数
+        let _ = writeln!(out, "# HELP antigravity_proxy_requests_total Total proxied requests.");
+        let _ = writeln!(out, "# TYPE antigravity_proxy_requests_total counter");
+        let mut requests: Vec<_> = self
+            .requests
+            .iter()
+            .map(|e| (e.key().clone(), e.value().count.load(Ordering::Relaxed)))
+            .collect();
+        requests.sort_by(|a, b| request_sort_key(&a.0).cmp(&request_sort_key(&b.0)));
+        for (labels, count) in &requests {
+            let _ = writeln!(
+                out,
+                "antigravity_proxy_requests_total{{{}}} {}",
+                request_label_str(labels),
+                count
+            );
+        }
+
+        // 延迟直方图
+        let _ = writeln!(out, "# HELP antigravity_proxy_request_duration_seconds Request latency in seconds.");
+        let _ = writeln!(out, "# TYPE antigravity_proxy_request_duration_seconds histogram");
+        for (labels, _) in &requests {
+            let Some(series) = self.requests.get(labels) else {
+                continue;
+            };
+            let base = request_label_str(labels);
+            for (idx, bound) in LATENCY_BUCKETS.iter().enumerate() {
+                let _ = writeln!(
+                    out,
+                    "antigravity_proxy_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    base,
+                    bound,
+                    series.buckets[idx].load(Ordering::Relaxed)
+                );
+            }
+            let count = series.count.load(Ordering::Relaxed);
+            let _ = writeln!(
+                out,
+                "antigravity_proxy_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                base, count
+            );
+            let _ = writeln!(
+                out,
+                "antigravity_proxy_request_duration_seconds_sum{{{}}} {}",
+                base,
+                series.sum_ms.load(Ordering::Relaxed) as f64 / 1000.0
+            );
+            let _ = writeln!(
+                out,
+                "antigravity_proxy_request_duration_seconds_count{{{}}} {}",
+                base, count
+            );
+        }
+
+        // token 计数
+        let _ = writeln!(out, "# HELP antigravity_proxy_tokens_total Tokens reported by upstream responses.");
+        let _ = writeln!(out, "# TYPE antigravity_proxy_tokens_total counter");
+        let mut tokens: Vec<_> = self
+            .tokens
+            .iter()
+            .map(|e| (e.key().clone(), e.value().load(Ordering::Relaxed)))
+            .collect();
+        tokens.sort_by(|a, b| {
+            (&a.0.model, &a.0.mapped_model, a.0.kind).cmp(&(&b.0.model, &b.0.mapped_model, b.0.kind))
+        });
+        for (labels, value) in tokens {
+            let _ = writeln!(
+                out,
+                "antigravity_proxy_tokens_total{{model=\"{}\",mapped_model=\"{}\",type=\"{}\"}} {}",
+                escape_label(&labels.model),
+                escape_label(&labels.mapped_model),
+                labels.kind,
+                value
+            );
+        }
+
+        // 账号池状态
+        let pool_size = token_manager.len();
+        let rate_limited = token_manager.rate_limited_account_count();
+        write_gauge(&mut out, "antigravity_proxy_pool_accounts", "Accounts loaded in the proxy pool.", pool_size);
+        write_gauge(&mut out, "antigravity_proxy_rate_limited_accounts", "Accounts currently rate limited.", rate_limited);
+        write_gauge(
+            &mut out,
+            "antigravity_proxy_available_accounts",
+            "Accounts in the pool that are not rate limited.",
+            pool_size.saturating_sub(rate_limited),
+        );
+        write_gauge(
+            &mut out,
+            "antigravity_proxy_bound_sessions",
+            "Sticky sessions currently bound to an account.",
+            token_manager.session_binding_count(),
+        );
+        write_gauge(
+            &mut out,
+            "antigravity_proxy_queued_requests",
+            "Requests waiting in the admission queue for a free account.",
+            token_manager.admission_queue().len(),
+        );
+
+        // 各账号进行中的请求数
+        let _ = writeln!(out, "# HELP antigravity_proxy_account_in_flight Requests currently in flight per account.");
+        let _ = writeln!(out, "# TYPE antigravity_proxy_account_in_flight gauge");
+        for (email, count) in token_manager.in_flight_by_account() {
+            let _ = writeln!(
+                out,
+                "antigravity_proxy_account_in_flight{{account=\"{}\"}} {}",
+                escape_label(&email),
+                count
+            );
+        }
+
+        // 锁定原因
+        let _ = writeln!(out, "# HELP antigravity_proxy_lockouts Active account lockouts by reason and scope.");
+        let _ = writeln!(out, "# TYPE antigravity_proxy_lockouts gauge");
+        let mut lockouts: Vec<_> = token_manager.lockout_counts().into_iter().collect();
+        lockouts.sort_by_key(|((reason, model_level), _)| (reason.as_str(), *model_level));
+        for ((reason, model_level), count) in lockouts {
+            let _ = writeln!(
+                out,
+                "antigravity_proxy_lockouts{{reason=\"{}\",scope=\"{}\"}} {}",
+                reason.as_str(),
+                if model_level { "model" } else { "account" },
+                count
+            );
+        }
+
+        out
+    }
+}
+
+impl Default for ProxyMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+type EndpointGauge = (&'static str, &'static str, fn(&EndpointHealthSnapshot) -> String);
+
+/// v1internal 端点健康指标 (熔断状态、错误率、平均延迟)
+pub fn render_endpoint_health(endpoints: &[EndpointHealthSnapshot]) -> String {
+    let mut out = String::new();
+    let series: [EndpointGauge; 3] = [
+        (
+            "antigravity_upstream_endpoint_up",
+            "Whether the v1internal endpoint circuit is closed (1) or open/half-open (0).",
+            |e| u8::from(e.state == CircuitState::Closed).to_string(),
+        ),
+        (
+            "antigravity_upstream_endpoint_error_rate",
+            "Rolling error rate of the v1internal endpoint.",
+            |e| e.error_rate.to_string(),
+        ),
+        (
+            "antigravity_upstream_endpoint_latency_seconds",
+            "Rolling average time to response headers of the v1internal endpoint.",
+            |e| (e.avg_latency_ms as f64 / 1000.0).to_string(),
+        ),
+    ];
+    for (name, help, value) in series {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        for endpoint in endpoints {
+            let _ = writeln!(
+                out,
+                "{}{{endpoint=\"{}\"}} {}",
+                name,
+                escape_label(&endpoint.base_url),
+                value(endpoint)
+            );
+        }
+    }
+    out
+}
+
+/// access_token 后台刷新指标 (累计成功 / 失败次数、刷新失败的账号数、最近的过期时间)
+pub fn render_token_refresh(snapshot: &TokenRefreshSnapshot) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# HELP antigravity_token_refresh_total Background access token refreshes by result.");
+    let _ = writeln!(out, "# TYPE antigravity_token_refresh_total counter");
+    let _ = writeln!(out, "antigravity_token_refresh_total{{result=\"success\"}} {}", snapshot.total_refreshed);
+    let _ = writeln!(out, "antigravity_token_refresh_total{{result=\"failure\"}} {}", snapshot.total_failed);
+    write_gauge(
+        &mut out,
+        "antigravity_token_refresh_failing_accounts",
+        "Accounts whose last background token refresh failed or that were disabled by invalid_grant.",
+        snapshot
+            .accounts
+            .iter()
+            .filter(|a| a.consecutive_failures > 0 || a.disabled)
+            .count(),
+    );
+    let _ = writeln!(
+        out,
+        "# HELP antigravity_token_expiry_timestamp_seconds Expiry time of each pooled account's access token."
+    );
+    let _ = writeln!(out, "# TYPE antigravity_token_expiry_timestamp_seconds gauge");
+    for account in snapshot.accounts.iter().filter(|a| !a.disabled) {
+        let _ = writeln!(
+            out,
+            "antigravity_token_expiry_timestamp_seconds{{account=\"{}\"}} {}",
+            escape_label(&account.email),
+            account.expires_at
+        );
+    }
+    out
+}
+
+fn write_gauge(out: &mut String, name: &str, help: &str, value: usize) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn request_sort_key(labels: &RequestLabels) -> (&str, &str, &str, u16) {
+    (&labels.route, &labels.model, &labels.mapped_model, labels.status)
+}
+
+fn request_label_str(labels: &RequestLabels) -> String {
+    format!(
+        "route=\"{}\",model=\"{}\",mapped_model=\"{}\",status=\"{}\"",
+        escape_label(&labels.route),
+        escape_label(&labels.model),
+        escape_label(&labels.mapped_model),
+        labels.status
+    )
+}
+
+/// 按 exposition format 规范转义标签值
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(status: u16, duration: u64, input: Option<u32>, output: Option<u32>) -> ProxyRequestLog {
+        ProxyRequestLog {
+            id: "id".to_string(),
+            timestamp: 0,
+            method: "POST".to_string(),
+            url: "/v1/messages".to_string(),
+            status,
+            duration,
+            model: Some("claude-sonnet-4-5".to_string()),
+            mapped_model: Some("claude-sonnet-4-5-thinking".to_string()),
+            account_email: None,
+            api_key_name: None,
+            fallback_from: None,
+            error: None,
+            request_body: None,
+            response_body: None,
+            input_tokens: input,
+            output_tokens: output,
+        }
+    }
+
+    #[test]
+    fn test_render_request_and_token_series() {
+        let metrics = ProxyMetrics::new();
+        metrics.observe_request("/v1/messages", "claude-sonnet-4-5", "claude-sonnet-4-5-thinking", &log(200, 300, Some(10), Some(20)));
+        metrics.observe_request("/v1/messages", "claude-sonnet-4-5", "claude-sonnet-4-5-thinking", &log(200, 1500, Some(5), None));
+        metrics.observe_request("/v1/messages", "claude-sonnet-4-5", "claude-sonnet-4-5-thinking", &log(429, 50, None, None));
+
+        let data_dir = std::env::temp_dir().join(format!("metrics_{}", uuid::Uuid::new_v4()));
+        let token_manager = TokenManager::new(data_dir.clone());
+        let text = metrics.render(&token_manager);
+        let _ = std::fs::remove_dir_all(&data_dir);
+
+        assert!(text.contains(
+            "antigravity_proxy_requests_total{route=\"/v1/messages\",model=\"claude-sonnet-4-5\",mapped_model=\"claude-sonnet-4-5-thinking\",status=\"200\"} 2"
+        ));
+        assert!(text.contains("status=\"429\"} 1"));
+        assert!(text.contains("status=\"200\",le=\"0.5\"} 1"));
+        assert!(text.contains("status=\"200\",le=\"+Inf\"} 2"));
+        assert!(text.contains("type=\"input\"} 15"));
+        assert!(text.contains("type=\"output\"} 20"));
+        assert!(text.contains("antigravity_proxy_pool_accounts 0"));
+        assert!(text.contains("antigravity_proxy_bound_sessions 0"));
+    }
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!(escape_label("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
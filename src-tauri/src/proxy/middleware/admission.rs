@@ -0,0 +1,295 @@
+// 准入队列中间件
+// 账号池中所有账号都被限流 / 并发已满时，生成类请求按 API Key 优先级排队等待，而不是直接返回 429
+// 流式请求立即返回 SSE 响应，排队期间以 SSE 注释推送队列位置 (兼作心跳)，放行后接续真实响应
+// 此时响应头已发出，内层响应的 X-Account-Email / X-Mapped-Model 改以 SSE 注释转发
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::{header, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use futures::StreamExt;
+use serde_json::Value;
+use std::time::{Duration, Instant};
+
+use crate::proxy::admission::{hold_until_leased, QueueStatus};
+use crate::proxy::common::model_fallback;
+use crate::proxy::common::model_router::RouteContext;
+use crate::proxy::common::protocol_error::{error_body, error_response, ClientProtocol};
+use crate::proxy::config::{ClientApiKey, ProviderProtocol};
+use crate::proxy::server::AppState;
+
+const MAX_QUEUED_BODY_SIZE: usize = 100 * 1024 * 1024; // 100MB
+
+/// 队列位置未变化时 SSE 注释的最小推送间隔
+const STATUS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 需要排队的生成类端点，返回可接管该端点的服务商协议 (原生协议在前，用于判断 Exclusive / Fallback 服务商)
+fn queued_endpoint(method: &Method, path: &str) -> Option<&'static [ProviderProtocol]> {
+    if method != Method::POST {
+        return None;
+    }
+    match path {
+        "/v1/chat/completions" => Some(&[ProviderProtocol::OpenAI, ProviderProtocol::Anthropic]),
+        "/v1/completions" | "/v1/responses" => Some(&[]),
+        "/v1/messages" => Some(&[ProviderProtocol::Anthropic]),
+        _ if path.starts_with("/v1beta/models/")
+            && (path.ends_with(":generateContent") || path.ends_with(":streamGenerateContent")) =>
+        {
+            Some(&[ProviderProtocol::Gemini])
+        }
+        _ => None,
+    }
+}
+
+/// 从请求中提取 (模型, 是否流式)
+fn request_model(path: &str, body: &Value) -> Option<(String, bool)> {
+    if let Some(rest) = path.strip_prefix("/v1beta/models/") {
+        let (model, method) = rest.split_once(':')?;
+        return Some((model.to_string(), method == "streamGenerateContent"));
+    }
+    let model = body.get("model")?.as_str()?.to_string();
+    let stream = body.get("stream").and_then(|v| v.as_bool()).unwrap_or(false);
+    Some((model, stream))
+}
+
+/// 排队状态的 SSE 注释 (客户端会忽略以冒号开头的行)
+fn status_comment(status: &QueueStatus) -> String {
+    match status.retry_in_secs {
+        Some(secs) => format!(
+            ": queued position={} of {} retry_in={}s\n\n",
+            status.position, status.depth, secs
+        ),
+        None => format!(": queued position={} of {}\n\n", status.position, status.depth),
+    }
+}
+
+/// 内层响应头的 SSE 注释 (排队的流式请求无法再设置响应头)
+fn headers_comment(headers: &header::HeaderMap) -> Option<String> {
+    let fields: Vec<String> = [("X-Account-Email", "account"), ("X-Mapped-Model", "mapped_model")]
+        .iter()
+        .filter_map(|(name, label)| {
+            let value = headers.get(*name)?.to_str().ok()?;
+            Some(format!("{}={}", label, value))
+        })
+        .collect();
+    (!fields.is_empty()).then(|| format!(": {}\n\n", fields.join(" ")))
+}
+
+/// 流式响应中的错误事件
+fn error_event(protocol: ClientProtocol, status: StatusCode, message: &str) -> Bytes {
+    let body = error_body(protocol, status, message);
+    match protocol {
+        ClientProtocol::Claude => Bytes::from(format!("event: error\ndata: {}\n\n", body)),
+        _ => Bytes::from(format!("data: {}\n\n", body)),
+    }
+}
+
+enum Step {
+    Status(QueueStatus),
+    Admitted(Result<(), String>),
+}
+
+pub async fn admission_middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path().to_string();
+    let Some(protocols) = queued_endpoint(request.method(), &path) else {
+        return next.run(request).await;
+    };
+
+    let scheduling = state.token_manager.get_sticky_config().await;
+    // Exclusive 服务商接管全部流量；Fallback 服务商会在账号池耗尽时承接，均无需排队
+    let provider_ready = {
+        let providers = state.providers.read().await;
+        providers.has_exclusive(protocols) || providers.fallback(protocols).is_some()
+    };
+    if !scheduling.queue_enabled || state.token_manager.len() == 0 || provider_ready {
+        return next.run(request).await;
+    }
+
+    let (parts, body) = request.into_parts();
+    let bytes = match axum::body::to_bytes(body, MAX_QUEUED_BODY_SIZE).await {
+        Ok(bytes) => bytes,
+        Err(_) => return next.run(Request::from_parts(parts, Body::empty())).await,
+    };
+    let json: Value = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+    let request = Request::from_parts(parts, Body::from(bytes));
+
+    let Some((model, stream)) = request_model(&path, &json) else {
+        return next.run(request).await;
+    };
+
+    let protocol = ClientProtocol::from_path(&path);
+    let client_key = request.extensions().get::<ClientApiKey>().cloned();
+    let ctx = RouteContext::from_request(protocol, &json, client_key.as_ref());
+    let selection =
+        model_fallback::resolve_with_fallback(&state.model_router, &state.token_manager, &model, &ctx).await;
+    let model = selection.model;
+
+    let queue = state.token_manager.admission_queue().clone();
+    if queue.is_empty() && state.token_manager.has_available_account(&model).await {
+        return next.run(request).await;
+    }
+
+    let priority = client_key.as_ref().map(|k| k.priority).unwrap_or(0);
+    let ticket = match queue.enqueue(priority, scheduling.queue_max_depth) {
+        Ok(ticket) => ticket,
+        Err(e) => {
+            tracing::warn!("[Queue] {} (model: {})", e, model);
+            return error_response(protocol, StatusCode::TOO_MANY_REQUESTS, &e, None);
+        }
+    };
+    let timeout = Duration::from_secs(scheduling.queue_timeout_seconds);
+    tracing::info!(
+        "[Queue] 账号池无可用账号，请求进入队列 (model: {}, priority: {}, depth: {})",
+        model,
+        priority,
+        queue.len()
+    );
+
+    if !stream {
+        let started = Instant::now();
+        let admitted = state
+            .token_manager
+            .wait_for_admission(&ticket, &model, timeout, |_| {})
+            .await;
+        return match admitted {
+            Ok(()) => {
+                tracing::info!("[Queue] 请求已放行，排队 {}ms (model: {})", started.elapsed().as_millis(), model);
+                hold_until_leased(ticket, next.run(request)).await
+            }
+            Err(e) => {
+                tracing::warn!("[Queue] {}", e);
+                let retry_after = state.token_manager.next_reset_in(&model);
+                error_response(protocol, StatusCode::SERVICE_UNAVAILABLE, &e, retry_after)
+            }
+        };
+    }
+
+    // 流式请求: 先返回 SSE 响应头，排队状态以注释推送，放行后接续内层响应体
+    let body = async_stream::stream! {
+        let started = Instant::now();
+        let admitted = {
+            let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<QueueStatus>();
+            let wait = state
+                .token_manager
+                .wait_for_admission(&ticket, &model, timeout, move |status| {
+                    let _ = tx.send(status);
+                });
+            tokio::pin!(wait);
+
+            let mut last_sent: Option<(QueueStatus, Instant)> = None;
+            loop {
+                let step = tokio::select! {
+                    result = &mut wait => Step::Admitted(result),
+                    Some(status) = rx.recv() => Step::Status(status),
+                };
+                match step {
+                    Step::Admitted(result) => break result,
+                    Step::Status(status) => {
+                        let due = match &last_sent {
+                            Some((prev, at)) => {
+                                prev.position != status.position || at.elapsed() >= STATUS_INTERVAL
+                            }
+                            None => true,
+                        };
+                        if due {
+                            last_sent = Some((status, Instant::now()));
+                            yield Ok::<Bytes, std::io::Error>(Bytes::from(status_comment(&status)));
+                        }
+                    }
+                }
+            }
+        };
+
+        match admitted {
+            Ok(()) => {
+                tracing::info!("[Queue] 流式请求已放行，排队 {}ms (model: {})", started.elapsed().as_millis(), model);
+                let response = hold_until_leased(ticket, next.run(request)).await;
+                let status = response.status();
+                if status.is_success() {
+                    if let Some(comment) = headers_comment(response.headers()) {
+                        yield Ok(Bytes::from(comment));
+                    }
+                    let mut inner = response.into_body().into_data_stream();
+                    while let Some(chunk) = inner.next().await {
+                        match chunk {
+                            Ok(bytes) => yield Ok(bytes),
+                            Err(e) => {
+                                yield Err(std::io::Error::other(e.to_string()));
+                                break;
+                            }
+                        }
+                    }
+                } else {
+                    // 响应头已发出，上游错误改以 SSE 错误事件返回
+                    let bytes = axum::body::to_bytes(response.into_body(), MAX_QUEUED_BODY_SIZE)
+                        .await
+                        .unwrap_or_default();
+                    let message = serde_json::from_slice::<Value>(&bytes)
+                        .ok()
+                        .and_then(|v| {
+                            v.pointer("/error/message")
+                                .and_then(|m| m.as_str())
+                                .map(|s| s.to_string())
+                        })
+                        .unwrap_or_else(|| String::from_utf8_lossy(&bytes).to_string());
+                    yield Ok(error_event(protocol, status, &message));
+                }
+            }
+            Err(e) => {
+                drop(ticket);
+                tracing::warn!("[Queue] {}", e);
+                yield Ok(error_event(protocol, StatusCode::SERVICE_UNAVAILABLE, &e));
+            }
+        }
+    };
+
+    let mut response = Response::new(Body::from_stream(body));
+    let headers = response.headers_mut();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("text/event-stream"));
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    headers.insert(header::CONNECTION, HeaderValue::from_static("keep-alive"));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queued_endpoint_and_model() {
+        assert!(queued_endpoint(&Method::POST, "/v1/messages").is_some());
+        assert!(queued_endpoint(&Method::POST, "/v1/messages/count_tokens").is_none());
+        assert!(queued_endpoint(&Method::GET, "/v1/chat/completions").is_none());
+        assert!(queued_endpoint(&Method::POST, "/v1beta/models/gemini-2.5-pro:countTokens").is_none());
+
+        let (model, stream) = request_model(
+            "/v1beta/models/gemini-2.5-pro:streamGenerateContent",
+            &Value::Null,
+        )
+        .unwrap();
+        assert_eq!(model, "gemini-2.5-pro");
+        assert!(stream);
+
+        let body = serde_json::json!({"model": "claude-sonnet-4-5", "stream": true});
+        assert_eq!(request_model("/v1/messages", &body), Some(("claude-sonnet-4-5".to_string(), true)));
+        assert_eq!(request_model("/v1/messages", &Value::Null), None);
+    }
+
+    #[test]
+    fn test_headers_comment() {
+        let mut headers = header::HeaderMap::new();
+        assert_eq!(headers_comment(&headers), None);
+        headers.insert("X-Account-Email", HeaderValue::from_static("a@example.com"));
+        headers.insert("X-Mapped-Model", HeaderValue::from_static("gemini-2.5-pro"));
+        assert_eq!(
+            headers_comment(&headers).as_deref(),
+            Some(": account=a@example.com mapped_model=gemini-2.5-pro\n\n")
+        );
+    }
+}
@@ -1,7 +1,8 @@
 // API Key 认证中间件
 use axum::{
+    body::Body,
     extract::State,
-    extract::Request,
+    extract::{FromRequest, Request},
     http::{header, StatusCode},
     middleware::Next,
     response::Response,
@@ -9,8 +10,12 @@ use axum::{
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+use crate::proxy::common::protocol_error::{error_response, ClientProtocol};
+use crate::proxy::config::ApiKeyScope;
 use crate::proxy::{ProxyAuthMode, ProxySecurityConfig};
 
+const MAX_BODY_SIZE: usize = 100 * 1024 * 1024; // 与 DefaultBodyLimit 保持一致
+
 /// API Key 认证中间件
 pub async fn auth_middleware(
     State(security): State<Arc<RwLock<ProxySecurityConfig>>>,
@@ -35,16 +40,8 @@ pub async fn auth_middleware(
     let security = security.read().await.clone();
     let effective_mode = security.effective_auth_mode();
 
-    if matches!(effective_mode, ProxyAuthMode::Off) {
-        return Ok(next.run(request).await);
-    }
-
-    if matches!(effective_mode, ProxyAuthMode::AllExceptHealth) && path == "/healthz" {
-        return Ok(next.run(request).await);
-    }
-    
     // 从 header 中提取 API key
-    let api_key = request
+    let presented_key = request
         .headers()
         .get(header::AUTHORIZATION)
         .and_then(|h| h.to_str().ok())
@@ -54,30 +51,230 @@ pub async fn auth_middleware(
                 .headers()
                 .get("x-api-key")
                 .and_then(|h| h.to_str().ok())
-        });
+        })
+        .map(|s| s.to_string());
 
-    if security.api_key.is_empty() {
-        tracing::error!("Proxy auth is enabled but api_key is empty; denying request");
+    let client_key = presented_key.as_deref().and_then(|k| security.find_key(k));
+
+    let auth_exempt = matches!(effective_mode, ProxyAuthMode::Off)
+        || (matches!(effective_mode, ProxyAuthMode::AllExceptHealth) && path == "/healthz");
+
+    if auth_exempt {
+        // 未启用认证时仍记录调用方身份，便于日志区分
+        let mut request = request;
+        if let Some(key) = client_key {
+            request.extensions_mut().insert(key);
+        }
+        return Ok(next.run(request).await);
+    }
+
+    if !security.has_any_key() {
+        tracing::error!("Proxy auth is enabled but no api_key is configured; denying request");
         return Err(StatusCode::UNAUTHORIZED);
     }
 
     // Constant-time compare is unnecessary here, but keep strict equality and avoid leaking values.
-    let authorized = api_key.map(|k| k == security.api_key).unwrap_or(false);
+    let Some(client_key) = client_key else {
+        return Err(StatusCode::UNAUTHORIZED);
+    };
 
-    if authorized {
-        Ok(next.run(request).await)
+    if !client_key.enabled {
+        tracing::warn!("[Auth] API key '{}' is disabled; denying {}", client_key.name, path);
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    if client_key.is_expired(chrono::Utc::now().timestamp()) {
+        tracing::warn!("[Auth] API key '{}' has expired; denying {}", client_key.name, path);
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    if let Some(scope) = ApiKeyScope::from_path(&path) {
+        if !client_key.allows_scope(scope) {
+            tracing::warn!(
+                "[Auth] API key '{}' lacks scope {:?} for {}",
+                client_key.name,
+                scope,
+                path
+            );
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+
+    let mut request = if client_key.allowed_models.is_empty() {
+        request
     } else {
-        Err(StatusCode::UNAUTHORIZED)
+        let (request, model) = extract_request_model(request).await;
+        match model {
+            Some(model) if !client_key.allows_model(&model) => {
+                tracing::warn!(
+                    "[Auth] API key '{}' is not allowed to use model {}",
+                    client_key.name,
+                    model
+                );
+                return Err(StatusCode::FORBIDDEN);
+            }
+            // 无法确定模型时拒绝，避免受限密钥借助默认模型绕过白名单
+            None if requires_model(&method, &path) => {
+                tracing::warn!(
+                    "[Auth] API key '{}' is restricted to specific models but {} has no readable model",
+                    client_key.name,
+                    path
+                );
+                return Err(StatusCode::FORBIDDEN);
+            }
+            _ => {}
+        }
+        request
+    };
+
+    if let Err(exceeded) = crate::proxy::usage::check_budget(&client_key) {
+        let message = exceeded.message(&client_key.name);
+        tracing::warn!("[Auth] {}", message);
+        return Ok(error_response(
+            ClientProtocol::from_path(&path),
+            StatusCode::TOO_MANY_REQUESTS,
+            &message,
+            Some(exceeded.retry_after_secs),
+        ));
+    }
+
+    request.extensions_mut().insert(client_key);
+    Ok(next.run(request).await)
+}
+
+/// 会调用上游模型的请求 (MCP 转发不涉及模型)
+fn requires_model(method: &axum::http::Method, path: &str) -> bool {
+    *method == axum::http::Method::POST
+        && matches!(ApiKeyScope::from_path(path), Some(scope) if scope != ApiKeyScope::Mcp)
+}
+
+#[derive(serde::Deserialize)]
+struct ModelField {
+    model: Option<String>,
+}
+
+/// 提取请求的目标模型 (Gemini 从路径读取，其余协议从 JSON body 或 multipart 表单的 `model` 字段读取)
+///
+/// 读取 body 后会重新构造请求，其他类型的请求返回 None
+pub(crate) async fn extract_request_model(request: Request) -> (Request, Option<String>) {
+    let path = request.uri().path();
+    if let Some(rest) = path.strip_prefix("/v1beta/models/") {
+        let model = rest.split([':', '/']).next().unwrap_or("").to_string();
+        return (request, Some(model).filter(|m| !m.is_empty()));
+    }
+
+    let content_type = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+    let is_json = content_type.contains("application/json");
+    let is_multipart = content_type.starts_with("multipart/form-data");
+    if request.method() != axum::http::Method::POST || !(is_json || is_multipart) {
+        return (request, None);
+    }
+
+    let (parts, body) = request.into_parts();
+    match axum::body::to_bytes(body, MAX_BODY_SIZE).await {
+        Ok(bytes) => {
+            let model = if is_json {
+                // 只反序列化 model 字段，其余内容跳过不分配
+                serde_json::from_slice::<ModelField>(&bytes)
+                    .ok()
+                    .and_then(|f| f.model)
+            } else {
+                multipart_model(&content_type, bytes.clone()).await
+            };
+            (Request::from_parts(parts, Body::from(bytes)), model)
+        }
+        Err(_) => (Request::from_parts(parts, Body::empty()), None),
     }
 }
 
+/// 读取 multipart 表单中的 `model` 字段 (音频转录、图像编辑)
+async fn multipart_model(content_type: &str, bytes: axum::body::Bytes) -> Option<String> {
+    let request = Request::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .body(Body::from(bytes))
+        .ok()?;
+    let mut multipart = axum::extract::Multipart::from_request(request, &()).await.ok()?;
+    while let Ok(Some(field)) = multipart.next_field().await {
+        if field.name() == Some("model") {
+            return field.text().await.ok().filter(|m| !m.is_empty());
+        }
+    }
+    None
+}
+
 #[cfg(test)]
 mod tests {
-    // 移除未使用的 use super::*;
+    use super::*;
+
+    #[test]
+    fn test_scope_from_path() {
+        assert_eq!(ApiKeyScope::from_path("/v1/chat/completions"), Some(ApiKeyScope::OpenAI));
+        assert_eq!(ApiKeyScope::from_path("/v1/responses"), Some(ApiKeyScope::OpenAI));
+        assert_eq!(ApiKeyScope::from_path("/v1/models"), Some(ApiKeyScope::OpenAI));
+        assert_eq!(ApiKeyScope::from_path("/v1/messages/count_tokens"), Some(ApiKeyScope::Claude));
+        assert_eq!(ApiKeyScope::from_path("/v1/models/claude"), Some(ApiKeyScope::Claude));
+        assert_eq!(
+            ApiKeyScope::from_path("/v1beta/models/gemini-2.5-flash:generateContent"),
+            Some(ApiKeyScope::Gemini)
+        );
+        assert_eq!(ApiKeyScope::from_path("/mcp/web_reader/mcp"), Some(ApiKeyScope::Mcp));
+        assert_eq!(ApiKeyScope::from_path("/v1/images/edits"), Some(ApiKeyScope::Images));
+        assert_eq!(ApiKeyScope::from_path("/v1/audio/transcriptions"), Some(ApiKeyScope::Audio));
+        assert_eq!(ApiKeyScope::from_path("/healthz"), None);
+        assert_eq!(ApiKeyScope::from_path("/v1/models/detect"), None);
+    }
+
+    #[tokio::test]
+    async fn test_extract_model_from_gemini_path() {
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1beta/models/gemini-2.5-pro:streamGenerateContent?alt=sse")
+            .body(Body::empty())
+            .unwrap();
+        let (_, model) = extract_request_model(request).await;
+        assert_eq!(model.as_deref(), Some("gemini-2.5-pro"));
+    }
+
+    #[tokio::test]
+    async fn test_extract_model_from_json_body_keeps_body() {
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1/chat/completions")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"model":"gpt-4o","messages":[]}"#))
+            .unwrap();
+        let (request, model) = extract_request_model(request).await;
+        assert_eq!(model.as_deref(), Some("gpt-4o"));
+        let bytes = axum::body::to_bytes(request.into_body(), usize::MAX).await.unwrap();
+        assert!(bytes.starts_with(b"{\"model\""));
+    }
+
+    #[tokio::test]
+    async fn test_extract_model_from_multipart_form() {
+        let body = "--b\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.wav\"\r\n\r\nxx\r\n\
+                    --b\r\nContent-Disposition: form-data; name=\"model\"\r\n\r\nwhisper-1\r\n--b--\r\n";
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1/audio/transcriptions")
+            .header(header::CONTENT_TYPE, "multipart/form-data; boundary=b")
+            .body(Body::from(body))
+            .unwrap();
+        let (request, model) = extract_request_model(request).await;
+        assert_eq!(model.as_deref(), Some("whisper-1"));
+        let bytes = axum::body::to_bytes(request.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(bytes.len(), body.len());
+    }
 
     #[test]
-    fn test_auth_placeholder() {
-        // Placeholder test
-        assert!(true);
+    fn test_requires_model() {
+        assert!(requires_model(&axum::http::Method::POST, "/v1/images/edits"));
+        assert!(requires_model(&axum::http::Method::POST, "/v1/chat/completions"));
+        assert!(!requires_model(&axum::http::Method::GET, "/v1/models"));
+        assert!(!requires_model(&axum::http::Method::POST, "/mcp/web_reader/mcp"));
     }
 }
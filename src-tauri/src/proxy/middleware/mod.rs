@@ -1,5 +1,6 @@
 // Middleware 模块 - Axum 中间件
 
+pub mod admission;
 pub mod auth;
 pub mod cors;
 pub mod logging;
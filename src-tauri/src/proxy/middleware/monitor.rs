@@ -1,9 +1,10 @@
 use axum::{
-    extract::{Request, State},
+    extract::{MatchedPath, Request, State},
     middleware::Next,
     response::Response,
     body::Body,
 };
+use std::collections::HashSet;
 use std::time::Instant;
 use crate::proxy::server::AppState;
 use crate::proxy::monitor::ProxyRequestLog;
@@ -13,23 +14,60 @@ use futures::StreamExt;
 const MAX_REQUEST_LOG_SIZE: usize = 100 * 1024 * 1024; // 100MB
 const MAX_RESPONSE_LOG_SIZE: usize = 100 * 1024 * 1024; // 100MB for image responses
 
+/// 未知模型名在指标中的统一标签
+const OTHER_MODEL_LABEL: &str = "other";
+
+/// 指标可使用的已知模型名: 模型列表 (内置映射、能力注册表、路由规则与自定义映射)、路由规则的固定目标与账号池模型目录
+async fn known_models(state: &AppState) -> HashSet<String> {
+    let mut known: HashSet<String> =
+        crate::proxy::common::model_mapping::get_all_dynamic_models(&state.model_router)
+            .await
+            .into_iter()
+            .collect();
+    known.extend(state.model_router.read().await.listed_targets());
+    known
+}
+
+/// 指标的模型标签: 仅使用已知模型名，其余归为 `other`，避免客户端传入任意模型名导致 Prometheus 标签基数失控
+fn metrics_model_label(state: &AppState, known: &HashSet<String>, model: Option<&str>) -> String {
+    let Some(model) = model.filter(|m| !m.is_empty()) else {
+        return String::new();
+    };
+    if known.contains(model) || state.model_catalog.contains(model) {
+        model.to_string()
+    } else {
+        OTHER_MODEL_LABEL.to_string()
+    }
+}
+
 pub async fn monitor_middleware(
     State(state): State<AppState>,
     request: Request,
     next: Next,
 ) -> Response {
-    if !state.monitor.is_enabled() {
-        return next.run(request).await;
-    }
+    let api_key_name = request
+        .extensions()
+        .get::<crate::proxy::config::ClientApiKey>()
+        .map(|k| k.name.clone());
+
+    // 监控关闭时仍需统计 Prometheus 指标与具名密钥用量，但不缓冲请求体、不保留响应体
+    let capture_bodies = state.monitor.is_enabled();
 
     let start = Instant::now();
     let method = request.method().to_string();
     let uri = request.uri().to_string();
     
-    if uri.contains("event_logging") {
+    if uri.contains("event_logging") || request.uri().path() == "/metrics" {
         return next.run(request).await;
     }
-    
+
+    // 使用路由模板作为指标标签 (如 /v1beta/models/:model)
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
     let mut model = if uri.contains("/v1beta/models/") {
         uri.split("/v1beta/models/")
             .nth(1)
@@ -40,7 +78,7 @@ pub async fn monitor_middleware(
     };
 
     let request_body_str;
-    let request = if method == "POST" {
+    let request = if method == "POST" && capture_bodies {
         let (parts, body) = request.into_parts();
         match axum::body::to_bytes(body, MAX_REQUEST_LOG_SIZE).await {
             Ok(bytes) => {
@@ -61,6 +99,12 @@ pub async fn monitor_middleware(
                 Request::from_parts(parts, Body::empty())
             }
         }
+    } else if model.is_none() && method == "POST" {
+        // 监控关闭时不保留请求体，仅读取 model 字段供指标使用
+        request_body_str = None;
+        let (request, body_model) = crate::proxy::middleware::auth::extract_request_model(request).await;
+        model = body_model;
+        request
     } else {
         request_body_str = None;
         request
@@ -90,6 +134,16 @@ pub async fn monitor_middleware(
         .and_then(|v| v.to_str().ok())
         .map(|s| s.to_string());
 
+    // Extract original model from fallback header if a fallback chain was used
+    let fallback_from = response
+        .headers()
+        .get(crate::proxy::common::model_fallback::FALLBACK_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string());
+
+    let known = known_models(&state).await;
+    let model_label = metrics_model_label(&state, &known, model.as_deref());
+    let mapped_model_label = metrics_model_label(&state, &known, mapped_model.as_deref());
     let monitor = state.monitor.clone();
     let mut log = ProxyRequestLog {
         id: uuid::Uuid::new_v4().to_string(),
@@ -101,6 +155,8 @@ pub async fn monitor_middleware(
         model,
         mapped_model,
         account_email,
+        api_key_name,
+        fallback_from,
         error: None,
         request_body: request_body_str,
         response_body: None,
@@ -132,33 +188,30 @@ pub async fn monitor_middleware(
                 }
             }
             
-            if let Ok(full_tail) = std::str::from_utf8(&last_few_bytes) {
-                for line in full_tail.lines().rev() {
-                    if line.starts_with("data: ") && (line.contains("\"usage\"") || line.contains("\"usageMetadata\"")) {
-                        let json_str = line.trim_start_matches("data: ").trim();
-                        if let Ok(json) = serde_json::from_str::<Value>(json_str) {
-                            // 支持 OpenAI "usage" 或 Gemini "usageMetadata"
-                            if let Some(usage) = json.get("usage").or(json.get("usageMetadata")) {
-                                log.input_tokens = usage.get("prompt_tokens")
-                                    .or(usage.get("input_tokens"))
-                                    .or(usage.get("promptTokenCount"))
-                                    .and_then(|v| v.as_u64())
-                                    .map(|v| v as u32);
-                                log.output_tokens = usage.get("completion_tokens")
-                                    .or(usage.get("output_tokens"))
-                                    .or(usage.get("candidatesTokenCount"))
-                                    .and_then(|v| v.as_u64())
-                                    .map(|v| v as u32);
-                                
-                                if log.input_tokens.is_none() && log.output_tokens.is_none() {
-                                    log.output_tokens = usage.get("total_tokens")
-                                        .or(usage.get("totalTokenCount"))
-                                        .and_then(|v| v.as_u64())
-                                        .map(|v| v as u32);
-                                }
-                                break;
-                            }
+            // 尾部截断可能落在多字节字符中间，使用 lossy 解码
+            let full_tail = String::from_utf8_lossy(&last_few_bytes);
+            for line in full_tail.lines().rev() {
+                if line.starts_with("data: ") && (line.contains("\"usage\"") || line.contains("\"usageMetadata\"")) {
+                    // 支持 OpenAI "usage"、Gemini "usageMetadata" 与 Responses "response.usage"
+                    if let Some(usage) = find_sse_usage(line.trim_start_matches("data: ").trim()).as_ref() {
+                        log.input_tokens = usage.get("prompt_tokens")
+                            .or(usage.get("input_tokens"))
+                            .or(usage.get("promptTokenCount"))
+                            .and_then(|v| v.as_u64())
+                            .map(|v| v as u32);
+                        log.output_tokens = usage.get("completion_tokens")
+                            .or(usage.get("output_tokens"))
+                            .or(usage.get("candidatesTokenCount"))
+                            .and_then(|v| v.as_u64())
+                            .map(|v| v as u32);
+                        
+                        if log.input_tokens.is_none() && log.output_tokens.is_none() {
+                            log.output_tokens = usage.get("total_tokens")
+                                .or(usage.get("totalTokenCount"))
+                                .and_then(|v| v.as_u64())
+                                .map(|v| v as u32);
                         }
+                        break;
                     }
                 }
             }
@@ -166,16 +219,19 @@ pub async fn monitor_middleware(
             if log.status >= 400 {
                 log.error = Some("Stream Error or Failed".to_string());
             }
-            monitor.log_request(log).await;
+            monitor.record(&route, &model_label, &mapped_model_label, log).await;
         });
 
         Response::from_parts(parts, Body::from_stream(tokio_stream::wrappers::ReceiverStream::new(rx)))
-    } else if content_type.contains("application/json") || content_type.contains("text/") {
+    } else if (capture_bodies || log.api_key_name.is_some())
+        && (content_type.contains("application/json") || content_type.contains("text/"))
+    {
+        // 仅在需要记录响应体或统计具名密钥用量时缓冲完整响应
         let (parts, body) = response.into_parts();
         match axum::body::to_bytes(body, MAX_RESPONSE_LOG_SIZE).await {
             Ok(bytes) => {
                 if let Ok(s) = std::str::from_utf8(&bytes) {
-                    if let Ok(json) = serde_json::from_str::<Value>(&s) {
+                    if let Ok(json) = serde_json::from_str::<Value>(s) {
                         // 支持 OpenAI "usage" 或 Gemini "usageMetadata"
                         if let Some(usage) = json.get("usage").or(json.get("usageMetadata")) {
                             log.input_tokens = usage.get("prompt_tokens")
@@ -197,7 +253,9 @@ pub async fn monitor_middleware(
                             }
                         }
                     }
-                    log.response_body = Some(s.to_string());
+                    if capture_bodies || log.status >= 400 {
+                        log.response_body = Some(s.to_string());
+                    }
                 } else {
                     log.response_body = Some("[Binary Response Data]".to_string());
                 }
@@ -205,18 +263,59 @@ pub async fn monitor_middleware(
                 if log.status >= 400 {
                     log.error = log.response_body.clone();
                 }
-                monitor.log_request(log).await;
+                monitor.record(&route, &model_label, &mapped_model_label, log).await;
                 Response::from_parts(parts, Body::from(bytes))
             }
             Err(_) => {
                 log.response_body = Some("[Response too large (>100MB)]".to_string());
-                monitor.log_request(log).await;
+                monitor.record(&route, &model_label, &mapped_model_label, log).await;
                 Response::from_parts(parts, Body::empty())
             }
         }
     } else {
         log.response_body = Some(format!("[{}]", content_type));
-        monitor.log_request(log).await;
+        monitor.record(&route, &model_label, &mapped_model_label, log).await;
         response
     }
 }
+
+/// 从单行 SSE data 中提取 usage 对象
+///
+/// Responses API 的 response.completed 事件携带完整输出，可能超出尾部缓冲而被截断；
+/// 此时直接定位 `"usage":` 并解析其后的对象
+fn find_sse_usage(json_str: &str) -> Option<Value> {
+    if let Ok(json) = serde_json::from_str::<Value>(json_str) {
+        return json
+            .get("usage")
+            .or(json.get("usageMetadata"))
+            .or(json.get("response").and_then(|r| r.get("usage")))
+            .filter(|u| u.is_object())
+            .cloned();
+    }
+    let pos = json_str.rfind("\"usage\":").or_else(|| json_str.rfind("\"usageMetadata\":"))?;
+    let rest = &json_str[pos..];
+    let colon = rest.find(':')?;
+    serde_json::Deserializer::from_str(rest[colon + 1..].trim_start())
+        .into_iter::<Value>()
+        .next()
+        .and_then(|v| v.ok())
+        .filter(|u| u.is_object())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_sse_usage_variants() {
+        let openai = r#"{"choices":[],"usage":{"prompt_tokens":3,"completion_tokens":5}}"#;
+        assert_eq!(find_sse_usage(openai).unwrap()["prompt_tokens"], 3);
+
+        let responses = r#"{"type":"response.completed","response":{"output":[],"usage":{"input_tokens":7,"output_tokens":2}}}"#;
+        assert_eq!(find_sse_usage(responses).unwrap()["input_tokens"], 7);
+
+        // 行首被截断的 response.completed 事件
+        let truncated = r#"xt":"..."}]}],"status":"completed","usage":{"input_tokens":9,"output_tokens":4}}}"#;
+        assert_eq!(find_sse_usage(truncated).unwrap()["output_tokens"], 4);
+    }
+}
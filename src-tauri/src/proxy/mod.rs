@@ -17,17 +17,23 @@ pub mod providers;         // Extra upstream providers (z.ai, etc.)
 pub mod zai_vision_mcp;    // Built-in Vision MCP server state
 pub mod zai_vision_tools;  // Built-in Vision MCP tools (z.ai vision API)
 pub mod monitor;           // 监控
+pub mod metrics;           // Prometheus 指标
 pub mod rate_limit;        // 限流跟踪
 pub mod sticky_config;     // 粘性调度配置
+pub mod load_balance;      // 负载 / 配额加权调度
+pub mod admission;         // 账号池耗尽时的准入队列
 pub mod session_manager;   // 会话指纹管理
+pub mod session_bindings;  // 粘性会话绑定表 (TTL / LRU / 持久化)
 pub mod audio;             // 音频处理模块 (PR #311)
 pub mod signature_cache;   // Signature Cache (v3.3.16)
+pub mod usage;             // 客户端密钥用量与 token 预算
+pub mod model_catalog;     // 账号可用模型目录 (fetchAvailableModels)
+pub mod token_refresh;     // access_token 后台主动刷新
 
 
 pub use config::ProxyConfig;
 pub use config::ProxyAuthMode;
 pub use config::ZaiConfig;
-pub use config::ZaiDispatchMode;
 pub use token_manager::TokenManager;
 pub use server::AxumServer;
 pub use security::ProxySecurityConfig;
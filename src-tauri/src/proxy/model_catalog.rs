@@ -0,0 +1,197 @@
+// 账号模型目录
+// 定期调用 fetchAvailableModels 获取每个账号实际可用的模型与剩余配额，
+// /v1/models 等列表接口据此返回账号池可服务的模型并集
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+
+use dashmap::DashMap;
+use serde_json::Value;
+
+use crate::proxy::upstream::client::UpstreamClient;
+use crate::proxy::TokenManager;
+
+/// 刷新间隔
+pub const CATALOG_REFRESH_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// 单个账号上某个模型的配额
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModelQuota {
+    /// 剩余配额比例 (0.0 - 1.0)
+    pub remaining_fraction: Option<f64>,
+    /// 配额重置时间 (RFC 3339)
+    pub reset_time: Option<String>,
+}
+
+/// 账号池维度的模型汇总
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolModel {
+    pub id: String,
+    /// 可使用该模型的账号数
+    pub accounts: usize,
+    /// 所有账号中剩余配额比例的最大值
+    pub remaining_fraction: Option<f64>,
+    /// 最早的配额重置时间
+    pub reset_time: Option<String>,
+}
+
+/// 解析 fetchAvailableModels 响应 (`models` 为 模型名 -> 信息 的对象)
+///
+/// 与配额查询保持一致，仅保留 gemini / claude 模型
+pub fn parse_available_models(resp: &Value) -> HashMap<String, ModelQuota> {
+    let Some(models) = resp.get("models").and_then(|m| m.as_object()) else {
+        return HashMap::new();
+    };
+    models
+        .iter()
+        .filter(|(name, _)| name.contains("gemini") || name.contains("claude"))
+        .map(|(name, info)| {
+            let quota = info.get("quotaInfo");
+            (
+                name.clone(),
+                ModelQuota {
+                    remaining_fraction: quota.and_then(|q| q.get("remainingFraction")).and_then(|v| v.as_f64()),
+                    reset_time: quota
+                        .and_then(|q| q.get("resetTime"))
+                        .and_then(|v| v.as_str())
+                        .map(|s| s.to_string()),
+                },
+            )
+        })
+        .collect()
+}
+
+#[derive(Default)]
+pub struct ModelCatalog {
+    /// email -> (模型名 -> 配额)
+    accounts: DashMap<String, HashMap<String, ModelQuota>>,
+}
+
+impl ModelCatalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update_account(&self, email: &str, models: HashMap<String, ModelQuota>) {
+        self.accounts.insert(email.to_string(), models);
+    }
+
+    /// 移除已不在账号池中的账号
+    pub fn retain_accounts(&self, emails: &[String]) {
+        self.accounts.retain(|email, _| emails.contains(email));
+    }
+
+    /// 是否有账号可使用该模型
+    pub fn contains(&self, model: &str) -> bool {
+        self.accounts.iter().any(|e| e.value().contains_key(model))
+    }
+
+    /// 账号池可服务的模型并集 (按模型名排序)
+    pub fn pool_models(&self) -> Vec<PoolModel> {
+        let mut merged: BTreeMap<String, PoolModel> = BTreeMap::new();
+        for entry in self.accounts.iter() {
+            for (id, quota) in entry.value() {
+                let model = merged.entry(id.clone()).or_insert_with(|| PoolModel {
+                    id: id.clone(),
+                    accounts: 0,
+                    remaining_fraction: None,
+                    reset_time: None,
+                });
+                model.accounts += 1;
+                if let Some(fraction) = quota.remaining_fraction {
+                    model.remaining_fraction = Some(model.remaining_fraction.map_or(fraction, |f| f.max(fraction)));
+                }
+                if let Some(reset) = &quota.reset_time {
+                    // RFC 3339 (UTC) 字符串可直接按字典序比较
+                    if model.reset_time.as_ref().is_none_or(|r| reset < r) {
+                        model.reset_time = Some(reset.clone());
+                    }
+                }
+            }
+        }
+        merged.into_values().collect()
+    }
+
+    /// 依次拉取每个账号的可用模型，单个账号失败不影响其他账号
+    pub async fn refresh(&self, token_manager: &TokenManager, upstream: &UpstreamClient) {
+        let emails = token_manager.account_emails();
+        self.retain_accounts(&emails);
+
+        for email in &emails {
+            let access_token = match token_manager.get_token_by_email(email).await {
+                Ok((token, _, _)) => token,
+                Err(e) => {
+                    tracing::debug!("[Catalog] 跳过账号 {}: {}", email, e);
+                    continue;
+                }
+            };
+            match upstream.fetch_available_models(&access_token).await {
+                Ok(resp) => {
+                    let models = parse_available_models(&resp);
+                    tracing::debug!("[Catalog] 账号 {} 可用模型 {} 个", email, models.len());
+                    // 同步给账号池，供配额加权调度使用
+                    token_manager.update_model_quotas(email, models.clone());
+                    self.update_account(email, models);
+                }
+                Err(e) => tracing::warn!("[Catalog] 获取账号 {} 的模型列表失败: {}", email, e),
+            }
+        }
+    }
+
+    /// 启动后台定时刷新任务 (服务停止时由调用方 abort)
+    pub fn spawn_refresh_task(
+        self: Arc<Self>,
+        token_manager: Arc<TokenManager>,
+        upstream: Arc<UpstreamClient>,
+    ) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(CATALOG_REFRESH_INTERVAL);
+            loop {
+                interval.tick().await;
+                self.refresh(&token_manager, &upstream).await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_pool_models_union() {
+        let a = parse_available_models(&json!({
+            "models": {
+                "gemini-3-flash": { "quotaInfo": { "remainingFraction": 0.2, "resetTime": "2026-01-02T00:00:00Z" } },
+                "claude-sonnet-4-5": { "quotaInfo": { "remainingFraction": 1.0 } },
+                "chat_20706": {}
+            }
+        }));
+        assert_eq!(a.len(), 2);
+
+        let b = parse_available_models(&json!({
+            "models": {
+                "gemini-3-flash": { "quotaInfo": { "remainingFraction": 0.6, "resetTime": "2026-01-01T00:00:00Z" } },
+                "gemini-3-pro-high": { "quotaInfo": { "remainingFraction": 0.0 } }
+            }
+        }));
+
+        let catalog = ModelCatalog::new();
+        catalog.update_account("a@example.com", a);
+        catalog.update_account("b@example.com", b);
+
+        let models = catalog.pool_models();
+        let ids: Vec<_> = models.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["claude-sonnet-4-5", "gemini-3-flash", "gemini-3-pro-high"]);
+
+        let flash = &models[1];
+        assert_eq!(flash.accounts, 2);
+        assert_eq!(flash.remaining_fraction, Some(0.6));
+        assert_eq!(flash.reset_time.as_deref(), Some("2026-01-01T00:00:00Z"));
+
+        catalog.retain_accounts(&["a@example.com".to_string()]);
+        assert_eq!(catalog.pool_models().len(), 2);
+    }
+}
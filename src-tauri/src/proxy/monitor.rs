@@ -2,8 +2,22 @@ use serde::{Serialize, Deserialize};
 use std::collections::VecDeque;
 use tokio::sync::RwLock;
 use tauri::Emitter;
+use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 
+/// 监控事件推送抽象
+///
+/// 桌面端通过 tauri::AppHandle 推送到前端，Headless 模式下可以不注入或自行实现
+pub trait ProxyEventEmitter: Send + Sync {
+    fn emit_request_log(&self, log: &ProxyRequestLog);
+}
+
+impl ProxyEventEmitter for tauri::AppHandle {
+    fn emit_request_log(&self, log: &ProxyRequestLog) {
+        let _ = self.emit("proxy://request", log);
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProxyRequestLog {
     pub id: String,
@@ -15,6 +29,10 @@ pub struct ProxyRequestLog {
     pub model: Option<String>,        // 客户端请求的模型名
     pub mapped_model: Option<String>, // 实际路由后使用的模型名
     pub account_email: Option<String>,
+    #[serde(default)]
+    pub api_key_name: Option<String>, // 发起请求的客户端密钥名称
+    #[serde(default)]
+    pub fallback_from: Option<String>, // 触发模型回退时的原始映射模型
     pub error: Option<String>,
     pub request_body: Option<String>,
     pub response_body: Option<String>,
@@ -34,11 +52,16 @@ pub struct ProxyMonitor {
     pub stats: RwLock<ProxyStats>,
     pub max_logs: usize,
     pub enabled: AtomicBool,
-    app_handle: Option<tauri::AppHandle>,
+    pub metrics: crate::proxy::metrics::ProxyMetrics,
+    /// v1internal 端点健康状态 (与 UpstreamClient 共享)
+    pub endpoints: Arc<crate::proxy::upstream::health::EndpointPool>,
+    /// access_token 后台刷新器及各账号刷新状态
+    pub token_refresh: Arc<crate::proxy::token_refresh::TokenRefresher>,
+    emitter: Option<Arc<dyn ProxyEventEmitter>>,
 }
 
 impl ProxyMonitor {
-    pub fn new(max_logs: usize, app_handle: Option<tauri::AppHandle>) -> Self {
+    pub fn new(max_logs: usize, emitter: Option<Arc<dyn ProxyEventEmitter>>) -> Self {
         // Initialize DB
         if let Err(e) = crate::modules::proxy_db::init_db() {
             tracing::error!("Failed to initialize proxy DB: {}", e);
@@ -63,7 +86,10 @@ impl ProxyMonitor {
             stats: RwLock::new(ProxyStats::default()),
             max_logs,
             enabled: AtomicBool::new(false), // Default to disabled
-            app_handle,
+            metrics: crate::proxy::metrics::ProxyMetrics::new(),
+            endpoints: Arc::new(crate::proxy::upstream::health::EndpointPool::default()),
+            token_refresh: Arc::new(crate::proxy::token_refresh::TokenRefresher::default()),
+            emitter,
         }
     }
 
@@ -75,7 +101,24 @@ impl ProxyMonitor {
         self.enabled.load(Ordering::Relaxed)
     }
 
+    /// 记录请求: 累加 Prometheus 指标 (`model_label` / `mapped_model_label` 为指标使用的模型标签) 并写入请求日志
+    pub async fn record(&self, route: &str, model_label: &str, mapped_model_label: &str, log: ProxyRequestLog) {
+        self.metrics.observe_request(route, model_label, mapped_model_label, &log);
+        self.log_request(log).await;
+    }
+
     pub async fn log_request(&self, log: ProxyRequestLog) {
+        // 密钥用量计量不受监控开关影响 (预算依赖该数据)
+        if let Some(key_name) = log.api_key_name.clone() {
+            let input = log.input_tokens.unwrap_or(0) as u64;
+            let output = log.output_tokens.unwrap_or(0) as u64;
+            tokio::spawn(async move {
+                if let Err(e) = crate::proxy::usage::record_usage(&key_name, input, output) {
+                    tracing::error!("Failed to record API key usage: {}", e);
+                }
+            });
+        }
+
         if !self.is_enabled() {
             return;
         }
@@ -109,8 +152,8 @@ impl ProxyMonitor {
         });
 
         // Emit event
-        if let Some(app) = &self.app_handle {
-             let _ = app.emit("proxy://request", &log);
+        if let Some(emitter) = &self.emitter {
+            emitter.emit_request_log(&log);
         }
     }
 
@@ -0,0 +1,345 @@
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Response},
+};
+use bytes::Bytes;
+use futures::StreamExt;
+use serde_json::Value;
+use tokio::time::Duration;
+
+use super::Provider;
+use crate::proxy::common::protocol_error::{error_response, ClientProtocol};
+use crate::proxy::config::ProviderProtocol;
+use crate::proxy::mappers::openai::{anthropic, OpenAIRequest};
+use crate::proxy::server::AppState;
+
+/// 客户端未携带 anthropic-version 时使用的默认版本
+const DEFAULT_ANTHROPIC_VERSION: &str = "2023-06-01";
+
+fn join_base_url(base: &str, path: &str) -> Result<String, String> {
+    let base = base.trim_end_matches('/');
+    let path = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("/{}", path)
+    };
+    Ok(format!("{}{}", base, path))
+}
+
+fn build_client(
+    upstream_proxy: Option<crate::proxy::config::UpstreamProxyConfig>,
+    timeout_secs: u64,
+) -> Result<reqwest::Client, String> {
+    let mut builder = reqwest::Client::builder()
+        .timeout(Duration::from_secs(timeout_secs.max(5)));
+
+    if let Some(config) = upstream_proxy {
+        if config.enabled && !config.url.is_empty() {
+            let proxy = reqwest::Proxy::all(&config.url)
+                .map_err(|e| format!("Invalid upstream proxy url: {}", e))?;
+            builder = builder.proxy(proxy);
+        }
+    }
+
+    builder
+        .tcp_nodelay(true) // [FIX #307] Disable Nagle's algorithm to improve latency for small requests
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {}", e))
+}
+
+fn copy_passthrough_headers(incoming: &HeaderMap) -> HeaderMap {
+    // Only forward a conservative set of headers to avoid leaking the local proxy key or cookies.
+    let mut out = HeaderMap::new();
+
+    for (k, v) in incoming.iter() {
+        let key = k.as_str().to_ascii_lowercase();
+        match key.as_str() {
+            "content-type" | "accept" | "anthropic-version" | "user-agent" => {
+                out.insert(k.clone(), v.clone());
+            }
+            // Some clients use these for streaming; safe to pass through.
+            "accept-encoding" | "cache-control" => {
+                out.insert(k.clone(), v.clone());
+            }
+            _ => {}
+        }
+    }
+
+    out
+}
+
+fn set_anthropic_auth(headers: &mut HeaderMap, incoming: &HeaderMap, api_key: &str) {
+    // Prefer to keep the same auth scheme as the incoming request:
+    // - If the client used x-api-key (Anthropic style), replace it.
+    // - Else if it used Authorization, replace it with Bearer.
+    // - Else default to x-api-key.
+    let has_x_api_key = incoming.contains_key("x-api-key");
+    let has_auth = incoming.contains_key(header::AUTHORIZATION);
+
+    if has_x_api_key || !has_auth {
+        if let Ok(v) = HeaderValue::from_str(api_key) {
+            headers.insert("x-api-key", v);
+        }
+    }
+
+    if has_auth {
+        if let Ok(v) = HeaderValue::from_str(&format!("Bearer {}", api_key)) {
+            headers.insert(header::AUTHORIZATION, v);
+        }
+    }
+}
+
+/// 按服务商协议设置认证头 (未配置 api_key 时不附加)
+fn set_provider_auth(headers: &mut HeaderMap, incoming: &HeaderMap, provider: &Provider) {
+    let api_key = provider.config.api_key.trim();
+    if api_key.is_empty() {
+        return;
+    }
+    match provider.config.protocol {
+        ProviderProtocol::Anthropic => set_anthropic_auth(headers, incoming, api_key),
+        ProviderProtocol::OpenAI => {
+            if let Ok(v) = HeaderValue::from_str(&format!("Bearer {}", api_key)) {
+                headers.insert(header::AUTHORIZATION, v);
+            }
+        }
+        ProviderProtocol::Gemini => {
+            if let Ok(v) = HeaderValue::from_str(api_key) {
+                headers.insert("x-goog-api-key", v);
+            }
+        }
+    }
+}
+
+/// Recursively remove cache_control from all nested objects/arrays
+/// [FIX #290] This is a defensive fix that works regardless of serde annotations
+pub fn deep_remove_cache_control(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            map.remove("cache_control");
+            for v in map.values_mut() {
+                deep_remove_cache_control(v);
+            }
+        }
+        Value::Array(arr) => {
+            for v in arr {
+                deep_remove_cache_control(v);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// 转发 JSON 请求到服务商 (请求体中的 `model` 按服务商映射替换)
+pub async fn forward_json(
+    state: &AppState,
+    provider: &Provider,
+    method: Method,
+    path: &str,
+    incoming_headers: &HeaderMap,
+    mut body: Value,
+) -> Response {
+    let mapped_model = body
+        .get("model")
+        .and_then(|v| v.as_str())
+        .map(|model| provider.map_model(model));
+    if let Some(mapped) = &mapped_model {
+        body["model"] = Value::String(mapped.clone());
+    }
+
+    if provider.config.protocol == ProviderProtocol::Anthropic {
+        // [FIX #290] Clean cache_control before sending to Anthropic API
+        // This prevents "Extra inputs are not permitted" errors
+        deep_remove_cache_control(&mut body);
+    }
+
+    send_json(state, provider, method, path, incoming_headers, body, mapped_model).await
+}
+
+/// 转发 Gemini generateContent / streamGenerateContent 请求 (模型位于路径中)
+pub async fn forward_gemini(
+    state: &AppState,
+    provider: &Provider,
+    model: &str,
+    method: &str,
+    incoming_headers: &HeaderMap,
+    body: Value,
+) -> Response {
+    let mapped = provider.map_model(model);
+    let mut path = format!("/v1beta/models/{}:{}", mapped, method);
+    if method == "streamGenerateContent" {
+        path.push_str("?alt=sse");
+    }
+    send_json(state, provider, Method::POST, &path, incoming_headers, body, Some(mapped)).await
+}
+
+/// 发送请求到服务商，失败时返回可直接交给客户端的错误响应
+async fn send_request(
+    state: &AppState,
+    provider: &Provider,
+    method: Method,
+    path: &str,
+    incoming_headers: &HeaderMap,
+    body: &Value,
+) -> Result<reqwest::Response, Response> {
+    let url = join_base_url(&provider.config.base_url, path)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e).into_response())?;
+
+    let timeout_secs = state.request_timeout.max(5);
+    let upstream_proxy = state.upstream_proxy.read().await.clone();
+    let client = build_client(Some(upstream_proxy), timeout_secs)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e).into_response())?;
+
+    let mut headers = copy_passthrough_headers(incoming_headers);
+    set_provider_auth(&mut headers, incoming_headers, provider);
+
+    // Ensure JSON content type.
+    headers
+        .entry(header::CONTENT_TYPE)
+        .or_insert(HeaderValue::from_static("application/json"));
+    if provider.config.protocol == ProviderProtocol::Anthropic {
+        headers
+            .entry("anthropic-version")
+            .or_insert(HeaderValue::from_static(DEFAULT_ANTHROPIC_VERSION));
+    }
+
+    // [FIX #307] Explicitly serialize body to Vec<u8> to ensure Content-Length is set correctly.
+    // This avoids "Transfer-Encoding: chunked" for small bodies which caused connection errors.
+    let body_bytes = serde_json::to_vec(body).unwrap_or_default();
+    let body_len = body_bytes.len();
+
+    tracing::debug!(
+        "Forwarding request to provider {} (len: {} bytes): {}",
+        provider.id(),
+        body_len,
+        url
+    );
+
+    let req = client.request(method, &url)
+        .headers(headers)
+        .body(body_bytes); // Use .body(Vec<u8>) instead of .json()
+
+    req.send().await.map_err(|e| {
+        (
+            StatusCode::BAD_GATEWAY,
+            format!("Upstream request failed: {}", e),
+        )
+            .into_response()
+    })
+}
+
+/// 监控日志: 以 provider:{id} 记录实际服务方
+fn provider_response(provider: &Provider, status: StatusCode, mapped_model: Option<&str>) -> axum::http::response::Builder {
+    let mut out = Response::builder()
+        .status(status)
+        .header("X-Account-Email", format!("provider:{}", provider.id()));
+    if let Some(mapped) = mapped_model {
+        out = out.header("X-Mapped-Model", mapped);
+    }
+    out
+}
+
+async fn send_json(
+    state: &AppState,
+    provider: &Provider,
+    method: Method,
+    path: &str,
+    incoming_headers: &HeaderMap,
+    body: Value,
+    mapped_model: Option<String>,
+) -> Response {
+    let resp = match send_request(state, provider, method, path, incoming_headers, &body).await {
+        Ok(r) => r,
+        Err(response) => return response,
+    };
+
+    let status = StatusCode::from_u16(resp.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+
+    let mut out = provider_response(provider, status, mapped_model.as_deref());
+    if let Some(ct) = resp.headers().get(header::CONTENT_TYPE) {
+        out = out.header(header::CONTENT_TYPE, ct.clone());
+    }
+
+    // Stream response body to the client (covers SSE and non-SSE).
+    let stream = resp.bytes_stream().map(|chunk| match chunk {
+        Ok(b) => Ok::<Bytes, std::io::Error>(b),
+        Err(e) => Ok(Bytes::from(format!("Upstream stream error: {}", e))),
+    });
+
+    out.body(Body::from_stream(stream)).unwrap_or_else(|_| {
+        (StatusCode::INTERNAL_SERVER_ERROR, "Failed to build response").into_response()
+    })
+}
+
+/// OpenAI Chat 请求转换为 Anthropic Messages 后转发，响应 (含流式) 转换回 OpenAI 格式
+pub async fn forward_openai_chat_to_anthropic(
+    state: &AppState,
+    provider: &Provider,
+    body: Value,
+) -> Response {
+    let request: OpenAIRequest = match serde_json::from_value(body) {
+        Ok(r) => r,
+        Err(e) => return (StatusCode::BAD_REQUEST, format!("Invalid request: {}", e)).into_response(),
+    };
+    let mapped_model = provider.map_model(&request.model);
+    let anthropic_body = anthropic::openai_to_anthropic_request(&request, &mapped_model);
+
+    // 客户端请求头属于 OpenAI 协议，不透传 (认证默认使用 x-api-key)
+    let resp = match send_request(
+        state,
+        provider,
+        Method::POST,
+        "/v1/messages",
+        &HeaderMap::new(),
+        &anthropic_body,
+    )
+    .await
+    {
+        Ok(r) => r,
+        Err(response) => return response,
+    };
+
+    let status = StatusCode::from_u16(resp.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+    if !status.is_success() {
+        let error_text = resp.text().await.unwrap_or_default();
+        tracing::warn!("[Provider] {} 返回错误 {}: {}", provider.id(), status, error_text);
+        // Anthropic 错误体 ({"type":"error","error":{...}}) 转为 OpenAI 错误格式
+        let message = serde_json::from_str::<Value>(&error_text)
+            .ok()
+            .and_then(|v| v.pointer("/error/message").and_then(|m| m.as_str()).map(str::to_string))
+            .unwrap_or(error_text);
+        return error_response(ClientProtocol::OpenAI, status, &message, None);
+    }
+
+    let out = provider_response(provider, status, Some(&mapped_model));
+    if request.stream {
+        let stream = anthropic::create_openai_sse_from_anthropic(
+            Box::pin(resp.bytes_stream()),
+            request.model.clone(),
+            request.include_stream_usage(),
+        );
+        return out
+            .header(header::CONTENT_TYPE, "text/event-stream")
+            .header(header::CACHE_CONTROL, "no-cache")
+            .body(Body::from_stream(stream))
+            .unwrap_or_else(|_| {
+                (StatusCode::INTERNAL_SERVER_ERROR, "Failed to build response").into_response()
+            });
+    }
+
+    match resp.json::<Value>().await {
+        Ok(json) => {
+            let openai = anthropic::anthropic_to_openai_response(&json, &request.model);
+            out.header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(openai.to_string()))
+                .unwrap_or_else(|_| {
+                    (StatusCode::INTERNAL_SERVER_ERROR, "Failed to build response").into_response()
+                })
+        }
+        Err(e) => (
+            StatusCode::BAD_GATEWAY,
+            format!("Failed to parse provider response: {}", e),
+        )
+            .into_response(),
+    }
+}
@@ -1,2 +1,271 @@
-pub mod zai_anthropic;
+// 上游服务商 (Anthropic / OpenAI / Gemini 兼容)
+// z.ai 与自定义服务商统一抽象为 Provider，由 ProviderRegistry 按调度方式与 Google 账号池分流
 
+pub mod forward;       // 通用请求转发
+pub mod zai_anthropic; // z.ai 服务商实例
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::proxy::common::model_router::{ModelRouter, RouteContext};
+use crate::proxy::config::{ProviderDispatchMode, ProviderProtocol, UpstreamProviderConfig, ZaiConfig};
+
+pub struct Provider {
+    pub config: UpstreamProviderConfig,
+    /// model_mapping 编译后的路由表 (精确 > 通配符)
+    router: ModelRouter,
+}
+
+impl Provider {
+    pub fn new(config: UpstreamProviderConfig) -> Self {
+        let router = ModelRouter::new(&[], &config.model_mapping);
+        Self { config, router }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.config.id
+    }
+
+    /// 入站模型 -> 上游模型
+    ///
+    /// 优先级: `{id}:` 前缀直通 > model_mapping (先原样后小写) > 原样转发
+    pub fn map_model(&self, model: &str) -> String {
+        let prefix = format!("{}:", self.config.id);
+        if let Some(head) = model.get(..prefix.len()) {
+            if head.eq_ignore_ascii_case(&prefix) && model.len() > prefix.len() {
+                return model[prefix.len()..].to_string();
+            }
+        }
+
+        let ctx = RouteContext::default();
+        let lower = model.to_lowercase();
+        self.router
+            .route(model, &ctx)
+            .or_else(|| self.router.route(&lower, &ctx))
+            .map(|m| m.target)
+            .unwrap_or_else(|| model.to_string())
+    }
+}
+
+/// 按调度方式选择服务商，返回 None 表示交给 Google 账号池处理
+///
+/// - Exclusive: 始终使用该服务商
+/// - 账号池为空: 使用任一启用的服务商
+/// - Weighted: `roll` (0-99) 落在权重区间内时使用
+/// - Pooled: 每个服务商在账号池中占一个轮询槽位
+pub fn select_provider(
+    candidates: &[Arc<Provider>],
+    google_accounts: usize,
+    slot: usize,
+    roll: u32,
+) -> Option<Arc<Provider>> {
+    let by_mode = |mode: ProviderDispatchMode| {
+        candidates
+            .iter()
+            .filter(move |p| p.config.dispatch_mode == mode)
+    };
+
+    if let Some(provider) = by_mode(ProviderDispatchMode::Exclusive).next() {
+        return Some(provider.clone());
+    }
+    if google_accounts == 0 {
+        return candidates.first().cloned();
+    }
+
+    let mut cumulative = 0;
+    for provider in by_mode(ProviderDispatchMode::Weighted) {
+        cumulative += provider.config.weight.min(100);
+        if roll < cumulative {
+            return Some(provider.clone());
+        }
+    }
+
+    let pooled: Vec<_> = by_mode(ProviderDispatchMode::Pooled).collect();
+    if !pooled.is_empty() {
+        let slot = slot % (google_accounts + pooled.len());
+        if slot < pooled.len() {
+            return Some(pooled[slot].clone());
+        }
+    }
+    None
+}
+
+/// 已启用的上游服务商 (z.ai 在前，其余按配置顺序)
+#[derive(Default)]
+pub struct ProviderRegistry {
+    providers: Vec<Arc<Provider>>,
+}
+
+impl ProviderRegistry {
+    pub fn from_config(zai: &ZaiConfig, providers: &[UpstreamProviderConfig]) -> Self {
+        let mut list: Vec<Arc<Provider>> = Vec::new();
+        for config in std::iter::once(zai_anthropic::provider_config(zai)).chain(providers.iter().cloned()) {
+            if !config.is_active() {
+                continue;
+            }
+            if list.iter().any(|p| p.id() == config.id) {
+                tracing::warn!("[Provider] 服务商 id '{}' 重复，已忽略", config.id);
+                continue;
+            }
+            list.push(Arc::new(Provider::new(config)));
+        }
+        Self { providers: list }
+    }
+
+    /// 可服务该请求的服务商 (按配置顺序)
+    ///
+    /// `protocols[0]` 为请求的原生协议，其余协议的服务商需开启 `cross_protocol` 才会承接 (经协议转换)
+    fn serving<'a>(&'a self, protocols: &'a [ProviderProtocol]) -> impl Iterator<Item = &'a Arc<Provider>> + 'a {
+        self.providers.iter().filter(move |p| {
+            let protocol = p.config.protocol;
+            protocols.first() == Some(&protocol)
+                || (p.config.cross_protocol && protocols.contains(&protocol))
+        })
+    }
+
+    /// 在可服务该请求的协议中选择服务商，None 表示走 Google 账号池
+    pub fn select(
+        &self,
+        protocols: &[ProviderProtocol],
+        google_accounts: usize,
+        rr: &AtomicUsize,
+    ) -> Option<Arc<Provider>> {
+        let candidates: Vec<_> = self.serving(protocols).cloned().collect();
+        if candidates.is_empty() {
+            return None;
+        }
+
+        let slot = rr.fetch_add(1, Ordering::Relaxed);
+        let roll = rand::thread_rng().gen_range(0..100);
+        let selected = select_provider(&candidates, google_accounts, slot, roll);
+        if let Some(provider) = &selected {
+            tracing::debug!("[Provider] 请求分流到服务商 {}", provider.id());
+        }
+        selected
+    }
+
+    /// 是否有 Exclusive 服务商接管这些协议的全部请求 (此时不经过 Google 账号池)
+    pub fn has_exclusive(&self, protocols: &[ProviderProtocol]) -> bool {
+        self.serving(protocols)
+            .any(|p| p.config.dispatch_mode == ProviderDispatchMode::Exclusive)
+    }
+
+    /// 不推进轮询、不参与权重抽样的查询 (用于 count_tokens 等辅助请求)
+    ///
+    /// 仅返回必然接管的服务商: Exclusive 服务商，或账号池为空时的首个服务商
+    pub fn peek(&self, protocols: &[ProviderProtocol], google_accounts: usize) -> Option<Arc<Provider>> {
+        let mut serving = self.serving(protocols);
+        if google_accounts == 0 {
+            return serving.next().cloned();
+        }
+        serving
+            .find(|p| p.config.dispatch_mode == ProviderDispatchMode::Exclusive)
+            .cloned()
+    }
+
+    /// 账号池耗尽 (全部限流 / 失败) 时接管请求的 Fallback 服务商
+    pub fn fallback(&self, protocols: &[ProviderProtocol]) -> Option<Arc<Provider>> {
+        self.serving(protocols)
+            .find(|p| p.config.dispatch_mode == ProviderDispatchMode::Fallback)
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn provider(id: &str, mode: ProviderDispatchMode, weight: u32) -> Arc<Provider> {
+        Arc::new(Provider::new(UpstreamProviderConfig {
+            id: id.to_string(),
+            name: String::new(),
+            enabled: true,
+            base_url: "http://localhost:8000".to_string(),
+            api_key: String::new(),
+            protocol: ProviderProtocol::OpenAI,
+            dispatch_mode: mode,
+            weight,
+            model_mapping: HashMap::new(),
+            cross_protocol: false,
+        }))
+    }
+
+    #[test]
+    fn test_select_provider() {
+        let weighted = provider("vllm", ProviderDispatchMode::Weighted, 30);
+        let pooled = provider("overflow", ProviderDispatchMode::Pooled, 0);
+        let fallback = provider("backup", ProviderDispatchMode::Fallback, 0);
+        let candidates = vec![weighted, pooled, fallback.clone()];
+
+        assert_eq!(select_provider(&candidates, 2, 1, 10).unwrap().id(), "vllm");
+        assert!(select_provider(&candidates, 2, 1, 30).is_none());
+        assert_eq!(select_provider(&candidates, 2, 3, 99).unwrap().id(), "overflow");
+        // 账号池为空时任一服务商均可接管
+        assert_eq!(select_provider(&[fallback], 0, 0, 99).unwrap().id(), "backup");
+
+        let exclusive = provider("only", ProviderDispatchMode::Exclusive, 0);
+        assert_eq!(select_provider(&[exclusive], 5, 1, 99).unwrap().id(), "only");
+    }
+
+    #[test]
+    fn test_cross_protocol_requires_opt_in() {
+        let mut zai = ZaiConfig {
+            enabled: true,
+            api_key: "k".to_string(),
+            dispatch_mode: ProviderDispatchMode::Fallback,
+            ..Default::default()
+        };
+        let chat = [ProviderProtocol::OpenAI, ProviderProtocol::Anthropic];
+        let rr = AtomicUsize::new(0);
+
+        let registry = ProviderRegistry::from_config(&zai, &[]);
+        assert!(registry.fallback(&chat).is_none());
+        assert!(registry.select(&chat, 0, &rr).is_none());
+        assert_eq!(registry.fallback(&[ProviderProtocol::Anthropic]).unwrap().id(), "zai");
+
+        zai.cross_protocol = true;
+        let registry = ProviderRegistry::from_config(&zai, &[]);
+        assert_eq!(registry.fallback(&chat).unwrap().id(), "zai");
+    }
+
+    #[test]
+    fn test_peek_only_returns_certain_provider() {
+        let mut zai = ZaiConfig {
+            enabled: true,
+            api_key: "k".to_string(),
+            dispatch_mode: ProviderDispatchMode::Pooled,
+            ..Default::default()
+        };
+        let anthropic = [ProviderProtocol::Anthropic];
+
+        // Pooled 服务商只在生成请求的轮询中占槽位，count_tokens 走账号池
+        let registry = ProviderRegistry::from_config(&zai, &[]);
+        assert!(registry.peek(&anthropic, 2).is_none());
+        assert_eq!(registry.peek(&anthropic, 0).unwrap().id(), "zai");
+
+        zai.dispatch_mode = ProviderDispatchMode::Exclusive;
+        let registry = ProviderRegistry::from_config(&zai, &[]);
+        assert_eq!(registry.peek(&anthropic, 2).unwrap().id(), "zai");
+    }
+
+    #[test]
+    fn test_zai_model_mapping() {
+        let mut zai = ZaiConfig {
+            enabled: true,
+            dispatch_mode: ProviderDispatchMode::Exclusive,
+            ..Default::default()
+        };
+        zai.models.haiku = "glm-4.5-air".to_string();
+        zai.model_mapping.insert("claude-sonnet-4-5".to_string(), "glm-4.6".to_string());
+
+        let zai = Provider::new(zai_anthropic::provider_config(&zai));
+        assert_eq!(zai.map_model("claude-sonnet-4-5"), "glm-4.6");
+        assert_eq!(zai.map_model("claude-3-5-haiku-20241022"), "glm-4.5-air");
+        assert_eq!(zai.map_model("Claude-Opus-4-5"), "glm-4.7");
+        assert_eq!(zai.map_model("zai:glm-4.5"), "glm-4.5");
+        assert_eq!(zai.map_model("glm-4.6"), "glm-4.6");
+    }
+}
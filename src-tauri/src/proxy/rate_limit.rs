@@ -1,9 +1,15 @@
 use dashmap::DashMap;
-use std::time::{SystemTime, Duration};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, Duration, UNIX_EPOCH};
 use regex::Regex;
 
 /// 限流原因类型
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum RateLimitReason {
     /// 配额耗尽 (QUOTA_EXHAUSTED)
     QuotaExhausted,
@@ -17,6 +23,19 @@ pub enum RateLimitReason {
     Unknown,
 }
 
+impl RateLimitReason {
+    /// 用于指标标签 / 日志的稳定名称
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::QuotaExhausted => "quota_exhausted",
+            Self::RateLimitExceeded => "rate_limit_exceeded",
+            Self::ModelCapacityExhausted => "model_capacity_exhausted",
+            Self::ServerError => "server_error",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
 /// 限流信息
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
@@ -36,20 +55,212 @@ pub struct RateLimitInfo {
     pub model: Option<String>,
 }
 
+/// 持久化到磁盘的限流状态，代理重启后恢复
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PersistedState {
+    #[serde(default)]
+    lockouts: Vec<PersistedLockout>,
+    #[serde(default)]
+    failure_counts: HashMap<String, u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PersistedLockout {
+    account: String,
+    #[serde(default)]
+    model: Option<String>,
+    reason: RateLimitReason,
+    /// 重置时间 (Unix 秒)
+    reset_at: u64,
+    /// 检测时间 (Unix 秒)
+    detected_at: u64,
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// 限流状态文件写入器
+///
+/// 请求路径上只提交最新快照，由 blocking 线程写盘；写入期间的多次提交合并为一次 (只写最新状态)
+struct PersistWriter {
+    path: PathBuf,
+    pending: Mutex<Option<PersistedState>>,
+    writing: AtomicBool,
+}
+
+impl PersistWriter {
+    fn submit(self: &Arc<Self>, state: PersistedState) {
+        *self.pending.lock().unwrap_or_else(|e| e.into_inner()) = Some(state);
+        if self.writing.swap(true, Ordering::AcqRel) {
+            return; // 正在写入，完成后会取走最新快照
+        }
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                let writer = self.clone();
+                handle.spawn_blocking(move || writer.drain());
+            }
+            // 无运行时 (启动阶段 / 测试) 时同步写入
+            Err(_) => self.drain(),
+        }
+    }
+
+    fn drain(&self) {
+        loop {
+            let next = self.pending.lock().unwrap_or_else(|e| e.into_inner()).take();
+            match next {
+                Some(state) => self.write(&state),
+                None => {
+                    self.writing.store(false, Ordering::Release);
+                    // 释放写入标记前后可能有新的提交
+                    let has_pending = self.pending.lock().unwrap_or_else(|e| e.into_inner()).is_some();
+                    if !has_pending || self.writing.swap(true, Ordering::AcqRel) {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// 先写临时文件再替换
+    fn write(&self, state: &PersistedState) {
+        let tmp = self.path.with_extension("json.tmp");
+        let result = serde_json::to_string_pretty(state)
+            .map_err(|e| e.to_string())
+            .and_then(|content| {
+                std::fs::write(&tmp, content)
+                    .and_then(|_| std::fs::rename(&tmp, &self.path))
+                    .map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            tracing::warn!("[RateLimit] 保存限流状态失败 {:?}: {}", self.path, e);
+        }
+    }
+}
+
 /// 限流跟踪器
 pub struct RateLimitTracker {
     limits: DashMap<String, RateLimitInfo>,
+    /// 模型级别限流 (账号, 模型)，不影响该账号的其他模型
+    model_limits: DashMap<(String, String), RateLimitInfo>,
     /// 连续失败计数（用于智能指数退避）
     failure_counts: DashMap<String, u32>,
+    /// 持久化文件路径 (None 表示仅保存在内存中)
+    persist_path: Option<PathBuf>,
+    /// 后台写入器 (与 persist_path 同时存在)
+    writer: Option<Arc<PersistWriter>>,
 }
 
 impl RateLimitTracker {
     pub fn new() -> Self {
         Self {
             limits: DashMap::new(),
+            model_limits: DashMap::new(),
             failure_counts: DashMap::new(),
+            persist_path: None,
+            writer: None,
+        }
+    }
+
+    /// 创建持久化到 `path` 的跟踪器，并恢复文件中尚未过期的锁定与失败计数
+    pub fn with_persistence(path: PathBuf) -> Self {
+        let tracker = Self {
+            writer: Some(Arc::new(PersistWriter {
+                path: path.clone(),
+                pending: Mutex::new(None),
+                writing: AtomicBool::new(false),
+            })),
+            persist_path: Some(path),
+            ..Self::new()
+        };
+        tracker.restore();
+        tracker
+    }
+
+    /// 从持久化文件恢复状态，过期的锁定在加载时清理
+    fn restore(&self) {
+        let Some(path) = &self.persist_path else {
+            return;
+        };
+        let Ok(content) = std::fs::read_to_string(path) else {
+            return; // 文件不存在 (首次启动)
+        };
+        let state: PersistedState = match serde_json::from_str(&content) {
+            Ok(state) => state,
+            Err(e) => {
+                tracing::warn!("[RateLimit] 解析限流状态文件失败 {:?}: {}", path, e);
+                return;
+            }
+        };
+
+        let now = SystemTime::now();
+        let mut restored = 0;
+        let mut pruned = 0;
+        for lockout in state.lockouts {
+            let reset_time = UNIX_EPOCH + Duration::from_secs(lockout.reset_at);
+            if reset_time <= now {
+                pruned += 1;
+                continue;
+            }
+            let detected_at = UNIX_EPOCH + Duration::from_secs(lockout.detected_at);
+            self.insert(
+                &lockout.account,
+                RateLimitInfo {
+                    reset_time,
+                    retry_after_sec: lockout.reset_at.saturating_sub(lockout.detected_at),
+                    detected_at,
+                    reason: lockout.reason,
+                    model: lockout.model,
+                },
+            );
+            restored += 1;
+        }
+        for (account, count) in state.failure_counts {
+            self.failure_counts.insert(account, count);
+        }
+
+        tracing::info!(
+            "[RateLimit] 已恢复 {} 条限流记录、{} 个失败计数 (清理过期记录 {} 条)",
+            restored,
+            self.failure_counts.len(),
+            pruned
+        );
+        if pruned > 0 {
+            self.persist();
         }
     }
+
+    /// 将当前生效的锁定与失败计数提交给后台写入器 (不在调用方线程上写盘)
+    fn persist(&self) {
+        let Some(writer) = &self.writer else {
+            return;
+        };
+
+        let now = SystemTime::now();
+        let lockouts = self
+            .limits
+            .iter()
+            .map(|e| (e.key().clone(), e.value().clone()))
+            .chain(self.model_limits.iter().map(|e| (e.key().0.clone(), e.value().clone())))
+            .filter(|(_, info)| info.reset_time > now)
+            .map(|(account, info)| PersistedLockout {
+                account,
+                model: info.model,
+                reason: info.reason,
+                reset_at: unix_secs(info.reset_time),
+                detected_at: unix_secs(info.detected_at),
+            })
+            .collect();
+        let state = PersistedState {
+            lockouts,
+            failure_counts: self
+                .failure_counts
+                .iter()
+                .map(|e| (e.key().clone(), *e.value()))
+                .collect(),
+        };
+        writer.submit(state);
+    }
     
     /// 获取账号剩余的等待时间(秒)
     pub fn get_remaining_wait(&self, account_id: &str) -> u64 {
@@ -67,11 +278,15 @@ impl RateLimitTracker {
     /// 当账号成功完成请求后调用此方法，将其失败计数归零，
     /// 这样下次失败时会从最短的锁定时间（60秒）开始。
     pub fn mark_success(&self, account_id: &str) {
-        if self.failure_counts.remove(account_id).is_some() {
+        let had_failures = self.failure_counts.remove(account_id).is_some();
+        if had_failures {
             tracing::debug!("账号 {} 请求成功，已重置失败计数", account_id);
         }
         // 同时清除限流记录（如果有）
-        self.limits.remove(account_id);
+        let had_limit = self.limits.remove(account_id).is_some();
+        if had_failures || had_limit {
+            self.persist();
+        }
     }
     
     /// 精确锁定账号到指定时间点
@@ -96,7 +311,7 @@ impl RateLimitTracker {
             model: model.clone(),  // 🆕 支持模型级别限流
         };
         
-        self.limits.insert(account_id.to_string(), info);
+        self.store(account_id, info);
         
         if let Some(m) = &model {
             tracing::info!(
@@ -252,8 +467,8 @@ impl RateLimitTracker {
             model,
         };
         
-        // 存储
-        self.limits.insert(account_id.to_string(), info.clone());
+        // 存储 (携带 model 时为模型级别限流)
+        self.store(account_id, info.clone());
         
         tracing::warn!(
             "账号 {} [{}] 限流类型: {:?}, 重置延时: {}秒",
@@ -436,6 +651,40 @@ impl RateLimitTracker {
         None
     }
     
+    /// 写入限流表并持久化
+    fn store(&self, account_id: &str, info: RateLimitInfo) {
+        self.insert(account_id, info);
+        self.persist();
+    }
+
+    /// 按 info.model 写入账号级别或模型级别限流表
+    fn insert(&self, account_id: &str, info: RateLimitInfo) {
+        match info.model.clone() {
+            Some(model) => {
+                self.model_limits.insert((account_id.to_string(), model), info);
+            }
+            None => {
+                self.limits.insert(account_id.to_string(), info);
+            }
+        }
+    }
+
+    /// 检查账号的指定模型是否处于模型级别限流中 (不含账号级别限流)
+    pub fn is_model_rate_limited(&self, account_id: &str, model: &str) -> bool {
+        self.model_limits
+            .get(&(account_id.to_string(), model.to_string()))
+            .map(|info| info.reset_time > SystemTime::now())
+            .unwrap_or(false)
+    }
+
+    /// 账号的指定模型距模型级别限流重置还有多少秒
+    pub fn get_model_reset_seconds(&self, account_id: &str, model: &str) -> Option<u64> {
+        self.model_limits
+            .get(&(account_id.to_string(), model.to_string()))
+            .and_then(|info| info.reset_time.duration_since(SystemTime::now()).ok())
+            .map(|d| d.as_secs())
+    }
+
     /// 获取账号的限流信息
     pub fn get(&self, account_id: &str) -> Option<RateLimitInfo> {
         self.limits.get(account_id).map(|r| r.clone())
@@ -462,6 +711,23 @@ impl RateLimitTracker {
         }
     }
     
+    /// 统计当前生效的锁定数量，按 (原因, 是否模型级别) 分组
+    pub fn active_lockout_counts(&self) -> HashMap<(RateLimitReason, bool), usize> {
+        let now = SystemTime::now();
+        let mut counts = HashMap::new();
+        let infos = self
+            .limits
+            .iter()
+            .map(|e| e.value().clone())
+            .chain(self.model_limits.iter().map(|e| e.value().clone()));
+        for info in infos {
+            if info.reset_time > now {
+                *counts.entry((info.reason, info.model.is_some())).or_insert(0) += 1;
+            }
+        }
+        counts
+    }
+
     /// 清除过期的限流记录
     #[allow(dead_code)]
     pub fn cleanup_expired(&self) -> usize {
@@ -476,9 +742,18 @@ impl RateLimitTracker {
                 true
             }
         });
+        self.model_limits.retain(|_k, v| {
+            if v.reset_time <= now {
+                count += 1;
+                false
+            } else {
+                true
+            }
+        });
         
         if count > 0 {
             tracing::debug!("清除了 {} 个过期的限流记录", count);
+            self.persist();
         }
         
         count
@@ -487,7 +762,11 @@ impl RateLimitTracker {
     /// 清除指定账号的限流记录
     #[allow(dead_code)]
     pub fn clear(&self, account_id: &str) -> bool {
-        self.limits.remove(account_id).is_some()
+        let removed = self.limits.remove(account_id).is_some();
+        if removed {
+            self.persist();
+        }
+        removed
     }
     
     /// 清除所有限流记录 (乐观重置策略)
@@ -495,8 +774,10 @@ impl RateLimitTracker {
     /// 用于乐观重置机制,当所有账号都被限流但等待时间很短时,
     /// 清除所有限流记录以解决时序竞争条件
     pub fn clear_all(&self) {
-        let count = self.limits.len();
+        let count = self.limits.len() + self.model_limits.len();
         self.limits.clear();
+        self.model_limits.clear();
+        self.persist();
         tracing::warn!("🔄 Optimistic reset: Cleared all {} rate limit record(s)", count);
     }
 }
@@ -511,6 +792,23 @@ impl Default for RateLimitTracker {
 mod tests {
     use super::*;
     
+    #[test]
+    fn test_active_lockout_counts_by_reason() {
+        let tracker = RateLimitTracker::new();
+        let future = SystemTime::now() + Duration::from_secs(600);
+        let past = SystemTime::now() - Duration::from_secs(10);
+        tracker.set_lockout_until("a", future, RateLimitReason::QuotaExhausted, None);
+        tracker.set_lockout_until("b", future, RateLimitReason::QuotaExhausted, Some("gemini-3-pro-high".to_string()));
+        tracker.set_lockout_until("c", future, RateLimitReason::RateLimitExceeded, None);
+        tracker.set_lockout_until("d", past, RateLimitReason::ServerError, None);
+
+        let counts = tracker.active_lockout_counts();
+        assert_eq!(counts.get(&(RateLimitReason::QuotaExhausted, false)), Some(&1));
+        assert_eq!(counts.get(&(RateLimitReason::QuotaExhausted, true)), Some(&1));
+        assert_eq!(counts.get(&(RateLimitReason::RateLimitExceeded, false)), Some(&1));
+        assert!(!counts.contains_key(&(RateLimitReason::ServerError, false)));
+    }
+
     #[test]
     fn test_parse_retry_time_minutes_seconds() {
         let tracker = RateLimitTracker::new();
@@ -572,4 +870,46 @@ mod tests {
         // 应该被识别为 RateLimitExceeded，而不是 QuotaExhausted
         assert_eq!(reason, RateLimitReason::RateLimitExceeded);
     }
+
+    #[test]
+    fn test_model_lockout_does_not_block_account() {
+        let tracker = RateLimitTracker::new();
+        tracker.parse_from_error(
+            "acc1",
+            429,
+            Some("60"),
+            r#"{"error":{"details":[{"reason":"QUOTA_EXHAUSTED"}]}}"#,
+            Some("claude-opus-4-5-thinking".to_string()),
+        );
+        assert!(tracker.is_model_rate_limited("acc1", "claude-opus-4-5-thinking"));
+        assert!(!tracker.is_model_rate_limited("acc1", "gemini-3-pro-high"));
+        assert!(!tracker.is_rate_limited("acc1"));
+
+        tracker.clear_all();
+        assert!(!tracker.is_model_rate_limited("acc1", "claude-opus-4-5-thinking"));
+    }
+
+    #[test]
+    fn test_lockouts_persist_across_restart() {
+        let path = std::env::temp_dir().join(format!("rate_limits_{}.json", uuid::Uuid::new_v4()));
+        {
+            let tracker = RateLimitTracker::with_persistence(path.clone());
+            let future = SystemTime::now() + Duration::from_secs(3600);
+            tracker.set_lockout_until("a", future, RateLimitReason::QuotaExhausted, None);
+            tracker.set_lockout_until("b", future, RateLimitReason::QuotaExhausted, Some("gemini-3-pro-high".to_string()));
+            tracker.set_lockout_until("c", SystemTime::now() - Duration::from_secs(10), RateLimitReason::ServerError, None);
+            tracker.parse_from_error("d", 429, None, r#"{"error":{"details":[{"reason":"QUOTA_EXHAUSTED"}]}}"#, None);
+        }
+
+        let restored = RateLimitTracker::with_persistence(path.clone());
+        assert!(restored.is_rate_limited("a"));
+        assert_eq!(restored.get("a").unwrap().reason, RateLimitReason::QuotaExhausted);
+        assert!(restored.is_model_rate_limited("b", "gemini-3-pro-high"));
+        assert!(!restored.is_rate_limited("b"));
+        // 过期记录在加载时被清理
+        assert!(restored.get("c").is_none());
+        assert_eq!(restored.failure_counts.get("d").map(|c| *c), Some(1));
+
+        let _ = std::fs::remove_file(&path);
+    }
 }
@@ -1,9 +1,13 @@
-use crate::proxy::config::{ProxyAuthMode, ProxyConfig};
+use crate::proxy::config::{ClientApiKey, ProxyAuthMode, ProxyConfig};
+
+/// 主密钥 (ProxyConfig.api_key) 在日志中使用的名称
+pub const PRIMARY_API_KEY_NAME: &str = "default";
 
 #[derive(Debug, Clone)]
 pub struct ProxySecurityConfig {
     pub auth_mode: ProxyAuthMode,
     pub api_key: String,
+    pub api_keys: Vec<ClientApiKey>,
     pub allow_lan_access: bool,
 }
 
@@ -12,6 +16,7 @@ impl ProxySecurityConfig {
         Self {
             auth_mode: config.auth_mode.clone(),
             api_key: config.api_key.clone(),
+            api_keys: config.api_keys.clone(),
             allow_lan_access: config.allow_lan_access,
         }
     }
@@ -28,6 +33,37 @@ impl ProxySecurityConfig {
             ref other => other.clone(),
         }
     }
+
+    /// 是否配置了任何可用于认证的密钥
+    pub fn has_any_key(&self) -> bool {
+        !self.api_key.is_empty() || self.api_keys.iter().any(|k| !k.key.is_empty())
+    }
+
+    /// 根据客户端提交的密钥查找对应的 ClientApiKey
+    ///
+    /// 主密钥视为名为 `default` 的全权限密钥；不检查 enabled / 过期状态，由调用方决定如何处理
+    pub fn find_key(&self, presented: &str) -> Option<ClientApiKey> {
+        if presented.is_empty() {
+            return None;
+        }
+        if !self.api_key.is_empty() && presented == self.api_key {
+            return Some(ClientApiKey {
+                name: PRIMARY_API_KEY_NAME.to_string(),
+                key: self.api_key.clone(),
+                enabled: true,
+                scopes: Vec::new(),
+                allowed_models: Vec::new(),
+                expires_at: None,
+                daily_token_budget: None,
+                monthly_token_budget: None,
+                priority: 0,
+            });
+        }
+        self.api_keys
+            .iter()
+            .find(|k| !k.key.is_empty() && k.key == presented)
+            .cloned()
+    }
 }
 
 #[cfg(test)]
@@ -39,6 +75,7 @@ mod tests {
         let s = ProxySecurityConfig {
             auth_mode: ProxyAuthMode::Auto,
             api_key: "sk-test".to_string(),
+            api_keys: Vec::new(),
             allow_lan_access: false,
         };
         assert!(matches!(s.effective_auth_mode(), ProxyAuthMode::Off));
@@ -49,6 +86,7 @@ mod tests {
         let s = ProxySecurityConfig {
             auth_mode: ProxyAuthMode::Auto,
             api_key: "sk-test".to_string(),
+            api_keys: Vec::new(),
             allow_lan_access: true,
         };
         assert!(matches!(
@@ -56,5 +94,43 @@ mod tests {
             ProxyAuthMode::AllExceptHealth
         ));
     }
-}
 
+    fn named_key(name: &str, key: &str) -> ClientApiKey {
+        ClientApiKey {
+            name: name.to_string(),
+            key: key.to_string(),
+            enabled: true,
+            scopes: Vec::new(),
+            allowed_models: Vec::new(),
+            expires_at: None,
+            daily_token_budget: None,
+            monthly_token_budget: None,
+            priority: 0,
+        }
+    }
+
+    #[test]
+    fn find_key_resolves_primary_and_named_keys() {
+        let s = ProxySecurityConfig {
+            auth_mode: ProxyAuthMode::Strict,
+            api_key: "sk-main".to_string(),
+            api_keys: vec![named_key("ci", "sk-ci"), named_key("empty", "")],
+            allow_lan_access: false,
+        };
+        assert_eq!(s.find_key("sk-main").unwrap().name, PRIMARY_API_KEY_NAME);
+        assert_eq!(s.find_key("sk-ci").unwrap().name, "ci");
+        assert!(s.find_key("").is_none());
+        assert!(s.find_key("sk-other").is_none());
+    }
+
+    #[test]
+    fn named_keys_count_as_configured_without_primary() {
+        let s = ProxySecurityConfig {
+            auth_mode: ProxyAuthMode::Strict,
+            api_key: String::new(),
+            api_keys: vec![named_key("ci", "sk-ci")],
+            allow_lan_access: false,
+        };
+        assert!(s.has_any_key());
+    }
+}
@@ -3,7 +3,7 @@ use axum::{
     extract::DefaultBodyLimit,
     http::StatusCode,
     response::{IntoResponse, Json, Response},
-    routing::{any, get, post},
+    routing::{any, get, post, put},
     Router,
 };
 use std::sync::Arc;
@@ -17,7 +17,7 @@ use std::sync::atomic::AtomicUsize;
 #[derive(Clone)]
 pub struct AppState {
     pub token_manager: Arc<TokenManager>,
-    pub custom_mapping: Arc<tokio::sync::RwLock<std::collections::HashMap<String, String>>>,
+    pub model_router: Arc<tokio::sync::RwLock<crate::proxy::common::model_router::ModelRouter>>,
     #[allow(dead_code)]
     pub request_timeout: u64, // API 请求超时(秒)
     #[allow(dead_code)]
@@ -26,28 +26,40 @@ pub struct AppState {
     pub upstream_proxy: Arc<tokio::sync::RwLock<crate::proxy::config::UpstreamProxyConfig>>,
     pub upstream: Arc<crate::proxy::upstream::client::UpstreamClient>,
     pub zai: Arc<RwLock<crate::proxy::ZaiConfig>>,
+    pub providers: Arc<RwLock<crate::proxy::providers::ProviderRegistry>>,
     pub provider_rr: Arc<AtomicUsize>,
     pub zai_vision_mcp: Arc<crate::proxy::zai_vision_mcp::ZaiVisionMcpState>,
     pub monitor: Arc<crate::proxy::monitor::ProxyMonitor>,
     pub experimental: Arc<RwLock<crate::proxy::config::ExperimentalConfig>>,
+    pub security: Arc<RwLock<crate::proxy::ProxySecurityConfig>>,
+    pub model_catalog: Arc<crate::proxy::model_catalog::ModelCatalog>,
 }
 
 /// Axum 服务器实例
 pub struct AxumServer {
     shutdown_tx: Option<oneshot::Sender<()>>,
-    custom_mapping: Arc<tokio::sync::RwLock<std::collections::HashMap<String, String>>>,
+    model_router: Arc<tokio::sync::RwLock<crate::proxy::common::model_router::ModelRouter>>,
     proxy_state: Arc<tokio::sync::RwLock<crate::proxy::config::UpstreamProxyConfig>>,
     security_state: Arc<RwLock<crate::proxy::ProxySecurityConfig>>,
     zai_state: Arc<RwLock<crate::proxy::ZaiConfig>>,
+    providers_state: Arc<RwLock<crate::proxy::providers::ProviderRegistry>>,
+    endpoints: Arc<crate::proxy::upstream::health::EndpointPool>,
+    catalog_task: Option<tokio::task::JoinHandle<()>>,
+    token_refresh: Arc<crate::proxy::token_refresh::TokenRefresher>,
+    token_refresh_task: Option<tokio::task::JoinHandle<()>>,
+    session_flush_task: Option<tokio::task::JoinHandle<()>>,
+    response_cleanup_task: Option<tokio::task::JoinHandle<()>>,
+    token_manager: Arc<TokenManager>,
 }
 
 impl AxumServer {
     pub async fn update_mapping(&self, config: &crate::proxy::config::ProxyConfig) {
         {
-            let mut m = self.custom_mapping.write().await;
-            *m = config.custom_mapping.clone();
+            let mut router = self.model_router.write().await;
+            *router = crate::proxy::common::model_router::ModelRouter::from_config(config);
         }
-        tracing::debug!("模型映射 (Custom) 已全量热更新");
+        crate::proxy::common::model_registry::ModelRegistry::global().reload(&config.model_capabilities);
+        tracing::debug!("模型路由规则已全量热更新");
     }
 
     /// 更新代理配置
@@ -68,45 +80,85 @@ impl AxumServer {
         *zai = config.zai.clone();
         tracing::info!("z.ai 配置已热更新");
     }
+
+    pub async fn update_providers(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut providers = self.providers_state.write().await;
+        *providers = crate::proxy::providers::ProviderRegistry::from_config(&config.zai, &config.providers);
+        tracing::info!("上游服务商配置已热更新");
+    }
+
+    pub fn update_endpoints(&self, config: &crate::proxy::config::ProxyConfig) {
+        self.endpoints.configure(&config.upstream_endpoints);
+        tracing::info!("v1internal 端点配置已热更新");
+    }
+
+    pub fn update_token_refresh(&self, config: &crate::proxy::config::ProxyConfig) {
+        self.token_refresh.configure(&config.token_refresh);
+        tracing::info!("token 后台刷新配置已热更新");
+    }
     /// 启动 Axum 服务器
     pub async fn start(
         host: String,
         port: u16,
         token_manager: Arc<TokenManager>,
-        custom_mapping: std::collections::HashMap<String, String>,
+        model_router: crate::proxy::common::model_router::ModelRouter,
         _request_timeout: u64,
         upstream_proxy: crate::proxy::config::UpstreamProxyConfig,
         security_config: crate::proxy::ProxySecurityConfig,
         zai_config: crate::proxy::ZaiConfig,
+        providers_config: Vec<crate::proxy::config::UpstreamProviderConfig>,
         monitor: Arc<crate::proxy::monitor::ProxyMonitor>,
         experimental_config: crate::proxy::config::ExperimentalConfig,
 
     ) -> Result<(Self, tokio::task::JoinHandle<()>), String> {
-        let custom_mapping_state = Arc::new(tokio::sync::RwLock::new(custom_mapping));
+        let model_router_state = Arc::new(tokio::sync::RwLock::new(model_router));
 	        let proxy_state = Arc::new(tokio::sync::RwLock::new(upstream_proxy.clone()));
 	        let security_state = Arc::new(RwLock::new(security_config));
+	        let providers_state = Arc::new(RwLock::new(
+	            crate::proxy::providers::ProviderRegistry::from_config(&zai_config, &providers_config),
+	        ));
 	        let zai_state = Arc::new(RwLock::new(zai_config));
 	        let provider_rr = Arc::new(AtomicUsize::new(0));
 	        let zai_vision_mcp_state =
 	            Arc::new(crate::proxy::zai_vision_mcp::ZaiVisionMcpState::new());
 	        let experimental_state = Arc::new(RwLock::new(experimental_config));
 
+        // Responses API 存储保留 30 天，定期清理
+        let response_cleanup_task = crate::modules::response_db::spawn_cleanup_task();
+
+        // 定期拉取各账号可用模型
+        let upstream = Arc::new(
+            crate::proxy::upstream::client::UpstreamClient::new(Some(upstream_proxy.clone()))
+                .with_endpoints(monitor.endpoints.clone()),
+        );
+        let model_catalog = Arc::new(crate::proxy::model_catalog::ModelCatalog::new());
+        let catalog_task = model_catalog
+            .clone()
+            .spawn_refresh_task(token_manager.clone(), upstream.clone());
+
+        // 后台提前刷新账号池的 access_token
+        let token_refresh_task = monitor.token_refresh.clone().spawn(token_manager.clone());
+
+        // 定期写回粘性会话绑定
+        let session_flush_task = token_manager.spawn_session_flush_task();
+
 	        let state = AppState {
 	            token_manager: token_manager.clone(),
-	            custom_mapping: custom_mapping_state.clone(),
+	            model_router: model_router_state.clone(),
 	            request_timeout: 300, // 5分钟超时
             thought_signature_map: Arc::new(tokio::sync::Mutex::new(
                 std::collections::HashMap::new(),
             )),
             upstream_proxy: proxy_state.clone(),
-            upstream: Arc::new(crate::proxy::upstream::client::UpstreamClient::new(Some(
-                upstream_proxy.clone(),
-            ))),
+            upstream,
             zai: zai_state.clone(),
+            providers: providers_state.clone(),
             provider_rr: provider_rr.clone(),
             zai_vision_mcp: zai_vision_mcp_state,
             monitor: monitor.clone(),
             experimental: experimental_state,
+            security: security_state.clone(),
+            model_catalog,
         };
 
 
@@ -124,7 +176,16 @@ impl AxumServer {
                 "/v1/completions",
                 post(handlers::openai::handle_completions),
             )
-            .route("/v1/responses", post(handlers::openai::handle_completions)) // 兼容 Codex CLI
+            .route("/v1/responses", post(handlers::responses::handle_create_response)) // 兼容 Codex CLI
+            .route(
+                "/v1/responses/:id",
+                get(handlers::responses::handle_get_response)
+                    .delete(handlers::responses::handle_delete_response),
+            )
+            .route(
+                "/v1/responses/:id/input_items",
+                get(handlers::responses::handle_list_input_items),
+            )
             .route(
                 "/v1/images/generations",
                 post(handlers::openai::handle_images_generations),
@@ -133,6 +194,10 @@ impl AxumServer {
                 "/v1/images/edits",
                 post(handlers::openai::handle_images_edits),
             ) // 图像编辑 API
+            .route(
+                "/v1/embeddings",
+                post(handlers::embeddings::handle_embeddings),
+            ) // Embeddings API
             .route(
                 "/v1/audio/transcriptions",
                 post(handlers::audio::handle_audio_transcription),
@@ -172,12 +237,22 @@ impl AxumServer {
                 post(handlers::gemini::handle_count_tokens),
             ) // Specific route priority
             .route("/v1/models/detect", post(handlers::common::handle_detect_model))
+            .route("/v1/models/explain", post(handlers::common::handle_explain_route)) // 路由规则命中说明
+            .route("/v1/usage", get(handlers::usage::handle_get_usage)) // 密钥用量查询
+            .route("/v1/sessions", get(handlers::sessions::handle_list_sessions)) // 粘性会话管理
+            .route(
+                "/v1/sessions/:id",
+                put(handlers::sessions::handle_rebind_session)
+                    .delete(handlers::sessions::handle_evict_session),
+            )
             .route("/internal/warmup", post(handlers::warmup::handle_warmup)) // 内部预热端点
             .route("/v1/api/event_logging/batch", post(silent_ok_handler))
             .route("/v1/api/event_logging", post(silent_ok_handler))
             .route("/healthz", get(health_check_handler))
+            .route("/metrics", get(handlers::metrics::handle_metrics))
             .layer(DefaultBodyLimit::max(100 * 1024 * 1024))
             .layer(axum::middleware::from_fn_with_state(state.clone(), crate::proxy::middleware::monitor::monitor_middleware))
+            .layer(axum::middleware::from_fn_with_state(state.clone(), crate::proxy::middleware::admission::admission_middleware))
             .layer(TraceLayer::new_for_http())
             .layer(axum::middleware::from_fn_with_state(
                 security_state.clone(),
@@ -199,10 +274,18 @@ impl AxumServer {
 
         let server_instance = Self {
             shutdown_tx: Some(shutdown_tx),
-            custom_mapping: custom_mapping_state.clone(),
+            model_router: model_router_state.clone(),
             proxy_state,
             security_state,
             zai_state,
+            providers_state,
+            endpoints: monitor.endpoints.clone(),
+            catalog_task: Some(catalog_task),
+            token_refresh: monitor.token_refresh.clone(),
+            token_refresh_task: Some(token_refresh_task),
+            session_flush_task: Some(session_flush_task),
+            response_cleanup_task: Some(response_cleanup_task),
+            token_manager: token_manager.clone(),
         };
 
         // 在新任务中启动服务器
@@ -250,6 +333,19 @@ impl AxumServer {
         if let Some(tx) = self.shutdown_tx.take() {
             let _ = tx.send(());
         }
+        if let Some(task) = self.catalog_task.take() {
+            task.abort();
+        }
+        if let Some(task) = self.token_refresh_task.take() {
+            task.abort();
+        }
+        if let Some(task) = self.session_flush_task.take() {
+            task.abort();
+        }
+        if let Some(task) = self.response_cleanup_task.take() {
+            task.abort();
+        }
+        self.token_manager.flush_sessions();
     }
 }
 
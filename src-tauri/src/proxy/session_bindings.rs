@@ -0,0 +1,378 @@
+// 粘性会话绑定表 (SessionID -> AccountID)
+// 记录绑定的创建 / 最近使用时间，按空闲 TTL 过期、按 LRU 上限淘汰，可选持久化到数据目录
+
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// 后台写回变更的间隔，请求路径上只标记变更
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 单个会话的绑定信息 (时间为 Unix 秒)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionBinding {
+    pub account_id: String,
+    pub created_at: i64,
+    pub last_used: i64,
+}
+
+/// 会话绑定表
+pub struct SessionBindings {
+    bindings: Arc<DashMap<String, SessionBinding>>,
+    /// 空闲过期时间 (秒)，0 表示不过期
+    idle_ttl_secs: AtomicU64,
+    /// 最大绑定数，0 表示不限制
+    max_sessions: AtomicUsize,
+    writer: Arc<PersistWriter>,
+}
+
+/// 持久化写入器: 合并多次变更，在阻塞线程中序列化并写入
+struct PersistWriter {
+    path: PathBuf,
+    bindings: Arc<DashMap<String, SessionBinding>>,
+    enabled: AtomicBool,
+    /// 存在尚未写盘的变更
+    dirty: AtomicBool,
+    /// 已有写入任务在运行
+    writing: AtomicBool,
+    /// 串行化文件写入 (后台任务与退出时的同步写入)
+    write_lock: Mutex<()>,
+}
+
+impl PersistWriter {
+    fn mark_dirty(&self) {
+        if self.enabled.load(Ordering::Relaxed) {
+            self.dirty.store(true, Ordering::Release);
+        }
+    }
+
+    /// 有待写变更时提交后台写入，已有写入任务时由其取走
+    fn submit(self: &Arc<Self>) {
+        if !self.dirty.load(Ordering::Acquire) || self.writing.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                let writer = self.clone();
+                handle.spawn_blocking(move || writer.drain());
+            }
+            // 无运行时 (测试) 时同步写入
+            Err(_) => self.drain(),
+        }
+    }
+
+    fn drain(&self) {
+        loop {
+            if self.dirty.swap(false, Ordering::AcqRel) {
+                self.write();
+                continue;
+            }
+            self.writing.store(false, Ordering::Release);
+            // 释放写入标记前后可能有新的变更
+            if !self.dirty.load(Ordering::Acquire) || self.writing.swap(true, Ordering::AcqRel) {
+                return;
+            }
+        }
+    }
+
+    /// 在当前线程写入待写变更 (进程退出时使用)
+    fn flush_blocking(&self) {
+        if self.dirty.swap(false, Ordering::AcqRel) {
+            self.write();
+        }
+    }
+
+    /// 先写临时文件再替换
+    fn write(&self) {
+        let _guard = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        if !self.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+        let snapshot: Vec<(String, SessionBinding)> = self
+            .bindings
+            .iter()
+            .map(|e| (e.key().clone(), e.value().clone()))
+            .collect();
+        let tmp = self.path.with_extension("json.tmp");
+        let result = serde_json::to_string(&snapshot)
+            .map_err(|e| e.to_string())
+            .and_then(|content| {
+                std::fs::write(&tmp, content)
+                    .and_then(|_| std::fs::rename(&tmp, &self.path))
+                    .map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            tracing::warn!("[Session] 保存会话绑定失败 {:?}: {}", self.path, e);
+        }
+    }
+}
+
+fn now_secs() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+impl SessionBindings {
+    pub fn new(persist_path: PathBuf) -> Self {
+        let bindings = Arc::new(DashMap::new());
+        Self {
+            bindings: bindings.clone(),
+            idle_ttl_secs: AtomicU64::new(0),
+            max_sessions: AtomicUsize::new(0),
+            writer: Arc::new(PersistWriter {
+                path: persist_path,
+                bindings,
+                enabled: AtomicBool::new(false),
+                dirty: AtomicBool::new(false),
+                writing: AtomicBool::new(false),
+                write_lock: Mutex::new(()),
+            }),
+        }
+    }
+
+    /// 更新 TTL / 上限 / 持久化设置
+    ///
+    /// 开启持久化时合并磁盘上的绑定 (内存中已有的会话优先)；关闭时删除持久化文件，避免日后重新开启时恢复过时的绑定
+    pub fn configure(&self, idle_ttl_secs: u64, max_sessions: usize, persist: bool) {
+        self.idle_ttl_secs.store(idle_ttl_secs, Ordering::Relaxed);
+        self.max_sessions.store(max_sessions, Ordering::Relaxed);
+
+        let was_enabled = self.writer.enabled.swap(persist, Ordering::Relaxed);
+        if persist && !was_enabled {
+            self.restore();
+        } else if !persist && was_enabled {
+            let _guard = self.writer.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+            let _ = std::fs::remove_file(&self.writer.path);
+        }
+
+        self.evict_expired();
+        self.enforce_cap();
+        self.save();
+    }
+
+    /// 查询会话绑定的账号并刷新最近使用时间，已空闲过期的绑定在此移除
+    pub fn get(&self, session_id: &str) -> Option<String> {
+        let now = now_secs();
+        let ttl = self.idle_ttl_secs.load(Ordering::Relaxed) as i64;
+        let account_id = {
+            let mut entry = self.bindings.get_mut(session_id)?;
+            if ttl > 0 && now - entry.last_used > ttl {
+                None
+            } else {
+                entry.last_used = now;
+                Some(entry.account_id.clone())
+            }
+        };
+
+        match account_id {
+            Some(id) => {
+                self.writer.mark_dirty();
+                Some(id)
+            }
+            None => {
+                tracing::debug!("Session {} binding expired after {}s idle", session_id, ttl);
+                self.remove(session_id);
+                None
+            }
+        }
+    }
+
+    /// 绑定 (或重新绑定) 会话到账号，超出上限时淘汰最久未使用的会话
+    pub fn bind(&self, session_id: &str, account_id: &str) {
+        let now = now_secs();
+        self.bindings
+            .entry(session_id.to_string())
+            .and_modify(|b| {
+                b.account_id = account_id.to_string();
+                b.last_used = now;
+            })
+            .or_insert_with(|| SessionBinding {
+                account_id: account_id.to_string(),
+                created_at: now,
+                last_used: now,
+            });
+        self.enforce_cap();
+        self.writer.mark_dirty();
+    }
+
+    pub fn remove(&self, session_id: &str) -> bool {
+        let removed = self.bindings.remove(session_id).is_some();
+        if removed {
+            self.writer.mark_dirty();
+        }
+        removed
+    }
+
+    pub fn clear(&self) {
+        self.bindings.clear();
+        self.save();
+    }
+
+    pub fn len(&self) -> usize {
+        self.bindings.len()
+    }
+
+    /// 当前有效的绑定，按最近使用时间倒序
+    pub fn list(&self) -> Vec<(String, SessionBinding)> {
+        self.evict_expired();
+        let mut list: Vec<(String, SessionBinding)> = self
+            .bindings
+            .iter()
+            .map(|e| (e.key().clone(), e.value().clone()))
+            .collect();
+        list.sort_by_key(|(_, b)| std::cmp::Reverse(b.last_used));
+        list
+    }
+
+    /// 移除空闲超过 TTL 的绑定，返回移除数量
+    pub fn evict_expired(&self) -> usize {
+        let ttl = self.idle_ttl_secs.load(Ordering::Relaxed) as i64;
+        if ttl == 0 {
+            return 0;
+        }
+        let now = now_secs();
+        let before = self.bindings.len();
+        self.bindings.retain(|_, b| now - b.last_used <= ttl);
+        let evicted = before.saturating_sub(self.bindings.len());
+        if evicted > 0 {
+            tracing::debug!("Evicted {} idle session binding(s)", evicted);
+            self.writer.mark_dirty();
+        }
+        evicted
+    }
+
+    /// 超出上限时按最近使用时间淘汰 (LRU)
+    fn enforce_cap(&self) {
+        let max = self.max_sessions.load(Ordering::Relaxed);
+        if max == 0 || self.bindings.len() <= max {
+            return;
+        }
+        let mut by_age: Vec<(String, i64)> = self
+            .bindings
+            .iter()
+            .map(|e| (e.key().clone(), e.value().last_used))
+            .collect();
+        by_age.sort_by_key(|(_, last_used)| *last_used);
+        let excess = by_age.len().saturating_sub(max);
+        for (session_id, _) in by_age.into_iter().take(excess) {
+            self.bindings.remove(&session_id);
+        }
+        tracing::debug!("Session bindings over cap {}, evicted {} least recently used", max, excess);
+    }
+
+    fn restore(&self) {
+        let Ok(content) = std::fs::read_to_string(&self.writer.path) else {
+            return;
+        };
+        let saved: Vec<(String, SessionBinding)> = match serde_json::from_str(&content) {
+            Ok(saved) => saved,
+            Err(e) => {
+                tracing::warn!("[Session] 解析会话绑定文件失败 {:?}: {}", self.writer.path, e);
+                return;
+            }
+        };
+        let count = saved.len();
+        for (session_id, binding) in saved {
+            self.bindings.entry(session_id).or_insert(binding);
+        }
+        tracing::info!("[Session] 已从磁盘恢复 {} 个会话绑定", count);
+    }
+
+    /// 在后台写入尚未落盘的变更
+    pub fn flush(&self) {
+        self.writer.submit();
+    }
+
+    /// 在当前线程写入尚未落盘的变更 (进程退出前调用)
+    pub fn flush_blocking(&self) {
+        self.writer.flush_blocking();
+    }
+
+    /// 标记变更并立即提交后台写入 (配置变更 / 清空等非请求路径操作)
+    fn save(&self) {
+        self.writer.mark_dirty();
+        self.writer.submit();
+    }
+
+    /// 定期写回请求路径上累积的变更
+    pub fn spawn_flush_task(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SAVE_INTERVAL);
+            loop {
+                interval.tick().await;
+                self.flush();
+            }
+        })
+    }
+}
+
+impl Drop for SessionBindings {
+    fn drop(&mut self) {
+        self.flush_blocking();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("session_bindings_{}.json", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn test_ttl_and_lru_cap() {
+        let sessions = SessionBindings::new(temp_path());
+        sessions.configure(600, 2, false);
+
+        sessions.bind("s1", "a");
+        sessions.bind("s2", "b");
+        // s1 被访问后成为最近使用，超出上限时淘汰 s2
+        sessions.bindings.get_mut("s2").unwrap().last_used -= 10;
+        assert_eq!(sessions.get("s1"), Some("a".to_string()));
+        sessions.bind("s3", "c");
+        assert_eq!(sessions.len(), 2);
+        assert!(sessions.get("s2").is_none());
+
+        // 空闲超过 TTL 的绑定在访问时移除
+        sessions.bindings.get_mut("s1").unwrap().last_used -= 601;
+        assert!(sessions.get("s1").is_none());
+        assert_eq!(sessions.list().len(), 1);
+    }
+
+    #[test]
+    fn test_bindings_persist_when_enabled() {
+        let path = temp_path();
+        let sessions = SessionBindings::new(path.clone());
+        sessions.configure(0, 0, true);
+        sessions.bind("s1", "a");
+        // 请求路径上不写盘，释放时补写
+        drop(sessions);
+
+        let restored = SessionBindings::new(path.clone());
+        restored.configure(0, 0, true);
+        assert_eq!(restored.get("s1"), Some("a".to_string()));
+
+        // 关闭持久化时删除文件
+        restored.configure(0, 0, false);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_bind_defers_save_until_flush() {
+        let path = temp_path();
+        let sessions = SessionBindings::new(path.clone());
+        sessions.configure(0, 0, true);
+        sessions.bind("s1", "a");
+        let on_disk = std::fs::read_to_string(&path).unwrap();
+        assert!(!on_disk.contains("s1"));
+
+        sessions.flush();
+        let on_disk = std::fs::read_to_string(&path).unwrap();
+        assert!(on_disk.contains("s1"));
+
+        sessions.configure(0, 0, false);
+    }
+}
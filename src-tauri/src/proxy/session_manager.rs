@@ -70,6 +70,12 @@ impl SessionManager {
         let mut hasher = Sha256::new();
         hasher.update(request.model.as_bytes());
 
+        // 客户端提供的 user 标识与内容指纹一同哈希: 区分同一用户的不同会话，且不在绑定表中暴露原始值
+        if let Some(user) = request.user.as_deref().filter(|u| !u.is_empty()) {
+            hasher.update(b"user:");
+            hasher.update(user.as_bytes());
+        }
+
         let mut content_found = false;
         for msg in &request.messages {
             if msg.role != "user" { continue; }
@@ -148,3 +154,32 @@ impl SessionManager {
         sid
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn openai_request(user: Option<&str>, content: &str) -> OpenAIRequest {
+        serde_json::from_value(serde_json::json!({
+            "model": "gemini-2.5-flash",
+            "messages": [{ "role": "user", "content": content }],
+            "user": user
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_openai_session_id_hashes_user() {
+        let first = SessionManager::extract_openai_session_id(&openai_request(Some("alice"), "first conversation here"));
+        let second = SessionManager::extract_openai_session_id(&openai_request(Some("alice"), "another conversation"));
+        let other_user = SessionManager::extract_openai_session_id(&openai_request(Some("bob"), "first conversation here"));
+
+        assert!(first.starts_with("sid-") && !first.contains("alice"));
+        assert_ne!(first, second);
+        assert_ne!(first, other_user);
+        assert_eq!(
+            first,
+            SessionManager::extract_openai_session_id(&openai_request(Some("alice"), "first conversation here"))
+        );
+    }
+}
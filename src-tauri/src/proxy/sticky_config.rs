@@ -9,6 +9,17 @@ pub enum SchedulingMode {
     Balance,
     /// 性能优先 (Performance-first): 纯轮询模式 (Round-robin)，账号负载最均衡，但不利用缓存
     PerformanceFirst,
+    /// 最少负载 (Least-loaded): 选择进行中请求最少、近期错误率最低的账号，不绑定会话
+    LeastLoaded,
+    /// 配额加权 (Quota-weighted): 按请求模型的剩余配额与距重置时间加权随机选择，使配额在重置窗口内均匀消耗
+    QuotaWeighted,
+}
+
+impl SchedulingMode {
+    /// 是否按会话绑定账号 (粘性会话)
+    pub fn is_sticky(self) -> bool {
+        matches!(self, Self::CacheFirst | Self::Balance)
+    }
 }
 
 impl Default for SchedulingMode {
@@ -24,6 +35,58 @@ pub struct StickySessionConfig {
     pub mode: SchedulingMode,
     /// 缓存优先模式下的最大等待时间 (秒)
     pub max_wait_seconds: u64,
+    /// 单个账号同时处理的最大请求数 (0 表示不限制)，已满的账号在调度时跳过
+    #[serde(default)]
+    pub max_concurrent_per_account: u32,
+    /// 订阅等级优先级 (靠前者优先，未列出的等级排在最后)
+    #[serde(default = "default_tier_priority")]
+    pub tier_priority: Vec<String>,
+    /// 会话绑定空闲过期时间 (秒)，0 表示不过期
+    #[serde(default = "default_session_ttl_seconds")]
+    pub session_ttl_seconds: u64,
+    /// 最多保留的会话绑定数，超出时淘汰最久未使用的会话 (0 表示不限制)
+    #[serde(default = "default_max_sessions")]
+    pub max_sessions: usize,
+    /// 将会话绑定保存到数据目录，重启后保持粘性
+    #[serde(default)]
+    pub persist_sessions: bool,
+    /// 账号池无可用账号时排队等待，而不是立即返回错误
+    #[serde(default)]
+    pub queue_enabled: bool,
+    /// 最大排队请求数 (0 表示不限制)，队列已满时直接返回 429
+    #[serde(default = "default_queue_max_depth")]
+    pub queue_max_depth: usize,
+    /// 单个请求的最长排队时间 (秒)
+    #[serde(default = "default_queue_timeout_seconds")]
+    pub queue_timeout_seconds: u64,
+}
+
+fn default_queue_max_depth() -> usize {
+    100
+}
+
+fn default_queue_timeout_seconds() -> u64 {
+    300
+}
+
+fn default_session_ttl_seconds() -> u64 {
+    3600
+}
+
+fn default_max_sessions() -> usize {
+    10_000
+}
+
+fn default_tier_priority() -> Vec<String> {
+    vec!["ULTRA".to_string(), "PRO".to_string(), "FREE".to_string()]
+}
+
+impl StickySessionConfig {
+    /// 订阅等级的排序位置 (不区分大小写，未知等级排在所有已配置等级之后)
+    pub fn tier_rank(&self, tier: Option<&str>) -> usize {
+        tier.and_then(|t| self.tier_priority.iter().position(|p| p.eq_ignore_ascii_case(t)))
+            .unwrap_or(self.tier_priority.len())
+    }
 }
 
 impl Default for StickySessionConfig {
@@ -31,6 +94,14 @@ impl Default for StickySessionConfig {
         Self {
             mode: SchedulingMode::Balance,
             max_wait_seconds: 60,
+            max_concurrent_per_account: 0,
+            tier_priority: default_tier_priority(),
+            session_ttl_seconds: default_session_ttl_seconds(),
+            max_sessions: default_max_sessions(),
+            persist_sessions: false,
+            queue_enabled: false,
+            queue_max_depth: default_queue_max_depth(),
+            queue_timeout_seconds: default_queue_timeout_seconds(),
         }
     }
 }
@@ -36,6 +36,8 @@ mod tests {
             }),
             metadata: None,
             output_config: None,
+            tool_choice: None,
+            stop_sequences: None,
         };
 
         // 2. 执行转换
@@ -1,12 +1,17 @@
 // 移除冗余的顶层导入，因为这些在代码中已由 full path 或局部导入处理
 use dashmap::DashMap;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
+use crate::proxy::admission::{AdmissionQueue, QueueStatus, QueueTicket};
+use crate::proxy::mappers::gemini::wrapper::EMBEDDING_REQUEST_TYPE;
+use crate::proxy::load_balance::{self, RecentOutcomes};
+use crate::proxy::model_catalog::ModelQuota;
 use crate::proxy::rate_limit::RateLimitTracker;
-use crate::proxy::sticky_config::StickySessionConfig;
+use crate::proxy::session_bindings::SessionBindings;
+use crate::proxy::sticky_config::{SchedulingMode, StickySessionConfig};
 
 #[derive(Debug, Clone)]
 pub struct ProxyToken {
@@ -23,6 +28,56 @@ pub struct ProxyToken {
 }
 
 
+/// 账号并发租约
+///
+/// 获取 Token 时占用该账号的一个并发槽位，Drop 时释放；流式响应需通过 `hold` 持有至流结束
+pub struct AccountLease {
+    account_id: String,
+    in_flight: Arc<DashMap<String, usize>>,
+    admission: Arc<AdmissionQueue>,
+}
+
+impl AccountLease {
+    /// 将租约绑定到响应体，响应 (含流) 发送完毕或客户端断开后释放
+    pub fn hold(self, response: axum::response::Response) -> axum::response::Response {
+        use futures::StreamExt;
+
+        let (parts, body) = response.into_parts();
+        let mut stream = body.into_data_stream();
+        let guarded = async_stream::stream! {
+            let _lease = self;
+            while let Some(chunk) = stream.next().await {
+                yield chunk;
+            }
+        };
+        axum::response::Response::from_parts(parts, axum::body::Body::from_stream(guarded))
+    }
+}
+
+impl Drop for AccountLease {
+    fn drop(&mut self) {
+        if let Some(mut count) = self.in_flight.get_mut(&self.account_id) {
+            *count = count.saturating_sub(1);
+        }
+        // 释放槽位后唤醒排队中的请求
+        self.admission.notify_all();
+    }
+}
+
+/// 会话绑定详情 (管理接口返回，时间为 Unix 秒)
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SessionBindingInfo {
+    pub session_id: String,
+    pub account_id: String,
+    /// 账号已不在账号池中时为 None
+    pub email: Option<String>,
+    pub created_at: i64,
+    pub last_used: i64,
+}
+
+/// (access_token, project_id, email, 并发租约)
+pub type LeasedToken = (String, String, String, AccountLease);
+
 pub struct TokenManager {
     tokens: Arc<DashMap<String, ProxyToken>>,  // account_id -> ProxyToken
     current_index: Arc<AtomicUsize>,
@@ -30,20 +85,31 @@ pub struct TokenManager {
     data_dir: PathBuf,
     rate_limit_tracker: Arc<RateLimitTracker>,  // 新增: 限流跟踪器
     sticky_config: Arc<tokio::sync::RwLock<StickySessionConfig>>, // 新增：调度配置
-    session_accounts: Arc<DashMap<String, String>>, // 新增：会话与账号映射 (SessionID -> AccountID)
+    session_accounts: Arc<SessionBindings>, // 新增：会话与账号映射 (SessionID -> AccountID)，带空闲过期与 LRU 上限
+    in_flight: Arc<DashMap<String, usize>>, // 进行中的请求数 (AccountID -> count)
+    outcomes: Arc<DashMap<String, RecentOutcomes>>, // 近期请求与失败记录 (Email -> outcomes)
+    model_quotas: Arc<DashMap<String, HashMap<String, ModelQuota>>>, // 模型配额 (Email -> 模型 -> 配额)，由模型目录刷新
+    admission: Arc<AdmissionQueue>, // 账号池耗尽时的准入队列
 }
 
 impl TokenManager {
     /// 创建新的 TokenManager
     pub fn new(data_dir: PathBuf) -> Self {
+        // 限流锁定持久化到数据目录，重启后恢复未过期的锁定
+        let rate_limit_tracker = RateLimitTracker::with_persistence(data_dir.join("rate_limits.json"));
+        let session_accounts = SessionBindings::new(data_dir.join("session_bindings.json"));
         Self {
             tokens: Arc::new(DashMap::new()),
             current_index: Arc::new(AtomicUsize::new(0)),
             last_used_account: Arc::new(tokio::sync::Mutex::new(None)),
             data_dir,
-            rate_limit_tracker: Arc::new(RateLimitTracker::new()),
+            rate_limit_tracker: Arc::new(rate_limit_tracker),
             sticky_config: Arc::new(tokio::sync::RwLock::new(StickySessionConfig::default())),
-            session_accounts: Arc::new(DashMap::new()),
+            session_accounts: Arc::new(session_accounts),
+            in_flight: Arc::new(DashMap::new()),
+            outcomes: Arc::new(DashMap::new()),
+            model_quotas: Arc::new(DashMap::new()),
+            admission: Arc::new(AdmissionQueue::new()),
         }
     }
     
@@ -429,17 +495,27 @@ impl TokenManager {
     /// 参数 `quota_group` 用于区分 "claude" vs "gemini" 组
     /// 参数 `force_rotate` 为 true 时将忽略锁定，强制切换账号
     /// 参数 `session_id` 用于跨请求维持会话粘性
-    pub async fn get_token(&self, quota_group: &str, force_rotate: bool, session_id: Option<&str>) -> Result<(String, String, String), String> {
+    /// 返回的租约占用该账号一个并发槽位，需持有至响应结束
+    pub async fn get_token(&self, quota_group: &str, force_rotate: bool, session_id: Option<&str>) -> Result<LeasedToken, String> {
+        self.get_token_with_timeout(quota_group, force_rotate, session_id, None).await
+    }
+
+    /// 获取可用于指定模型的 Token，跳过该模型处于模型级别限流中的账号
+    pub async fn get_token_for_model(&self, quota_group: &str, force_rotate: bool, session_id: Option<&str>, model: &str) -> Result<LeasedToken, String> {
+        self.get_token_with_timeout(quota_group, force_rotate, session_id, Some(model)).await
+    }
+
+    async fn get_token_with_timeout(&self, quota_group: &str, force_rotate: bool, session_id: Option<&str>, model: Option<&str>) -> Result<LeasedToken, String> {
         // 【优化 Issue #284】添加 5 秒超时，防止死锁
         let timeout_duration = std::time::Duration::from_secs(5);
-        match tokio::time::timeout(timeout_duration, self.get_token_internal(quota_group, force_rotate, session_id)).await {
+        match tokio::time::timeout(timeout_duration, self.get_token_internal(quota_group, force_rotate, session_id, model)).await {
             Ok(result) => result,
             Err(_) => Err("Token acquisition timeout (5s) - system too busy or deadlock detected".to_string()),
         }
     }
 
     /// 内部实现：获取 Token 的核心逻辑
-    async fn get_token_internal(&self, quota_group: &str, force_rotate: bool, session_id: Option<&str>) -> Result<(String, String, String), String> {
+    async fn get_token_internal(&self, quota_group: &str, force_rotate: bool, session_id: Option<&str>, model: Option<&str>) -> Result<LeasedToken, String> {
         let mut tokens_snapshot: Vec<ProxyToken> = self.tokens.iter().map(|e| e.value().clone()).collect();
         let total = tokens_snapshot.len();
         if total == 0 {
@@ -507,21 +583,39 @@ impl TokenManager {
             }
         }
 
+        // ===== 模型级别限流过滤 =====
+        // 限流记录以 email 为 key 存储
+        if let Some(model) = model {
+            tokens_snapshot.retain(|t| !self.rate_limit_tracker.is_model_rate_limited(&t.email, model));
+            if tokens_snapshot.is_empty() {
+                return Err(format!("All accounts are rate-limited for model {}", model));
+            }
+        }
+
+        // ===== 并发上限过滤 =====
+        // 跳过进行中请求数已达上限的账号 (0 表示不限制)
+        let scheduling = self.sticky_config.read().await.clone();
+        let max_concurrent = scheduling.max_concurrent_per_account;
+        if max_concurrent > 0 {
+            tokens_snapshot.retain(|t| !self.is_saturated(&t.account_id, max_concurrent));
+            if tokens_snapshot.is_empty() {
+                return Err(format!(
+                    "All accounts are at max concurrency ({} requests per account)",
+                    max_concurrent
+                ));
+            }
+        }
+        // 过滤后账号数可能减少，以实际候选数为准
+        let total = tokens_snapshot.len();
+
         // ===== 【优化】根据订阅等级和剩余配额排序 =====
-        // [FIX #563] 优先级: ULTRA > PRO > FREE, 同tier内优先高配额账号
+        // [FIX #563] 优先级默认: ULTRA > PRO > FREE (可通过 tier_priority 配置), 同tier内优先高配额账号
         // 理由: ULTRA/PRO 重置快，优先消耗；FREE 重置慢，用于兜底
         //       高配額账号优先使用，避免低配额账号被用光
         tokens_snapshot.sort_by(|a, b| {
-            let tier_priority = |tier: &Option<String>| match tier.as_deref() {
-                Some("ULTRA") => 0,
-                Some("PRO") => 1,
-                Some("FREE") => 2,
-                _ => 3,
-            };
-            
             // First: compare by subscription tier
-            let tier_cmp = tier_priority(&a.subscription_tier)
-                .cmp(&tier_priority(&b.subscription_tier));
+            let tier_cmp = scheduling.tier_rank(a.subscription_tier.as_deref())
+                .cmp(&scheduling.tier_rank(b.subscription_tier.as_deref()));
             
             if tier_cmp != std::cmp::Ordering::Equal {
                 return tier_cmp;
@@ -535,13 +629,12 @@ impl TokenManager {
         });
 
 
-        // 0. 读取当前调度配置
-        let scheduling = self.sticky_config.read().await.clone();
-        use crate::proxy::sticky_config::SchedulingMode;
+        // 图片生成与 embedding 请求不参与 60s 锁定，也不写回 last_used_account
+        let uses_last_used = quota_group != "image_gen" && quota_group != EMBEDDING_REQUEST_TYPE;
 
         // 【优化 Issue #284】将锁操作移到循环外，避免重复获取锁
         // 预先获取 last_used_account 的快照，避免在循环中多次加锁
-        let last_used_account_id = if quota_group != "image_gen" {
+        let last_used_account_id = if uses_last_used {
             let last_used = self.last_used_account.lock().await;
             last_used.clone()
         } else {
@@ -559,11 +652,11 @@ impl TokenManager {
             let mut target_token: Option<ProxyToken> = None;
             
             // 模式 A: 粘性会话处理 (CacheFirst 或 Balance 且有 session_id)
-            if !rotate && session_id.is_some() && scheduling.mode != SchedulingMode::PerformanceFirst {
+            if !rotate && session_id.is_some() && scheduling.mode.is_sticky() {
                 let sid = session_id.unwrap();
                 
                 // 1. 检查会话是否已绑定账号
-                if let Some(bound_id) = self.session_accounts.get(sid).map(|v| v.clone()) {
+                if let Some(bound_id) = self.session_accounts.get(sid) {
                     // 【修复】先通过 account_id 找到对应的账号，获取其 email
                     // 因为限流记录是以 email 为 key 存储的
                     if let Some(bound_token) = tokens_snapshot.iter().find(|t| t.account_id == bound_id) {
@@ -582,16 +675,23 @@ impl TokenManager {
                             tracing::debug!("Sticky Session: Successfully reusing bound account {} for session {}", bound_token.email, sid);
                             target_token = Some(bound_token.clone());
                         }
-                    } else {
+                    } else if !self.tokens.contains_key(&bound_id) {
                         // 绑定的账号已不存在（可能被删除），解绑
                         tracing::warn!("Session {} bound to non-existent account {}, unbinding.", sid, bound_id);
                         self.session_accounts.remove(sid);
+                    } else {
+                        // 绑定的账号暂时被过滤 (模型限流 / 并发已满)，保留绑定，本次使用其他账号
+                        tracing::debug!("Session {} bound account {} is unavailable for this request, keeping binding", sid, bound_id);
                     }
                 }
             }
 
-            // 模式 B: 原子化 60s 全局锁定 (针对无 session_id 情况的默认保护)
-            if target_token.is_none() && !rotate && quota_group != "image_gen" {
+            // 模式 D: 最少负载 / 配额加权 (每次请求独立选号，不做 60s 锁定)
+            let balanced = matches!(scheduling.mode, SchedulingMode::LeastLoaded | SchedulingMode::QuotaWeighted);
+            if balanced {
+                target_token = self.select_balanced(scheduling.mode, &tokens_snapshot, &attempted, model);
+            } else if target_token.is_none() && !rotate && uses_last_used {
+                // 模式 B: 原子化 60s 全局锁定 (针对无 session_id 情况的默认保护)
                 // 【优化】使用预先获取的快照，不再在循环内加锁
                 if let Some((account_id, last_time)) = &last_used_account_id {
                     if last_time.elapsed().as_secs() < 60 && !attempted.contains(account_id) {
@@ -628,8 +728,8 @@ impl TokenManager {
                         
                         // 如果是会话首次分配且需要粘性，在此建立绑定
                         if let Some(sid) = session_id {
-                            if scheduling.mode != SchedulingMode::PerformanceFirst {
-                                self.session_accounts.insert(sid.to_string(), candidate.account_id.clone());
+                            if scheduling.mode.is_sticky() {
+                                self.session_accounts.bind(sid, &candidate.account_id);
                                 tracing::debug!("Sticky Session: Bound new account {} to session {}", candidate.email, sid);
                             }
                         }
@@ -724,7 +824,18 @@ impl TokenManager {
                 }
             };
 
-        
+            // 占用并发槽位 (过滤后的并发请求可能已占满该账号)
+            let lease = match self.try_lease(&token.account_id, max_concurrent) {
+                Some(lease) => lease,
+                None => {
+                    tracing::debug!("账号 {} 并发已满，尝试下一个账号", token.email);
+                    last_error = Some(format!("Account reached max concurrency ({})", max_concurrent));
+                    attempted.insert(token.account_id.clone());
+                    continue;
+                }
+            };
+            self.record_request(&token.email);
+
             // 3. 检查 token 是否过期（提前5分钟刷新）
             let now = chrono::Utc::now().timestamp();
             if now >= token.timestamp - 300 {
@@ -754,7 +865,7 @@ impl TokenManager {
                     }
                     Err(e) => {
                         tracing::error!("Token 刷新失败 ({}): {}，尝试下一个账号", token.email, e);
-                        if e.contains("\"invalid_grant\"") || e.contains("invalid_grant") {
+                        if crate::modules::oauth::is_invalid_grant(&e) {
                             tracing::error!(
                                 "Disabling account due to invalid_grant ({}): refresh_token likely revoked/expired",
                                 token.email
@@ -769,7 +880,7 @@ impl TokenManager {
                         attempted.insert(token.account_id.clone());
 
                         // 【优化】标记需要清除锁定，避免在循环内加锁
-                        if quota_group != "image_gen" {
+                        if uses_last_used {
                             if matches!(&last_used_account_id, Some((id, _)) if id == &token.account_id) {
                                 need_update_last_used = Some((String::new(), std::time::Instant::now())); // 空字符串表示需要清除
                             }
@@ -798,7 +909,7 @@ impl TokenManager {
                         attempted.insert(token.account_id.clone());
 
                         // 【优化】标记需要清除锁定，避免在循环内加锁
-                        if quota_group != "image_gen" {
+                        if uses_last_used {
                             if matches!(&last_used_account_id, Some((id, _)) if id == &token.account_id) {
                                 need_update_last_used = Some((String::new(), std::time::Instant::now())); // 空字符串表示需要清除
                             }
@@ -810,7 +921,7 @@ impl TokenManager {
 
             // 【优化】在成功返回前，统一更新 last_used_account（如果需要）
             if let Some((new_account_id, new_time)) = need_update_last_used {
-                if quota_group != "image_gen" {
+                if uses_last_used {
                     let mut last_used = self.last_used_account.lock().await;
                     if new_account_id.is_empty() {
                         // 空字符串表示需要清除锁定
@@ -821,12 +932,137 @@ impl TokenManager {
                 }
             }
 
-            return Ok((token.access_token, project_id, token.email));
+            return Ok((token.access_token, project_id, token.email, lease));
         }
 
         Err(last_error.unwrap_or_else(|| "All accounts failed".to_string()))
     }
 
+    /// 模式 D 选号: 在未尝试且未限流的候选中按最少负载或配额权重选择
+    ///
+    /// QuotaWeighted 下所有候选的配额权重均为 0 时退化为最少负载
+    fn select_balanced(
+        &self,
+        mode: SchedulingMode,
+        tokens: &[ProxyToken],
+        attempted: &HashSet<String>,
+        model: Option<&str>,
+    ) -> Option<ProxyToken> {
+        let candidates: Vec<&ProxyToken> = tokens
+            .iter()
+            .filter(|t| {
+                !attempted.contains(&t.account_id)
+                    && !self.is_rate_limited(&t.account_id)
+                    && !self.is_rate_limited(&t.email)
+            })
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+
+        let weighted = match (mode, model) {
+            (SchedulingMode::QuotaWeighted, Some(model)) => {
+                let now = chrono::Utc::now();
+                let weights: Vec<f64> = candidates
+                    .iter()
+                    .map(|t| {
+                        let quota = self.model_quota(&t.email, model).unwrap_or_default();
+                        let secs_to_reset = quota
+                            .reset_time
+                            .as_deref()
+                            .and_then(|r| chrono::DateTime::parse_from_rfc3339(r).ok())
+                            .map(|reset| (reset.with_timezone(&chrono::Utc) - now).num_seconds().max(0) as f64);
+                        load_balance::quota_weight(quota.remaining_fraction, secs_to_reset)
+                    })
+                    .collect();
+                load_balance::pick_weighted(&weights, rand::random::<f64>())
+            }
+            _ => None,
+        };
+
+        let idx = match weighted {
+            Some(idx) => idx,
+            None => {
+                let now = std::time::Instant::now();
+                let loads: Vec<(usize, f64)> = candidates
+                    .iter()
+                    .map(|t| {
+                        let in_flight = self.in_flight.get(&t.account_id).map(|c| *c).unwrap_or(0);
+                        let error_rate = self
+                            .outcomes
+                            .get_mut(&t.email)
+                            .map(|mut o| o.error_rate(now))
+                            .unwrap_or(0.0);
+                        (in_flight, error_rate)
+                    })
+                    .collect();
+                let start = self.current_index.fetch_add(1, Ordering::SeqCst);
+                load_balance::pick_least_loaded(&loads, start)?
+            }
+        };
+
+        let selected = candidates[idx];
+        tracing::debug!("{:?}: selected account {}", mode, selected.email);
+        Some(selected.clone())
+    }
+
+    /// 记录一次请求 (获取 Token 时)，作为近期错误率的分母
+    fn record_request(&self, email: &str) {
+        self.outcomes.entry(email.to_string()).or_default().record_request(std::time::Instant::now());
+    }
+
+    /// 记录一次失败 (限流 / 上游错误)
+    fn record_failure(&self, email: &str) {
+        self.outcomes.entry(email.to_string()).or_default().record_failure(std::time::Instant::now());
+    }
+
+    /// 更新账号的模型配额 (模型目录刷新后调用)
+    pub fn update_model_quotas(&self, email: &str, quotas: HashMap<String, ModelQuota>) {
+        self.model_quotas.insert(email.to_string(), quotas);
+    }
+
+    fn model_quota(&self, email: &str, model: &str) -> Option<ModelQuota> {
+        self.model_quotas.get(email).and_then(|q| q.get(model).cloned())
+    }
+
+    /// 账号进行中的请求数是否已达上限 (0 表示不限制)
+    fn is_saturated(&self, account_id: &str, max_concurrent: u32) -> bool {
+        max_concurrent > 0
+            && self.in_flight.get(account_id).map(|c| *c).unwrap_or(0) >= max_concurrent as usize
+    }
+
+    /// 原子地检查上限并占用一个并发槽位
+    fn try_lease(&self, account_id: &str, max_concurrent: u32) -> Option<AccountLease> {
+        {
+            let mut count = self.in_flight.entry(account_id.to_string()).or_insert(0);
+            if max_concurrent > 0 && *count >= max_concurrent as usize {
+                return None;
+            }
+            *count += 1;
+        }
+        // 排队请求取得槽位后出队，放行下一个请求
+        crate::proxy::admission::release_admitted();
+        Some(AccountLease {
+            account_id: account_id.to_string(),
+            in_flight: self.in_flight.clone(),
+            admission: self.admission.clone(),
+        })
+    }
+
+    /// 各账号进行中的请求数 (email, count)，按 email 排序
+    pub fn in_flight_by_account(&self) -> Vec<(String, usize)> {
+        let mut counts: Vec<(String, usize)> = self
+            .tokens
+            .iter()
+            .map(|entry| {
+                let count = self.in_flight.get(entry.key()).map(|c| *c).unwrap_or(0);
+                (entry.value().email.clone(), count)
+            })
+            .collect();
+        counts.sort();
+        counts
+    }
+
     async fn disable_account(&self, account_id: &str, reason: &str) -> Result<(), String> {
         let path = if let Some(entry) = self.tokens.get(account_id) {
             entry.account_path.clone()
@@ -901,6 +1137,77 @@ impl TokenManager {
         self.tokens.len()
     }
 
+    // ===== 后台主动刷新 =====
+
+    /// 账号池中各账号的 (account_id, email, access_token 过期时间)
+    pub fn token_expiries(&self) -> Vec<(String, String, i64)> {
+        self.tokens
+            .iter()
+            .map(|entry| {
+                let token = entry.value();
+                (token.account_id.clone(), token.email.clone(), token.timestamp)
+            })
+            .collect()
+    }
+
+    /// 刷新指定账号的 access_token 并通过账号模块写回，返回新的过期时间
+    ///
+    /// refresh_token 已失效 (invalid_grant) 时禁用该账号并移出账号池
+    pub async fn refresh_account_token(&self, account_id: &str) -> Result<i64, String> {
+        let (email, refresh_token) = self
+            .tokens
+            .get(account_id)
+            .map(|t| (t.email.clone(), t.refresh_token.clone()))
+            .ok_or("账号不存在")?;
+
+        let token_response = match crate::modules::oauth::refresh_access_token(&refresh_token).await {
+            Ok(token_response) => token_response,
+            Err(e) => {
+                if crate::modules::oauth::is_invalid_grant(&e) {
+                    tracing::error!(
+                        "Disabling account due to invalid_grant ({}): refresh_token likely revoked/expired",
+                        email
+                    );
+                    let _ = self
+                        .disable_account(account_id, &format!("invalid_grant: {}", e))
+                        .await;
+                    self.tokens.remove(account_id);
+                }
+                return Err(e);
+            }
+        };
+
+        let expires_at = chrono::Utc::now().timestamp() + token_response.expires_in;
+        if let Some(mut entry) = self.tokens.get_mut(account_id) {
+            entry.access_token = token_response.access_token.clone();
+            entry.expires_in = token_response.expires_in;
+            entry.timestamp = expires_at;
+            if let Some(rotated) = &token_response.refresh_token {
+                entry.refresh_token = rotated.clone();
+            }
+        }
+
+        // 经 account 模块读写，保留账号文件中的配额、设备指纹等其他字段
+        let saved = crate::modules::account::load_account(account_id).and_then(|mut account| {
+            account.token.access_token = token_response.access_token;
+            account.token.expires_in = token_response.expires_in;
+            account.token.expiry_timestamp = expires_at;
+            if let Some(rotated) = token_response.refresh_token {
+                account.token.refresh_token = rotated;
+            }
+            crate::modules::account::save_account(&account)
+        });
+        if let Err(e) = saved {
+            tracing::warn!("[TokenRefresh] 保存刷新后的 token 失败 ({}): {}", email, e);
+        }
+        Ok(expires_at)
+    }
+
+    /// 当前账号池中所有账号的 email
+    pub fn account_emails(&self) -> Vec<String> {
+        self.tokens.iter().map(|entry| entry.value().email.clone()).collect()
+    }
+
     /// 通过 email 获取指定账号的 Token（用于预热等需要指定账号的场景）
     /// 此方法会自动刷新过期的 token
     pub async fn get_token_by_email(&self, email: &str) -> Result<(String, String, String), String> {
@@ -969,25 +1276,61 @@ impl TokenManager {
         }
     }
     
+    /// 只读取号 (用于 count_tokens 等辅助请求): 不轮换账号、不写回 last_used、不占用并发槽位
+    ///
+    /// 选择首个未处于限流中的账号 (按 email 排序，结果稳定)
+    pub async fn peek_token(&self, model: &str) -> Result<(String, String, String), String> {
+        let email = self
+            .tokens
+            .iter()
+            .filter(|entry| {
+                let token = entry.value();
+                !self.is_rate_limited(&token.account_id)
+                    && !self.is_rate_limited(&token.email)
+                    && !self.rate_limit_tracker.is_model_rate_limited(&token.email, model)
+            })
+            .map(|entry| entry.value().email.clone())
+            .min()
+            .ok_or_else(|| "No available account".to_string())?;
+        self.get_token_by_email(&email).await
+    }
+
     // ===== 限流管理方法 =====
     
-    /// 标记账号限流(从外部调用,通常在 handler 中)
-    pub fn mark_rate_limited(
+    /// 标记限流，配额 / 容量耗尽时仅锁定该账号的指定模型
+    pub fn mark_model_rate_limited(
         &self,
         account_id: &str,
         status: u16,
         retry_after_header: Option<&str>,
         error_body: &str,
+        model: &str,
     ) {
+        self.record_failure(account_id);
         self.rate_limit_tracker.parse_from_error(
             account_id,
             status,
             retry_after_header,
             error_body,
-            None,
+            Self::model_lock_scope(error_body, model).map(|s| s.to_string()),
         );
     }
-    
+
+    /// 限流锁定的模型范围: 配额 / 容量耗尽时仅锁定该模型，其余错误锁定整个账号 (None)
+    pub fn model_lock_scope<'a>(error_body: &str, model: &'a str) -> Option<&'a str> {
+        let model_scoped = error_body.contains("QUOTA_EXHAUSTED") || error_body.contains("MODEL_CAPACITY_EXHAUSTED");
+        model_scoped.then_some(model)
+    }
+
+    /// 指定模型是否仍有可用账号 (未处于账号级别或该模型的模型级别限流中)
+    pub fn is_model_available(&self, model: &str) -> bool {
+        self.tokens.iter().any(|entry| {
+            let email = &entry.value().email;
+            !self.rate_limit_tracker.is_rate_limited(email)
+                && !self.rate_limit_tracker.is_model_rate_limited(email, model)
+        })
+    }
+
     /// 检查账号是否在限流中
     pub fn is_rate_limited(&self, account_id: &str) -> bool {
         self.rate_limit_tracker.is_rate_limited(account_id)
@@ -1164,6 +1507,7 @@ impl TokenManager {
         error_body: &str,
         model: Option<&str>,  // 🆕 新增模型参数
     ) {
+        self.record_failure(account_id);
         // 检查 API 是否返回了精确的重试时间
         let has_explicit_retry_time = retry_after_header.is_some() || 
             error_body.contains("quotaResetDelay");
@@ -1232,21 +1576,191 @@ impl TokenManager {
 
     /// 更新调度配置
     pub async fn update_sticky_config(&self, new_config: StickySessionConfig) {
+        self.session_accounts.configure(
+            new_config.session_ttl_seconds,
+            new_config.max_sessions,
+            new_config.persist_sessions,
+        );
         let mut config = self.sticky_config.write().await;
         *config = new_config;
         tracing::debug!("Scheduling configuration updated: {:?}", *config);
     }
 
-    /// 清除特定会话的粘性映射
-    #[allow(dead_code)]
-    pub fn clear_session_binding(&self, session_id: &str) {
-        self.session_accounts.remove(session_id);
+    /// 清除特定会话的粘性映射，返回该会话此前是否存在绑定
+    pub fn clear_session_binding(&self, session_id: &str) -> bool {
+        let removed = self.session_accounts.remove(session_id);
+        self.session_accounts.flush();
+        removed
+    }
+
+    /// 当前有效的会话绑定 (按最近使用时间倒序)
+    pub fn list_sessions(&self) -> Vec<SessionBindingInfo> {
+        self.session_accounts
+            .list()
+            .into_iter()
+            .map(|(session_id, binding)| SessionBindingInfo {
+                email: self.tokens.get(&binding.account_id).map(|t| t.email.clone()),
+                session_id,
+                account_id: binding.account_id,
+                created_at: binding.created_at,
+                last_used: binding.last_used,
+            })
+            .collect()
+    }
+
+    /// 将会话手动绑定到指定账号 (`account` 可为 account_id 或 email)
+    pub fn rebind_session(&self, session_id: &str, account: &str) -> Result<SessionBindingInfo, String> {
+        let token = self
+            .tokens
+            .iter()
+            .find(|e| e.key() == account || e.value().email == account)
+            .map(|e| e.value().clone())
+            .ok_or_else(|| format!("Account not found in pool: {}", account))?;
+
+        self.session_accounts.bind(session_id, &token.account_id);
+        self.session_accounts.flush();
+        tracing::info!("Session {} manually bound to account {}", session_id, token.email);
+        self.list_sessions()
+            .into_iter()
+            .find(|s| s.session_id == session_id)
+            .ok_or_else(|| format!("Session {} was evicted immediately (max_sessions too low?)", session_id))
     }
 
     /// 清除所有会话的粘性映射
     pub fn clear_all_sessions(&self) {
         self.session_accounts.clear();
     }
+
+    /// 启动会话绑定的定期写回任务
+    pub fn spawn_session_flush_task(&self) -> tokio::task::JoinHandle<()> {
+        self.session_accounts.clone().spawn_flush_task()
+    }
+
+    /// 同步写入尚未落盘的会话绑定 (停止服务 / 退出进程前调用)
+    pub fn flush_sessions(&self) {
+        self.session_accounts.flush_blocking();
+    }
+
+    // ===== 准入队列 =====
+
+    pub fn admission_queue(&self) -> &Arc<AdmissionQueue> {
+        &self.admission
+    }
+
+    /// 账号池中是否有可服务该模型的账号 (未限流且并发未满)
+    pub async fn has_available_account(&self, model: &str) -> bool {
+        let max_concurrent = self.sticky_config.read().await.max_concurrent_per_account;
+        self.has_available_account_with(model, max_concurrent)
+    }
+
+    fn has_available_account_with(&self, model: &str, max_concurrent: u32) -> bool {
+        self.tokens.iter().any(|entry| {
+            let token = entry.value();
+            !self.is_rate_limited(&token.account_id)
+                && !self.is_rate_limited(&token.email)
+                && !self.rate_limit_tracker.is_model_rate_limited(&token.email, model)
+                && !self.is_saturated(&token.account_id, max_concurrent)
+        })
+    }
+
+    /// 最早解除限流的账号还需等待多久 (账号级别与该模型的模型级别锁定均需解除)
+    ///
+    /// 返回 None 表示没有账号处于限流中 (只能等待并发槽位释放)
+    pub fn next_reset_in(&self, model: &str) -> Option<u64> {
+        self.tokens
+            .iter()
+            .filter_map(|entry| {
+                let token = entry.value();
+                let account = [&token.account_id, &token.email]
+                    .iter()
+                    .filter_map(|id| self.rate_limit_tracker.get_reset_seconds(id))
+                    .max()
+                    .unwrap_or(0);
+                let model_wait = self
+                    .rate_limit_tracker
+                    .get_model_reset_seconds(&token.email, model)
+                    .unwrap_or(0);
+                Some(account.max(model_wait)).filter(|&w| w > 0)
+            })
+            .min()
+    }
+
+    /// 排队直到该请求位于队首且账号池中有可用账号
+    ///
+    /// 每次被唤醒 (账号释放、队列变化、限流到期或定时轮询) 都会通过 `report` 报告排队状态
+    pub async fn wait_for_admission(
+        &self,
+        ticket: &QueueTicket,
+        model: &str,
+        timeout: std::time::Duration,
+        mut report: impl FnMut(QueueStatus),
+    ) -> Result<(), String> {
+        // 无限流记录时的轮询间隔上限 (并发释放会主动唤醒)
+        const MAX_POLL: std::time::Duration = std::time::Duration::from_secs(5);
+        const MIN_POLL: std::time::Duration = std::time::Duration::from_millis(200);
+
+        let max_concurrent = self.sticky_config.read().await.max_concurrent_per_account;
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let notified = self.admission.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            let ahead = self.admission.ahead_of(ticket);
+            if ahead == 0 && self.has_available_account_with(model, max_concurrent) {
+                return Ok(());
+            }
+
+            let retry_in = self.next_reset_in(model);
+            report(QueueStatus {
+                position: ahead + 1,
+                depth: self.admission.len(),
+                retry_in_secs: retry_in,
+            });
+
+            let now = tokio::time::Instant::now();
+            if now >= deadline {
+                return Err(format!(
+                    "No account became available for {} within {}s (queue position {})",
+                    model,
+                    timeout.as_secs(),
+                    ahead + 1
+                ));
+            }
+            let wake = retry_in
+                .map(std::time::Duration::from_secs)
+                .unwrap_or(MAX_POLL)
+                .clamp(MIN_POLL, MAX_POLL)
+                .min(deadline - now);
+            tokio::select! {
+                _ = notified => {}
+                _ = tokio::time::sleep(wake) => {}
+            }
+        }
+    }
+
+    // ===== 监控指标 =====
+
+    /// 当前处于限流中的账号数 (限流记录可能以 account_id 或 email 为键)
+    pub fn rate_limited_account_count(&self) -> usize {
+        self.tokens
+            .iter()
+            .filter(|entry| {
+                let token = entry.value();
+                self.is_rate_limited(&token.account_id) || self.is_rate_limited(&token.email)
+            })
+            .count()
+    }
+
+    /// 当前绑定的粘性会话数
+    pub fn session_binding_count(&self) -> usize {
+        self.session_accounts.len()
+    }
+
+    /// 当前生效的锁定数量，按 (原因, 是否模型级别) 分组
+    pub fn lockout_counts(&self) -> std::collections::HashMap<(crate::proxy::rate_limit::RateLimitReason, bool), usize> {
+        self.rate_limit_tracker.active_lockout_counts()
+    }
 }
 
 fn truncate_reason(reason: &str, max_len: usize) -> String {
@@ -1257,3 +1771,36 @@ fn truncate_reason(reason: &str, max_len: usize) -> String {
     s.push('…');
     s
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_lock_scope() {
+        let model = "claude-sonnet-4-5";
+        assert_eq!(TokenManager::model_lock_scope("RESOURCE_EXHAUSTED: QUOTA_EXHAUSTED", model), Some(model));
+        assert_eq!(TokenManager::model_lock_scope("MODEL_CAPACITY_EXHAUSTED", model), Some(model));
+        // 普通频率限制 / 服务端错误锁定整个账号
+        assert_eq!(TokenManager::model_lock_scope("Too Many Requests", model), None);
+        assert_eq!(TokenManager::model_lock_scope("Internal error", model), None);
+    }
+
+    #[test]
+    fn test_account_lease_limits_concurrency() {
+        // 独立的数据目录，避免与其他测试共享 rate_limits.json / session_bindings.json
+        let data_dir = std::env::temp_dir().join(format!("token_manager_{}", uuid::Uuid::new_v4()));
+        let manager = TokenManager::new(data_dir.clone());
+        let first = manager.try_lease("a", 2).unwrap();
+        let _second = manager.try_lease("a", 2).unwrap();
+        assert!(manager.is_saturated("a", 2));
+        assert!(manager.try_lease("a", 2).is_none());
+
+        // 租约释放后槽位归还
+        drop(first);
+        assert!(!manager.is_saturated("a", 2));
+        assert!(manager.try_lease("a", 0).is_some());
+
+        let _ = std::fs::remove_dir_all(&data_dir);
+    }
+}
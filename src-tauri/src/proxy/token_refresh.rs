@@ -0,0 +1,267 @@
+// access_token 后台主动刷新
+// 定期扫描账号池，在过期前 margin 秒 (按账号错开的抖动) 刷新，限制并发，刷新结果记录到监控
+// 请求路径上的按需刷新 (get_token) 仍保留作为兜底
+
+use dashmap::DashMap;
+use futures::StreamExt;
+use serde::Serialize;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::proxy::config::TokenRefreshConfig;
+use crate::proxy::TokenManager;
+
+/// 扫描账号池的间隔
+const SCAN_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 刷新失败后的重试退避 (秒)，按连续失败次数翻倍
+const RETRY_BACKOFF_BASE_SECS: i64 = 30;
+const RETRY_BACKOFF_MAX_SECS: i64 = 600;
+
+/// 单个账号的刷新状态
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountRefreshHealth {
+    pub account_id: String,
+    pub email: String,
+    /// access_token 过期时间 (Unix 秒)
+    pub expires_at: i64,
+    pub last_attempt_at: Option<i64>,
+    pub last_success_at: Option<i64>,
+    pub last_error: Option<String>,
+    pub consecutive_failures: u32,
+    /// 因 invalid_grant 已被禁用
+    pub disabled: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TokenRefreshSnapshot {
+    pub enabled: bool,
+    pub total_refreshed: u64,
+    pub total_failed: u64,
+    pub accounts: Vec<AccountRefreshHealth>,
+}
+
+/// 后台刷新器 (由 ProxyMonitor 持有，以便各处读取刷新健康状态)
+pub struct TokenRefresher {
+    config: RwLock<TokenRefreshConfig>,
+    accounts: DashMap<String, AccountRefreshHealth>,
+    total_refreshed: AtomicU64,
+    total_failed: AtomicU64,
+}
+
+impl Default for TokenRefresher {
+    fn default() -> Self {
+        Self::new(&TokenRefreshConfig::default())
+    }
+}
+
+/// 按账号 ID 固定的抖动偏移 (0..jitter)，使同时导入的账号错开刷新时间
+fn jitter_offset(account_id: &str, jitter_secs: u64) -> i64 {
+    if jitter_secs == 0 {
+        return 0;
+    }
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    account_id.hash(&mut hasher);
+    (hasher.finish() % jitter_secs) as i64
+}
+
+/// 该账号本轮是否需要刷新
+///
+/// 距过期不足 margin + 抖动时刷新；连续失败后按指数退避，避免反复请求 OAuth 端点
+fn refresh_due(
+    health: Option<&AccountRefreshHealth>,
+    account_id: &str,
+    expires_at: i64,
+    now: i64,
+    config: &TokenRefreshConfig,
+) -> bool {
+    let jitter = config.jitter_secs.min(config.margin_secs / 2);
+    let lead = config.margin_secs as i64 + jitter_offset(account_id, jitter);
+    if now + lead < expires_at {
+        return false;
+    }
+    match health {
+        Some(h) if h.consecutive_failures > 0 => {
+            let backoff = RETRY_BACKOFF_BASE_SECS
+                .saturating_mul(1 << (h.consecutive_failures - 1).min(10))
+                .min(RETRY_BACKOFF_MAX_SECS);
+            h.last_attempt_at.is_none_or(|at| now - at >= backoff)
+        }
+        _ => true,
+    }
+}
+
+impl TokenRefresher {
+    pub fn new(config: &TokenRefreshConfig) -> Self {
+        Self {
+            config: RwLock::new(config.clone()),
+            accounts: DashMap::new(),
+            total_refreshed: AtomicU64::new(0),
+            total_failed: AtomicU64::new(0),
+        }
+    }
+
+    pub fn configure(&self, config: &TokenRefreshConfig) {
+        if let Ok(mut current) = self.config.write() {
+            *current = config.clone();
+        }
+    }
+
+    fn config(&self) -> TokenRefreshConfig {
+        self.config.read().map(|c| c.clone()).unwrap_or_default()
+    }
+
+    pub fn snapshot(&self) -> TokenRefreshSnapshot {
+        let mut accounts: Vec<AccountRefreshHealth> =
+            self.accounts.iter().map(|e| e.value().clone()).collect();
+        accounts.sort_by_key(|a| a.expires_at);
+        TokenRefreshSnapshot {
+            enabled: self.config().enabled,
+            total_refreshed: self.total_refreshed.load(Ordering::Relaxed),
+            total_failed: self.total_failed.load(Ordering::Relaxed),
+            accounts,
+        }
+    }
+
+    /// 启动后台刷新任务 (服务停止时由调用方 abort)
+    pub fn spawn(self: Arc<Self>, token_manager: Arc<TokenManager>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SCAN_INTERVAL);
+            loop {
+                interval.tick().await;
+                self.refresh_expiring(&token_manager).await;
+            }
+        })
+    }
+
+    /// 扫描一次账号池，刷新即将过期的 token
+    async fn refresh_expiring(&self, token_manager: &TokenManager) {
+        let config = self.config();
+        let now = chrono::Utc::now().timestamp();
+        let tokens = token_manager.token_expiries();
+
+        // 同步账号列表：移除已不在账号池中的账号 (被禁用的保留以便查看原因)
+        self.accounts
+            .retain(|id, h| h.disabled || tokens.iter().any(|(account_id, _, _)| account_id == id));
+        for (account_id, email, expires_at) in &tokens {
+            self.accounts
+                .entry(account_id.clone())
+                .and_modify(|h| {
+                    h.expires_at = *expires_at;
+                    // 重新启用并加载回账号池的账号重新开始计数
+                    if h.disabled {
+                        h.disabled = false;
+                        h.consecutive_failures = 0;
+                    }
+                })
+                .or_insert_with(|| AccountRefreshHealth {
+                    account_id: account_id.clone(),
+                    email: email.clone(),
+                    expires_at: *expires_at,
+                    last_attempt_at: None,
+                    last_success_at: None,
+                    last_error: None,
+                    consecutive_failures: 0,
+                    disabled: false,
+                });
+        }
+
+        if !config.enabled {
+            return;
+        }
+        let due: Vec<(String, String)> = tokens
+            .into_iter()
+            .filter(|(account_id, _, expires_at)| {
+                let health = self.accounts.get(account_id);
+                refresh_due(health.as_deref(), account_id, *expires_at, now, &config)
+            })
+            .map(|(account_id, email, _)| (account_id, email))
+            .collect();
+        if due.is_empty() {
+            return;
+        }
+
+        tracing::debug!("[TokenRefresh] {} 个账号的 token 即将过期，开始后台刷新", due.len());
+        futures::stream::iter(due)
+            .for_each_concurrent(config.max_concurrency.max(1), |(account_id, email)| async move {
+                let result = token_manager.refresh_account_token(&account_id).await;
+                self.record(&account_id, &email, result);
+            })
+            .await;
+    }
+
+    fn record(&self, account_id: &str, email: &str, result: Result<i64, String>) {
+        let now = chrono::Utc::now().timestamp();
+        let Some(mut health) = self.accounts.get_mut(account_id) else {
+            return;
+        };
+        health.last_attempt_at = Some(now);
+        match result {
+            Ok(expires_at) => {
+                self.total_refreshed.fetch_add(1, Ordering::Relaxed);
+                tracing::debug!("[TokenRefresh] {} 刷新成功，{}s 后过期", email, expires_at - now);
+                health.expires_at = expires_at;
+                health.last_success_at = Some(now);
+                health.last_error = None;
+                health.consecutive_failures = 0;
+            }
+            Err(e) => {
+                self.total_failed.fetch_add(1, Ordering::Relaxed);
+                health.consecutive_failures += 1;
+                health.disabled = crate::modules::oauth::is_invalid_grant(&e);
+                tracing::warn!(
+                    "[TokenRefresh] {} 刷新失败 (连续 {} 次){}: {}",
+                    email,
+                    health.consecutive_failures,
+                    if health.disabled { "，账号已禁用" } else { "" },
+                    e
+                );
+                health.last_error = Some(e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn health(failures: u32, last_attempt_at: i64) -> AccountRefreshHealth {
+        AccountRefreshHealth {
+            account_id: "a".to_string(),
+            email: "a@example.com".to_string(),
+            expires_at: 0,
+            last_attempt_at: Some(last_attempt_at),
+            last_success_at: None,
+            last_error: Some("boom".to_string()),
+            consecutive_failures: failures,
+            disabled: false,
+        }
+    }
+
+    #[test]
+    fn test_refresh_due_margin_jitter_and_backoff() {
+        let config = TokenRefreshConfig {
+            enabled: true,
+            margin_secs: 600,
+            jitter_secs: 120,
+            max_concurrency: 4,
+        };
+        let now = 1_000_000;
+        let offset = jitter_offset("a", 120);
+        assert!(offset < 120);
+        assert_eq!(jitter_offset("a", 120), offset);
+
+        // 距过期超过 margin + 抖动时不刷新
+        assert!(!refresh_due(None, "a", now + 600 + offset + 1, now, &config));
+        assert!(refresh_due(None, "a", now + 600 + offset, now, &config));
+        assert!(refresh_due(None, "a", now - 10, now, &config));
+
+        // 失败一次后 30s 内不重试，两次后退避 60s
+        assert!(!refresh_due(Some(&health(1, now - 10)), "a", now, now, &config));
+        assert!(refresh_due(Some(&health(1, now - 30)), "a", now, now, &config));
+        assert!(!refresh_due(Some(&health(2, now - 45)), "a", now, now, &config));
+    }
+}
@@ -3,19 +3,15 @@
 
 use reqwest::{header, Client, Response, StatusCode};
 use serde_json::Value;
-use tokio::time::Duration;
+use std::sync::Arc;
+use tokio::time::{Duration, Instant};
 
-// Cloud Code v1internal endpoints (fallback order: prod → daily)
-// 优先使用稳定的 prod 端点，避免影响缓存命中率
-const V1_INTERNAL_BASE_URL_PROD: &str = "https://cloudcode-pa.googleapis.com/v1internal";
-const V1_INTERNAL_BASE_URL_DAILY: &str = "https://daily-cloudcode-pa.sandbox.googleapis.com/v1internal";
-const V1_INTERNAL_BASE_URL_FALLBACKS: [&str; 2] = [
-    V1_INTERNAL_BASE_URL_PROD,   // 优先使用生产环境（稳定）
-    V1_INTERNAL_BASE_URL_DAILY,  // 备用测试环境（新功能）
-];
+use super::health::EndpointPool;
 
 pub struct UpstreamClient {
     http_client: Client,
+    /// v1internal 端点 (顺序、健康状态与熔断)
+    endpoints: Arc<EndpointPool>,
 }
 
 impl UpstreamClient {
@@ -40,7 +36,16 @@ impl UpstreamClient {
 
         let http_client = builder.build().expect("Failed to create HTTP client");
 
-        Self { http_client }
+        Self {
+            http_client,
+            endpoints: Arc::new(EndpointPool::default()),
+        }
+    }
+
+    /// 使用共享的端点集合 (监控面板读取同一份健康状态)
+    pub fn with_endpoints(mut self, endpoints: Arc<EndpointPool>) -> Self {
+        self.endpoints = endpoints;
+        self
     }
 
     /// 构建 v1internal URL
@@ -68,6 +73,11 @@ impl UpstreamClient {
             || status.is_server_error()
     }
 
+    /// 是否计入端点故障 (429 属于账号配额问题，不影响端点健康)
+    fn is_endpoint_failure(status: StatusCode) -> bool {
+        status != StatusCode::TOO_MANY_REQUESTS && Self::should_try_next_endpoint(status)
+    }
+
     /// 调用 v1internal API（基础方法）
     /// 
     /// 发起基础网络请求，支持多端点自动 Fallback
@@ -96,11 +106,20 @@ impl UpstreamClient {
 
         let mut last_err: Option<String> = None;
 
-        // 遍历所有端点，失败时自动切换
-        for (idx, base_url) in V1_INTERNAL_BASE_URL_FALLBACKS.iter().enumerate() {
+        // 按健康状态过滤后的端点顺序尝试，失败时自动切换
+        let plan = self.endpoints.plan();
+        let endpoints = &plan.endpoints;
+        for (idx, endpoint) in endpoints.iter().enumerate() {
+            let base_url = endpoint.base_url.as_str();
+            // 半开端点的探测名额已被其他请求占用时跳过
+            if !self.endpoints.acquire(&plan, endpoint) {
+                tracing::debug!("Upstream endpoint {} is probing, skipping", base_url);
+                continue;
+            }
             let url = Self::build_url(base_url, method, query_string);
-            let has_next = idx + 1 < V1_INTERNAL_BASE_URL_FALLBACKS.len();
+            let has_next = idx + 1 < endpoints.len();
 
+            let started = Instant::now();
             let response = self
                 .http_client
                 .post(&url)
@@ -112,6 +131,8 @@ impl UpstreamClient {
             match response {
                 Ok(resp) => {
                     let status = resp.status();
+                    self.endpoints
+                        .record(endpoint, !Self::is_endpoint_failure(status), started.elapsed());
                     if status.is_success() {
                         if idx > 0 {
                             tracing::info!(
@@ -119,7 +140,7 @@ impl UpstreamClient {
                                 base_url,
                                 status,
                                 idx + 1,
-                                V1_INTERNAL_BASE_URL_FALLBACKS.len()
+                                endpoints.len()
                             );
                         } else {
                             tracing::debug!("✓ Upstream request succeeded | Endpoint: {} | Status: {}", base_url, status);
@@ -143,6 +164,7 @@ impl UpstreamClient {
                     return Ok(resp);
                 }
                 Err(e) => {
+                    self.endpoints.record(endpoint, false, started.elapsed());
                     let msg = format!("HTTP request failed at {}: {}", base_url, e);
                     tracing::debug!("{}", msg);
                     last_err = Some(msg);
@@ -178,90 +200,54 @@ impl UpstreamClient {
 
     // 已移除弃用的辅助方法 (parse_duration_ms)
 
+    /// 调用 v1internal countTokens
+    ///
+    /// `contents` 为 Gemini 格式的 contents 数组，返回上游计算的 totalTokens
+    pub async fn count_tokens(
+        &self,
+        access_token: &str,
+        model: &str,
+        contents: Value,
+    ) -> Result<u64, String> {
+        let body = serde_json::json!({
+            "request": {
+                "model": format!("models/{}", model),
+                "contents": contents,
+            }
+        });
+
+        let resp = self
+            .call_v1_internal("countTokens", access_token, body, None)
+            .await?;
+        let status = resp.status();
+        if !status.is_success() {
+            let text = resp.text().await.unwrap_or_default();
+            return Err(format!("countTokens returned {}: {}", status, text));
+        }
+
+        let json: Value = resp
+            .json()
+            .await
+            .map_err(|e| format!("Parse json failed: {}", e))?;
+        json.get("totalTokens")
+            .and_then(|v| v.as_u64())
+            .ok_or_else(|| format!("countTokens response missing totalTokens: {}", json))
+    }
+
     /// 获取可用模型列表
     /// 
     /// 获取远端模型列表，支持多端点自动 Fallback
-    #[allow(dead_code)]
     pub async fn fetch_available_models(&self, access_token: &str) -> Result<Value, String> {
-        let mut headers = header::HeaderMap::new();
-        headers.insert(
-            header::CONTENT_TYPE,
-            header::HeaderValue::from_static("application/json"),
-        );
-        headers.insert(
-            header::AUTHORIZATION,
-            header::HeaderValue::from_str(&format!("Bearer {}", access_token))
-                .map_err(|e| e.to_string())?,
-        );
-        headers.insert(
-            header::USER_AGENT,
-            header::HeaderValue::from_static("antigravity/1.11.9 windows/amd64"),
-        );
-
-        let mut last_err: Option<String> = None;
-
-        // 遍历所有端点，失败时自动切换
-        for (idx, base_url) in V1_INTERNAL_BASE_URL_FALLBACKS.iter().enumerate() {
-            let url = Self::build_url(base_url, "fetchAvailableModels", None);
-
-            let response = self
-                .http_client
-                .post(&url)
-                .headers(headers.clone())
-                .json(&serde_json::json!({}))
-                .send()
-                .await;
-
-            match response {
-                Ok(resp) => {
-                    let status = resp.status();
-                    if status.is_success() {
-                        if idx > 0 {
-                            tracing::info!(
-                                "✓ Upstream fallback succeeded for fetchAvailableModels | Endpoint: {} | Status: {}",
-                                base_url,
-                                status
-                            );
-                        } else {
-                            tracing::debug!("✓ fetchAvailableModels succeeded | Endpoint: {}", base_url);
-                        }
-                        let json: Value = resp
-                            .json()
-                            .await
-                            .map_err(|e| format!("Parse json failed: {}", e))?;
-                        return Ok(json);
-                    }
-
-                    // 如果有下一个端点且当前错误可重试，则切换
-                    let has_next = idx + 1 < V1_INTERNAL_BASE_URL_FALLBACKS.len();
-                    if has_next && Self::should_try_next_endpoint(status) {
-                        tracing::warn!(
-                            "fetchAvailableModels returned {} at {}, trying next endpoint",
-                            status,
-                            base_url
-                        );
-                        last_err = Some(format!("Upstream error: {}", status));
-                        continue;
-                    }
-
-                    // 不可重试的错误或已是最后一个端点
-                    return Err(format!("Upstream error: {}", status));
-                }
-                Err(e) => {
-                    let msg = format!("Request failed at {}: {}", base_url, e);
-                    tracing::debug!("{}", msg);
-                    last_err = Some(msg);
-
-                    // 如果是最后一个端点，退出循环
-                    if idx + 1 >= V1_INTERNAL_BASE_URL_FALLBACKS.len() {
-                        break;
-                    }
-                    continue;
-                }
-            }
+        let resp = self
+            .call_v1_internal("fetchAvailableModels", access_token, serde_json::json!({}), None)
+            .await?;
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(format!("Upstream error: {}", status));
         }
-
-        Err(last_err.unwrap_or_else(|| "All endpoints failed".to_string()))
+        resp.json()
+            .await
+            .map_err(|e| format!("Parse json failed: {}", e))
     }
 }
 
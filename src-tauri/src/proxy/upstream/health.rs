@@ -0,0 +1,453 @@
+// v1internal 端点健康状态与熔断
+// 每个端点维护滚动窗口内的错误率与延迟；错误率超过阈值时熔断，
+// 熔断到期后放行一个半开探测请求，探测成功则恢复，失败则重新熔断
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::proxy::config::UpstreamEndpointConfig;
+
+// Cloud Code v1internal endpoints (fallback order: prod → daily)
+// 优先使用稳定的 prod 端点，避免影响缓存命中率
+pub const V1_INTERNAL_BASE_URL_PROD: &str = "https://cloudcode-pa.googleapis.com/v1internal";
+pub const V1_INTERNAL_BASE_URL_DAILY: &str = "https://daily-cloudcode-pa.sandbox.googleapis.com/v1internal";
+pub const V1_INTERNAL_BASE_URL_FALLBACKS: [&str; 2] = [
+    V1_INTERNAL_BASE_URL_PROD,   // 优先使用生产环境（稳定）
+    V1_INTERNAL_BASE_URL_DAILY,  // 备用测试环境（新功能）
+];
+
+/// 滚动窗口最多保留的样本数
+const WINDOW_SIZE: usize = 50;
+/// 样本最长保留时间
+const WINDOW_AGE: Duration = Duration::from_secs(300);
+
+/// 熔断参数
+#[derive(Debug, Clone)]
+pub struct BreakerConfig {
+    pub error_rate_threshold: f64,
+    pub min_samples: usize,
+    pub open_duration: Duration,
+}
+
+impl From<&UpstreamEndpointConfig> for BreakerConfig {
+    fn from(config: &UpstreamEndpointConfig) -> Self {
+        Self {
+            error_rate_threshold: config.error_rate_threshold.clamp(0.0, 1.0),
+            min_samples: config.min_samples.max(1) as usize,
+            open_duration: Duration::from_secs(config.open_secs.max(1)),
+        }
+    }
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        Self::from(&UpstreamEndpointConfig::default())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    at: Instant,
+    success: bool,
+    latency_ms: u64,
+}
+
+#[derive(Debug)]
+enum Circuit {
+    Closed,
+    Open { until: Instant },
+    /// 半开状态，记录探测请求的开始时间 (探测未回报结果超过熔断时长后允许重新探测)
+    HalfOpen { probe_started: Option<Instant> },
+}
+
+#[derive(Debug)]
+struct HealthState {
+    samples: VecDeque<Sample>,
+    circuit: Circuit,
+    total_requests: u64,
+    total_failures: u64,
+}
+
+impl HealthState {
+    fn prune(&mut self, now: Instant) {
+        while self.samples.len() > WINDOW_SIZE
+            || self
+                .samples
+                .front()
+                .is_some_and(|s| now.duration_since(s.at) > WINDOW_AGE)
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    fn error_rate(&self) -> f64 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        let failures = self.samples.iter().filter(|s| !s.success).count();
+        failures as f64 / self.samples.len() as f64
+    }
+}
+
+/// 单个端点的健康快照 (监控面板与 /metrics)
+#[derive(Debug, Clone, Serialize)]
+pub struct EndpointHealthSnapshot {
+    pub base_url: String,
+    pub state: CircuitState,
+    /// 滚动窗口内的错误率 (0-1)
+    pub error_rate: f64,
+    /// 滚动窗口内的平均延迟 (首字节)
+    pub avg_latency_ms: u64,
+    pub samples: usize,
+    pub total_requests: u64,
+    pub total_failures: u64,
+    /// 熔断剩余秒数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub open_remaining_secs: Option<u64>,
+}
+
+pub struct EndpointHealth {
+    pub base_url: String,
+    state: Mutex<HealthState>,
+}
+
+impl EndpointHealth {
+    fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.to_string(),
+            state: Mutex::new(HealthState {
+                samples: VecDeque::with_capacity(WINDOW_SIZE + 1),
+                circuit: Circuit::Closed,
+                total_requests: 0,
+                total_failures: 0,
+            }),
+        }
+    }
+
+    /// 是否可以尝试 (不改变状态，探测名额由 try_acquire 在实际发送前占用)
+    fn is_available(&self, now: Instant, breaker: &BreakerConfig) -> bool {
+        let Ok(state) = self.state.lock() else {
+            return true;
+        };
+        match state.circuit {
+            Circuit::Closed => true,
+            Circuit::Open { until } => now >= until,
+            Circuit::HalfOpen { probe_started } => {
+                probe_started.is_none_or(|t| now.duration_since(t) >= breaker.open_duration)
+            }
+        }
+    }
+
+    /// 是否放行本次请求 (熔断到期时转为半开并放行一个探测请求)
+    fn try_acquire(&self, now: Instant, breaker: &BreakerConfig) -> bool {
+        let Ok(mut state) = self.state.lock() else {
+            return true;
+        };
+        match state.circuit {
+            Circuit::Closed => true,
+            Circuit::Open { until } if now < until => false,
+            Circuit::Open { .. } => {
+                tracing::info!("[Upstream] 端点 {} 熔断到期，放行半开探测请求", self.base_url);
+                state.circuit = Circuit::HalfOpen { probe_started: Some(now) };
+                true
+            }
+            Circuit::HalfOpen { probe_started } => {
+                let stale = probe_started.is_none_or(|t| now.duration_since(t) >= breaker.open_duration);
+                if stale {
+                    state.circuit = Circuit::HalfOpen { probe_started: Some(now) };
+                }
+                stale
+            }
+        }
+    }
+
+    fn record(&self, success: bool, latency: Duration, now: Instant, breaker: &BreakerConfig) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        state.total_requests += 1;
+        if !success {
+            state.total_failures += 1;
+        }
+        let sample = Sample {
+            at: now,
+            success,
+            latency_ms: latency.as_millis() as u64,
+        };
+        state.samples.push_back(sample);
+        state.prune(now);
+
+        match state.circuit {
+            Circuit::HalfOpen { .. } if success => {
+                tracing::info!("[Upstream] 端点 {} 探测成功，熔断恢复", self.base_url);
+                // 熔断前的失败样本不再参与错误率计算
+                state.samples.clear();
+                state.samples.push_back(sample);
+                state.circuit = Circuit::Closed;
+            }
+            Circuit::HalfOpen { .. } => {
+                tracing::warn!("[Upstream] 端点 {} 探测失败，重新熔断", self.base_url);
+                state.circuit = Circuit::Open { until: now + breaker.open_duration };
+            }
+            Circuit::Closed if !success => {
+                let error_rate = state.error_rate();
+                if state.samples.len() >= breaker.min_samples && error_rate >= breaker.error_rate_threshold {
+                    tracing::warn!(
+                        "[Upstream] 端点 {} 错误率 {:.0}% ({} 个样本)，熔断 {} 秒",
+                        self.base_url,
+                        error_rate * 100.0,
+                        state.samples.len(),
+                        breaker.open_duration.as_secs()
+                    );
+                    state.circuit = Circuit::Open { until: now + breaker.open_duration };
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn snapshot(&self, now: Instant) -> EndpointHealthSnapshot {
+        let mut snapshot = EndpointHealthSnapshot {
+            base_url: self.base_url.clone(),
+            state: CircuitState::Closed,
+            error_rate: 0.0,
+            avg_latency_ms: 0,
+            samples: 0,
+            total_requests: 0,
+            total_failures: 0,
+            open_remaining_secs: None,
+        };
+        let Ok(mut state) = self.state.lock() else {
+            return snapshot;
+        };
+        state.prune(now);
+        snapshot.state = match state.circuit {
+            Circuit::Closed => CircuitState::Closed,
+            Circuit::Open { until } if now < until => {
+                snapshot.open_remaining_secs = Some(until.duration_since(now).as_secs());
+                CircuitState::Open
+            }
+            _ => CircuitState::HalfOpen,
+        };
+        snapshot.error_rate = state.error_rate();
+        snapshot.samples = state.samples.len();
+        if !state.samples.is_empty() {
+            snapshot.avg_latency_ms =
+                state.samples.iter().map(|s| s.latency_ms).sum::<u64>() / state.samples.len() as u64;
+        }
+        snapshot.total_requests = state.total_requests;
+        snapshot.total_failures = state.total_failures;
+        snapshot
+    }
+}
+
+struct PoolInner {
+    endpoints: Vec<Arc<EndpointHealth>>,
+    breaker: BreakerConfig,
+}
+
+/// 单次请求的端点尝试顺序
+pub struct EndpointPlan {
+    pub endpoints: Vec<Arc<EndpointHealth>>,
+    /// 全部熔断时强制尝试，不占用探测名额
+    forced: bool,
+}
+
+/// 按优先级排列的 v1internal 端点集合
+pub struct EndpointPool {
+    inner: RwLock<PoolInner>,
+}
+
+impl Default for EndpointPool {
+    fn default() -> Self {
+        Self::new(&UpstreamEndpointConfig::default())
+    }
+}
+
+impl EndpointPool {
+    pub fn new(config: &UpstreamEndpointConfig) -> Self {
+        let pool = Self {
+            inner: RwLock::new(PoolInner {
+                endpoints: Vec::new(),
+                breaker: BreakerConfig::default(),
+            }),
+        };
+        pool.configure(config);
+        pool
+    }
+
+    /// 应用端点顺序与熔断参数 (地址未变化的端点保留已有健康状态)
+    pub fn configure(&self, config: &UpstreamEndpointConfig) {
+        let base_urls: Vec<String> = if config.base_urls.is_empty() {
+            V1_INTERNAL_BASE_URL_FALLBACKS.iter().map(|u| u.to_string()).collect()
+        } else {
+            config
+                .base_urls
+                .iter()
+                .map(|u| u.trim().trim_end_matches('/').to_string())
+                .filter(|u| !u.is_empty())
+                .collect()
+        };
+
+        let Ok(mut inner) = self.inner.write() else {
+            return;
+        };
+        let endpoints = base_urls
+            .iter()
+            .map(|url| {
+                inner
+                    .endpoints
+                    .iter()
+                    .find(|e| &e.base_url == url)
+                    .cloned()
+                    .unwrap_or_else(|| Arc::new(EndpointHealth::new(url)))
+            })
+            .collect();
+        inner.endpoints = endpoints;
+        inner.breaker = BreakerConfig::from(config);
+    }
+
+    /// 本次请求依次尝试的端点: 可用的端点按配置顺序；全部熔断时按配置顺序全部尝试
+    ///
+    /// 半开探测名额不在此占用，由 `acquire` 在实际切换到该端点时占用
+    pub fn plan(&self) -> EndpointPlan {
+        let Ok(inner) = self.inner.read() else {
+            return EndpointPlan { endpoints: Vec::new(), forced: false };
+        };
+        let now = Instant::now();
+        let available: Vec<_> = inner
+            .endpoints
+            .iter()
+            .filter(|e| e.is_available(now, &inner.breaker))
+            .cloned()
+            .collect();
+        if available.is_empty() {
+            tracing::warn!("[Upstream] 所有 v1internal 端点均处于熔断状态，仍按顺序尝试");
+            return EndpointPlan { endpoints: inner.endpoints.clone(), forced: true };
+        }
+        EndpointPlan { endpoints: available, forced: false }
+    }
+
+    /// 发送前占用端点 (熔断到期的端点转为半开并占用探测名额)，名额已被其他请求占用时返回 false
+    pub fn acquire(&self, plan: &EndpointPlan, endpoint: &EndpointHealth) -> bool {
+        if plan.forced {
+            return true;
+        }
+        let breaker = self
+            .inner
+            .read()
+            .map(|inner| inner.breaker.clone())
+            .unwrap_or_default();
+        endpoint.try_acquire(Instant::now(), &breaker)
+    }
+
+    pub fn record(&self, endpoint: &EndpointHealth, success: bool, latency: Duration) {
+        let breaker = self
+            .inner
+            .read()
+            .map(|inner| inner.breaker.clone())
+            .unwrap_or_default();
+        endpoint.record(success, latency, Instant::now(), &breaker);
+    }
+
+    pub fn snapshot(&self) -> Vec<EndpointHealthSnapshot> {
+        let now = Instant::now();
+        self.inner
+            .read()
+            .map(|inner| inner.endpoints.iter().map(|e| e.snapshot(now)).collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_breaker_transitions() {
+        let breaker = BreakerConfig {
+            error_rate_threshold: 0.5,
+            min_samples: 4,
+            open_duration: Duration::from_secs(30),
+        };
+        let endpoint = EndpointHealth::new("http://127.0.0.1:9999/v1internal");
+        let start = Instant::now();
+        let latency = Duration::from_millis(100);
+
+        // 样本不足时不熔断
+        for _ in 0..3 {
+            endpoint.record(false, latency, start, &breaker);
+        }
+        assert!(endpoint.try_acquire(start, &breaker));
+
+        endpoint.record(false, latency, start, &breaker);
+        assert_eq!(endpoint.snapshot(start).state, CircuitState::Open);
+        assert!(!endpoint.try_acquire(start + Duration::from_secs(10), &breaker));
+
+        // 到期后只放行一个探测请求
+        let later = start + Duration::from_secs(31);
+        assert!(endpoint.try_acquire(later, &breaker));
+        assert!(!endpoint.try_acquire(later, &breaker));
+
+        // 探测失败重新熔断，探测成功恢复
+        endpoint.record(false, latency, later, &breaker);
+        assert_eq!(endpoint.snapshot(later).state, CircuitState::Open);
+        let probe = later + Duration::from_secs(31);
+        assert!(endpoint.try_acquire(probe, &breaker));
+        endpoint.record(true, latency, probe, &breaker);
+        let snapshot = endpoint.snapshot(probe);
+        assert_eq!(snapshot.state, CircuitState::Closed);
+        assert_eq!(snapshot.total_requests, 6);
+        assert_eq!(snapshot.avg_latency_ms, 100);
+    }
+
+    #[test]
+    fn test_availability_check_does_not_take_probe() {
+        let breaker = BreakerConfig {
+            error_rate_threshold: 0.5,
+            min_samples: 1,
+            open_duration: Duration::from_secs(30),
+        };
+        let endpoint = EndpointHealth::new("http://127.0.0.1:9999/v1internal");
+        let start = Instant::now();
+        endpoint.record(false, Duration::from_millis(100), start, &breaker);
+        assert!(!endpoint.is_available(start, &breaker));
+
+        // 熔断到期后可进入计划，但只有实际占用时才转为半开
+        let later = start + Duration::from_secs(31);
+        assert!(endpoint.is_available(later, &breaker));
+        assert!(endpoint.is_available(later, &breaker));
+        assert!(endpoint.try_acquire(later, &breaker));
+        assert!(!endpoint.is_available(later, &breaker));
+    }
+
+    #[test]
+    fn test_pool_configure_keeps_health() {
+        let pool = EndpointPool::default();
+        assert_eq!(pool.plan().endpoints.len(), 2);
+
+        let endpoint = pool.plan().endpoints[0].clone();
+        pool.record(&endpoint, true, Duration::from_millis(50));
+
+        pool.configure(&UpstreamEndpointConfig {
+            base_urls: vec![
+                "http://127.0.0.1:8080/v1internal/".to_string(),
+                V1_INTERNAL_BASE_URL_PROD.to_string(),
+            ],
+            ..Default::default()
+        });
+        let snapshot = pool.snapshot();
+        assert_eq!(snapshot[0].base_url, "http://127.0.0.1:8080/v1internal");
+        assert_eq!(snapshot[1].total_requests, 1);
+    }
+}
@@ -0,0 +1,244 @@
+// 对冲请求 (Hedged Requests)
+// 首个上游调用在阈值内未返回响应时，换一个账号发起同样的请求，取先成功者并取消另一方
+// 会额外消耗配额，仅对 model_hedging 中配置的模型启用
+
+use reqwest::{Response, StatusCode};
+use serde_json::Value;
+use std::future::Future;
+use tokio::time::{Duration, Instant};
+
+use super::client::UpstreamClient;
+use crate::proxy::monitor::{ProxyMonitor, ProxyRequestLog};
+use crate::proxy::token_manager::AccountLease;
+use crate::proxy::TokenManager;
+
+/// 对冲所需的上下文 (账号池、监控与本次请求的模型)
+pub struct HedgeOptions<'a> {
+    /// 首个调用等待多久后发起对冲
+    pub after: Duration,
+    pub token_manager: &'a TokenManager,
+    pub monitor: &'a ProxyMonitor,
+    pub quota_group: &'a str,
+    pub model: &'a str,
+}
+
+/// 对冲调用结果
+pub struct HedgedCall {
+    pub result: Result<Response, String>,
+    /// 实际服务该请求的账号
+    pub email: String,
+    /// 对冲账号胜出时为其并发租约 (调用方应以此替换首个账号的租约)
+    pub lease: Option<AccountLease>,
+}
+
+/// 两个调用中先成功者胜出；先返回的一方失败时等待另一方
+///
+/// 返回 (结果, 是否来自 secondary, 先失败一方的结果)
+async fn race<F1, F2>(
+    primary: F1,
+    secondary: F2,
+) -> (Result<Response, String>, bool, Option<Result<Response, String>>)
+where
+    F1: Future<Output = Result<Response, String>>,
+    F2: Future<Output = Result<Response, String>>,
+{
+    tokio::pin!(primary);
+    tokio::pin!(secondary);
+
+    let (first, from_secondary) = tokio::select! {
+        r = &mut primary => (r, false),
+        r = &mut secondary => (r, true),
+    };
+    if matches!(&first, Ok(r) if r.status().is_success()) {
+        return (first, from_secondary, None);
+    }
+
+    let other = if from_secondary {
+        (&mut primary).await
+    } else {
+        (&mut secondary).await
+    };
+    if matches!(&other, Ok(r) if r.status().is_success()) {
+        (other, !from_secondary, Some(first))
+    } else {
+        // 双方均失败，以先返回的错误为准
+        (first, from_secondary, None)
+    }
+}
+
+impl UpstreamClient {
+    /// 调用 v1internal，按 `hedge` 配置在首字节 (响应头) 超时后换账号对冲
+    ///
+    /// 对冲请求沿用同一请求体，仅替换 `project`；被取消的一方以 499 记录到监控日志，
+    /// 输入 token 按请求体估算计入该账号
+    pub async fn call_v1_internal_hedged(
+        &self,
+        method: &str,
+        access_token: &str,
+        email: &str,
+        body: Value,
+        query_string: Option<&str>,
+        hedge: Option<HedgeOptions<'_>>,
+    ) -> HedgedCall {
+        let primary_call = |result| HedgedCall {
+            result,
+            email: email.to_string(),
+            lease: None,
+        };
+        let Some(hedge) = hedge else {
+            return primary_call(self.call_v1_internal(method, access_token, body, query_string).await);
+        };
+
+        let hedge_body = body.clone();
+        let started = Instant::now();
+        let primary = self.call_v1_internal(method, access_token, body, query_string);
+        tokio::pin!(primary);
+
+        tokio::select! {
+            r = &mut primary => return primary_call(r),
+            _ = tokio::time::sleep(hedge.after) => {}
+        }
+
+        // 获取对冲账号期间首个调用仍可能返回
+        let acquire = hedge
+            .token_manager
+            .get_token_for_model(hedge.quota_group, true, None, hedge.model);
+        let account = tokio::select! {
+            r = &mut primary => return primary_call(r),
+            acquired = acquire => acquired,
+        };
+        let (hedge_token, hedge_project, hedge_email, hedge_lease) = match account {
+            Ok(t) if t.2 != email => t,
+            Ok(_) => {
+                tracing::debug!("[Hedge] 无其他可用账号，继续等待 {}", email);
+                return primary_call(primary.await);
+            }
+            Err(e) => {
+                tracing::debug!("[Hedge] 获取对冲账号失败: {}", e);
+                return primary_call(primary.await);
+            }
+        };
+
+        tracing::info!(
+            "[Hedge] {} 在 {}ms 内未响应 (model: {})，使用 {} 发起对冲请求",
+            email,
+            hedge.after.as_millis(),
+            hedge.model,
+            hedge_email
+        );
+        let mut hedge_body = hedge_body;
+        hedge_body["project"] = Value::String(hedge_project);
+        let estimated_input = crate::proxy::common::token_counter::estimate_gemini_request_tokens(
+            hedge_body.get("request").unwrap_or(&hedge_body),
+        );
+        let hedge_started = Instant::now();
+        let secondary = self.call_v1_internal(method, &hedge_token, hedge_body, query_string);
+
+        let (result, from_hedge, failed_first) = race(primary, secondary).await;
+        let (winner, loser, loser_elapsed) = if from_hedge {
+            (hedge_email.as_str(), email, started.elapsed())
+        } else {
+            (email, hedge_email.as_str(), hedge_started.elapsed())
+        };
+
+        match failed_first {
+            // 先返回的一方失败后被另一方接管：限流信息仍需同步到账号池
+            Some(failed) => {
+                if let Ok(resp) = failed {
+                    if resp.status() == StatusCode::TOO_MANY_REQUESTS {
+                        let retry_after = resp
+                            .headers()
+                            .get("Retry-After")
+                            .and_then(|h| h.to_str().ok())
+                            .map(|s| s.to_string());
+                        let error_text = resp.text().await.unwrap_or_default();
+                        hedge.token_manager.mark_model_rate_limited(
+                            loser,
+                            429,
+                            retry_after.as_deref(),
+                            &error_text,
+                            hedge.model,
+                        );
+                    }
+                }
+                tracing::warn!("[Hedge] {} 请求失败，由 {} 的响应接管", loser, winner);
+            }
+            None if result.as_ref().is_ok_and(|r| r.status().is_success()) => {
+                tracing::info!("[Hedge] {} 先返回，已取消 {} 的请求", winner, loser);
+                log_cancelled(hedge.monitor, method, loser, hedge.model, loser_elapsed, estimated_input).await;
+            }
+            None => {}
+        }
+
+        HedgedCall {
+            result,
+            email: winner.to_string(),
+            lease: from_hedge.then_some(hedge_lease),
+        }
+    }
+}
+
+/// 被取消的一方计入监控日志 (上游已接收请求，输入 token 按估算计入该账号)
+async fn log_cancelled(
+    monitor: &ProxyMonitor,
+    method: &str,
+    email: &str,
+    model: &str,
+    elapsed: Duration,
+    estimated_input: u64,
+) {
+    monitor
+        .log_request(ProxyRequestLog {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            method: "POST".to_string(),
+            url: format!("v1internal:{} (hedge)", method),
+            status: 499,
+            duration: elapsed.as_millis() as u64,
+            model: Some(model.to_string()),
+            mapped_model: Some(model.to_string()),
+            account_email: Some(email.to_string()),
+            api_key_name: None,
+            fallback_from: None,
+            error: Some("Hedged request cancelled: another account answered first".to_string()),
+            request_body: None,
+            response_body: None,
+            input_tokens: Some(estimated_input.min(u32::MAX as u64) as u32),
+            output_tokens: Some(0),
+        })
+        .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(status: u16) -> Result<Response, String> {
+        Ok(Response::from(
+            axum::http::Response::builder().status(status).body("").unwrap(),
+        ))
+    }
+
+    async fn delayed(ms: u64, status: u16) -> Result<Response, String> {
+        tokio::time::sleep(Duration::from_millis(ms)).await;
+        response(status)
+    }
+
+    #[tokio::test]
+    async fn test_race_prefers_first_success() {
+        let (result, from_secondary, failed) = race(delayed(50, 200), delayed(5, 200)).await;
+        assert!(from_secondary && failed.is_none());
+        assert_eq!(result.unwrap().status(), StatusCode::OK);
+
+        // 先返回的 429 不会抢占随后成功的响应
+        let (result, from_secondary, failed) = race(delayed(30, 200), delayed(5, 429)).await;
+        assert!(!from_secondary);
+        assert_eq!(result.unwrap().status(), StatusCode::OK);
+        assert_eq!(failed.unwrap().unwrap().status(), StatusCode::TOO_MANY_REQUESTS);
+
+        // 双方均失败时返回先到的错误
+        let (result, from_secondary, _) = race(delayed(5, 503), delayed(30, 429)).await;
+        assert!(!from_secondary);
+        assert_eq!(result.unwrap().status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
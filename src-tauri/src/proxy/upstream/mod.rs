@@ -2,5 +2,7 @@
 // 对应上游通讯接口
 
 pub mod client;
+pub mod health; // v1internal 端点健康状态与熔断
+pub mod hedge;  // 首字节超时后的跨账号对冲请求
 pub mod retry;
 pub mod models;
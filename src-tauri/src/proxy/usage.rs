@@ -0,0 +1,233 @@
+// 客户端密钥用量统计与 token 预算
+// 用量按本地日期聚合写入 proxy_logs.db (api_key_usage 表)，月用量为当月各天之和
+
+use chrono::{Datelike, Local, NaiveDate, TimeZone};
+use serde::{Deserialize, Serialize};
+
+use crate::proxy::config::ClientApiKey;
+use crate::proxy::security::{ProxySecurityConfig, PRIMARY_API_KEY_NAME};
+
+/// 单个客户端密钥的当日 / 当月用量
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ApiKeyUsage {
+    pub key_name: String,
+    pub day: String,
+    pub month: String,
+    pub daily_input_tokens: u64,
+    pub daily_output_tokens: u64,
+    pub daily_requests: u64,
+    pub monthly_input_tokens: u64,
+    pub monthly_output_tokens: u64,
+    pub monthly_requests: u64,
+    pub daily_token_budget: Option<u64>,
+    pub monthly_token_budget: Option<u64>,
+}
+
+impl ApiKeyUsage {
+    pub fn daily_tokens(&self) -> u64 {
+        self.daily_input_tokens + self.daily_output_tokens
+    }
+
+    pub fn monthly_tokens(&self) -> u64 {
+        self.monthly_input_tokens + self.monthly_output_tokens
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetPeriod {
+    Daily,
+    Monthly,
+}
+
+/// 预算耗尽信息
+#[derive(Debug, Clone)]
+pub struct BudgetExceeded {
+    pub period: BudgetPeriod,
+    pub used: u64,
+    pub limit: u64,
+    /// 距离预算重置的秒数 (用于 Retry-After)
+    pub retry_after_secs: u64,
+}
+
+impl BudgetExceeded {
+    pub fn message(&self, key_name: &str) -> String {
+        let period = match self.period {
+            BudgetPeriod::Daily => "daily",
+            BudgetPeriod::Monthly => "monthly",
+        };
+        format!(
+            "API key '{}' has exhausted its {} token budget ({} / {} tokens). Resets in {}s.",
+            key_name, period, self.used, self.limit, self.retry_after_secs
+        )
+    }
+}
+
+/// 当前本地日期 (YYYY-MM-DD) 与月份 (YYYY-MM)
+pub fn current_periods() -> (String, String) {
+    let today = Local::now().date_naive();
+    (
+        today.format("%Y-%m-%d").to_string(),
+        today.format("%Y-%m").to_string(),
+    )
+}
+
+/// 距离下一个本地零点 / 下月一日零点的秒数
+fn seconds_until_reset(period: BudgetPeriod) -> u64 {
+    let now = Local::now();
+    let today = now.date_naive();
+    let next: Option<NaiveDate> = match period {
+        BudgetPeriod::Daily => today.succ_opt(),
+        BudgetPeriod::Monthly => {
+            if today.month() == 12 {
+                NaiveDate::from_ymd_opt(today.year() + 1, 1, 1)
+            } else {
+                NaiveDate::from_ymd_opt(today.year(), today.month() + 1, 1)
+            }
+        }
+    };
+    next.and_then(|d| d.and_hms_opt(0, 0, 0))
+        .and_then(|dt| Local.from_local_datetime(&dt).earliest())
+        .map(|reset| (reset - now).num_seconds().max(1) as u64)
+        .unwrap_or(60)
+}
+
+/// 判断用量是否超出预算，优先报告日预算
+pub fn evaluate_budget(usage: &ApiKeyUsage) -> Option<BudgetExceeded> {
+    if let Some(limit) = usage.daily_token_budget {
+        if usage.daily_tokens() >= limit {
+            return Some(BudgetExceeded {
+                period: BudgetPeriod::Daily,
+                used: usage.daily_tokens(),
+                limit,
+                retry_after_secs: seconds_until_reset(BudgetPeriod::Daily),
+            });
+        }
+    }
+    if let Some(limit) = usage.monthly_token_budget {
+        if usage.monthly_tokens() >= limit {
+            return Some(BudgetExceeded {
+                period: BudgetPeriod::Monthly,
+                used: usage.monthly_tokens(),
+                limit,
+                retry_after_secs: seconds_until_reset(BudgetPeriod::Monthly),
+            });
+        }
+    }
+    None
+}
+
+/// 读取密钥的当日 / 当月用量 (附带该密钥配置的预算)
+pub fn get_usage(key: &ClientApiKey) -> Result<ApiKeyUsage, String> {
+    let (day, month) = current_periods();
+    let (d_in, d_out, d_req) = crate::modules::proxy_db::get_key_usage(&key.name, &day)?;
+    let (m_in, m_out, m_req) = crate::modules::proxy_db::get_key_usage(&key.name, &month)?;
+    Ok(ApiKeyUsage {
+        key_name: key.name.clone(),
+        day,
+        month,
+        daily_input_tokens: d_in,
+        daily_output_tokens: d_out,
+        daily_requests: d_req,
+        monthly_input_tokens: m_in,
+        monthly_output_tokens: m_out,
+        monthly_requests: m_req,
+        daily_token_budget: key.daily_token_budget,
+        monthly_token_budget: key.monthly_token_budget,
+    })
+}
+
+/// 检查密钥预算，未配置预算的密钥不会访问数据库
+pub fn check_budget(key: &ClientApiKey) -> Result<(), BudgetExceeded> {
+    if key.daily_token_budget.is_none() && key.monthly_token_budget.is_none() {
+        return Ok(());
+    }
+    match get_usage(key) {
+        Ok(usage) => match evaluate_budget(&usage) {
+            Some(exceeded) => Err(exceeded),
+            None => Ok(()),
+        },
+        Err(e) => {
+            // 统计读取失败时放行，避免因数据库问题阻断所有请求
+            tracing::warn!("[Usage] Failed to read usage for key '{}': {}", key.name, e);
+            Ok(())
+        }
+    }
+}
+
+/// 记录一次请求的 token 用量
+pub fn record_usage(key_name: &str, input_tokens: u64, output_tokens: u64) -> Result<(), String> {
+    let (day, _) = current_periods();
+    crate::modules::proxy_db::record_key_usage(key_name, &day, input_tokens, output_tokens)
+}
+
+/// 汇总所有密钥的用量：包含主密钥、全部具名密钥，以及本月有记录但已从配置移除的密钥
+pub fn collect_all_usage(security: &ProxySecurityConfig) -> Result<Vec<ApiKeyUsage>, String> {
+    let mut keys: Vec<ClientApiKey> = Vec::new();
+    if let Some(primary) = security.find_key(&security.api_key) {
+        keys.push(primary);
+    }
+    keys.extend(security.api_keys.iter().cloned());
+
+    let (_, month) = current_periods();
+    for name in crate::modules::proxy_db::list_usage_key_names(&month)? {
+        if !keys.iter().any(|k| k.name == name) {
+            keys.push(ClientApiKey {
+                name,
+                key: String::new(),
+                enabled: false,
+                scopes: Vec::new(),
+                allowed_models: Vec::new(),
+                expires_at: None,
+                daily_token_budget: None,
+                monthly_token_budget: None,
+                priority: 0,
+            });
+        }
+    }
+
+    keys.iter().map(get_usage).collect()
+}
+
+/// 主密钥用于查看全部用量，其余密钥仅能查看自身
+pub fn is_admin_key(key: &ClientApiKey) -> bool {
+    key.name == PRIMARY_API_KEY_NAME
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(daily: u64, monthly: u64, daily_budget: Option<u64>, monthly_budget: Option<u64>) -> ApiKeyUsage {
+        ApiKeyUsage {
+            key_name: "ci".to_string(),
+            daily_input_tokens: daily / 2,
+            daily_output_tokens: daily - daily / 2,
+            monthly_input_tokens: monthly,
+            daily_token_budget: daily_budget,
+            monthly_token_budget: monthly_budget,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_budget_not_exceeded() {
+        assert!(evaluate_budget(&usage(100, 1000, Some(200), Some(2000))).is_none());
+        assert!(evaluate_budget(&usage(100, 1000, None, None)).is_none());
+    }
+
+    #[test]
+    fn test_daily_budget_reported_first() {
+        let exceeded = evaluate_budget(&usage(300, 5000, Some(300), Some(2000))).unwrap();
+        assert_eq!(exceeded.period, BudgetPeriod::Daily);
+        assert_eq!(exceeded.used, 300);
+        assert_eq!(exceeded.limit, 300);
+        assert!(exceeded.retry_after_secs > 0 && exceeded.retry_after_secs <= 86400 + 3600);
+    }
+
+    #[test]
+    fn test_monthly_budget_exceeded() {
+        let exceeded = evaluate_budget(&usage(10, 2000, Some(300), Some(2000))).unwrap();
+        assert_eq!(exceeded.period, BudgetPeriod::Monthly);
+        assert!(exceeded.message("ci").contains("monthly"));
+    }
+}
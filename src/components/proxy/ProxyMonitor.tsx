@@ -22,6 +22,8 @@ interface ProxyRequestLog {
     input_tokens?: number;
     output_tokens?: number;
     account_email?: string;
+    api_key_name?: string;
+    fallback_from?: string;
 }
 
 interface ProxyStats {
@@ -354,6 +356,12 @@ export const ProxyMonitor: React.FC<ProxyMonitorProps> = ({ className }) => {
                                                 <span className="font-mono font-black text-green-600 dark:text-green-400 break-all text-sm">{selectedLog.mapped_model}</span>
                                             </div>
                                         )}
+                                        {selectedLog.fallback_from && (
+                                            <div className="space-y-1.5">
+                                                <span className="block text-gray-500 dark:text-slate-400 uppercase font-black text-[10px] tracking-widest">{t('monitor.details.fallback_from')}</span>
+                                                <span className="font-mono font-black text-amber-600 dark:text-amber-400 break-all text-sm">{selectedLog.fallback_from}</span>
+                                            </div>
+                                        )}
                                     </div>
                                 </div>
                                 {selectedLog.account_email && (
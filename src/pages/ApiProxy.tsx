@@ -916,7 +916,23 @@ print(response.text)`;
                                                 <option value="exclusive">{t('proxy.config.zai.modes.exclusive')}</option>
                                                 <option value="pooled">{t('proxy.config.zai.modes.pooled')}</option>
                                                 <option value="fallback">{t('proxy.config.zai.modes.fallback')}</option>
+                                                <option value="weighted">{t('proxy.config.zai.modes.weighted')}</option>
                                             </select>
+                                            {appConfig.proxy.zai?.dispatch_mode === 'weighted' && (
+                                                <div className="flex items-center gap-2 pt-1">
+                                                    <span className="text-[11px] text-gray-500 dark:text-gray-400">
+                                                        {t('proxy.config.zai.weight')}
+                                                    </span>
+                                                    <input
+                                                        type="number"
+                                                        min={0}
+                                                        max={100}
+                                                        value={appConfig.proxy.zai?.weight ?? 0}
+                                                        onChange={(e) => updateZaiGeneralConfig({ weight: Math.min(100, Math.max(0, Number(e.target.value) || 0)) })}
+                                                        className="input input-sm input-bordered w-20 text-xs"
+                                                    />
+                                                </div>
+                                            )}
                                         </div>
                                     </div>
 
@@ -1133,7 +1149,7 @@ print(response.text)`;
                                                 </button>
                                             </div>
                                             <div className="grid grid-cols-1 gap-2">
-                                                {(['CacheFirst', 'Balance', 'PerformanceFirst'] as const).map(mode => (
+                                                {(['CacheFirst', 'Balance', 'PerformanceFirst', 'LeastLoaded', 'QuotaWeighted'] as const).map(mode => (
                                                     <label
                                                         key={mode}
                                                         className={`flex items-start gap-3 p-3 rounded-xl border cursor-pointer transition-all duration-200 ${(appConfig.proxy.scheduling?.mode || 'Balance') === mode
@@ -1155,7 +1171,9 @@ print(response.text)`;
                                                                 {t(`proxy.config.scheduling.modes_desc.${mode}`, {
                                                                     defaultValue: mode === 'CacheFirst' ? 'Binds session to account, waits precisely if limited (Maximizes Prompt Cache hits).' :
                                                                         mode === 'Balance' ? 'Binds session, auto-switches to available account if limited (Balanced cache & availability).' :
-                                                                            'No session binding, pure round-robin rotation (Best for high concurrency).'
+                                                                            mode === 'LeastLoaded' ? 'No session binding, picks the account with the fewest in-flight requests and the lowest recent error rate.' :
+                                                                                mode === 'QuotaWeighted' ? 'No session binding, weighted random by remaining model quota and time to reset (drains quota evenly).' :
+                                                                                    'No session binding, pure round-robin rotation (Best for high concurrency).'
                                                                 })}
                                                             </div>
                                                         </div>
@@ -1191,6 +1209,129 @@ print(response.text)`;
                                                 </div>
                                             </div>
 
+                                            <div className="bg-slate-100 dark:bg-slate-800/80 rounded-xl p-4 border border-slate-200 dark:border-slate-700">
+                                                <div className="flex items-center justify-between">
+                                                    <label className="text-xs font-medium text-gray-700 dark:text-gray-300 inline-flex items-center gap-1">
+                                                        {t('proxy.config.scheduling.max_concurrent')}
+                                                        <HelpTooltip text={t('proxy.config.scheduling.max_concurrent_tooltip')} />
+                                                    </label>
+                                                    <input
+                                                        type="number"
+                                                        min="0"
+                                                        max="64"
+                                                        className="input input-bordered input-xs w-20 text-right font-mono"
+                                                        value={appConfig.proxy.scheduling?.max_concurrent_per_account || 0}
+                                                        onChange={(e) => updateSchedulingConfig({ max_concurrent_per_account: Math.max(0, parseInt(e.target.value) || 0) })}
+                                                    />
+                                                </div>
+                                            </div>
+
+                                            <div className="bg-slate-100 dark:bg-slate-800/80 rounded-xl p-4 border border-slate-200 dark:border-slate-700">
+                                                <div className="flex items-center justify-between gap-3">
+                                                    <label className="text-xs font-medium text-gray-700 dark:text-gray-300 inline-flex items-center gap-1 shrink-0">
+                                                        {t('proxy.config.scheduling.tier_priority')}
+                                                        <HelpTooltip text={t('proxy.config.scheduling.tier_priority_tooltip')} />
+                                                    </label>
+                                                    <input
+                                                        type="text"
+                                                        key={(appConfig.proxy.scheduling?.tier_priority || ['ULTRA', 'PRO', 'FREE']).join(',')}
+                                                        className="input input-bordered input-xs w-40 text-right font-mono"
+                                                        defaultValue={(appConfig.proxy.scheduling?.tier_priority || ['ULTRA', 'PRO', 'FREE']).join(', ')}
+                                                        onBlur={(e) => updateSchedulingConfig({
+                                                            tier_priority: e.target.value.split(',').map(s => s.trim().toUpperCase()).filter(Boolean)
+                                                        })}
+                                                    />
+                                                </div>
+                                            </div>
+
+                                            <div className="bg-slate-100 dark:bg-slate-800/80 rounded-xl p-4 border border-slate-200 dark:border-slate-700 space-y-3">
+                                                <div className="flex items-center justify-between">
+                                                    <label className="text-xs font-medium text-gray-700 dark:text-gray-300 inline-flex items-center gap-1">
+                                                        {t('proxy.config.scheduling.session_ttl')}
+                                                        <HelpTooltip text={t('proxy.config.scheduling.session_ttl_tooltip')} />
+                                                    </label>
+                                                    <input
+                                                        type="number"
+                                                        min="0"
+                                                        step="60"
+                                                        className="input input-bordered input-xs w-24 text-right font-mono"
+                                                        value={appConfig.proxy.scheduling?.session_ttl_seconds ?? 3600}
+                                                        onChange={(e) => updateSchedulingConfig({ session_ttl_seconds: Math.max(0, parseInt(e.target.value) || 0) })}
+                                                    />
+                                                </div>
+                                                <div className="flex items-center justify-between">
+                                                    <label className="text-xs font-medium text-gray-700 dark:text-gray-300 inline-flex items-center gap-1">
+                                                        {t('proxy.config.scheduling.max_sessions')}
+                                                        <HelpTooltip text={t('proxy.config.scheduling.max_sessions_tooltip')} />
+                                                    </label>
+                                                    <input
+                                                        type="number"
+                                                        min="0"
+                                                        step="100"
+                                                        className="input input-bordered input-xs w-24 text-right font-mono"
+                                                        value={appConfig.proxy.scheduling?.max_sessions ?? 10000}
+                                                        onChange={(e) => updateSchedulingConfig({ max_sessions: Math.max(0, parseInt(e.target.value) || 0) })}
+                                                    />
+                                                </div>
+                                                <div className="flex items-center justify-between">
+                                                    <label className="text-xs font-medium text-gray-700 dark:text-gray-300 inline-flex items-center gap-1">
+                                                        {t('proxy.config.scheduling.persist_sessions')}
+                                                        <HelpTooltip text={t('proxy.config.scheduling.persist_sessions_tooltip')} />
+                                                    </label>
+                                                    <input
+                                                        type="checkbox"
+                                                        className="toggle toggle-sm bg-gray-200 dark:bg-gray-700 border-gray-300 dark:border-gray-600 checked:bg-blue-500 checked:border-blue-500"
+                                                        checked={appConfig.proxy.scheduling?.persist_sessions || false}
+                                                        onChange={(e) => updateSchedulingConfig({ persist_sessions: e.target.checked })}
+                                                    />
+                                                </div>
+                                            </div>
+
+                                            <div className="bg-slate-100 dark:bg-slate-800/80 rounded-xl p-4 border border-slate-200 dark:border-slate-700 space-y-3">
+                                                <div className="flex items-center justify-between">
+                                                    <label className="text-xs font-medium text-gray-700 dark:text-gray-300 inline-flex items-center gap-1">
+                                                        {t('proxy.config.scheduling.queue_enabled')}
+                                                        <HelpTooltip text={t('proxy.config.scheduling.queue_enabled_tooltip')} />
+                                                    </label>
+                                                    <input
+                                                        type="checkbox"
+                                                        className="toggle toggle-sm bg-gray-200 dark:bg-gray-700 border-gray-300 dark:border-gray-600 checked:bg-blue-500 checked:border-blue-500"
+                                                        checked={appConfig.proxy.scheduling?.queue_enabled || false}
+                                                        onChange={(e) => updateSchedulingConfig({ queue_enabled: e.target.checked })}
+                                                    />
+                                                </div>
+                                                <div className="flex items-center justify-between">
+                                                    <label className="text-xs font-medium text-gray-700 dark:text-gray-300 inline-flex items-center gap-1">
+                                                        {t('proxy.config.scheduling.queue_max_depth')}
+                                                        <HelpTooltip text={t('proxy.config.scheduling.queue_max_depth_tooltip')} />
+                                                    </label>
+                                                    <input
+                                                        type="number"
+                                                        min="0"
+                                                        step="10"
+                                                        className="input input-bordered input-xs w-24 text-right font-mono"
+                                                        disabled={!appConfig.proxy.scheduling?.queue_enabled}
+                                                        value={appConfig.proxy.scheduling?.queue_max_depth ?? 100}
+                                                        onChange={(e) => updateSchedulingConfig({ queue_max_depth: Math.max(0, parseInt(e.target.value) || 0) })}
+                                                    />
+                                                </div>
+                                                <div className="flex items-center justify-between">
+                                                    <label className="text-xs font-medium text-gray-700 dark:text-gray-300 inline-flex items-center gap-1">
+                                                        {t('proxy.config.scheduling.queue_timeout')}
+                                                        <HelpTooltip text={t('proxy.config.scheduling.queue_timeout_tooltip')} />
+                                                    </label>
+                                                    <input
+                                                        type="number"
+                                                        min="1"
+                                                        step="30"
+                                                        className="input input-bordered input-xs w-24 text-right font-mono"
+                                                        disabled={!appConfig.proxy.scheduling?.queue_enabled}
+                                                        value={appConfig.proxy.scheduling?.queue_timeout_seconds ?? 300}
+                                                        onChange={(e) => updateSchedulingConfig({ queue_timeout_seconds: Math.max(1, parseInt(e.target.value) || 1) })}
+                                                    />
+                                                </div>
+                                            </div>
+
                                             <div className="p-3 bg-amber-50 dark:bg-amber-900/10 border border-amber-100 dark:border-amber-900/20 rounded-xl">
                                                 <p className="text-[10px] text-amber-700 dark:text-amber-500 leading-relaxed">
                                                     <strong>{t('common.info')}:</strong> {t('proxy.config.scheduling.subtitle')}
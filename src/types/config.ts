@@ -9,23 +9,112 @@ export interface ProxyConfig {
     auth_mode?: 'off' | 'strict' | 'all_except_health' | 'auto';
     port: number;
     api_key: string;
+    api_keys?: ClientApiKey[];
     auto_start: boolean;
     custom_mapping?: Record<string, string>;
+    model_routing_rules?: ModelRoutingRule[]; // 有序，优先于 custom_mapping
+    model_fallback_chains?: Record<string, string[]>; // 映射后模型 -> 回退链
+    model_hedging?: Record<string, number>; // 映射后模型 (支持 * 通配符) -> 对冲阈值 ms
+    model_capabilities?: Record<string, ModelCapabilities>; // 模型能力覆盖，key 支持 * 通配符
     request_timeout: number;
     enable_logging: boolean;
     upstream_proxy: UpstreamProxyConfig;
     zai?: ZaiConfig;
+    providers?: UpstreamProviderConfig[]; // 自定义上游服务商
+    upstream_endpoints?: UpstreamEndpointConfig; // v1internal 端点顺序与熔断
+    token_refresh?: TokenRefreshConfig; // access_token 后台主动刷新
     scheduling?: StickySessionConfig;
 }
 
-export type SchedulingMode = 'CacheFirst' | 'Balance' | 'PerformanceFirst';
+export interface UpstreamEndpointConfig {
+    base_urls?: string[]; // 空表示 prod -> daily
+    error_rate_threshold?: number;
+    min_samples?: number;
+    open_secs?: number;
+}
+
+export interface TokenRefreshConfig {
+    enabled?: boolean;
+    margin_secs?: number; // 过期前多少秒刷新
+    jitter_secs?: number; // 按账号错开的额外提前量 (不超过 margin 的一半)
+    max_concurrency?: number;
+}
+
+export type ApiKeyScope = 'openai' | 'claude' | 'gemini' | 'mcp' | 'images' | 'audio';
+
+export interface ClientApiKey {
+    name: string;
+    key: string;
+    enabled: boolean;
+    scopes?: ApiKeyScope[]; // 空表示不限制
+    allowed_models?: string[]; // 支持 * 通配符，空表示不限制
+    expires_at?: number | null; // Unix 秒
+    daily_token_budget?: number | null; // 每日 token 预算，空表示不限制
+    monthly_token_budget?: number | null; // 每月 token 预算，空表示不限制
+    priority?: number; // 账号池耗尽排队时的优先级，越大越先放行
+}
+
+export type ModelMatchType = 'exact' | 'glob' | 'regex';
+export type ClientProtocol = 'openai' | 'claude' | 'gemini';
+
+export interface ModelRoutingRule {
+    name?: string;
+    enabled?: boolean;
+    match_type: ModelMatchType;
+    pattern: string;
+    target: string; // glob / regex 支持 $1 / ${name} 捕获组替换
+    protocols?: ClientProtocol[]; // 空表示不限制
+    api_keys?: string[]; // ClientApiKey.name，空表示不限制
+    features?: {
+        tools?: boolean;
+        images?: boolean;
+        thinking?: boolean;
+    };
+}
+
+export interface ModelCapabilities {
+    context_window?: number;
+    max_output_tokens?: number;
+    tools?: boolean;
+    vision?: boolean;
+    thinking?: boolean; // 接受 thinkingBudget
+    thinking_passthrough?: boolean; // Claude 协议 thinking 块可透传
+    thinking_by_default?: boolean;
+    search?: boolean;
+    image_output?: boolean;
+}
+
+export type SchedulingMode = 'CacheFirst' | 'Balance' | 'PerformanceFirst' | 'LeastLoaded' | 'QuotaWeighted';
 
 export interface StickySessionConfig {
     mode: SchedulingMode;
     max_wait_seconds: number;
+    max_concurrent_per_account?: number; // 0 表示不限制
+    tier_priority?: string[]; // 订阅等级优先级，默认 ['ULTRA', 'PRO', 'FREE']
+    session_ttl_seconds?: number; // 会话绑定空闲过期时间，0 表示不过期
+    max_sessions?: number; // 会话绑定上限 (LRU 淘汰)，0 表示不限制
+    persist_sessions?: boolean; // 重启后保留会话绑定
+    queue_enabled?: boolean; // 账号池耗尽时排队等待而非直接失败
+    queue_max_depth?: number; // 队列上限，0 表示不限制
+    queue_timeout_seconds?: number; // 排队超时
 }
 
-export type ZaiDispatchMode = 'off' | 'exclusive' | 'pooled' | 'fallback';
+export type ProviderDispatchMode = 'off' | 'exclusive' | 'pooled' | 'fallback' | 'weighted';
+export type ZaiDispatchMode = ProviderDispatchMode;
+export type ProviderProtocol = 'anthropic' | 'openai' | 'gemini';
+
+export interface UpstreamProviderConfig {
+    id: string; // 请求中可用 `{id}:{model}` 直接指定上游模型
+    name?: string;
+    enabled?: boolean;
+    base_url: string;
+    api_key?: string; // 留空时不附加认证头
+    protocol: ProviderProtocol;
+    dispatch_mode?: ProviderDispatchMode;
+    weight?: number; // weighted 模式下的请求百分比 (0-100)
+    model_mapping?: Record<string, string>; // 支持 * 通配符
+    cross_protocol?: boolean; // 允许经协议转换承接其他协议的请求 (默认关闭)
+}
 
 export interface ZaiMcpConfig {
     enabled: boolean;
@@ -45,7 +134,9 @@ export interface ZaiConfig {
     base_url: string;
     api_key: string;
     dispatch_mode: ZaiDispatchMode;
+    weight?: number; // weighted 模式下的请求百分比 (0-100)
     model_mapping?: Record<string, string>;
+    cross_protocol?: boolean; // 允许承接 OpenAI 协议请求 (默认关闭)
     models: ZaiModelDefaults;
     mcp: ZaiMcpConfig;
 }